@@ -0,0 +1,37 @@
+// Pipeline definition API
+// Checks a TOML or YAML pipeline definition without registering or running it
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use pyro_core::pipeline_definition::{DefinitionErrors, DefinitionFormat, PipelineDefinition};
+use serde_json::json;
+
+use super::state::AppState;
+use crate::definitions;
+
+/// Validate a pipeline definition (dictionary: validate_definition)
+/// Pseudocode: Pick TOML or YAML by content type, parse and check the definition and answer
+/// with every problem and its position or field, or with the pipeline record it compiles to
+pub async fn validate_definition(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let format = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(DefinitionFormat::from_content_type)
+        .ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+
+    let checked = match PipelineDefinition::parse(&body, format) {
+        Ok(definition) => definitions::check(&state, &definition).await,
+        Err(errors) => Err(errors),
+    };
+    Ok(Json(match checked {
+        Ok(record) => json!({ "valid": true, "pipeline": record }),
+        Err(DefinitionErrors(issues)) => json!({ "valid": false, "errors": issues }),
+    }))
+}
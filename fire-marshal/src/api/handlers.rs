@@ -7,6 +7,14 @@ use node_red_bridge::NodeRedMessage;
 use serde_json::{json, Value};
 
 use super::state::AppState;
+use crate::graph;
+use crate::orchestrator::{LifecycleError, PipelineAction};
+use crate::scheduler::ScheduleError;
+use crate::sink::SinkError;
+use crate::source::SourceError;
+use crate::transform::TransformError;
+use pyro_core::pipeline::PipelineSchedule;
+use pyro_core::pipeline_graph::{GraphError, PipelineGraph};
 
 /// Root endpoint
 pub async fn root() -> impl IntoResponse {
@@ -18,6 +26,15 @@ pub async fn root() -> impl IntoResponse {
             "GET /health",
             "GET /api/monitor",
             "POST /api/orchestrate",
+            "GET /api/pipelines",
+            "GET /api/pipelines/{id}",
+            "DELETE /api/pipelines/{id}",
+            "POST /api/pipelines/{id}/pause",
+            "POST /api/pipelines/{id}/resume",
+            "POST /api/pipelines/{id}/stop",
+            "POST /api/pipelines/{id}/rerun",
+            "GET /api/pipelines/{id}/history",
+            "POST /api/node-red/inbound",
         ]
     }))
 }
@@ -42,15 +59,29 @@ pub async fn get_monitoring(
         "data_processed": stats.data_processed,
         "errors": stats.errors,
         "last_update": stats.last_update,
+        "queue_pickup": stats.queue_pickup,
     })))
 }
 
 #[derive(serde::Deserialize)]
 pub struct OrchestrateRequest {
     pub pipeline_id: String,
+    #[serde(default)]
     pub source: String,
+    /// Transformer chain, e.g. `["dedupe", "redact:email"]`
+    #[serde(default)]
+    pub transformers: Vec<String>,
+    #[serde(default)]
     pub destination: String,
+    /// Omit to read the data from `source`
+    #[serde(default)]
     pub payload: Value,
+    /// Register a recurring pipeline instead of running it now
+    #[serde(default)]
+    pub schedule: Option<PipelineSchedule>,
+    /// Steps of a graph pipeline, run instead of `source`, `transformers` and `destination`
+    #[serde(default)]
+    pub graph: Option<PipelineGraph>,
 }
 
 /// Orchestrate data flow
@@ -58,16 +89,97 @@ pub async fn orchestrate(
     State(state): State<AppState>,
     Json(req): Json<OrchestrateRequest>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
+    let Some(schedule) = req.schedule.clone() else {
+        return run_pipeline(&state, req).await;
+    };
+
+    // Scheduled runs read fresh data from the source every time
+    if !req.payload.is_null() || req.graph.as_ref().is_some_and(|g| g.has_payload()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let pipeline = state
+        .register_pipeline(
+            &req.pipeline_id,
+            &req.source,
+            &req.transformers,
+            &req.destination,
+            req.graph.as_ref(),
+            Some(schedule),
+        )
+        .await
+        .map_err(|err| pipeline_error_status(&err))?;
+
+    Ok(Json(json!({
+        "message": "Pipeline scheduled successfully",
+        "pipeline_id": pipeline.id,
+        "schedule": pipeline.schedule,
+        "next_run": pipeline.next_run,
+    })))
+}
+
+/// Map orchestrator errors to responses: unknown pipelines are 404, refused lifecycle
+/// transitions 409 and bad transformer chains, graphs, schedules, sources or destinations 400
+pub(super) fn pipeline_error_status(err: &anyhow::Error) -> StatusCode {
+    match err.downcast_ref::<LifecycleError>() {
+        Some(LifecycleError::NotFound(_)) => StatusCode::NOT_FOUND,
+        Some(LifecycleError::InvalidTransition { .. }) => StatusCode::CONFLICT,
+        None if err.is::<TransformError>()
+            || err.is::<GraphError>()
+            || err.is::<ScheduleError>()
+            || err.is::<SourceError>()
+            || err.is::<SinkError>() =>
+        {
+            StatusCode::BAD_REQUEST
+        }
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Run a pipeline and deliver its output, for `/api/orchestrate` and reruns
+pub(super) async fn run_pipeline(
+    state: &AppState,
+    req: OrchestrateRequest,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    // Refuse paused and stopped pipelines before their source is read
+    if let Err(err) = state
+        .orchestrator
+        .read()
+        .await
+        .ensure_allowed(&req.pipeline_id, PipelineAction::Run)
+    {
+        if !matches!(
+            err.downcast_ref::<LifecycleError>(),
+            Some(LifecycleError::NotFound(_))
+        ) {
+            return Err(pipeline_error_status(&err));
+        }
+    }
+    if let Some(graph) = &req.graph {
+        return run_graph_pipeline(state, &req, graph).await;
+    }
+
+    // Only sources and destinations with a registered scheme are accepted
+    let source = state
+        .sources
+        .resolve(&req.source, &req.payload)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    state
+        .sinks
+        .resolve(&req.destination)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let documents = source.fetch().await.map_err(|_| StatusCode::BAD_GATEWAY)?;
+
     let mut orchestrator = state.orchestrator.write().await;
 
-    let run_stats = orchestrator
+    let (run_stats, output) = orchestrator
         .create_pipeline(
             &req.pipeline_id,
             &req.source,
+            &req.transformers,
             &req.destination,
-            &req.payload,
+            &documents,
         )
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|err| pipeline_error_status(&err))?;
     let active_count = orchestrator.active_pipeline_count();
     drop(orchestrator);
 
@@ -77,6 +189,15 @@ pub async fn orchestrate(
         monitor.increment_data((run_stats.nodes_count + run_stats.edges_count) as u64);
     }
 
+    let delivery = state
+        .deliver_output(&req.pipeline_id, &req.source, &req.destination, &output)
+        .await
+        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    source
+        .acknowledge()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     {
         let node_red = state.node_red.read().await;
         let message = NodeRedMessage::new(
@@ -87,6 +208,8 @@ pub async fn orchestrate(
                 "destination": req.destination,
                 "nodes": run_stats.nodes_count,
                 "edges": run_stats.edges_count,
+                "transformers": run_stats.steps,
+                "delivered": delivery.records,
                 "processed_at": run_stats.processed_at,
             }),
         );
@@ -98,5 +221,77 @@ pub async fn orchestrate(
         "pipeline_id": req.pipeline_id,
         "nodes": run_stats.nodes_count,
         "edges": run_stats.edges_count,
+        "transformers": run_stats.steps,
+        "delivery": delivery,
+    })))
+}
+
+/// Run a graph pipeline for `/api/orchestrate` and reruns; its sources carry their own input
+async fn run_graph_pipeline(
+    state: &AppState,
+    req: &OrchestrateRequest,
+    graph: &PipelineGraph,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if !req.payload.is_null() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let run_stats = graph::run_graph(state, &req.pipeline_id, graph)
+        .await
+        .map_err(|err| match pipeline_error_status(&err) {
+            // A source or sink failed while the graph ran
+            StatusCode::INTERNAL_SERVER_ERROR => StatusCode::BAD_GATEWAY,
+            status => status,
+        })?;
+
+    {
+        let node_red = state.node_red.read().await;
+        let message = NodeRedMessage::new(
+            "fire-marshal/pipelines".to_string(),
+            json!({
+                "pipeline_id": req.pipeline_id,
+                "nodes": run_stats.nodes_count,
+                "edges": run_stats.edges_count,
+                "graph": run_stats.nodes,
+                "processed_at": run_stats.processed_at,
+            }),
+        );
+        let _ = node_red.send(message).await;
+    }
+
+    Ok(Json(json!({
+        "message": "Pipeline orchestrated successfully",
+        "pipeline_id": req.pipeline_id,
+        "nodes": run_stats.nodes_count,
+        "edges": run_stats.edges_count,
+        "graph": run_stats.nodes,
+    })))
+}
+
+#[derive(serde::Deserialize)]
+pub struct InboundRequest {
+    pub topic: String,
+    pub payload: Value,
+}
+
+/// Queue a message on the Node-RED bridge's inbound channel for `node-red:` pipeline sources
+pub async fn node_red_inbound(
+    State(state): State<AppState>,
+    Json(req): Json<InboundRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let message = NodeRedMessage::new(req.topic, req.payload);
+    let msg_id = message.msg_id.clone();
+    let topic = message.topic.clone();
+    state
+        .node_red
+        .read()
+        .await
+        .sender()
+        .send(message)
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    Ok(Json(json!({
+        "message": "Message queued",
+        "msg_id": msg_id,
+        "topic": topic,
     })))
 }
@@ -0,0 +1,158 @@
+// Internal pipeline API
+// pyro-core hands pipeline records to fire-marshal here instead of writing them into a shared
+// work directory; requests carry the shared `pipeline.handoff.token`
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{Json, Response},
+};
+use pyro_core::handoff::{self, RunStatus, StatusUpdate, SubmitReceipt};
+use pyro_core::pipeline::{is_valid_pipeline_id, PipelineRecord, PipelineRegistry};
+
+use super::state::AppState;
+
+/// Require the shared token on an API request (dictionary: require_token)
+/// Pseudocode: Answer 401 unless the request carries `pipeline.handoff.token` as its bearer
+/// token; layered over the whole API when fire-marshal listens beyond loopback
+pub async fn require_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let token = state
+        .handoff_token
+        .as_deref()
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !handoff::authorized(presented, token) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Accept a pipeline record from pyro-core (dictionary: submit_pipeline)
+/// Pseudocode: Check the shared token, reject records whose source, destination or schedule
+/// cannot run, write the record into the queue and report it as queued
+pub async fn submit_pipeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(record): Json<PipelineRecord>,
+) -> Result<(StatusCode, Json<SubmitReceipt>), StatusCode> {
+    let token = state
+        .handoff_token
+        .as_deref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !handoff::authorized(presented, token) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    // Scheduled pipelines read fresh data from their source on every run
+    let payload = match &record.graph {
+        Some(graph) => graph.has_payload(),
+        None => !record.payload.is_null(),
+    };
+    if !is_valid_pipeline_id(&record.id)
+        || (record.graph.is_none() && record.transformers.is_empty())
+        || (record.schedule.is_some() && payload)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let resolved = match &record.graph {
+        Some(graph) => graph
+            .order()
+            .map_err(|err| err.to_string())
+            .and_then(|_| state.resolve_graph(graph).map_err(|err| err.to_string())),
+        None => match state.sources.resolve(&record.source, &record.payload) {
+            Ok(_) => state
+                .sinks
+                .resolve(&record.destination)
+                .map(|_| ())
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        },
+    };
+    if let Err(err) = resolved {
+        eprintln!("[Fire Marshal] rejected pipeline {}: {err}", record.id);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let file_name = format!("{}.json", record.id.replace(':', "_"));
+    if state.pipeline_dir.join(&file_name).exists() {
+        return Err(StatusCode::CONFLICT);
+    }
+    let path = PipelineRegistry::new(&state.pipeline_dir)
+        .persist(&record)
+        .map_err(|err| {
+            eprintln!(
+                "[Fire Marshal] failed to queue pipeline {}: {err:?}",
+                record.id
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    state.report_status(
+        record.report_status,
+        StatusUpdate::new(&record.id, RunStatus::Queued),
+    );
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(SubmitReceipt {
+            pipeline_id: record.id,
+            file: path.display().to_string(),
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyro_core::config::Config;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_status_goes_to_configured_endpoint_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let pyro = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config::default();
+        config.pipeline.work_dir = temp_dir.path().join("pipelines");
+        config.database.path = temp_dir.path().join("db");
+        config.pipeline.handoff.token = Some("t".repeat(32));
+        config.pipeline.handoff.callback_url =
+            Some(format!("http://{}", pyro.local_addr().unwrap()));
+        let state = AppState::new(config).await.unwrap();
+
+        // A record only asks for reports; they go to the configured pyro-core endpoint
+        let record = PipelineRecord::new(
+            "p1",
+            "watch://incoming",
+            vec!["redact:email".to_string()],
+            "redb:",
+            serde_json::Value::Null,
+        )
+        .with_status_reports(true);
+        state.report_status(
+            record.report_status,
+            StatusUpdate::new(&record.id, RunStatus::Queued),
+        );
+
+        let (mut socket, _) = tokio::time::timeout(Duration::from_secs(5), pyro.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut request = vec![0; 64];
+        let read = socket.read(&mut request).await.unwrap();
+        let request = String::from_utf8_lossy(&request[..read]);
+        assert!(request.starts_with("POST /internal/pipelines/status "));
+    }
+}
@@ -1,2 +1,6 @@
+pub mod definitions;
 pub mod handlers;
+pub mod internal;
+pub mod pipelines;
+pub mod queue;
 pub mod state;
@@ -0,0 +1,159 @@
+// Pipeline lifecycle handlers
+// List and inspect orchestrated pipelines and move them through the status state machine
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::handlers::{pipeline_error_status, run_pipeline, OrchestrateRequest};
+use super::state::AppState;
+use crate::orchestrator::{Pipeline, PipelineAction};
+use crate::store::PipelineHistory;
+
+/// List every pipeline
+pub async fn list_pipelines(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let orchestrator = state.orchestrator.read().await;
+    let pipelines = orchestrator.list_pipelines();
+
+    Ok(Json(json!({
+        "count": pipelines.len(),
+        "pipelines": pipelines,
+    })))
+}
+
+/// Get a pipeline with its status and last run
+pub async fn get_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Pipeline>, StatusCode> {
+    let orchestrator = state.orchestrator.read().await;
+    orchestrator
+        .get_pipeline(&id)
+        .cloned()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Runs and status transitions recorded for a pipeline
+pub async fn get_pipeline_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<PipelineHistory>, StatusCode> {
+    let orchestrator = state.orchestrator.read().await;
+    orchestrator
+        .history(&id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Apply a lifecycle action and refresh the monitor's active count
+async fn change_pipeline(
+    state: &AppState,
+    id: &str,
+    action: PipelineAction,
+) -> Result<Option<Pipeline>, StatusCode> {
+    let mut orchestrator = state.orchestrator.write().await;
+    let pipeline = orchestrator
+        .apply_action(id, action)
+        .map_err(|err| pipeline_error_status(&err))?;
+    let active_count = orchestrator.active_pipeline_count();
+    drop(orchestrator);
+
+    state
+        .monitor
+        .write()
+        .await
+        .set_active_pipelines(active_count);
+    Ok(pipeline)
+}
+
+/// Pause an active pipeline; queued runs wait until it is resumed
+pub async fn pause_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Pipeline>, StatusCode> {
+    change_pipeline(&state, &id, PipelineAction::Pause)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Resume a paused pipeline
+pub async fn resume_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Pipeline>, StatusCode> {
+    change_pipeline(&state, &id, PipelineAction::Resume)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Stop a pipeline for good; it can only be deleted afterwards
+pub async fn stop_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Pipeline>, StatusCode> {
+    change_pipeline(&state, &id, PipelineAction::Stop)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Delete a pipeline that is not active, with its run history
+pub async fn delete_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    change_pipeline(&state, &id, PipelineAction::Delete).await?;
+
+    Ok(Json(json!({
+        "message": "Pipeline deleted",
+        "pipeline_id": id,
+    })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RerunRequest {
+    /// Input for pipelines whose source is only a label
+    #[serde(default)]
+    pub payload: Value,
+}
+
+/// Run an active or failed pipeline again with its stored source, transformers and destination,
+/// or its stored graph
+pub async fn rerun_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<RerunRequest>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let pipeline = {
+        let orchestrator = state.orchestrator.read().await;
+        orchestrator
+            .ensure_allowed(&id, PipelineAction::Run)
+            .map_err(|err| pipeline_error_status(&err))?
+            .clone()
+    };
+    let Json(body) = body.unwrap_or_default();
+
+    run_pipeline(
+        &state,
+        OrchestrateRequest {
+            pipeline_id: pipeline.id,
+            source: pipeline.source,
+            transformers: pipeline.transformers,
+            destination: pipeline.destination,
+            payload: body.payload,
+            schedule: None,
+            graph: pipeline.graph,
+        },
+    )
+    .await
+}
@@ -0,0 +1,54 @@
+// Pipeline queue handlers
+// List dead-lettered pipeline files with their failure chains and move them back into the queue
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::json;
+
+use super::state::AppState;
+use crate::datapipe::FAILED_DIR;
+use crate::retry::{self, RequeueError};
+
+/// List pipeline files that failed permanently or ran out of attempts
+pub async fn list_dead_letters(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let dead_letters = retry::list_dead_letters(&state.pipeline_dir.join(FAILED_DIR))
+        .await
+        .map_err(|err| {
+            eprintln!("[Fire Marshal] failed to list dead letters: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "count": dead_letters.len(),
+        "dead_letters": dead_letters,
+    })))
+}
+
+/// Move a dead-lettered pipeline file back into the queue with a fresh attempt count
+pub async fn requeue_dead_letter(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let failed_dir = state.pipeline_dir.join(FAILED_DIR);
+    retry::requeue(&failed_dir, &state.pipeline_dir, &file)
+        .await
+        .map_err(|err| match err.downcast_ref::<RequeueError>() {
+            Some(RequeueError::InvalidName(_)) => StatusCode::BAD_REQUEST,
+            Some(RequeueError::NotFound(_)) => StatusCode::NOT_FOUND,
+            Some(RequeueError::AlreadyQueued(_)) => StatusCode::CONFLICT,
+            None => {
+                eprintln!("[Fire Marshal] failed to requeue {}: {err:?}", file);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(Json(json!({
+        "message": "Pipeline requeued",
+        "file": file,
+    })))
+}
@@ -7,8 +7,16 @@ use std::time::Duration;
 use tokio::sync::RwLock;
 
 use crate::monitoring::Monitor;
-use crate::orchestrator::Orchestrator;
-use pyro_core::config::Config;
+use crate::orchestrator::{Orchestrator, Pipeline};
+use crate::sink::{DeliveryReport, OutputBatch, SinkRegistry, SinkResources};
+use crate::source::{SourceRegistry, SourceResources};
+use crate::store::PipelineStore;
+use pyro_core::config::{Config, RuntimeConfig};
+use pyro_core::data_extractor::ExtractedData;
+use pyro_core::handoff::{self, StatusUpdate};
+use pyro_core::pipeline::PipelineSchedule;
+use pyro_core::pipeline_graph::{GraphStep, PipelineGraph};
+use serde_json::Value;
 
 /// Application state for Fire Marshal
 #[derive(Clone)]
@@ -18,7 +26,21 @@ pub struct AppState {
     pub node_red: Arc<RwLock<NodeRedBridge>>,
     pub db: Arc<Database>,
     pub pipeline_dir: PathBuf,
-    pub pipeline_interval: Duration,
+    /// Queued pipeline files run at the same time
+    pub workers: usize,
+    /// Reloadable configuration sections, swapped together with `node_red`
+    pub runtime: Arc<RwLock<RuntimeConfig>>,
+    /// Destinations pipeline output is delivered to
+    pub sinks: Arc<SinkRegistry>,
+    /// Where pipelines without an embedded payload read their input
+    pub sources: Arc<SourceRegistry>,
+    /// Shared token of the internal pipeline API and status callbacks; both are off without it
+    pub handoff_token: Option<String>,
+    /// pyro-core's status endpoint from `pipeline.handoff.callback_url`; the only place run
+    /// status is reported to
+    pub status_url: Option<String>,
+    /// Client for status callbacks, bounded by `pipeline.handoff.timeout_secs`
+    pub callbacks: reqwest::Client,
 }
 
 impl AppState {
@@ -29,24 +51,45 @@ impl AppState {
         let db_path = config.pipeline.work_dir.join("fire_marshal.redb");
         let db = Arc::new(Database::create(&db_path)?);
 
-        // Initialize orchestrator
-        let orchestrator = Arc::new(RwLock::new(Orchestrator::new()));
+        // Initialize orchestrator with the pipelines persisted by previous runs
+        let orchestrator = Orchestrator::load(PipelineStore::open(db.clone())?)?;
 
         // Initialize monitor
-        let monitor = Arc::new(RwLock::new(Monitor::new()));
+        let mut monitor = Monitor::new();
+        monitor.set_active_pipelines(orchestrator.active_pipeline_count());
+        let orchestrator = Arc::new(RwLock::new(orchestrator));
+        let monitor = Arc::new(RwLock::new(monitor));
 
         // Initialize Node-RED bridge
-        let mut bridge = NodeRedBridge::new();
+        let runtime = RuntimeConfig::from_config(&config);
+        let node_red = Arc::new(RwLock::new(runtime.node_red_bridge()));
+        let runtime = Arc::new(RwLock::new(runtime));
 
-        if let Some(mqtt_broker) = &config.node_red.mqtt_broker {
-            bridge = bridge.with_mqtt(mqtt_broker.clone());
-        }
-
-        if let Some(http_endpoint) = &config.node_red.http_endpoint {
-            bridge = bridge.with_http(http_endpoint.clone());
-        }
-
-        let node_red = Arc::new(RwLock::new(bridge));
+        // Sinks and sources share the bridge, database and an HTTP client that does not follow
+        // redirects, which could lead them away from the allowed hosts; file input and output
+        // stay under the work directory and cryptex stores live next to pyro's database
+        let timeout = Duration::from_secs(config.pipeline.handoff.timeout_secs);
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        let cryptex_root = config.database.path.join("cryptex");
+        let sinks = Arc::new(SinkRegistry::new(SinkResources {
+            node_red: node_red.clone(),
+            db: db.clone(),
+            http: http.clone(),
+            output_root: config.pipeline.work_dir.join("output"),
+            allowed_hosts: config.pipeline.sink_hosts.clone(),
+            cryptex_root: cryptex_root.clone(),
+        }));
+        let sources = Arc::new(SourceRegistry::new(SourceResources {
+            node_red: node_red.clone(),
+            http: http.clone(),
+            input_root: config.pipeline.work_dir.join("input"),
+            allowed_hosts: config.pipeline.source_hosts.clone(),
+            cryptex_root,
+        }));
+        let callbacks = http.clone();
 
         Ok(Self {
             orchestrator,
@@ -54,7 +97,125 @@ impl AppState {
             node_red,
             db,
             pipeline_dir: config.pipeline.work_dir.clone(),
-            pipeline_interval: Duration::from_secs(config.pipeline.datapipe_interval_secs.max(5)),
+            workers: config.pipeline.workers,
+            runtime,
+            sinks,
+            sources,
+            handoff_token: config.pipeline.handoff.token.clone(),
+            status_url: config
+                .pipeline
+                .handoff
+                .callback_url
+                .as_deref()
+                .map(|url| handoff::endpoint(url, handoff::STATUS_PATH)),
+            callbacks,
         })
     }
+
+    /// Deliver a pipeline's output to its destination, recording the outcome on the pipeline
+    pub async fn deliver_output(
+        &self,
+        pipeline_id: &str,
+        source: &str,
+        destination: &str,
+        data: &ExtractedData,
+    ) -> Result<DeliveryReport> {
+        let batch = OutputBatch {
+            pipeline_id,
+            source,
+            data,
+        };
+        match self.sinks.deliver(destination, &batch).await {
+            Ok(report) => {
+                let mut orchestrator = self.orchestrator.write().await;
+                orchestrator.record_delivery(pipeline_id, report.clone())?;
+                Ok(report)
+            }
+            Err(err) => {
+                let mut orchestrator = self.orchestrator.write().await;
+                if let Err(store_err) = orchestrator.fail_pipeline(pipeline_id, format!("{err:#}"))
+                {
+                    eprintln!(
+                        "[Fire Marshal] failed to record failure of pipeline {}: {store_err:?}",
+                        pipeline_id
+                    );
+                }
+                self.monitor.write().await.increment_errors();
+                Err(err)
+            }
+        }
+    }
+
+    /// Create or update a pipeline without running it, recurring when it has a schedule; its
+    /// sources must be URIs fire-marshal can read on its own
+    pub async fn register_pipeline(
+        &self,
+        id: &str,
+        source: &str,
+        transformers: &[String],
+        destination: &str,
+        graph: Option<&PipelineGraph>,
+        schedule: Option<PipelineSchedule>,
+    ) -> Result<Pipeline> {
+        match graph {
+            Some(graph) => self.resolve_graph(graph)?,
+            None => {
+                self.sources.resolve(source, &Value::Null)?;
+                self.sinks.resolve(destination)?;
+            }
+        }
+
+        let mut orchestrator = self.orchestrator.write().await;
+        let pipeline = orchestrator
+            .register_pipeline(id, source, transformers, destination, graph, schedule)?
+            .clone();
+        let active_count = orchestrator.active_pipeline_count();
+        drop(orchestrator);
+
+        self.monitor
+            .write()
+            .await
+            .set_active_pipelines(active_count);
+        Ok(pipeline)
+    }
+
+    /// Report a run status to pyro-core's configured status endpoint in the background when
+    /// the pipeline's record `requested` reports; without the endpoint or a hand-off token
+    /// nothing is reported
+    pub fn report_status(&self, requested: bool, update: StatusUpdate) {
+        let (true, Some(url), Some(token)) = (requested, &self.status_url, &self.handoff_token)
+        else {
+            return;
+        };
+        let (http, url, token) = (self.callbacks.clone(), url.to_string(), token.clone());
+        tokio::spawn(async move {
+            if let Err(err) = handoff::report_status(&http, &url, &token, &update).await {
+                eprintln!(
+                    "[Fire Marshal] failed to report status of pipeline {}: {err:#}",
+                    update.pipeline_id
+                );
+            }
+        });
+    }
+
+    /// Check every source and sink of a graph has a registered scheme
+    pub fn resolve_graph(&self, graph: &PipelineGraph) -> Result<()> {
+        for node in &graph.nodes {
+            match &node.step {
+                GraphStep::Source { uri, payload } => {
+                    self.sources.resolve(uri, payload)?;
+                }
+                GraphStep::Sink { destination } => {
+                    self.sinks.resolve(destination)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Current delay between pipeline queue scans
+    pub async fn pipeline_interval(&self) -> Duration {
+        Duration::from_secs(self.runtime.read().await.datapipe_interval_secs.max(5))
+    }
 }
@@ -0,0 +1,35 @@
+// Command line flags
+// Configuration layers for the fire-marshal binary, sharing pyro's global flags
+
+use clap::Parser;
+use pyro_core::config::ConfigFlags;
+
+/// Fire Marshal - Data Flow Orchestration
+#[derive(Debug, Parser)]
+#[command(name = "fire-marshal", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub flags: ConfigFlags,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_layer_over_config() {
+        let cli = Cli::try_parse_from([
+            "fire-marshal",
+            "--no-env",
+            "--dev",
+            "--set",
+            "pipeline.workers=8",
+        ])
+        .unwrap();
+        let config = cli.flags.loader().load().unwrap();
+        assert!(config.dev_mode);
+        assert_eq!(config.pipeline.workers, 8);
+
+        assert!(Cli::try_parse_from(["fire-marshal", "--set", "workers"]).is_err());
+    }
+}
@@ -1,74 +1,490 @@
 use crate::api::state::AppState;
+use crate::graph;
+use crate::lease::{self, Claim, LEASE_RENEW_INTERVAL};
+use crate::orchestrator::{PipelineAction, PipelineStatus};
+use crate::retry::{self, FailureRecord};
+use crate::watcher::{QueueWatcher, DEBOUNCE};
 use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
 use node_red_bridge::NodeRedMessage;
+use pyro_core::handoff::{RunStatus, StatusUpdate};
+use pyro_core::logging::{self, LogLevel};
 use pyro_core::pipeline::PipelineRecord;
-use std::path::PathBuf;
+use pyro_core::pipeline_graph::PipelineGraph;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use tokio::fs;
+use tokio::task::{Id, JoinError, JoinSet};
 use tokio::time::{sleep, Duration};
+use uuid::Uuid;
 
 const PROCESSED_DIR: &str = "processed";
-const FAILED_DIR: &str = "failed";
+/// Dead letters: pipeline files that failed permanently or ran out of attempts
+pub const FAILED_DIR: &str = "failed";
+
+/// What happened to a queued pipeline file
+pub enum QueueOutcome {
+    Processed,
+    /// The pipeline is paused; the file stays queued until it is resumed
+    Deferred,
+}
+
+/// A pipeline file waiting in the queue
+struct QueuedFile {
+    path: PathBuf,
+    pipeline_id: Option<String>,
+    /// Files of one source run one at a time, in `created_at` order
+    source: String,
+    created_at: i64,
+    /// When the file landed in the queue
+    modified: Option<SystemTime>,
+    /// Whether the file failed before
+    retried: bool,
+    /// Whether the backoff after its last failure has passed
+    ready: bool,
+    /// Why its error sidecar could not be read; such files are dead-lettered
+    unreadable: Option<String>,
+}
+
+/// Workers running claimed pipeline files
+struct WorkerPool {
+    state: AppState,
+    /// Lease owner of this process's claims
+    owner: String,
+    workers: JoinSet<()>,
+    /// Source of the file each worker runs
+    running: HashMap<Id, String>,
+    /// Change notifications for the queue; scans alone find files without them
+    watcher: Option<QueueWatcher>,
+}
 
 pub fn spawn_pipeline_worker(state: AppState) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
+        let mut pool = WorkerPool {
+            owner: format!("fire-marshal-{}-{}", std::process::id(), Uuid::new_v4()),
+            state,
+            workers: JoinSet::new(),
+            running: HashMap::new(),
+            watcher: None,
+        };
+        pool.watch().await;
+
+        // fire_marshal.redb in the pipeline directory is only opened by one process, so every
+        // claim left in inprogress/ belongs to a run that died with the previous one
+        pool.recover(true).await;
         loop {
-            if let Err(err) = process_queue(&state).await {
+            pool.recover(false).await;
+            if let Err(err) = pool.fill().await {
                 eprintln!("[Fire Marshal] pipeline processing error: {err:?}");
             }
-            sleep(state.pipeline_interval).await;
+
+            // Scans keep running next to the watcher for changes it misses, e.g. on network
+            // filesystems
+            let interval = pool.state.pipeline_interval().await;
+            let arrived = async {
+                match pool.watcher.as_mut() {
+                    Some(watcher) => watcher.arrived().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = sleep(interval) => {}
+                Some(done) = pool.workers.join_next_with_id() => pool.finished(done),
+                watching = arrived => {
+                    if !watching {
+                        eprintln!("[Fire Marshal] pipeline queue watcher stopped, polling only");
+                        pool.watcher = None;
+                        pool.state.monitor.write().await.set_pickup_mode("polling");
+                    }
+                }
+            }
         }
     })
 }
 
-async fn process_queue(state: &AppState) -> Result<()> {
-    let mut dir = match fs::read_dir(&state.pipeline_dir).await {
-        Ok(dir) => dir,
-        Err(err) => {
-            if err.kind() == std::io::ErrorKind::NotFound {
-                fs::create_dir_all(&state.pipeline_dir).await?;
-                return Ok(());
+impl WorkerPool {
+    /// Watch the queue directory, falling back to scans where notifications are unavailable
+    async fn watch(&mut self) {
+        let queue_dir = &self.state.pipeline_dir;
+        let watcher = match fs::create_dir_all(queue_dir).await {
+            Ok(()) => QueueWatcher::new(queue_dir),
+            Err(err) => Err(err.into()),
+        };
+        let mode = match watcher {
+            Ok(watcher) => {
+                self.watcher = Some(watcher);
+                "notify"
+            }
+            Err(err) => {
+                eprintln!(
+                    "[Fire Marshal] watching the pipeline queue failed, polling only: {err:?}"
+                );
+                "polling"
+            }
+        };
+        self.state.monitor.write().await.set_pickup_mode(mode);
+    }
+
+    /// Settle claims whose worker died as failed attempts, so a file that crashes the
+    /// process every time ends up dead-lettered
+    async fn recover(&mut self, all: bool) {
+        let now = Utc::now().timestamp();
+        let recovered =
+            lease::recover_stale(&self.state.pipeline_dir, &self.owner, now, all).await;
+        let stale = match recovered {
+            Ok(stale) => stale,
+            Err(err) => {
+                eprintln!("[Fire Marshal] failed to recover pipeline leases: {err:?}");
+                return;
+            }
+        };
+
+        for (claim, previous) in stale {
+            let holder = previous
+                .map(|lease| lease.owner)
+                .unwrap_or_else(|| "an unknown worker".to_string());
+            let err = anyhow!("Lease of {} expired before the run finished", holder);
+            let record = read_pipeline_file(claim.path()).await.ok();
+            let path = claim.path().to_path_buf();
+            if let Err(err) = settle(&self.state, claim, record.as_ref(), Err(err)).await {
+                eprintln!("[Fire Marshal] failed to recover pipeline {:?}: {err:?}", path);
             }
-            return Err(err.into());
         }
-    };
+    }
+
+    /// Claim queued files for idle workers (dictionary: process_pipeline_queue)
+    /// Pseudocode: Order queued files by source and creation time; start the oldest file of
+    /// every source without a running file, until all workers are busy. A file waiting out
+    /// its backoff holds back the newer files of its source
+    async fn fill(&mut self) -> Result<()> {
+        let queued = match queued_files(&self.state.pipeline_dir).await {
+            Ok(queued) => queued,
+            Err(err) => {
+                if !self.state.pipeline_dir.exists() {
+                    fs::create_dir_all(&self.state.pipeline_dir).await?;
+                    return Ok(());
+                }
+                return Err(err);
+            }
+        };
+
+        // Files of paused pipelines stay queued without being claimed until they are resumed
+        let paused: HashSet<String> = self
+            .state
+            .orchestrator
+            .read()
+            .await
+            .list_pipelines()
+            .into_iter()
+            .filter(|pipeline| pipeline.status == PipelineStatus::Paused)
+            .map(|pipeline| pipeline.id.clone())
+            .collect();
+
+        let mut blocked: HashSet<String> = self.running.values().cloned().collect();
+        for file in queued {
+            if self.running.len() >= self.state.workers {
+                break;
+            }
+            if let Some(err) = &file.unreadable {
+                self.dead_letter_unreadable(&file.path, err).await;
+                continue;
+            }
+            if !blocked.insert(file.source.clone()) || !file.ready {
+                continue;
+            }
+            if file.pipeline_id.as_ref().is_some_and(|id| paused.contains(id)) {
+                continue;
+            }
+
+            let now = Utc::now().timestamp();
+            let claim =
+                Claim::acquire(&self.state.pipeline_dir, &file.path, &self.owner, now).await?;
+            let Some(claim) = claim else {
+                continue;
+            };
+            if !file.retried {
+                let latency = file.modified.and_then(|at| at.elapsed().ok());
+                if let Some(latency) = latency {
+                    self.state.monitor.write().await.record_pickup(latency);
+                }
+            }
+            let worker = self.workers.spawn(run_claim(self.state.clone(), claim));
+            self.running.insert(worker.id(), file.source);
+        }
+
+        Ok(())
+    }
 
+    /// Dead-letter a file whose error sidecar is corrupt; its attempts are unknown, so it is
+    /// not run again
+    async fn dead_letter_unreadable(&self, path: &Path, err: &str) {
+        let now = Utc::now().timestamp();
+        let dead_lettered = match Claim::acquire(&self.state.pipeline_dir, path, &self.owner, now)
+            .await
+        {
+            Ok(Some(claim)) => claim
+                .finish(&self.state.pipeline_dir.join(FAILED_DIR), true)
+                .await
+                .map(|()| true),
+            Ok(None) => Ok(false),
+            Err(err) => Err(err),
+        };
+        match dead_lettered {
+            Ok(true) => eprintln!("[Fire Marshal] Dead-lettered pipeline {:?}: {err}", path),
+            Ok(false) => {}
+            Err(move_err) => eprintln!(
+                "[Fire Marshal] failed to dead-letter pipeline {:?} ({err}): {move_err:?}",
+                path
+            ),
+        }
+    }
+
+    fn finished(&mut self, done: Result<(Id, ()), JoinError>) {
+        let id = match done {
+            Ok((id, ())) => id,
+            Err(err) => {
+                // The lease is no longer renewed; the file is recovered once it expires
+                eprintln!("[Fire Marshal] pipeline worker failed: {err}");
+                err.id()
+            }
+        };
+        self.running.remove(&id);
+    }
+}
+
+/// Queued pipeline files, oldest first
+async fn queued_files(queue_dir: &Path) -> Result<Vec<QueuedFile>> {
+    let mut dir = fs::read_dir(queue_dir)
+        .await
+        .with_context(|| format!("Failed to read {:?}", queue_dir))?;
+    let now = Utc::now().timestamp();
+
+    let mut queued = Vec::new();
     while let Some(entry) = dir.next_entry().await? {
         let path = entry.path();
-        if path.is_dir() {
+        // Skip folders and error sidecars
+        if !retry::is_record_file(&path) {
             continue;
         }
 
-        if let Err(err) = handle_pipeline_file(state, &path).await {
-            eprintln!(
-                "[Fire Marshal] Failed to handle pipeline {:?}: {err:?}",
-                path
-            );
-            move_to_folder(&path, &state.pipeline_dir.join(FAILED_DIR)).await?;
-        } else {
-            move_to_folder(&path, &state.pipeline_dir.join(PROCESSED_DIR)).await?;
+        // One corrupt sidecar must not hold up the rest of the queue
+        let (failures, unreadable) = match FailureRecord::load(&path).await {
+            Ok(failures) => (failures, None),
+            Err(err) => (None, Some(format!("{err:#}"))),
+        };
+        let modified = entry.metadata().await.ok().and_then(|meta| meta.modified().ok());
+        let age = modified.and_then(|at| at.elapsed().ok());
+        let (pipeline_id, source, created_at) = match read_pipeline_file(&path).await {
+            Ok(record) => (Some(record.id), record.source, record.created_at),
+            // Producers that do not write to a temporary name first may not be done yet
+            Err(_) if age.is_some_and(|age| age < DEBOUNCE) => continue,
+            // Malformed files are their own source so they are dead-lettered right away
+            Err(_) => (None, path.display().to_string(), i64::MIN),
+        };
+        queued.push(QueuedFile {
+            path,
+            pipeline_id,
+            source,
+            created_at,
+            modified,
+            retried: failures.is_some(),
+            ready: failures.is_none_or(|failures| failures.ready(now)),
+            unreadable,
+        });
+    }
+    queued.sort_by(|a, b| (a.created_at, &a.path).cmp(&(b.created_at, &b.path)));
+    Ok(queued)
+}
+
+/// Run a claimed file, renewing its lease until the run is over
+async fn run_claim(state: AppState, mut claim: Claim) {
+    let path = claim.path().to_path_buf();
+    let record = read_pipeline_file(&path).await;
+
+    let (record, outcome) = match record {
+        Ok(record) => {
+            let outcome = {
+                let run = run_record(&state, &record);
+                tokio::pin!(run);
+                loop {
+                    tokio::select! {
+                        outcome = &mut run => break outcome,
+                        _ = sleep(LEASE_RENEW_INTERVAL) => {
+                            if let Err(err) = claim.renew(Utc::now().timestamp()).await {
+                                eprintln!(
+                                    "[Fire Marshal] failed to renew lease of {:?}: {err:?}",
+                                    path
+                                );
+                            }
+                        }
+                    }
+                }
+            };
+            (Some(record), outcome)
         }
+        Err(err) => (None, Err(err)),
+    };
+
+    if let Err(err) = settle(&state, claim, record.as_ref(), outcome).await {
+        eprintln!("[Fire Marshal] failed to settle pipeline {:?}: {err:?}", path);
+    }
+}
+
+/// Move a claimed file on after its run: processed, back to the queue, or dead-lettered
+async fn settle(
+    state: &AppState,
+    claim: Claim,
+    record: Option<&PipelineRecord>,
+    outcome: Result<QueueOutcome>,
+) -> Result<()> {
+    let err = match outcome {
+        Ok(QueueOutcome::Processed) => {
+            return claim
+                .finish(&state.pipeline_dir.join(PROCESSED_DIR), false)
+                .await;
+        }
+        Ok(QueueOutcome::Deferred) => return claim.release().await,
+        Err(err) => err,
+    };
+
+    let path = claim.path().to_path_buf();
+    let now = Utc::now().timestamp();
+    let mut failures = FailureRecord::load(&path).await?.unwrap_or_default();
+    if let Some(record) = record {
+        failures.pipeline_id = Some(record.id.clone());
+    }
+    let retry = state.runtime.read().await.retry.clone();
+    let retrying = failures.record(&err, &retry, now);
+    failures.save(&path).await?;
+
+    if let Some(record) = record {
+        let status = if retrying {
+            RunStatus::Retrying
+        } else {
+            RunStatus::DeadLettered
+        };
+        let update = StatusUpdate::new(&record.id, status)
+            .with_attempt(failures.attempts)
+            .with_message(format!("{err:#}"));
+        state.report_status(record.report_status, update);
+    }
+
+    if retrying {
+        eprintln!(
+            "[Fire Marshal] Failed to handle pipeline {:?} (attempt {}): {err:?}",
+            path, failures.attempts
+        );
+        return claim.release().await;
     }
 
+    eprintln!(
+        "[Fire Marshal] Dead-lettered pipeline {:?} after {} attempt(s): {err:?}",
+        path, failures.attempts
+    );
+    claim.finish(&state.pipeline_dir.join(FAILED_DIR), true).await?;
+
+    let node_red = state.node_red.read().await;
+    let message = NodeRedMessage::new(
+        "fire-marshal/pipelines".to_string(),
+        serde_json::json!({
+            "event": "pipeline_dead_lettered",
+            "pipeline_id": failures.pipeline_id,
+            "file": path.file_name().map(|name| name.to_string_lossy()),
+            "attempts": failures.attempts,
+            "permanent": failures.permanent,
+            "error": err.to_string(),
+        }),
+    );
+    let _ = node_red.send(message).await;
+
     Ok(())
 }
 
-async fn handle_pipeline_file(state: &AppState, path: &PathBuf) -> Result<()> {
+async fn read_pipeline_file(path: &Path) -> Result<PipelineRecord> {
     let bytes = fs::read(path)
         .await
         .with_context(|| format!("Failed to read pipeline file {:?}", path))?;
-    let record: PipelineRecord = serde_json::from_slice(&bytes)
+    let record = serde_json::from_slice(&bytes)
         .with_context(|| format!("Failed to parse pipeline file {:?}", path))?;
 
-    let run_stats = {
-        let mut orchestrator = state.orchestrator.write().await;
-        let stats = orchestrator
-            .create_pipeline(
+    Ok(record)
+}
+
+/// Run a queued or scheduled pipeline record, or register it when it carries a schedule
+pub async fn run_record(state: &AppState, record: &PipelineRecord) -> Result<QueueOutcome> {
+    // Paused pipelines keep their queued runs; stopped ones refuse them
+    {
+        let orchestrator = state.orchestrator.read().await;
+        if let Some(pipeline) = orchestrator.get_pipeline(&record.id) {
+            if pipeline.status == PipelineStatus::Paused {
+                return Ok(QueueOutcome::Deferred);
+            }
+            orchestrator.ensure_allowed(&record.id, PipelineAction::Run)?;
+        }
+    }
+
+    if let Some(schedule) = &record.schedule {
+        if !record.payload.is_null() || record.graph.as_ref().is_some_and(|g| g.has_payload()) {
+            return Err(anyhow!("Scheduled pipeline {} cannot embed a payload", record.id));
+        }
+        let pipeline = state
+            .register_pipeline(
                 &record.id,
                 &record.source,
+                &record.transformers,
                 &record.destination,
-                &record.payload,
+                record.graph.as_ref(),
+                Some(schedule.clone()),
             )
-            .map_err(|e| anyhow!(e))?;
+            .await?;
+        state.report_status(
+            record.report_status,
+            StatusUpdate::new(&record.id, RunStatus::Scheduled)
+                .with_details(serde_json::json!({ "next_run": pipeline.next_run })),
+        );
+        if logging::enabled(LogLevel::Debug) {
+            println!(
+                "[Fire Marshal] scheduled pipeline {} (next run {:?})",
+                pipeline.id, pipeline.next_run
+            );
+        }
+        return Ok(QueueOutcome::Processed);
+    }
+
+    state.report_status(
+        record.report_status,
+        StatusUpdate::new(&record.id, RunStatus::Running),
+    );
+    if let Some(graph) = &record.graph {
+        return run_graph_record(state, record, graph).await;
+    }
+
+    // Reject unknown sources and destinations before doing any work
+    let source = state.sources.resolve(&record.source, &record.payload)?;
+    state.sinks.resolve(&record.destination)?;
+    let documents = source
+        .fetch()
+        .await
+        .with_context(|| format!("Failed to read pipeline source {}", record.source))?;
+
+    // Transform under the read lock so other workers' runs are not held up
+    let (run_stats, output) = {
+        let (stats, output) = state.orchestrator.read().await.transform(
+            &record.id,
+            &record.source,
+            &record.transformers,
+            &documents,
+        )?;
+        let mut orchestrator = state.orchestrator.write().await;
+        orchestrator.record_run(
+            &record.id,
+            &record.source,
+            &record.transformers,
+            &record.destination,
+            None,
+            &stats,
+        )?;
         let active_count = orchestrator.active_pipeline_count();
         drop(orchestrator);
 
@@ -78,9 +494,14 @@ async fn handle_pipeline_file(state: &AppState, path: &PathBuf) -> Result<()> {
             monitor.increment_data((stats.nodes_count + stats.edges_count) as u64);
         }
 
-        stats
+        (stats, output)
     };
 
+    let delivery = state
+        .deliver_output(&record.id, &record.source, &record.destination, &output)
+        .await?;
+    source.acknowledge().await?;
+
     let node_red = state.node_red.read().await;
     let message = NodeRedMessage::new(
         "fire-marshal/pipelines".to_string(),
@@ -91,28 +512,81 @@ async fn handle_pipeline_file(state: &AppState, path: &PathBuf) -> Result<()> {
             "destination": record.destination,
             "nodes": run_stats.nodes_count,
             "edges": run_stats.edges_count,
+            "transformers": run_stats.steps,
+            "delivered": delivery.records,
             "processed_at": run_stats.processed_at,
         }),
     );
     let _ = node_red.send(message).await;
 
-    Ok(())
+    state.report_status(
+        record.report_status,
+        StatusUpdate::new(&record.id, RunStatus::Succeeded).with_details(serde_json::json!({
+            "nodes": run_stats.nodes_count,
+            "edges": run_stats.edges_count,
+            "delivered": delivery.records,
+        })),
+    );
+
+    if logging::enabled(LogLevel::Debug) {
+        println!(
+            "[Fire Marshal] processed pipeline {} ({} nodes, {} edges)",
+            record.id, run_stats.nodes_count, run_stats.edges_count
+        );
+    }
+
+    Ok(QueueOutcome::Processed)
 }
 
-async fn move_to_folder(path: &PathBuf, folder: &PathBuf) -> Result<()> {
-    fs::create_dir_all(folder)
-        .await
-        .with_context(|| format!("Failed to create {:?}", folder))?;
+/// Run a graph pipeline record, reporting every node's outcome
+async fn run_graph_record(
+    state: &AppState,
+    record: &PipelineRecord,
+    graph: &PipelineGraph,
+) -> Result<QueueOutcome> {
+    let run_stats = graph::run_graph(state, &record.id, graph).await?;
+    let delivered: usize = run_stats
+        .nodes
+        .iter()
+        .filter_map(|node| node.delivery.as_ref())
+        .map(|delivery| delivery.records)
+        .sum();
 
-    let file_name = path
-        .file_name()
-        .ok_or_else(|| anyhow!("Invalid pipeline file name {:?}", path))?;
-    let target = folder.join(file_name);
-    fs::rename(path, &target)
-        .await
-        .with_context(|| format!("Failed to move {:?} -> {:?}", path, target))?;
+    let node_red = state.node_red.read().await;
+    let message = NodeRedMessage::new(
+        "fire-marshal/pipelines".to_string(),
+        serde_json::json!({
+            "event": "pipeline_processed",
+            "pipeline_id": record.id,
+            "source": record.source,
+            "destination": record.destination,
+            "nodes": run_stats.nodes_count,
+            "edges": run_stats.edges_count,
+            "graph": run_stats.nodes,
+            "delivered": delivered,
+            "processed_at": run_stats.processed_at,
+        }),
+    );
+    let _ = node_red.send(message).await;
 
-    Ok(())
-}
+    state.report_status(
+        record.report_status,
+        StatusUpdate::new(&record.id, RunStatus::Succeeded).with_details(serde_json::json!({
+            "nodes": run_stats.nodes_count,
+            "edges": run_stats.edges_count,
+            "delivered": delivered,
+            "graph": run_stats.nodes,
+        })),
+    );
 
+    if logging::enabled(LogLevel::Debug) {
+        println!(
+            "[Fire Marshal] processed graph pipeline {} ({} nodes, {} sinks delivered)",
+            record.id,
+            run_stats.nodes.len(),
+            run_stats.nodes.iter().filter(|node| node.delivery.is_some()).count()
+        );
+    }
 
+    Ok(QueueOutcome::Processed)
+}
@@ -0,0 +1,246 @@
+// Pipeline definition files
+// TOML and YAML pipeline definitions registered from `pipeline.definitions_dir` at startup, and
+// the checks a definition must pass against the sources, sinks and transformers fire-marshal has
+
+use anyhow::{Context, Result};
+use pyro_core::pipeline::PipelineRecord;
+use pyro_core::pipeline_definition::{
+    DefinitionErrors, DefinitionFormat, DefinitionIssue, PipelineDefinition,
+};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::api::state::AppState;
+use crate::orchestrator::Pipeline;
+use crate::scheduler::{ScheduleError, Trigger};
+
+/// Check a definition against what fire-marshal can run (dictionary: check_pipeline_definition)
+/// Pseudocode: Compile the definition, then point every unknown source or destination scheme,
+/// unknown transformer or bad transformer parameters and unparsable cron expression at its
+/// field, reporting all problems together
+pub async fn check(
+    state: &AppState,
+    definition: &PipelineDefinition,
+) -> Result<PipelineRecord, DefinitionErrors> {
+    let compiled = definition.compile();
+    let mut issues = match &compiled {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors.0.clone(),
+    };
+
+    for (i, uri) in listed(&definition.sources) {
+        if let Err(err) = state.sources.resolve(uri, &Value::Null) {
+            issues.push(DefinitionIssue::new(
+                format!("sources[{i}]"),
+                err.to_string(),
+            ));
+        }
+    }
+    for (i, uri) in listed(&definition.destinations) {
+        if let Err(err) = state.sinks.resolve(uri) {
+            issues.push(DefinitionIssue::new(
+                format!("destinations[{i}]"),
+                err.to_string(),
+            ));
+        }
+    }
+    {
+        let orchestrator = state.orchestrator.read().await;
+        for (i, transformer) in definition.transformers.iter().enumerate() {
+            let Ok(spec) = transformer.spec() else {
+                continue;
+            };
+            if let Err(err) = orchestrator.check_transformer(&spec) {
+                issues.push(DefinitionIssue::new(
+                    format!("transformers[{i}]"),
+                    err.to_string(),
+                ));
+            }
+        }
+    }
+    if let Some(schedule) = &definition.schedule {
+        if let Err(err @ ScheduleError::InvalidCron { .. }) = Trigger::parse(schedule) {
+            issues.push(DefinitionIssue::new("schedule.cron", err.to_string()));
+        }
+    }
+
+    if issues.is_empty() {
+        compiled
+    } else {
+        Err(DefinitionErrors(issues))
+    }
+}
+
+/// URIs with their index, leaving out the empty ones compile already reports
+fn listed(uris: &[String]) -> impl Iterator<Item = (usize, &String)> {
+    uris.iter()
+        .enumerate()
+        .filter(|(_, uri)| !uri.trim().is_empty())
+}
+
+/// Register the pipelines defined in a directory (dictionary: register_pipeline_definitions)
+/// Pseudocode: Read the .toml, .yaml and .yml files in name order, check each one and register
+/// its pipeline unless it is registered unchanged already. Files with problems, and later files
+/// defining an ID again, are skipped with their problems logged. Returns how many pipelines
+/// were registered or updated
+pub async fn register_dir(state: &AppState, dir: &Path) -> Result<usize> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read pipeline definitions in {:?}", dir))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && DefinitionFormat::from_path(path).is_some())
+        .collect();
+    paths.sort();
+
+    let mut defined = HashSet::new();
+    let mut registered = 0;
+    for path in paths {
+        match register_file(state, &path, &mut defined).await {
+            Ok(true) => registered += 1,
+            Ok(false) => {}
+            Err(err) => eprintln!(
+                "[Fire Marshal] skipped pipeline definition {}: {err:#}",
+                path.display()
+            ),
+        }
+    }
+    Ok(registered)
+}
+
+/// Register the pipeline of one definition file; false when it is registered unchanged
+async fn register_file(
+    state: &AppState,
+    path: &Path,
+    defined: &mut HashSet<String>,
+) -> Result<bool> {
+    let definition = PipelineDefinition::load(path)?;
+    let record = check(state, &definition).await?;
+    if !defined.insert(record.id.clone()) {
+        anyhow::bail!("pipeline {} is already defined by another file", record.id);
+    }
+
+    // Restarts find their pipelines registered, possibly paused, with their history
+    let unchanged = state
+        .orchestrator
+        .read()
+        .await
+        .get_pipeline(&record.id)
+        .is_some_and(|pipeline| is_defined_by(pipeline, &record));
+    if unchanged {
+        return Ok(false);
+    }
+
+    state
+        .register_pipeline(
+            &record.id,
+            &record.source,
+            &record.transformers,
+            &record.destination,
+            record.graph.as_ref(),
+            record.schedule.clone(),
+        )
+        .await?;
+    Ok(true)
+}
+
+/// Whether a registered pipeline already runs as the record describes
+fn is_defined_by(pipeline: &Pipeline, record: &PipelineRecord) -> bool {
+    pipeline.source == record.source
+        && pipeline.transformers == record.transformers
+        && pipeline.destination == record.destination
+        && pipeline.graph == record.graph
+        && pipeline.schedule == record.schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyro_core::config::Config;
+    use tempfile::TempDir;
+
+    async fn state(dir: &Path) -> AppState {
+        let mut config = Config::default();
+        config.pipeline.work_dir = dir.join("pipelines");
+        config.database.path = dir.join("db");
+        AppState::new(config).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_definitions_from_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = state(temp_dir.path()).await;
+        let defs = temp_dir.path().join("definitions");
+        std::fs::create_dir_all(&defs).unwrap();
+        std::fs::write(
+            defs.join("a-nightly.toml"),
+            r#"
+id = "nightly"
+sources = ["watch://incoming"]
+destinations = ["redb:", "node-red:"]
+transformers = [{ name = "redact", params = ["email"] }]
+schedule = { every_secs = 3600 }
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            defs.join("b-adhoc.yaml"),
+            "id: adhoc\nsources: [\"file://ad/*.json\"]\ndestinations: [\"redb:\"]\n",
+        )
+        .unwrap();
+        // Defines `nightly` again and is skipped
+        std::fs::write(
+            defs.join("c-again.yml"),
+            "id: nightly\nsources: [\"file://ad/*.json\"]\ndestinations: [\"redb:\"]\n",
+        )
+        .unwrap();
+        std::fs::write(defs.join("notes.txt"), "not a definition").unwrap();
+
+        assert_eq!(register_dir(&state, &defs).await.unwrap(), 2);
+        {
+            let orchestrator = state.orchestrator.read().await;
+            let nightly = orchestrator.get_pipeline("nightly").unwrap();
+            assert!(nightly.graph.is_some());
+            assert!(nightly.next_run.is_some());
+            let adhoc = orchestrator.get_pipeline("adhoc").unwrap();
+            assert!(adhoc.schedule.is_none() && adhoc.next_run.is_none());
+        }
+
+        // Loading the same definitions again leaves the pipelines alone
+        assert_eq!(register_dir(&state, &defs).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_check_points_at_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = state(temp_dir.path()).await;
+        let definition = PipelineDefinition::parse(
+            r#"
+id = "broken"
+sources = ["gopher://example.org"]
+destinations = ["redb:", "ftp://example.org"]
+transformers = [{ name = "fitler", params = ["User"] }, { name = "rename", params = "oops" }]
+schedule = { cron = "every night" }
+"#,
+            DefinitionFormat::Toml,
+        )
+        .unwrap();
+
+        let fields: Vec<String> = check(&state, &definition)
+            .await
+            .unwrap_err()
+            .0
+            .into_iter()
+            .map(|issue| issue.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "sources[0]",
+                "destinations[1]",
+                "transformers[0]",
+                "transformers[1]",
+                "schedule.cron"
+            ]
+        );
+    }
+}
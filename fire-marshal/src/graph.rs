@@ -0,0 +1,410 @@
+// Graph pipelines
+// Run a pipeline graph node by node: sources are read, transform and branch nodes reshape the
+// data flowing along their inputs, joins merge it and every sink delivers what reaches it
+
+use anyhow::{Context, Result};
+use pyro_core::data_extractor::ExtractedData;
+use pyro_core::pipeline_graph::{BranchCondition, GraphStep, PipelineGraph};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use crate::api::state::AppState;
+use crate::orchestrator::{extract_documents, PipelineRunStats};
+use crate::sink::{DeliveryReport, OutputBatch};
+use crate::source::Source;
+use crate::transform::{StepStats, TransformContext};
+
+/// How a graph node fared in a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeStatus {
+    Succeeded,
+    /// A branch that was not taken, or a node all of whose inputs were skipped
+    Skipped,
+    Failed,
+    /// Not run because another node failed first
+    Cancelled,
+}
+
+/// One node of a graph run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeRun {
+    pub node: String,
+    /// `source`, `transform`, `branch`, `join` or `sink`
+    pub kind: String,
+    pub status: NodeStatus,
+    /// Output of the node; what a sink received
+    pub nodes_count: usize,
+    pub edges_count: usize,
+    /// Counts around each transformer of a transform node
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<DeliveryReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Output of a node, for the nodes reading it
+enum Output {
+    Data(ExtractedData),
+    /// Nothing flows on; nodes reading only skipped inputs are skipped too
+    Skipped,
+}
+
+/// Run a graph pipeline and record the run (dictionary: run_pipeline_graph)
+/// Pseudocode: Plan the graph and resolve every source and sink before any work is done, then
+/// run the nodes in order, feeding each the merged output of its inputs. A failed node
+/// cancels the nodes after it; the run is recorded with every node's status either way, and
+/// sources are only acknowledged once every sink delivered
+pub async fn run_graph(
+    state: &AppState,
+    id: &str,
+    graph: &PipelineGraph,
+) -> Result<PipelineRunStats> {
+    let (order, mut chains) = state.orchestrator.read().await.plan_graph(id, graph)?;
+    let mut sources: HashMap<usize, Box<dyn Source>> = HashMap::new();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        match &node.step {
+            GraphStep::Source { uri, payload } => {
+                sources.insert(i, state.sources.resolve(uri, payload)?);
+            }
+            GraphStep::Sink { destination } => {
+                state.sinks.resolve(destination)?;
+            }
+            _ => {}
+        }
+    }
+
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+    let source_summary = graph.sources().collect::<Vec<_>>().join(", ");
+    let processed_at = chrono::Utc::now().timestamp();
+    let context = TransformContext {
+        pipeline_id: id.to_string(),
+        source: source_summary.clone(),
+        started_at: processed_at,
+    };
+
+    let mut outputs: HashMap<usize, Output> = HashMap::new();
+    let mut runs = Vec::with_capacity(order.len());
+    let mut failure = None;
+    for &i in &order {
+        let node = &graph.nodes[i];
+        let mut run = NodeRun {
+            node: node.id.clone(),
+            kind: node.step.kind().to_string(),
+            status: NodeStatus::Succeeded,
+            nodes_count: 0,
+            edges_count: 0,
+            steps: Vec::new(),
+            delivery: None,
+            error: None,
+            duration_ms: 0,
+        };
+        if failure.is_some() {
+            run.status = NodeStatus::Cancelled;
+            runs.push(run);
+            continue;
+        }
+
+        let started = Instant::now();
+        let inputs: Vec<&ExtractedData> = node
+            .inputs
+            .iter()
+            .filter_map(|input| match outputs.get(&index[input.as_str()]) {
+                Some(Output::Data(data)) => Some(data),
+                _ => None,
+            })
+            .collect();
+        let input = merge(&inputs, node.step == GraphStep::Join);
+
+        let output = match (&node.step, input) {
+            (GraphStep::Source { uri, .. }, _) => sources[&i]
+                .fetch()
+                .await
+                .with_context(|| format!("Failed to read pipeline source {}", uri))
+                .and_then(|documents| extract_documents(&documents))
+                .map(Output::Data),
+            // Every input was skipped
+            (_, None) => Ok(Output::Skipped),
+            (GraphStep::Transform { .. }, Some(mut data)) => {
+                if let Some(chain) = chains.remove(&i) {
+                    run.steps = chain.run(&mut data, &context);
+                }
+                Ok(Output::Data(data))
+            }
+            (GraphStep::Branch { when }, Some(data)) => Ok(select(&data, when)),
+            (GraphStep::Join, Some(data)) => Ok(Output::Data(data)),
+            (GraphStep::Sink { destination }, Some(data)) => {
+                let batch = OutputBatch {
+                    pipeline_id: id,
+                    source: &source_summary,
+                    data: &data,
+                };
+                match state.sinks.deliver(destination, &batch).await {
+                    Ok(report) => {
+                        run.delivery = Some(report);
+                        Ok(Output::Data(data))
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+        };
+        run.duration_ms = started.elapsed().as_millis() as u64;
+
+        match output {
+            Ok(Output::Data(data)) => {
+                run.nodes_count = data.nodes.len();
+                run.edges_count = data.edges.len();
+                outputs.insert(i, Output::Data(data));
+            }
+            Ok(Output::Skipped) => {
+                run.status = NodeStatus::Skipped;
+                outputs.insert(i, Output::Skipped);
+            }
+            Err(err) => {
+                let err = err.context(format!("Graph node '{}' failed", node.id));
+                run.status = NodeStatus::Failed;
+                run.error = Some(format!("{err:#}"));
+                failure = Some(err);
+            }
+        }
+        runs.push(run);
+    }
+
+    // Sinks that delivered make up the run's totals
+    let delivered = runs
+        .iter()
+        .filter(|run| run.delivery.is_some() && run.status == NodeStatus::Succeeded);
+    let (nodes_count, edges_count) = delivered.fold((0, 0), |(nodes, edges), run| {
+        (nodes + run.nodes_count, edges + run.edges_count)
+    });
+    let run_stats = PipelineRunStats {
+        nodes_count,
+        edges_count,
+        processed_at,
+        steps: Vec::new(),
+        delivery: None,
+        nodes: runs,
+    };
+
+    let destination_summary = graph.destinations().collect::<Vec<_>>().join(", ");
+    let mut orchestrator = state.orchestrator.write().await;
+    orchestrator.record_run(
+        id,
+        &source_summary,
+        &[],
+        &destination_summary,
+        Some(graph),
+        &run_stats,
+    )?;
+    if let Some(err) = failure {
+        if let Err(store_err) = orchestrator.fail_pipeline(id, format!("{err:#}")) {
+            eprintln!(
+                "[Fire Marshal] failed to record failure of pipeline {}: {store_err:?}",
+                id
+            );
+        }
+        drop(orchestrator);
+        state.monitor.write().await.increment_errors();
+        return Err(err);
+    }
+    let active_count = orchestrator.active_pipeline_count();
+    drop(orchestrator);
+
+    {
+        let mut monitor = state.monitor.write().await;
+        monitor.set_active_pipelines(active_count);
+        monitor.increment_data((run_stats.nodes_count + run_stats.edges_count) as u64);
+    }
+    for source in sources.values() {
+        source.acknowledge().await?;
+    }
+    Ok(run_stats)
+}
+
+/// Merge the outputs of a node's inputs, nothing when there are none; `dedupe` keeps the first
+/// node per id and edge per source, target and type
+fn merge(inputs: &[&ExtractedData], dedupe: bool) -> Option<ExtractedData> {
+    let (first, rest) = inputs.split_first()?;
+    let mut merged = (*first).clone();
+    for input in rest {
+        merged.nodes.extend(input.nodes.iter().cloned());
+        merged.edges.extend(input.edges.iter().cloned());
+    }
+
+    if dedupe {
+        let mut seen = HashSet::new();
+        merged.nodes.retain(|node| seen.insert(node.id.clone()));
+        let mut seen = HashSet::new();
+        merged.edges.retain(|edge| {
+            seen.insert((
+                edge.source.clone(),
+                edge.target.clone(),
+                edge.edge_type.clone(),
+            ))
+        });
+    }
+    merged.metadata.total_nodes = merged.nodes.len();
+    merged.metadata.total_edges = merged.edges.len();
+    Some(merged)
+}
+
+/// Nodes taking a branch and the edges between them, or nothing when too few match
+fn select(data: &ExtractedData, when: &BranchCondition) -> Output {
+    let nodes: Vec<_> = data
+        .nodes
+        .iter()
+        .filter(|node| when.matches(node))
+        .cloned()
+        .collect();
+    if nodes.len() < when.min_matches.max(1) {
+        return Output::Skipped;
+    }
+
+    let kept: HashSet<&str> = nodes.iter().map(|node| node.id.as_str()).collect();
+    let edges = data
+        .edges
+        .iter()
+        .filter(|edge| kept.contains(edge.source.as_str()) && kept.contains(edge.target.as_str()))
+        .cloned()
+        .collect();
+    let mut selected = ExtractedData {
+        nodes,
+        edges,
+        metadata: data.metadata.clone(),
+    };
+    selected.metadata.total_nodes = selected.nodes.len();
+    selected.metadata.total_edges = selected.edges.len();
+    Output::Data(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orchestrator::PipelineStatus;
+    use pyro_core::config::Config;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    async fn state(dir: &std::path::Path) -> AppState {
+        let mut config = Config::default();
+        config.pipeline.work_dir = dir.join("pipelines");
+        config.database.path = dir.join("db");
+        config.pipeline.sink_hosts = vec!["127.0.0.1".to_string()];
+        AppState::new(config).await.unwrap()
+    }
+
+    fn statuses(stats: &PipelineRunStats) -> Vec<(&str, NodeStatus)> {
+        stats
+            .nodes
+            .iter()
+            .map(|run| (run.node.as_str(), run.status))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_graph_joins_branches_and_fans_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = state(temp_dir.path()).await;
+        let out = |name: &str| format!("file://{}", name);
+        let graph: PipelineGraph = serde_json::from_value(json!({ "nodes": [
+            { "id": "ad", "type": "source", "uri": "ad", "payload": { "data": [
+                { "ObjectIdentifier": "u1", "ObjectType": "User", "Properties": {} },
+                { "ObjectIdentifier": "c1", "ObjectType": "Computer", "Properties": {} }
+            ] } },
+            { "id": "azure", "type": "source", "uri": "azure", "payload": { "data": [
+                { "ObjectIdentifier": "u1", "ObjectType": "User", "Properties": {} }
+            ] } },
+            { "id": "merged", "type": "join", "inputs": ["ad", "azure"] },
+            { "id": "all", "type": "sink", "destination": out("all.jsonl"), "inputs": ["merged"] },
+            { "id": "computers", "type": "branch", "inputs": ["merged"],
+              "when": { "node_types": ["Computer"] } },
+            { "id": "groups", "type": "branch", "inputs": ["merged"],
+              "when": { "node_types": ["Group"] } },
+            { "id": "tagged", "type": "transform", "transformers": ["enrich:tier=0"],
+              "inputs": ["computers", "groups"] },
+            { "id": "hosts", "type": "sink", "destination": out("hosts.jsonl"), "inputs": ["tagged"] },
+            { "id": "admins", "type": "sink", "destination": out("admins.jsonl"), "inputs": ["groups"] }
+        ] }))
+        .unwrap();
+
+        let stats = run_graph(&state, "g1", &graph).await.unwrap();
+        assert_eq!(
+            statuses(&stats),
+            vec![
+                ("ad", NodeStatus::Succeeded),
+                ("azure", NodeStatus::Succeeded),
+                ("merged", NodeStatus::Succeeded),
+                ("all", NodeStatus::Succeeded),
+                ("computers", NodeStatus::Succeeded),
+                ("groups", NodeStatus::Skipped),
+                ("tagged", NodeStatus::Succeeded),
+                ("hosts", NodeStatus::Succeeded),
+                ("admins", NodeStatus::Skipped),
+            ]
+        );
+        // The join dropped the duplicate user; the skipped branch is left out of the transform
+        assert_eq!(stats.nodes[2].nodes_count, 2);
+        assert_eq!(stats.nodes[6].nodes_count, 1);
+        assert_eq!(stats.nodes_count, 3);
+        let output = temp_dir.path().join("pipelines/output");
+        assert!(output.join("hosts.jsonl").exists());
+        assert!(!output.join("admins.jsonl").exists());
+
+        let orchestrator = state.orchestrator.read().await;
+        let pipeline = orchestrator.get_pipeline("g1").unwrap();
+        assert_eq!(pipeline.graph.as_ref(), Some(&graph));
+        let history = orchestrator.history("g1").unwrap().unwrap();
+        assert_eq!(history.runs[0].stats.nodes.len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_failed_node_cancels_the_rest() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = state(temp_dir.path()).await;
+        let graph: PipelineGraph = serde_json::from_value(json!({ "nodes": [
+            { "id": "in", "type": "source", "uri": "in", "payload": { "data": [] } },
+            { "id": "hook", "type": "sink", "destination": "http://127.0.0.1:9/", "inputs": ["in"] },
+            { "id": "copy", "type": "sink", "destination": "redb:", "inputs": ["in"] }
+        ] }))
+        .unwrap();
+
+        let err = run_graph(&state, "g2", &graph).await.unwrap_err();
+        assert!(err.to_string().contains("'hook'"));
+
+        let orchestrator = state.orchestrator.read().await;
+        let pipeline = orchestrator.get_pipeline("g2").unwrap();
+        assert!(matches!(pipeline.status, PipelineStatus::Error(_)));
+        let last_run = pipeline.last_run.as_ref().unwrap();
+        assert_eq!(
+            statuses(last_run),
+            vec![
+                ("in", NodeStatus::Succeeded),
+                ("hook", NodeStatus::Failed),
+                ("copy", NodeStatus::Cancelled),
+            ]
+        );
+        assert!(last_run.nodes[1].error.is_some());
+
+        // Cycles are refused before anything runs
+        let cyclic: PipelineGraph = serde_json::from_value(json!({ "nodes": [
+            { "id": "in", "type": "source", "uri": "in", "payload": { "data": [] } },
+            { "id": "a", "type": "join", "inputs": ["in", "b"] },
+            { "id": "b", "type": "transform", "transformers": [], "inputs": ["a"] },
+            { "id": "out", "type": "sink", "destination": "redb:", "inputs": ["b"] }
+        ] }))
+        .unwrap();
+        drop(orchestrator);
+        let err = run_graph(&state, "g3", &cyclic).await.unwrap_err();
+        assert!(err.is::<pyro_core::pipeline_graph::GraphError>());
+        assert!(state.orchestrator.read().await.get_pipeline("g3").is_none());
+    }
+}
@@ -0,0 +1,301 @@
+// Pipeline queue leases
+// Claim queued pipeline files by moving them to inprogress/ under a renewable lease, so each
+// file is run by one worker and files left behind by a crashed run can be recovered
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::time::Duration;
+
+use crate::retry;
+
+/// Claimed pipeline files, with their lease and error sidecar
+pub const INPROGRESS_DIR: &str = "inprogress";
+
+/// Appended to a claimed file's name for its lease, e.g. `nightly.json.lease`
+pub const LEASE_EXTENSION: &str = "lease";
+
+/// How long a claim stays valid without being renewed
+pub const LEASE_TTL_SECS: i64 = 300;
+
+/// How often a worker renews the lease of the file it is running
+pub const LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who holds a claimed file, and until when
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub owner: String,
+    pub claimed_at: i64,
+    pub expires_at: i64,
+}
+
+/// A pipeline file moved to inprogress/ by one worker
+#[derive(Debug)]
+pub struct Claim {
+    queue_dir: PathBuf,
+    path: PathBuf,
+    lease: Lease,
+}
+
+fn lease_path(claimed: &Path) -> PathBuf {
+    let mut name = claimed.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(LEASE_EXTENSION);
+    claimed.with_file_name(name)
+}
+
+/// Rename a file if it exists; returns whether it did
+async fn move_if_present(from: &Path, to: &Path) -> Result<bool> {
+    match fs::rename(from, to).await {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err).with_context(|| format!("Failed to move {:?} -> {:?}", from, to)),
+    }
+}
+
+impl Claim {
+    /// Claim a queued pipeline file (dictionary: claim_pipeline_file)
+    /// Pseudocode: Atomically rename the file into inprogress/ - only one worker can win the
+    /// rename - then write the lease and bring the error sidecar along. Returns nothing when
+    /// the file is gone or a file of the same name is still running
+    pub async fn acquire(
+        queue_dir: &Path,
+        file: &Path,
+        owner: &str,
+        now: i64,
+    ) -> Result<Option<Self>> {
+        let inprogress = queue_dir.join(INPROGRESS_DIR);
+        fs::create_dir_all(&inprogress)
+            .await
+            .with_context(|| format!("Failed to create {:?}", inprogress))?;
+
+        let Some(name) = file.file_name() else {
+            return Ok(None);
+        };
+        let path = inprogress.join(name);
+        if path.exists() {
+            return Ok(None);
+        }
+        if !move_if_present(file, &path).await? {
+            return Ok(None);
+        }
+
+        let claim = Self {
+            queue_dir: queue_dir.to_path_buf(),
+            path,
+            lease: Lease {
+                owner: owner.to_string(),
+                claimed_at: now,
+                expires_at: now + LEASE_TTL_SECS,
+            },
+        };
+        claim.write_lease().await?;
+        move_if_present(
+            &retry::sidecar_path(file),
+            &retry::sidecar_path(&claim.path),
+        )
+        .await?;
+        Ok(Some(claim))
+    }
+
+    /// The claimed file in inprogress/
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Extend the lease while the file is still being run
+    pub async fn renew(&mut self, now: i64) -> Result<()> {
+        self.lease.expires_at = now + LEASE_TTL_SECS;
+        self.write_lease().await
+    }
+
+    async fn write_lease(&self) -> Result<()> {
+        let path = lease_path(&self.path);
+        fs::write(&path, serde_json::to_vec_pretty(&self.lease)?)
+            .await
+            .with_context(|| format!("Failed to write lease {:?}", path))
+    }
+
+    /// Put the file and its error sidecar back in the queue
+    pub async fn release(self) -> Result<()> {
+        let queue_dir = self.queue_dir.clone();
+        self.finish(&queue_dir, true).await
+    }
+
+    /// Move the file to `folder`, keeping or dropping its error sidecar, and end the lease
+    pub async fn finish(self, folder: &Path, keep_sidecar: bool) -> Result<()> {
+        fs::create_dir_all(folder)
+            .await
+            .with_context(|| format!("Failed to create {:?}", folder))?;
+
+        let sidecar = retry::sidecar_path(&self.path);
+        if keep_sidecar {
+            let target = folder.join(sidecar.file_name().unwrap_or_default());
+            move_if_present(&sidecar, &target).await?;
+        } else if sidecar.exists() {
+            fs::remove_file(&sidecar)
+                .await
+                .with_context(|| format!("Failed to remove {:?}", sidecar))?;
+        }
+
+        let target = folder.join(self.path.file_name().unwrap_or_default());
+        fs::rename(&self.path, &target)
+            .await
+            .with_context(|| format!("Failed to move {:?} -> {:?}", self.path, target))?;
+
+        let lease = lease_path(&self.path);
+        if lease.exists() {
+            fs::remove_file(&lease)
+                .await
+                .with_context(|| format!("Failed to remove {:?}", lease))?;
+        }
+        Ok(())
+    }
+}
+
+/// Take over claims whose lease expired - or every claim, when `all` is set - returning them
+/// with the lease they had. Files claimed without a lease count as expired
+pub async fn recover_stale(
+    queue_dir: &Path,
+    owner: &str,
+    now: i64,
+    all: bool,
+) -> Result<Vec<(Claim, Option<Lease>)>> {
+    let inprogress = queue_dir.join(INPROGRESS_DIR);
+    let mut dir = match fs::read_dir(&inprogress).await {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {:?}", inprogress)),
+    };
+
+    let mut recovered = Vec::new();
+    while let Some(entry) = dir.next_entry().await? {
+        let path = entry.path();
+        if !retry::is_record_file(&path) {
+            continue;
+        }
+
+        let previous: Option<Lease> = fs::read(lease_path(&path))
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+        if !all
+            && previous
+                .as_ref()
+                .is_some_and(|lease| lease.expires_at > now)
+        {
+            continue;
+        }
+
+        let claim = Claim {
+            queue_dir: queue_dir.to_path_buf(),
+            path,
+            lease: Lease {
+                owner: owner.to_string(),
+                claimed_at: now,
+                expires_at: now + LEASE_TTL_SECS,
+            },
+        };
+        claim.write_lease().await?;
+        recovered.push((claim, previous));
+    }
+    Ok(recovered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_claim_release_and_finish() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = temp_dir.path();
+        let file = queue.join("p1.json");
+        std::fs::write(&file, "{}").unwrap();
+        std::fs::write(retry::sidecar_path(&file), "{}").unwrap();
+
+        let claim = Claim::acquire(queue, &file, "worker-a", 100)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!file.exists());
+        assert!(claim.path().exists());
+        assert!(retry::sidecar_path(claim.path()).exists());
+        assert_eq!(claim.lease.expires_at, 100 + LEASE_TTL_SECS);
+
+        // Only one worker wins a file
+        assert!(Claim::acquire(queue, &file, "worker-b", 100)
+            .await
+            .unwrap()
+            .is_none());
+
+        claim.release().await.unwrap();
+        assert!(file.exists());
+        assert!(retry::sidecar_path(&file).exists());
+        assert!(!queue.join(INPROGRESS_DIR).join("p1.json.lease").exists());
+
+        let claim = Claim::acquire(queue, &file, "worker-b", 200)
+            .await
+            .unwrap()
+            .unwrap();
+        claim.finish(&queue.join("processed"), false).await.unwrap();
+        assert!(queue.join("processed/p1.json").exists());
+        assert!(!queue.join("processed/p1.json.error").exists());
+        assert!(std::fs::read_dir(queue.join(INPROGRESS_DIR))
+            .unwrap()
+            .next()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recover_stale_leases() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = temp_dir.path();
+        for name in ["live.json", "expired.json"] {
+            std::fs::write(queue.join(name), "{}").unwrap();
+        }
+
+        let mut live = Claim::acquire(queue, &queue.join("live.json"), "crashed", 0)
+            .await
+            .unwrap()
+            .unwrap();
+        Claim::acquire(queue, &queue.join("expired.json"), "crashed", 0)
+            .await
+            .unwrap()
+            .unwrap();
+        live.renew(LEASE_TTL_SECS).await.unwrap();
+        // Claimed right before a crash, before its lease was written
+        std::fs::write(queue.join(INPROGRESS_DIR).join("orphan.json"), "{}").unwrap();
+
+        let now = LEASE_TTL_SECS + 1;
+        let mut recovered: Vec<_> = recover_stale(queue, "restarted", now, false)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(claim, previous)| {
+                assert_eq!(claim.lease.owner, "restarted");
+                let name = claim
+                    .path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned();
+                (name, previous.map(|lease| lease.owner))
+            })
+            .collect();
+        recovered.sort();
+        assert_eq!(
+            recovered,
+            vec![
+                ("expired.json".to_string(), Some("crashed".to_string())),
+                ("orphan.json".to_string(), None),
+            ]
+        );
+
+        // At startup every claim is stale
+        let recovered = recover_stale(queue, "restarted", now, true).await.unwrap();
+        assert_eq!(recovered.len(), 3);
+    }
+}
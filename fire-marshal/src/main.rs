@@ -1,5 +1,6 @@
 use anyhow::Result;
 use axum::{
+    middleware::from_fn_with_state,
     routing::{get, post},
     Router,
 };
@@ -7,13 +8,27 @@ use std::net::SocketAddr;
 use tokio::signal;
 
 mod api;
+mod cli;
 mod datapipe;
+mod definitions;
+mod graph;
+mod lease;
 mod monitoring;
 mod orchestrator;
+mod retry;
+mod scheduler;
+mod sink;
+mod source;
+mod store;
+mod transform;
+mod uri;
+mod watcher;
 
-use api::handlers;
+use api::{handlers, internal, pipelines, queue};
 use api::state::AppState;
-use pyro_core::config::Config;
+use clap::Parser;
+use cli::Cli;
+use pyro_core::config;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -21,30 +36,81 @@ async fn main() -> Result<()> {
     println!("🚒 Fire Marshal - Data Flow Orchestration");
     println!("   Watching over autonomous data pipelines\n");
 
-    // Load configuration
-    let config = Config::load()?;
+    // Load and validate configuration
+    let loader = Cli::parse().flags.loader();
+    let config = loader.load()?;
+    config.validate()?;
 
     // Initialize application state
-    let state = AppState::new(config).await?;
+    let state = AppState::new(config.clone()).await?;
+
+    // Register pipelines defined in files before the scheduler looks for due runs
+    if let Some(dir) = &config.pipeline.definitions_dir {
+        match definitions::register_dir(&state, dir).await {
+            Ok(count) => println!(
+                "🚒 Registered {} pipeline definition(s) from {}",
+                count,
+                dir.display()
+            ),
+            Err(err) => eprintln!("[Fire Marshal] failed to load pipeline definitions: {err:#}"),
+        }
+    }
+
+    // Reload Node-RED, pipeline interval and logging settings on file change or SIGHUP
+    let _config_watcher = config::spawn_runtime_updates(
+        state.runtime.clone(),
+        state.node_red.clone(),
+        config::spawn_config_watcher(loader, config.clone()),
+    );
 
     // Build router
     let app = Router::new()
-        .route("/", get(handlers::root))
-        .route("/health", get(handlers::health))
         .route("/api/monitor", get(handlers::get_monitoring))
         .route("/api/orchestrate", post(handlers::orchestrate))
+        .route("/api/pipelines", get(pipelines::list_pipelines))
+        .route(
+            "/api/pipelines/{id}",
+            get(pipelines::get_pipeline).delete(pipelines::delete_pipeline),
+        )
+        .route("/api/pipelines/{id}/pause", post(pipelines::pause_pipeline))
+        .route("/api/pipelines/{id}/resume", post(pipelines::resume_pipeline))
+        .route("/api/pipelines/{id}/stop", post(pipelines::stop_pipeline))
+        .route("/api/pipelines/{id}/rerun", post(pipelines::rerun_pipeline))
+        .route("/api/pipelines/{id}/history", get(pipelines::get_pipeline_history))
+        .route("/api/definitions/validate", post(api::definitions::validate_definition))
+        .route("/api/dead-letters", get(queue::list_dead_letters))
+        .route("/api/dead-letters/{file}/requeue", post(queue::requeue_dead_letter))
+        .route("/api/node-red/inbound", post(handlers::node_red_inbound));
+
+    // pyro-core submits pipelines here when both share a hand-off token
+    let app = if state.handoff_token.is_some() {
+        app.route(pyro_core::handoff::SUBMIT_PATH, post(internal::submit_pipeline))
+    } else {
+        app
+    };
+
+    // Beyond loopback every API route requires the hand-off token; validation refuses to start
+    // without one
+    let app = if config.pipeline.handoff.listens_on_loopback() {
+        app
+    } else {
+        app.route_layer(from_fn_with_state(state.clone(), internal::require_token))
+    };
+    let app = Router::new()
+        .route("/", get(handlers::root))
+        .route("/health", get(handlers::health))
+        .merge(app)
         .with_state(state.clone());
 
     let _pipeline_worker = datapipe::spawn_pipeline_worker(state.clone());
+    let _scheduler = scheduler::spawn_scheduler(state.clone());
 
-    // Start server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3001));
-    println!("🚒 Fire Marshal is on patrol at {}", addr);
+    // Start server (TLS settings are shared with pyro via [server.tls])
+    let addr: SocketAddr = config.pipeline.handoff.listen.parse()?;
+    let scheme = if config.server.tls.enabled { "https" } else { "http" };
+    println!("🚒 Fire Marshal is on patrol at {}://{}", scheme, addr);
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    pyro_core::tls::serve(app, addr, &config, shutdown_signal()).await?;
 
     Ok(())
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 /// Monitoring statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,6 +8,19 @@ pub struct MonitoringStats {
     pub data_processed: u64,
     pub errors: u64,
     pub last_update: i64,
+    #[serde(default)]
+    pub queue_pickup: PickupStats,
+}
+
+/// Time from a pipeline file landing in the queue until a worker claimed it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PickupStats {
+    /// `notify` when file changes wake the workers, `polling` when only scans find files
+    pub mode: String,
+    pub picked_up: u64,
+    pub last_ms: u64,
+    pub mean_ms: f64,
+    pub max_ms: u64,
 }
 
 /// Monitor for tracking system health
@@ -23,6 +37,7 @@ impl Monitor {
                 data_processed: 0,
                 errors: 0,
                 last_update: chrono::Utc::now().timestamp(),
+                queue_pickup: PickupStats::default(),
             },
         }
     }
@@ -50,6 +65,22 @@ impl Monitor {
         self.stats.last_update = chrono::Utc::now().timestamp();
     }
 
+    /// Record how queued pipeline files are picked up
+    pub fn set_pickup_mode(&mut self, mode: &str) {
+        self.stats.queue_pickup.mode = mode.to_string();
+    }
+
+    /// Record how long a queued pipeline file waited before a worker claimed it
+    pub fn record_pickup(&mut self, latency: Duration) {
+        let pickup = &mut self.stats.queue_pickup;
+        let ms = latency.as_millis().min(u64::MAX as u128) as u64;
+        pickup.picked_up += 1;
+        pickup.last_ms = ms;
+        pickup.mean_ms += (ms as f64 - pickup.mean_ms) / pickup.picked_up as f64;
+        pickup.max_ms = pickup.max_ms.max(ms);
+        self.stats.last_update = chrono::Utc::now().timestamp();
+    }
+
     /// Set number of active pipelines
     pub fn set_active_pipelines(&mut self, active: usize) {
         self.stats.active_pipelines = active;
@@ -77,4 +108,20 @@ mod tests {
         assert_eq!(stats.data_processed, 100);
         assert_eq!(stats.errors, 1);
     }
+
+    #[test]
+    fn test_pickup_latency() {
+        let mut monitor = Monitor::new();
+        monitor.set_pickup_mode("notify");
+        for ms in [100, 300, 200] {
+            monitor.record_pickup(Duration::from_millis(ms));
+        }
+
+        let pickup = monitor.get_stats().queue_pickup;
+        assert_eq!(pickup.mode, "notify");
+        assert_eq!(pickup.picked_up, 3);
+        assert_eq!(pickup.last_ms, 200);
+        assert_eq!(pickup.max_ms, 300);
+        assert!((pickup.mean_ms - 200.0).abs() < f64::EPSILON);
+    }
 }
@@ -1,20 +1,46 @@
 use anyhow::Result;
-use pyro_core::data_extractor::BloodHoundExtractor;
+use pyro_core::data_extractor::{BloodHoundExtractor, ExtractedData};
+use pyro_core::pipeline::PipelineSchedule;
+use pyro_core::pipeline_graph::{GraphStep, PipelineGraph};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+
+use crate::graph::NodeRun;
+use crate::scheduler::Trigger;
+use crate::sink::DeliveryReport;
+use crate::store::{PipelineHistory, PipelineStore, RunEntry, StatusTransition};
+use crate::transform::{
+    StepStats, TransformChain, TransformContext, TransformError, TransformerRegistry,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pipeline {
     pub id: String,
     pub source: String,
+    #[serde(default)]
+    pub transformers: Vec<String>,
     pub destination: String,
     pub status: PipelineStatus,
     pub created_at: i64,
+    /// Number of runs so far; the last one is `last_run`
+    #[serde(default)]
+    pub runs: u64,
     pub last_run: Option<PipelineRunStats>,
+    /// Recurring pipelines run on this schedule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<PipelineSchedule>,
+    /// When the scheduler starts the next run (unix seconds)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<i64>,
+    /// Steps of a graph pipeline; `source` and `destination` then summarize its sources and
+    /// sinks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graph: Option<PipelineGraph>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PipelineStatus {
     Active,
     Paused,
@@ -22,78 +48,405 @@ pub enum PipelineStatus {
     Error(String),
 }
 
+impl PipelineStatus {
+    /// Whether the lifecycle state machine allows `action` from this status
+    /// Pseudocode: Active runs, pauses or stops; Paused resumes, stops or is deleted; a failed
+    /// pipeline runs again, stops or is deleted; Stopped can only be deleted
+    pub fn allows(&self, action: PipelineAction) -> bool {
+        use PipelineAction::*;
+        match self {
+            PipelineStatus::Active => matches!(action, Run | Pause | Stop),
+            PipelineStatus::Paused => matches!(action, Resume | Stop | Delete),
+            PipelineStatus::Error(_) => matches!(action, Run | Stop | Delete),
+            PipelineStatus::Stopped => matches!(action, Delete),
+        }
+    }
+}
+
+impl fmt::Display for PipelineStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineStatus::Active => f.write_str("active"),
+            PipelineStatus::Paused => f.write_str("paused"),
+            PipelineStatus::Stopped => f.write_str("stopped"),
+            PipelineStatus::Error(_) => f.write_str("failed"),
+        }
+    }
+}
+
+/// Something a client asks of an existing pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineAction {
+    Run,
+    Pause,
+    Resume,
+    Stop,
+    Delete,
+}
+
+impl PipelineAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PipelineAction::Run => "run",
+            PipelineAction::Pause => "pause",
+            PipelineAction::Resume => "resume",
+            PipelineAction::Stop => "stop",
+            PipelineAction::Delete => "delete",
+        }
+    }
+
+    /// Status a pipeline ends up in, `None` for delete
+    fn target(&self) -> Option<PipelineStatus> {
+        match self {
+            PipelineAction::Run | PipelineAction::Resume => Some(PipelineStatus::Active),
+            PipelineAction::Pause => Some(PipelineStatus::Paused),
+            PipelineAction::Stop => Some(PipelineStatus::Stopped),
+            PipelineAction::Delete => None,
+        }
+    }
+}
+
+/// Why a lifecycle action was refused
+#[derive(Debug, thiserror::Error)]
+pub enum LifecycleError {
+    #[error("pipeline not found: {0}")]
+    NotFound(String),
+    #[error("pipeline {id} cannot {action} while {status}")]
+    InvalidTransition {
+        id: String,
+        action: &'static str,
+        status: PipelineStatus,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PipelineRunStats {
     pub nodes_count: usize,
     pub edges_count: usize,
     pub processed_at: i64,
+    /// Counts around each transformer, in chain order
+    #[serde(default)]
+    pub steps: Vec<StepStats>,
+    /// Set once the output reached the pipeline's destination
+    #[serde(default)]
+    pub delivery: Option<DeliveryReport>,
+    /// How each node of a graph pipeline fared, in run order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<NodeRun>,
 }
 
 /// Orchestrator for managing data pipelines
 pub struct Orchestrator {
     pipelines: HashMap<String, Pipeline>,
+    transformers: TransformerRegistry,
+    store: PipelineStore,
 }
 
 impl Orchestrator {
-    /// Create new orchestrator
-    pub fn new() -> Self {
-        Self {
-            pipelines: HashMap::new(),
-        }
+    /// Create an orchestrator holding the pipelines already in `store`
+    pub fn load(store: PipelineStore) -> Result<Self> {
+        let pipelines = store
+            .load()?
+            .into_iter()
+            .map(|pipeline| (pipeline.id.clone(), pipeline))
+            .collect();
+        Ok(Self {
+            pipelines,
+            transformers: TransformerRegistry::new(),
+            store,
+        })
     }
 
-    /// Create a new pipeline and process payload
+    /// Create a new pipeline, process the documents fetched from its source and run its
+    /// transformer chain, returning the transformed data for delivery
     pub fn create_pipeline(
         &mut self,
         id: &str,
         source: &str,
+        transformers: &[String],
         destination: &str,
-        payload: &Value,
-    ) -> Result<PipelineRunStats> {
-        let extracted = BloodHoundExtractor::extract_from_json(payload)?;
-        let nodes_count = extracted.nodes.len();
-        let edges_count = extracted.edges.len();
+        documents: &[Value],
+    ) -> Result<(PipelineRunStats, ExtractedData)> {
+        let (run_stats, extracted) = self.transform(id, source, transformers, documents)?;
+        self.record_run(id, source, transformers, destination, None, &run_stats)?;
+        Ok((run_stats, extracted))
+    }
+
+    /// Process the documents fetched from a pipeline's source and run its transformer chain
+    /// without recording the run, so runs of different pipelines transform concurrently
+    pub fn transform(
+        &self,
+        id: &str,
+        source: &str,
+        transformers: &[String],
+        documents: &[Value],
+    ) -> Result<(PipelineRunStats, ExtractedData)> {
+        // Paused and stopped pipelines do not run; resolve the chain before any work is done
+        if let Some(existing) = self.pipelines.get(id) {
+            self.check(existing, PipelineAction::Run)?;
+        }
+        let chain = self.transformers.chain(transformers)?;
+        let mut extracted = extract_documents(documents)?;
         let processed_at = chrono::Utc::now().timestamp();
 
+        let context = TransformContext {
+            pipeline_id: id.to_string(),
+            source: source.to_string(),
+            started_at: processed_at,
+        };
+        let steps = chain.run(&mut extracted, &context);
+
         let run_stats = PipelineRunStats {
-            nodes_count,
-            edges_count,
+            nodes_count: extracted.nodes.len(),
+            edges_count: extracted.edges.len(),
             processed_at,
+            steps,
+            delivery: None,
+            nodes: Vec::new(),
         };
+        Ok((run_stats, extracted))
+    }
 
+    /// Check a transformer spec resolves to a transformer with valid arguments
+    pub fn check_transformer(&self, spec: &str) -> Result<(), TransformError> {
+        self.transformers.chain(&[spec.to_string()]).map(|_| ())
+    }
+
+    /// Check a graph pipeline may run and prepare it (dictionary: plan_pipeline_graph)
+    /// Pseudocode: Refuse paused and stopped pipelines, order the graph - rejecting unknown
+    /// inputs and cycles - and resolve the transformer chain of every transform node
+    pub fn plan_graph(
+        &self,
+        id: &str,
+        graph: &PipelineGraph,
+    ) -> Result<(Vec<usize>, HashMap<usize, TransformChain>)> {
+        if let Some(existing) = self.pipelines.get(id) {
+            self.check(existing, PipelineAction::Run)?;
+        }
+        let order = graph.order()?;
+        let mut chains = HashMap::new();
+        for (i, node) in graph.nodes.iter().enumerate() {
+            if let GraphStep::Transform { transformers } = &node.step {
+                chains.insert(i, self.transformers.chain(transformers)?);
+            }
+        }
+        Ok((order, chains))
+    }
+
+    /// Create or update a pipeline with a finished run
+    pub fn record_run(
+        &mut self,
+        id: &str,
+        source: &str,
+        transformers: &[String],
+        destination: &str,
+        graph: Option<&PipelineGraph>,
+        run_stats: &PipelineRunStats,
+    ) -> Result<()> {
+        // The pipeline may have been paused or stopped while the run was transforming
+        let previous = self.pipelines.get(id);
+        if let Some(existing) = previous {
+            self.check(existing, PipelineAction::Run)?;
+        }
+
+        // Running an existing pipeline again keeps its creation time and run count
+        let from = previous.map(|p| p.status.clone());
         let pipeline = Pipeline {
             id: id.to_string(),
             source: source.to_string(),
+            transformers: transformers.to_vec(),
             destination: destination.to_string(),
             status: PipelineStatus::Active,
-            created_at: chrono::Utc::now().timestamp(),
+            created_at: previous.map_or(run_stats.processed_at, |p| p.created_at),
+            runs: previous.map_or(0, |p| p.runs) + 1,
             last_run: Some(run_stats.clone()),
+            schedule: previous.and_then(|p| p.schedule.clone()),
+            next_run: previous.and_then(|p| p.next_run),
+            graph: graph.cloned(),
+        };
+        let run = RunEntry {
+            run: pipeline.runs,
+            stats: run_stats.clone(),
         };
+        let transition = (from != Some(PipelineStatus::Active)).then_some(StatusTransition {
+            from,
+            to: PipelineStatus::Active,
+            at: run_stats.processed_at,
+        });
 
+        self.store
+            .save(&pipeline, Some(&run), transition.as_ref())?;
         self.pipelines.insert(id.to_string(), pipeline);
-        Ok(run_stats)
+        Ok(())
     }
 
-    /// Get pipeline by ID
-    pub fn get_pipeline(&self, id: &str) -> Option<&Pipeline> {
-        self.pipelines.get(id)
+    /// Create or update a pipeline without running it (dictionary: schedule_pipeline)
+    /// Pseudocode: Validate the schedule and transformer chain or graph, keep an existing
+    /// pipeline's history and status, and set the next run to the schedule's first time after
+    /// now. Pipelines without a schedule only run when asked to
+    pub fn register_pipeline(
+        &mut self,
+        id: &str,
+        source: &str,
+        transformers: &[String],
+        destination: &str,
+        graph: Option<&PipelineGraph>,
+        schedule: Option<PipelineSchedule>,
+    ) -> Result<&Pipeline> {
+        if let Some(existing) = self.pipelines.get(id) {
+            self.check(existing, PipelineAction::Run)?;
+        }
+        let trigger = schedule.as_ref().map(Trigger::parse).transpose()?;
+        match graph {
+            Some(graph) => {
+                self.plan_graph(id, graph)?;
+            }
+            None => {
+                self.transformers.chain(transformers)?;
+            }
+        }
+        let now = chrono::Utc::now().timestamp();
+
+        let previous = self.pipelines.get(id);
+        let pipeline = Pipeline {
+            id: id.to_string(),
+            source: source.to_string(),
+            transformers: transformers.to_vec(),
+            destination: destination.to_string(),
+            status: previous.map_or(PipelineStatus::Active, |p| p.status.clone()),
+            created_at: previous.map_or(now, |p| p.created_at),
+            runs: previous.map_or(0, |p| p.runs),
+            last_run: previous.and_then(|p| p.last_run.clone()),
+            schedule,
+            next_run: trigger.and_then(|trigger| trigger.next_after(now)),
+            graph: graph.cloned(),
+        };
+        let transition = previous.is_none().then_some(StatusTransition {
+            from: None,
+            to: PipelineStatus::Active,
+            at: now,
+        });
+
+        self.store.save(&pipeline, None, transition.as_ref())?;
+        self.pipelines.insert(id.to_string(), pipeline);
+        Ok(&self.pipelines[id])
     }
 
-    /// List all pipelines
-    pub fn list_pipelines(&self) -> Vec<&Pipeline> {
-        self.pipelines.values().collect()
+    /// Set when a recurring pipeline runs next
+    pub fn schedule_next(&mut self, id: &str, next_run: Option<i64>) -> Result<()> {
+        let Some(pipeline) = self.pipelines.get_mut(id) else {
+            anyhow::bail!("Pipeline not found: {}", id)
+        };
+        if pipeline.next_run == next_run {
+            return Ok(());
+        }
+        pipeline.next_run = next_run;
+        self.store.save(pipeline, None, None)
     }
 
-    /// Stop a pipeline
-    pub fn stop_pipeline(&mut self, id: &str) -> Result<()> {
-        if let Some(pipeline) = self.pipelines.get_mut(id) {
-            pipeline.status = PipelineStatus::Stopped;
+    /// Record where a pipeline's last run was delivered
+    pub fn record_delivery(&mut self, id: &str, report: DeliveryReport) -> Result<()> {
+        let Some(pipeline) = self.pipelines.get_mut(id) else {
+            return Ok(());
+        };
+        let Some(stats) = pipeline.last_run.as_mut() else {
+            return Ok(());
+        };
+        stats.delivery = Some(report);
+
+        let run = RunEntry {
+            run: pipeline.runs,
+            stats: stats.clone(),
+        };
+        self.store.save(pipeline, Some(&run), None)
+    }
+
+    /// Mark a pipeline as failed
+    pub fn fail_pipeline(&mut self, id: &str, message: String) -> Result<()> {
+        self.set_status(id, PipelineStatus::Error(message))
+    }
+
+    /// Move a pipeline to `status`, recording the transition
+    fn set_status(&mut self, id: &str, status: PipelineStatus) -> Result<()> {
+        let Some(pipeline) = self.pipelines.get_mut(id) else {
+            anyhow::bail!("Pipeline not found: {}", id)
+        };
+        if pipeline.status == status {
+            return Ok(());
+        }
+
+        let transition = StatusTransition {
+            from: Some(pipeline.status.clone()),
+            to: status.clone(),
+            at: chrono::Utc::now().timestamp(),
+        };
+        let mut updated = pipeline.clone();
+        updated.status = status;
+        self.store.save(&updated, None, Some(&transition))?;
+        *pipeline = updated;
+        Ok(())
+    }
+
+    /// Refuse `action` unless the pipeline's status allows it
+    fn check(&self, pipeline: &Pipeline, action: PipelineAction) -> Result<(), LifecycleError> {
+        if pipeline.status.allows(action) {
             Ok(())
         } else {
-            anyhow::bail!("Pipeline not found: {}", id)
+            Err(LifecycleError::InvalidTransition {
+                id: pipeline.id.clone(),
+                action: action.as_str(),
+                status: pipeline.status.clone(),
+            })
+        }
+    }
+
+    /// Whether an existing pipeline may `action` (dictionary: pipeline_lifecycle_check)
+    /// Pseudocode: Unknown pipelines are not found; otherwise consult the status state machine
+    pub fn ensure_allowed(&self, id: &str, action: PipelineAction) -> Result<&Pipeline> {
+        let pipeline = self
+            .pipelines
+            .get(id)
+            .ok_or_else(|| LifecycleError::NotFound(id.to_string()))?;
+        self.check(pipeline, action)?;
+        Ok(pipeline)
+    }
+
+    /// Pause, resume or stop a pipeline, or delete it along with its history
+    pub fn apply_action(&mut self, id: &str, action: PipelineAction) -> Result<Option<Pipeline>> {
+        self.ensure_allowed(id, action)?;
+        match action.target() {
+            Some(status) => {
+                self.set_status(id, status)?;
+                Ok(self.pipelines.get(id).cloned())
+            }
+            None => {
+                self.store.delete(id)?;
+                self.pipelines.remove(id);
+                Ok(None)
+            }
         }
     }
 
+    /// Runs and status transitions recorded for a pipeline
+    pub fn history(&self, id: &str) -> Result<Option<PipelineHistory>> {
+        if !self.pipelines.contains_key(id) {
+            return Ok(None);
+        }
+        self.store.history(id).map(Some)
+    }
+
+    /// Get pipeline by ID
+    pub fn get_pipeline(&self, id: &str) -> Option<&Pipeline> {
+        self.pipelines.get(id)
+    }
+
+    /// List all pipelines, ordered by id
+    pub fn list_pipelines(&self) -> Vec<&Pipeline> {
+        let mut pipelines: Vec<&Pipeline> = self.pipelines.values().collect();
+        pipelines.sort_by(|a, b| a.id.cmp(&b.id));
+        pipelines
+    }
+
     /// Count active pipelines
     pub fn active_pipeline_count(&self) -> usize {
         self.pipelines
@@ -103,20 +456,37 @@ impl Orchestrator {
     }
 }
 
-impl Default for Orchestrator {
-    fn default() -> Self {
-        Self::new()
+/// Extract every document and merge the results into one batch
+pub fn extract_documents(documents: &[Value]) -> Result<ExtractedData> {
+    let mut merged = BloodHoundExtractor::extract_from_json(&Value::Null)?;
+    for document in documents {
+        let extracted = BloodHoundExtractor::extract_from_json(document)?;
+        merged.nodes.extend(extracted.nodes);
+        merged.edges.extend(extracted.edges);
     }
+    merged.metadata.total_nodes = merged.nodes.len();
+    merged.metadata.total_edges = merged.edges.len();
+    Ok(merged)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use redb::Database;
     use serde_json::json;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn orchestrator(dir: &Path) -> Orchestrator {
+        let db = Arc::new(Database::create(dir.join("fire_marshal.redb")).unwrap());
+        Orchestrator::load(PipelineStore::open(db).unwrap()).unwrap()
+    }
 
     #[test]
     fn test_orchestrator() {
-        let mut orch = Orchestrator::new();
+        let temp_dir = TempDir::new().unwrap();
+        let mut orch = orchestrator(temp_dir.path());
         let payload = json!({
             "data": [
                 {
@@ -128,11 +498,191 @@ mod tests {
                 }
             ]
         });
-        orch.create_pipeline("test1", "source1", "dest1", &payload)
-            .unwrap();
+        orch.create_pipeline(
+            "test1",
+            "source1",
+            &[],
+            "dest1",
+            &[payload.clone(), payload],
+        )
+        .unwrap();
 
         let pipeline = orch.get_pipeline("test1").unwrap();
         assert_eq!(pipeline.source, "source1");
-        assert!(pipeline.last_run.is_some());
+        assert_eq!(pipeline.last_run.as_ref().unwrap().nodes_count, 2);
+    }
+
+    #[test]
+    fn test_pipeline_runs_transformers() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut orch = orchestrator(temp_dir.path());
+        let payload = json!({
+            "data": [
+                { "ObjectIdentifier": "u1", "ObjectType": "User", "Properties": { "name": "a" } },
+                { "ObjectIdentifier": "c1", "ObjectType": "Computer", "Properties": { "name": "b" } }
+            ]
+        });
+
+        let (stats, data) = orch
+            .create_pipeline(
+                "p1",
+                "inline",
+                &["filter:User".to_string()],
+                "dest",
+                std::slice::from_ref(&payload),
+            )
+            .unwrap();
+        assert_eq!(stats.nodes_count, 1);
+        assert_eq!(stats.steps.len(), 1);
+        assert_eq!(stats.steps[0].nodes_in, 2);
+        assert_eq!(data.nodes[0].node_type, "User");
+
+        assert!(orch
+            .create_pipeline("p2", "inline", &["decrypt".to_string()], "dest", &[payload])
+            .is_err());
+        assert!(orch.get_pipeline("p2").is_none());
+    }
+
+    #[test]
+    fn test_state_survives_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let payload = json!({ "data": [{ "ObjectIdentifier": "u1", "Properties": {} }] });
+        {
+            let mut orch = orchestrator(temp_dir.path());
+            orch.create_pipeline("p1", "inline", &[], "redb:", std::slice::from_ref(&payload))
+                .unwrap();
+            orch.record_delivery(
+                "p1",
+                DeliveryReport {
+                    sink: "redb".to_string(),
+                    destination: "redb:".to_string(),
+                    records: 1,
+                    delivered_at: 0,
+                },
+            )
+            .unwrap();
+            orch.fail_pipeline("p1", "sink offline".to_string())
+                .unwrap();
+            orch.create_pipeline("p1", "inline", &[], "redb:", &[payload])
+                .unwrap();
+        }
+
+        let orch = orchestrator(temp_dir.path());
+        let pipeline = orch.get_pipeline("p1").unwrap();
+        assert_eq!(pipeline.status, PipelineStatus::Active);
+        assert_eq!(pipeline.runs, 2);
+        assert_eq!(orch.active_pipeline_count(), 1);
+
+        let history = orch.history("p1").unwrap().unwrap();
+        assert_eq!(history.runs.len(), 2);
+        assert_eq!(history.runs[0].run, 1);
+        assert_eq!(history.runs[0].stats.delivery.as_ref().unwrap().records, 1);
+        assert!(history.runs[1].stats.delivery.is_none());
+        let statuses: Vec<_> = history.transitions.iter().map(|t| t.to.clone()).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                PipelineStatus::Active,
+                PipelineStatus::Error("sink offline".to_string()),
+                PipelineStatus::Active,
+            ]
+        );
+        assert!(history.transitions[0].from.is_none());
+        assert!(orch.history("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_lifecycle_state_machine() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut orch = orchestrator(temp_dir.path());
+        let payload = json!({ "data": [{ "ObjectIdentifier": "u1", "Properties": {} }] });
+        let run = |orch: &mut Orchestrator| {
+            orch.create_pipeline("p1", "inline", &[], "redb:", std::slice::from_ref(&payload))
+        };
+        run(&mut orch).unwrap();
+
+        let refused = |result: Result<Option<Pipeline>>| {
+            matches!(
+                result.unwrap_err().downcast_ref::<LifecycleError>(),
+                Some(LifecycleError::InvalidTransition { .. })
+            )
+        };
+        assert!(refused(orch.apply_action("p1", PipelineAction::Resume)));
+        assert!(refused(orch.apply_action("p1", PipelineAction::Delete)));
+
+        let paused = orch.apply_action("p1", PipelineAction::Pause).unwrap();
+        assert_eq!(paused.unwrap().status, PipelineStatus::Paused);
+        assert_eq!(orch.active_pipeline_count(), 0);
+        assert!(run(&mut orch)
+            .unwrap_err()
+            .downcast_ref::<LifecycleError>()
+            .is_some());
+
+        orch.apply_action("p1", PipelineAction::Resume).unwrap();
+        run(&mut orch).unwrap();
+        orch.apply_action("p1", PipelineAction::Stop).unwrap();
+        assert!(refused(orch.apply_action("p1", PipelineAction::Pause)));
+        assert!(run(&mut orch).is_err());
+
+        assert!(orch
+            .apply_action("p1", PipelineAction::Delete)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            orch.apply_action("p1", PipelineAction::Stop)
+                .unwrap_err()
+                .downcast_ref::<LifecycleError>(),
+            Some(LifecycleError::NotFound(_))
+        ));
+        assert!(orch.store.load().unwrap().is_empty());
+        assert!(orch.store.history("p1").unwrap().runs.is_empty());
+
+        // A deleted pipeline can be created again from scratch
+        run(&mut orch).unwrap();
+        assert_eq!(orch.get_pipeline("p1").unwrap().runs, 1);
+    }
+
+    #[test]
+    fn test_register_scheduled_pipeline() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut orch = orchestrator(temp_dir.path());
+        let schedule = PipelineSchedule {
+            cron: None,
+            every_secs: Some(3600),
+            catch_up: Default::default(),
+            concurrency: Default::default(),
+        };
+
+        let before = chrono::Utc::now().timestamp();
+        let pipeline = orch
+            .register_pipeline(
+                "nightly",
+                "watch:///data",
+                &[],
+                "redb:",
+                None,
+                Some(schedule.clone()),
+            )
+            .unwrap();
+        assert_eq!(pipeline.runs, 0);
+        assert!(pipeline.next_run.unwrap() >= before + 3600);
+
+        // Runs keep the schedule
+        let payload = json!({ "data": [] });
+        orch.create_pipeline("nightly", "watch:///data", &[], "redb:", &[payload])
+            .unwrap();
+        assert_eq!(
+            orch.get_pipeline("nightly").unwrap().schedule,
+            Some(schedule.clone())
+        );
+
+        let invalid = PipelineSchedule {
+            cron: Some("whenever".to_string()),
+            ..schedule
+        };
+        assert!(orch
+            .register_pipeline("bad", "watch:///data", &[], "redb:", None, Some(invalid))
+            .is_err());
+        assert!(orch.get_pipeline("bad").is_none());
     }
 }
@@ -0,0 +1,288 @@
+// Pipeline retries
+// Track failed attempts of queued pipeline files in an error sidecar, back off between retries
+// and dead-letter records that keep failing or can never succeed
+
+use anyhow::{Context, Result};
+use pyro_core::config::RetryConfig;
+use pyro_core::pipeline_graph::GraphError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::orchestrator::LifecycleError;
+use crate::scheduler::ScheduleError;
+use crate::sink::SinkError;
+use crate::source::SourceError;
+use crate::transform::TransformError;
+
+/// Appended to a record's file name for its error sidecar, e.g. `nightly.json.error`
+pub const SIDECAR_EXTENSION: &str = "error";
+
+/// One failed attempt, with the error and its causes outermost first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub attempt: u32,
+    pub at: i64,
+    pub chain: Vec<String>,
+}
+
+/// Failed attempts of a queued pipeline file, stored next to it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailureRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline_id: Option<String>,
+    pub attempts: u32,
+    /// When the record may run again; unset once it is dead-lettered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<i64>,
+    /// Set when the last failure cannot be fixed by retrying
+    #[serde(default)]
+    pub permanent: bool,
+    pub failures: Vec<Failure>,
+}
+
+/// Error sidecar of a pipeline file
+pub fn sidecar_path(record: &Path) -> PathBuf {
+    let mut name = record.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    record.with_file_name(name)
+}
+
+/// Whether retrying cannot help: malformed records, unknown schemes, bad transformer chains,
+/// graphs or schedules, and pipelines whose status refuses the run
+pub fn is_permanent(err: &anyhow::Error) -> bool {
+    err.is::<serde_json::Error>()
+        || err.is::<GraphError>()
+        || err.is::<SourceError>()
+        || err.is::<SinkError>()
+        || err.is::<TransformError>()
+        || err.is::<ScheduleError>()
+        || err.is::<LifecycleError>()
+}
+
+impl FailureRecord {
+    /// Sidecar of a pipeline file, if it failed before
+    pub async fn load(record: &Path) -> Result<Option<Self>> {
+        let path = sidecar_path(record);
+        match fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .with_context(|| format!("Failed to parse error sidecar {:?}", path)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {:?}", path)),
+        }
+    }
+
+    pub async fn save(&self, record: &Path) -> Result<()> {
+        let path = sidecar_path(record);
+        fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write error sidecar {:?}", path))
+    }
+
+    /// Whether the backoff since the last failure has passed
+    pub fn ready(&self, now: i64) -> bool {
+        self.next_attempt_at.is_none_or(|at| at <= now)
+    }
+
+    /// Record a failed attempt and schedule the next one (dictionary: record_pipeline_failure)
+    /// Pseudocode: Append the error chain; permanent errors and records out of attempts are
+    /// dead-lettered, others wait an exponentially growing backoff. Returns whether to retry
+    pub fn record(&mut self, err: &anyhow::Error, retry: &RetryConfig, now: i64) -> bool {
+        self.attempts += 1;
+        self.permanent = is_permanent(err);
+        self.failures.push(Failure {
+            attempt: self.attempts,
+            at: now,
+            chain: err.chain().map(|cause| cause.to_string()).collect(),
+        });
+
+        let retrying = !self.permanent && self.attempts < retry.max_attempts;
+        self.next_attempt_at = retrying.then(|| {
+            let delay = retry.backoff(self.attempts).min(i64::MAX as u64) as i64;
+            now.saturating_add(delay)
+        });
+        retrying
+    }
+}
+
+/// A pipeline file that was given up on
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub file: String,
+    pub pipeline_id: Option<String>,
+    pub attempts: Option<u32>,
+    pub permanent: bool,
+    pub failures: Vec<Failure>,
+}
+
+/// Dead-lettered pipeline files in `failed_dir`, by file name
+pub async fn list_dead_letters(failed_dir: &Path) -> Result<Vec<DeadLetter>> {
+    let mut dir = match fs::read_dir(failed_dir).await {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {:?}", failed_dir)),
+    };
+
+    let mut dead_letters = Vec::new();
+    while let Some(entry) = dir.next_entry().await? {
+        let path = entry.path();
+        if !is_record_file(&path) {
+            continue;
+        }
+
+        // Files that failed before sidecars existed only have their record, and files
+        // dead-lettered for a corrupt sidecar have no readable one
+        let failures = FailureRecord::load(&path).await.unwrap_or_else(|err| {
+            eprintln!("[Fire Marshal] {err:#}");
+            None
+        });
+        let pipeline_id = match failures.as_ref().and_then(|f| f.pipeline_id.clone()) {
+            Some(id) => Some(id),
+            None => fs::read(&path)
+                .await
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+                .and_then(|record| record.get("id")?.as_str().map(str::to_string)),
+        };
+        dead_letters.push(DeadLetter {
+            file: entry.file_name().to_string_lossy().into_owned(),
+            pipeline_id,
+            attempts: failures.as_ref().map(|f| f.attempts),
+            permanent: failures.as_ref().is_some_and(|f| f.permanent),
+            failures: failures.map(|f| f.failures).unwrap_or_default(),
+        });
+    }
+    dead_letters.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(dead_letters)
+}
+
+/// Why a dead letter could not be requeued
+#[derive(Debug, thiserror::Error)]
+pub enum RequeueError {
+    #[error("invalid dead letter name '{0}'")]
+    InvalidName(String),
+    #[error("dead letter not found: {0}")]
+    NotFound(String),
+    #[error("a pipeline file named {0} is already queued")]
+    AlreadyQueued(String),
+}
+
+/// Whether a path is a queued pipeline record rather than a sidecar or folder
+pub fn is_record_file(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some("json") && path.is_file()
+}
+
+/// Move a dead-lettered file back into the queue with a fresh attempt count
+pub async fn requeue(failed_dir: &Path, queue_dir: &Path, file: &str) -> Result<PathBuf> {
+    let valid = !file.is_empty()
+        && Path::new(file).file_name().and_then(|n| n.to_str()) == Some(file)
+        && file.ends_with(".json");
+    if !valid {
+        return Err(RequeueError::InvalidName(file.to_string()).into());
+    }
+
+    let source = failed_dir.join(file);
+    if !source.is_file() {
+        return Err(RequeueError::NotFound(file.to_string()).into());
+    }
+    let target = queue_dir.join(file);
+    if target.exists() {
+        return Err(RequeueError::AlreadyQueued(file.to_string()).into());
+    }
+
+    let sidecar = sidecar_path(&source);
+    if sidecar.exists() {
+        fs::remove_file(&sidecar)
+            .await
+            .with_context(|| format!("Failed to remove {:?}", sidecar))?;
+    }
+    fs::rename(&source, &target)
+        .await
+        .with_context(|| format!("Failed to move {:?} -> {:?}", source, target))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_failures_back_off_then_dead_letter() {
+        let retry = RetryConfig {
+            max_attempts: 3,
+            backoff_secs: 10,
+            max_backoff_secs: 60,
+        };
+        let mut failures = FailureRecord::default();
+        let err = anyhow!("connection refused").context("Failed to fetch https://example.org");
+
+        assert!(failures.record(&err, &retry, 100));
+        assert_eq!(failures.next_attempt_at, Some(110));
+        assert!(!failures.ready(105));
+        assert!(failures.ready(110));
+        assert!(failures.record(&err, &retry, 110));
+        assert_eq!(failures.next_attempt_at, Some(130));
+        assert!(!failures.record(&err, &retry, 130));
+        assert_eq!(failures.next_attempt_at, None);
+        assert_eq!(failures.attempts, 3);
+        assert_eq!(
+            failures.failures[0].chain,
+            vec!["Failed to fetch https://example.org", "connection refused"]
+        );
+
+        // Retrying an unknown source scheme cannot help
+        let mut failures = FailureRecord::default();
+        let err = anyhow::Error::from(SourceError::UnsupportedScheme("s3".to_string()));
+        assert!(!failures.record(&err.context("Failed to run"), &retry, 100));
+        assert!(failures.permanent);
+    }
+
+    #[tokio::test]
+    async fn test_list_and_requeue_dead_letters() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = temp_dir.path();
+        let failed = queue.join("failed");
+        std::fs::create_dir_all(&failed).unwrap();
+
+        let record = failed.join("p1.json");
+        std::fs::write(&record, r#"{"id": "p1"}"#).unwrap();
+        let mut failures = FailureRecord::default();
+        failures.record(&anyhow!("boom"), &RetryConfig::default(), 0);
+        failures.save(&record).await.unwrap();
+        std::fs::write(failed.join("legacy.json"), r#"{"id": "old"}"#).unwrap();
+        std::fs::write(failed.join("torn.json"), r#"{"id": "torn"}"#).unwrap();
+        std::fs::write(sidecar_path(&failed.join("torn.json")), "{").unwrap();
+
+        let dead_letters = list_dead_letters(&failed).await.unwrap();
+        assert_eq!(dead_letters.len(), 3);
+        assert_eq!(dead_letters[2].pipeline_id.as_deref(), Some("torn"));
+        assert_eq!(dead_letters[0].file, "legacy.json");
+        assert_eq!(dead_letters[0].pipeline_id.as_deref(), Some("old"));
+        assert_eq!(dead_letters[0].attempts, None);
+        assert_eq!(dead_letters[1].attempts, Some(1));
+
+        for (file, expected) in [("../p1.json", "invalid"), ("missing.json", "not found")] {
+            let err = requeue(&failed, queue, file).await.unwrap_err();
+            assert!(err.to_string().contains(expected), "{}", err);
+        }
+
+        let requeued = requeue(&failed, queue, "p1.json").await.unwrap();
+        assert_eq!(requeued, queue.join("p1.json"));
+        assert!(!sidecar_path(&record).exists());
+        assert!(FailureRecord::load(&requeued).await.unwrap().is_none());
+
+        std::fs::write(&record, "{}").unwrap();
+        assert!(matches!(
+            requeue(&failed, queue, "p1.json")
+                .await
+                .unwrap_err()
+                .downcast_ref::<RequeueError>(),
+            Some(RequeueError::AlreadyQueued(_))
+        ));
+    }
+}
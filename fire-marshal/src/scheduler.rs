@@ -0,0 +1,352 @@
+// Pipeline scheduler
+// Start runs of pipelines with a cron or fixed-interval schedule, catching up on runs missed
+// while fire-marshal was down and applying each schedule's concurrency policy
+
+use chrono::{TimeZone, Utc};
+use pyro_core::logging::{self, LogLevel};
+use pyro_core::pipeline::{CatchUpPolicy, ConcurrencyPolicy, PipelineRecord, PipelineSchedule};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+use crate::api::state::AppState;
+use crate::datapipe;
+use crate::orchestrator::PipelineAction;
+
+/// Delay between checks for due runs
+pub const SCHEDULER_TICK: Duration = Duration::from_secs(1);
+
+/// A run starting this many seconds after its scheduled time counts as missed
+pub const MISSED_RUN_GRACE_SECS: i64 = 60;
+
+/// Most missed runs replayed by `catch_up = "all"`
+pub const MAX_CATCH_UP_RUNS: usize = 24;
+
+/// Why a schedule was rejected
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("schedule needs exactly one of cron or every_secs")]
+    Ambiguous,
+    #[error("every_secs must be at least 1")]
+    ZeroInterval,
+    #[error("invalid cron expression '{expression}': {message}")]
+    InvalidCron { expression: String, message: String },
+}
+
+/// A parsed schedule
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    Cron(Box<cron::Schedule>),
+    Every(i64),
+}
+
+impl Trigger {
+    /// Parse a schedule; five-field crontab expressions run at second 0
+    pub fn parse(schedule: &PipelineSchedule) -> Result<Self, ScheduleError> {
+        match (&schedule.cron, schedule.every_secs) {
+            (Some(expression), None) => {
+                let fields = expression.split_whitespace().count();
+                let full = if fields == 5 {
+                    format!("0 {}", expression)
+                } else {
+                    expression.clone()
+                };
+                cron::Schedule::from_str(&full)
+                    .map(|cron| Trigger::Cron(Box::new(cron)))
+                    .map_err(|e| ScheduleError::InvalidCron {
+                        expression: expression.clone(),
+                        message: e.to_string(),
+                    })
+            }
+            (None, Some(0)) => Err(ScheduleError::ZeroInterval),
+            (None, Some(secs)) => Ok(Trigger::Every(secs.min(i64::MAX as u64) as i64)),
+            _ => Err(ScheduleError::Ambiguous),
+        }
+    }
+
+    /// First scheduled time strictly after `after` (unix seconds)
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        match self {
+            Trigger::Cron(cron) => {
+                let after = Utc.timestamp_opt(after, 0).single()?;
+                cron.after(&after).next().map(|at| at.timestamp())
+            }
+            Trigger::Every(secs) => after.checked_add(*secs),
+        }
+    }
+
+    /// First scheduled time after `now` for a schedule that was next due at `due`; intervals
+    /// stay aligned to `due`
+    pub fn following(&self, due: i64, now: i64) -> Option<i64> {
+        match self {
+            Trigger::Every(secs) if due <= now => {
+                let elapsed = ((now - due) / secs).checked_add(1)?;
+                due.checked_add(elapsed.checked_mul(*secs)?)
+            }
+            Trigger::Every(_) => Some(due),
+            Trigger::Cron(_) => self.next_after(now),
+        }
+    }
+}
+
+/// Runs to start for a pipeline whose next run was due at `next_run`, and when the one after
+/// is due (dictionary: schedule_due_runs)
+/// Pseudocode: Walk the scheduled times up to now; times within the grace period run, older
+/// ones are missed and replayed according to the catch-up policy; the next run is the first
+/// scheduled time after now
+pub fn due_runs(
+    trigger: &Trigger,
+    catch_up: CatchUpPolicy,
+    next_run: i64,
+    now: i64,
+) -> (usize, Option<i64>) {
+    let mut on_time = 0;
+    let mut missed = 0;
+    let mut at = Some(next_run);
+    while let Some(time) = at.filter(|time| *time <= now) {
+        if now - time > MISSED_RUN_GRACE_SECS {
+            missed += 1;
+            // Long outages are not walked one scheduled time at a time
+            if missed > MAX_CATCH_UP_RUNS {
+                break;
+            }
+        } else {
+            on_time += 1;
+        }
+        at = trigger.next_after(time);
+    }
+
+    let replayed = match catch_up {
+        CatchUpPolicy::Skip => 0,
+        CatchUpPolicy::Once => missed.min(1),
+        CatchUpPolicy::All => missed.min(MAX_CATCH_UP_RUNS),
+    };
+    (replayed + on_time, trigger.following(next_run, now))
+}
+
+/// Scheduled runs in flight and waiting, per pipeline
+struct Scheduler {
+    state: AppState,
+    running: HashMap<String, JoinHandle<()>>,
+    queued: HashMap<String, usize>,
+}
+
+impl Scheduler {
+    /// Start due runs and runs waiting for their predecessor
+    async fn tick(&mut self) {
+        self.running.retain(|_, run| !run.is_finished());
+
+        let now = Utc::now().timestamp();
+        let mut due = Vec::new();
+        {
+            let mut orchestrator = self.state.orchestrator.write().await;
+            let scheduled: Vec<_> = orchestrator
+                .list_pipelines()
+                .into_iter()
+                .filter(|p| p.status.allows(PipelineAction::Run))
+                .filter_map(|p| Some((p.id.clone(), p.schedule.clone()?, p.next_run)))
+                .collect();
+
+            for (id, schedule, next_run) in scheduled {
+                let Ok(trigger) = Trigger::parse(&schedule) else {
+                    continue;
+                };
+                let (runs, next) = match next_run {
+                    Some(next_run) if next_run <= now => {
+                        due_runs(&trigger, schedule.catch_up, next_run, now)
+                    }
+                    Some(_) => continue,
+                    None => (0, trigger.next_after(now)),
+                };
+                if let Err(err) = orchestrator.schedule_next(&id, next) {
+                    eprintln!("[Fire Marshal] failed to schedule pipeline {}: {err:?}", id);
+                    continue;
+                }
+                if runs > 0 {
+                    due.push((id, schedule.concurrency, runs));
+                }
+            }
+        }
+
+        for (id, concurrency, runs) in due {
+            self.dispatch(id, concurrency, runs);
+        }
+
+        let waiting: Vec<String> = self
+            .queued
+            .keys()
+            .filter(|id| !self.running.contains_key(*id))
+            .cloned()
+            .collect();
+        for id in waiting {
+            self.take_queued(&id);
+            self.start(id);
+        }
+    }
+
+    /// Start, queue, skip or replace `runs` due runs of a pipeline
+    fn dispatch(&mut self, id: String, concurrency: ConcurrencyPolicy, runs: usize) {
+        if let Some(previous) = self.running.get(&id) {
+            match concurrency {
+                ConcurrencyPolicy::Skip => {
+                    if logging::enabled(LogLevel::Debug) {
+                        println!(
+                            "[Fire Marshal] skipped scheduled run of {}: previous run still going",
+                            id
+                        );
+                    }
+                    return;
+                }
+                ConcurrencyPolicy::Queue => {
+                    *self.queued.entry(id).or_default() += runs;
+                    return;
+                }
+                ConcurrencyPolicy::Replace => {
+                    previous.abort();
+                    self.running.remove(&id);
+                    self.queued.remove(&id);
+                }
+            }
+        }
+
+        // Catch-up runs of one pipeline never overlap
+        if runs > 1 {
+            *self.queued.entry(id.clone()).or_default() += runs - 1;
+        }
+        self.start(id);
+    }
+
+    fn take_queued(&mut self, id: &str) {
+        if let Some(count) = self.queued.get_mut(id) {
+            *count -= 1;
+            if *count == 0 {
+                self.queued.remove(id);
+            }
+        }
+    }
+
+    /// Run the pipeline's current definition in the background
+    fn start(&mut self, id: String) {
+        let state = self.state.clone();
+        let run_id = id.clone();
+        let run = tokio::spawn(async move {
+            let record = {
+                let orchestrator = state.orchestrator.read().await;
+                match orchestrator.ensure_allowed(&run_id, PipelineAction::Run) {
+                    Ok(pipeline) => match &pipeline.graph {
+                        Some(graph) => {
+                            PipelineRecord::from_graph(pipeline.id.clone(), graph.clone())
+                        }
+                        None => PipelineRecord::new(
+                            pipeline.id.clone(),
+                            pipeline.source.clone(),
+                            pipeline.transformers.clone(),
+                            pipeline.destination.clone(),
+                            Value::Null,
+                        ),
+                    },
+                    // Paused, stopped or deleted since the run was due
+                    Err(_) => return,
+                }
+            };
+            if let Err(err) = datapipe::run_record(&state, &record).await {
+                eprintln!("[Fire Marshal] scheduled run of {} failed: {err:?}", run_id);
+            }
+        });
+        self.running.insert(id, run);
+    }
+}
+
+pub fn spawn_scheduler(state: AppState) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut scheduler = Scheduler {
+            state,
+            running: HashMap::new(),
+            queued: HashMap::new(),
+        };
+        loop {
+            scheduler.tick().await;
+            sleep(SCHEDULER_TICK).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(cron: Option<&str>, every_secs: Option<u64>) -> PipelineSchedule {
+        PipelineSchedule {
+            cron: cron.map(str::to_string),
+            every_secs,
+            catch_up: CatchUpPolicy::default(),
+            concurrency: ConcurrencyPolicy::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_triggers() {
+        // 2024-01-01T00:00:00Z
+        let midnight = 1_704_067_200;
+
+        let nightly = Trigger::parse(&schedule(Some("0 2 * * *"), None)).unwrap();
+        assert_eq!(nightly.next_after(midnight), Some(midnight + 2 * 3600));
+        let with_seconds = Trigger::parse(&schedule(Some("30 0 2 * * *"), None)).unwrap();
+        assert_eq!(
+            with_seconds.next_after(midnight),
+            Some(midnight + 2 * 3600 + 30)
+        );
+
+        let every = Trigger::parse(&schedule(None, Some(300))).unwrap();
+        assert_eq!(every.next_after(midnight), Some(midnight + 300));
+
+        assert!(matches!(
+            Trigger::parse(&schedule(Some("nightly"), None)),
+            Err(ScheduleError::InvalidCron { .. })
+        ));
+        assert!(matches!(
+            Trigger::parse(&schedule(None, Some(0))),
+            Err(ScheduleError::ZeroInterval)
+        ));
+        assert!(matches!(
+            Trigger::parse(&schedule(Some("0 2 * * *"), Some(60))),
+            Err(ScheduleError::Ambiguous)
+        ));
+        assert!(matches!(
+            Trigger::parse(&schedule(None, None)),
+            Err(ScheduleError::Ambiguous)
+        ));
+    }
+
+    #[test]
+    fn test_due_runs_catch_up() {
+        let hourly = Trigger::Every(3600);
+        let next_run = 1_000_000;
+
+        // On time
+        assert_eq!(
+            due_runs(&hourly, CatchUpPolicy::Skip, next_run, next_run + 5),
+            (1, Some(next_run + 3600))
+        );
+
+        // Down for five scheduled runs; the last is still within the grace period
+        let now = next_run + 4 * 3600 + 10;
+        assert_eq!(due_runs(&hourly, CatchUpPolicy::Skip, next_run, now).0, 1);
+        assert_eq!(due_runs(&hourly, CatchUpPolicy::Once, next_run, now).0, 2);
+        assert_eq!(due_runs(&hourly, CatchUpPolicy::All, next_run, now).0, 5);
+
+        // Long outages replay at most MAX_CATCH_UP_RUNS
+        let now = next_run + 1000 * 3600 + 600;
+        let (runs, next) = due_runs(&hourly, CatchUpPolicy::All, next_run, now);
+        assert_eq!(runs, MAX_CATCH_UP_RUNS);
+        assert_eq!(next, Some(next_run + 1001 * 3600));
+
+        // Not due yet
+        assert_eq!(
+            due_runs(&hourly, CatchUpPolicy::All, next_run, next_run - 1).0,
+            0
+        );
+    }
+}
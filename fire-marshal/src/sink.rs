@@ -0,0 +1,735 @@
+// Pipeline sinks
+// Deliver transformed pipeline output to the destination named by a pipeline record, with the
+// sink chosen by the destination URI scheme
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use node_red_bridge::{NodeRedBridge, NodeRedMessage};
+use parquet::data_type::{ByteArray, ByteArrayType};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use pyro_core::data_extractor::ExtractedData;
+use pyro_core::extraction_catalog::{write_catalog, ExtractionCatalog};
+use redb::{Database, TableDefinition};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::uri::{under_root, PipelineUri};
+
+/// Nodes delivered to the redb graph store, keyed by node id
+pub const GRAPH_NODES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("graph_nodes");
+/// Edges delivered to the redb graph store, keyed by `source|type|target`
+pub const GRAPH_EDGES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("graph_edges");
+
+/// Node-RED topic used when a `node-red:` destination names none
+pub const DEFAULT_NODE_RED_TOPIC: &str = "fire-marshal/output";
+
+/// Why a destination could not be turned into a sink
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    #[error("unsupported destination scheme '{0}'")]
+    UnsupportedScheme(String),
+    #[error("invalid destination '{destination}': {message}")]
+    InvalidDestination {
+        destination: String,
+        message: String,
+    },
+}
+
+impl SinkError {
+    fn invalid(destination: &PipelineUri, message: impl Into<String>) -> Self {
+        SinkError::InvalidDestination {
+            destination: destination.raw.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Transformed output of one pipeline run
+pub struct OutputBatch<'a> {
+    pub pipeline_id: &'a str,
+    pub source: &'a str,
+    pub data: &'a ExtractedData,
+}
+
+impl OutputBatch<'_> {
+    fn records(&self) -> usize {
+        self.data.nodes.len() + self.data.edges.len()
+    }
+
+    /// One flat row per node and edge, shared by the file formats
+    fn rows(&self) -> Vec<OutputRow> {
+        let nodes = self.data.nodes.iter().map(|node| OutputRow {
+            record: "node",
+            id: Some(node.id.clone()),
+            kind: node.node_type.clone(),
+            label: Some(node.label.clone()),
+            source: None,
+            target: None,
+            properties: node.properties.to_string(),
+        });
+        let edges = self.data.edges.iter().map(|edge| OutputRow {
+            record: "edge",
+            id: None,
+            kind: edge.edge_type.clone(),
+            label: None,
+            source: Some(edge.source.clone()),
+            target: Some(edge.target.clone()),
+            properties: edge.properties.to_string(),
+        });
+        nodes.chain(edges).collect()
+    }
+}
+
+/// A node or edge flattened for file output; `properties` holds JSON
+#[derive(Debug, Clone, Serialize)]
+struct OutputRow {
+    record: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    properties: String,
+}
+
+/// Where one pipeline run's output went
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryReport {
+    pub sink: String,
+    pub destination: String,
+    pub records: usize,
+    pub delivered_at: i64,
+}
+
+/// A destination for pipeline output
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Deliver the batch, returning the number of records written
+    async fn deliver(&self, batch: &OutputBatch<'_>) -> Result<usize>;
+}
+
+/// Shared handles sinks deliver through
+#[derive(Clone)]
+pub struct SinkResources {
+    pub node_red: Arc<RwLock<NodeRedBridge>>,
+    pub db: Arc<Database>,
+    pub http: reqwest::Client,
+    /// Directory file and redb destinations are resolved under
+    pub output_root: PathBuf,
+    /// Hosts webhook destinations may post to
+    pub allowed_hosts: Vec<String>,
+    /// Directory holding pyro's cryptex stores
+    pub cryptex_root: PathBuf,
+}
+
+/// Builds a sink for a destination
+pub type SinkFactory = fn(&PipelineUri, &SinkResources) -> Result<Box<dyn Sink>, SinkError>;
+
+/// Sinks by destination scheme
+pub struct SinkRegistry {
+    factories: BTreeMap<String, SinkFactory>,
+    resources: SinkResources,
+}
+
+impl SinkRegistry {
+    /// Registry with the built-in sinks
+    pub fn new(resources: SinkResources) -> Self {
+        let mut registry = Self {
+            factories: BTreeMap::new(),
+            resources,
+        };
+        registry.register("file", FileSink::build);
+        registry.register("node-red", NodeRedSink::build);
+        registry.register("http", WebhookSink::build);
+        registry.register("https", WebhookSink::build);
+        registry.register("redb", GraphStoreSink::build);
+        registry.register("cryptex", CryptexSink::build);
+        registry
+    }
+
+    pub fn register(&mut self, scheme: &str, factory: SinkFactory) {
+        self.factories.insert(scheme.to_ascii_lowercase(), factory);
+    }
+
+    /// Sink for a destination URI
+    pub fn resolve(&self, destination: &str) -> Result<Box<dyn Sink>, SinkError> {
+        let destination = PipelineUri::parse(destination);
+        let factory = self
+            .factories
+            .get(&destination.scheme)
+            .ok_or_else(|| SinkError::UnsupportedScheme(destination.scheme.clone()))?;
+        factory(&destination, &self.resources)
+    }
+
+    /// Deliver a batch to a destination (dictionary: deliver_output)
+    /// Pseudocode: Pick the sink registered for the destination scheme, hand it the
+    /// transformed nodes and edges and report how many records it wrote
+    pub async fn deliver(
+        &self,
+        destination: &str,
+        batch: &OutputBatch<'_>,
+    ) -> Result<DeliveryReport> {
+        let sink = self.resolve(destination)?;
+        let records = sink.deliver(batch).await?;
+        Ok(DeliveryReport {
+            sink: PipelineUri::parse(destination).scheme,
+            destination: destination.to_string(),
+            records,
+            delivered_at: chrono::Utc::now().timestamp(),
+        })
+    }
+}
+
+/// Output file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    JsonLines,
+    Csv,
+    Parquet,
+}
+
+/// `file://out/{pipeline_id}.jsonl` writes a JSON Lines, CSV or Parquet file chosen by
+/// extension under the output root; `{pipeline_id}` is replaced with the pipeline's id
+struct FileSink {
+    root: PathBuf,
+    path: String,
+    format: FileFormat,
+}
+
+impl FileSink {
+    fn build(
+        destination: &PipelineUri,
+        resources: &SinkResources,
+    ) -> Result<Box<dyn Sink>, SinkError> {
+        if destination.target.is_empty() {
+            return Err(SinkError::invalid(destination, "expects a file path"));
+        }
+        if destination.path_under(&resources.output_root).is_none() {
+            return Err(SinkError::invalid(
+                destination,
+                "expects a relative path inside the output directory",
+            ));
+        }
+        let extension = Path::new(&destination.target)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let format = match extension.as_deref() {
+            Some("jsonl" | "ndjson") => FileFormat::JsonLines,
+            Some("csv") => FileFormat::Csv,
+            Some("parquet") => FileFormat::Parquet,
+            _ => {
+                return Err(SinkError::invalid(
+                    destination,
+                    "file extension must be .jsonl, .csv or .parquet",
+                ))
+            }
+        };
+        Ok(Box::new(Self {
+            root: resources.output_root.clone(),
+            path: destination.target.clone(),
+            format,
+        }))
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    async fn deliver(&self, batch: &OutputBatch<'_>) -> Result<usize> {
+        let relative = self.path.replace("{pipeline_id}", batch.pipeline_id);
+        let path = under_root(&self.root, &relative)
+            .with_context(|| format!("Output path {:?} leaves the output directory", relative))?;
+        let rows = batch.rows();
+        let format = self.format;
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {:?}", parent))?;
+            }
+
+            // Write next to the target and rename so readers never see a partial file
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(".tmp");
+            let tmp = PathBuf::from(tmp);
+            let file = std::fs::File::create(&tmp)
+                .with_context(|| format!("Failed to create {:?}", tmp))?;
+            match format {
+                FileFormat::JsonLines => write_jsonl(std::io::BufWriter::new(file), &rows)?,
+                FileFormat::Csv => write_csv(std::io::BufWriter::new(file), &rows)?,
+                FileFormat::Parquet => write_parquet(file, &rows)?,
+            }
+            std::fs::rename(&tmp, &path)
+                .with_context(|| format!("Failed to move {:?} -> {:?}", tmp, path))?;
+            Ok(())
+        })
+        .await??;
+
+        Ok(batch.records())
+    }
+}
+
+fn write_jsonl<W: Write>(mut writer: W, rows: &[OutputRow]) -> Result<()> {
+    for row in rows {
+        serde_json::to_writer(&mut writer, row)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_csv<W: Write>(mut writer: W, rows: &[OutputRow]) -> Result<()> {
+    writeln!(writer, "record,id,type,label,source,target,properties")?;
+    for row in rows {
+        let fields = [
+            Some(row.record),
+            row.id.as_deref(),
+            Some(row.kind.as_str()),
+            row.label.as_deref(),
+            row.source.as_deref(),
+            row.target.as_deref(),
+            Some(row.properties.as_str()),
+        ];
+        let line: Vec<String> = fields
+            .iter()
+            .map(|f| csv_field(f.unwrap_or_default()))
+            .collect();
+        writeln!(writer, "{}", line.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+const PARQUET_SCHEMA: &str = "
+message pipeline_output {
+    REQUIRED BYTE_ARRAY record (UTF8);
+    OPTIONAL BYTE_ARRAY id (UTF8);
+    REQUIRED BYTE_ARRAY type (UTF8);
+    OPTIONAL BYTE_ARRAY label (UTF8);
+    OPTIONAL BYTE_ARRAY source (UTF8);
+    OPTIONAL BYTE_ARRAY target (UTF8);
+    REQUIRED BYTE_ARRAY properties (UTF8);
+}
+";
+
+fn write_parquet(file: std::fs::File, rows: &[OutputRow]) -> Result<()> {
+    let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(file, schema, properties)?;
+
+    let columns: [fn(&OutputRow) -> Option<&str>; 7] = [
+        |r| Some(r.record),
+        |r| r.id.as_deref(),
+        |r| Some(r.kind.as_str()),
+        |r| r.label.as_deref(),
+        |r| r.source.as_deref(),
+        |r| r.target.as_deref(),
+        |r| Some(r.properties.as_str()),
+    ];
+    // Required columns carry no definition levels
+    let optional = [false, true, false, true, true, true, false];
+
+    let mut row_group = writer.next_row_group()?;
+    for (column, optional) in columns.iter().zip(optional) {
+        let mut column_writer = row_group
+            .next_column()?
+            .context("Parquet schema has fewer columns than expected")?;
+        let values: Vec<ByteArray> = rows
+            .iter()
+            .filter_map(column)
+            .map(ByteArray::from)
+            .collect();
+        let levels: Vec<i16> = rows
+            .iter()
+            .map(|row| i16::from(column(row).is_some()))
+            .collect();
+        column_writer.typed::<ByteArrayType>().write_batch(
+            &values,
+            optional.then_some(levels.as_slice()),
+            None,
+        )?;
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+/// `node-red:` or `node-red://custom/topic` publishes the output through the Node-RED bridge
+struct NodeRedSink {
+    node_red: Arc<RwLock<NodeRedBridge>>,
+    topic: String,
+}
+
+impl NodeRedSink {
+    fn build(
+        destination: &PipelineUri,
+        resources: &SinkResources,
+    ) -> Result<Box<dyn Sink>, SinkError> {
+        let topic = match destination.target.trim_matches('/') {
+            "" => DEFAULT_NODE_RED_TOPIC.to_string(),
+            topic => topic.to_string(),
+        };
+        Ok(Box::new(Self {
+            node_red: resources.node_red.clone(),
+            topic,
+        }))
+    }
+}
+
+#[async_trait]
+impl Sink for NodeRedSink {
+    async fn deliver(&self, batch: &OutputBatch<'_>) -> Result<usize> {
+        let message = NodeRedMessage::new(self.topic.clone(), webhook_body(batch));
+        self.node_red.read().await.send(message).await?;
+        Ok(batch.records())
+    }
+}
+
+/// JSON document posted to webhooks and Node-RED
+fn webhook_body(batch: &OutputBatch<'_>) -> Value {
+    json!({
+        "pipeline_id": batch.pipeline_id,
+        "source": batch.source,
+        "metadata": batch.data.metadata,
+        "nodes": batch.data.nodes,
+        "edges": batch.data.edges,
+    })
+}
+
+/// `https://example.org/hook` posts the output as JSON to a host listed in
+/// `pipeline.sink_hosts` and expects a 2xx response
+struct WebhookSink {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    fn build(
+        destination: &PipelineUri,
+        resources: &SinkResources,
+    ) -> Result<Box<dyn Sink>, SinkError> {
+        let url = reqwest::Url::parse(&destination.raw)
+            .map_err(|e| SinkError::invalid(destination, e.to_string()))?;
+        let Some(host) = url.host_str() else {
+            return Err(SinkError::invalid(destination, "expects a host"));
+        };
+        let allowed = resources
+            .allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host));
+        if !allowed {
+            return Err(SinkError::invalid(
+                destination,
+                format!("host '{}' is not in pipeline.sink_hosts", host),
+            ));
+        }
+        Ok(Box::new(Self {
+            http: resources.http.clone(),
+            url: destination.raw.clone(),
+        }))
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    async fn deliver(&self, batch: &OutputBatch<'_>) -> Result<usize> {
+        self.http
+            .post(&self.url)
+            .json(&webhook_body(batch))
+            .send()
+            .await
+            .with_context(|| format!("Failed to post to {}", self.url))?
+            .error_for_status()
+            .with_context(|| format!("Webhook {} rejected the output", self.url))?;
+        Ok(batch.records())
+    }
+}
+
+/// `redb:` upserts nodes and edges into fire-marshal's database; `redb://graphs/ad.redb`
+/// uses another database file under the output root
+enum GraphStoreSink {
+    Shared(Arc<Database>),
+    File(PathBuf),
+}
+
+impl GraphStoreSink {
+    fn build(
+        destination: &PipelineUri,
+        resources: &SinkResources,
+    ) -> Result<Box<dyn Sink>, SinkError> {
+        Ok(Box::new(match destination.target.as_str() {
+            "" | "graph" => GraphStoreSink::Shared(resources.db.clone()),
+            path => {
+                GraphStoreSink::File(under_root(&resources.output_root, path).ok_or_else(|| {
+                    SinkError::invalid(
+                        destination,
+                        "expects a relative path inside the output directory",
+                    )
+                })?)
+            }
+        }))
+    }
+}
+
+#[async_trait]
+impl Sink for GraphStoreSink {
+    async fn deliver(&self, batch: &OutputBatch<'_>) -> Result<usize> {
+        let data = batch.data.clone();
+        // Opening a store file is blocking I/O and waits for its lock, so it runs with the write
+        let write = match self {
+            GraphStoreSink::Shared(db) => {
+                let db = db.clone();
+                tokio::task::spawn_blocking(move || write_graph(&db, &data))
+            }
+            GraphStoreSink::File(path) => {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || {
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)
+                            .with_context(|| format!("Failed to create {:?}", parent))?;
+                    }
+                    let db = Database::create(&path)
+                        .with_context(|| format!("Failed to open graph store {:?}", path))?;
+                    write_graph(&db, &data)
+                })
+            }
+        };
+
+        write.await??;
+        Ok(batch.records())
+    }
+}
+
+/// Upsert nodes and edges into the graph tables
+pub fn write_graph(db: &Database, data: &ExtractedData) -> Result<()> {
+    let txn = db.begin_write()?;
+    {
+        let mut nodes = txn.open_table(GRAPH_NODES_TABLE)?;
+        for node in &data.nodes {
+            nodes.insert(node.id.as_str(), serde_json::to_vec(node)?.as_slice())?;
+        }
+        let mut edges = txn.open_table(GRAPH_EDGES_TABLE)?;
+        for edge in &data.edges {
+            let key = format!("{}|{}|{}", edge.source, edge.edge_type, edge.target);
+            edges.insert(key.as_str(), serde_json::to_vec(edge)?.as_slice())?;
+        }
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+/// `cryptex://extracted` catalogs the output per object and relationship kind in an existing
+/// cryptex store, the same way pyro's `/api/extract` does, under the store lock pyro takes
+struct CryptexSink {
+    path: PathBuf,
+}
+
+impl CryptexSink {
+    fn build(
+        destination: &PipelineUri,
+        resources: &SinkResources,
+    ) -> Result<Box<dyn Sink>, SinkError> {
+        let store = destination
+            .store_name()
+            .ok_or_else(|| SinkError::invalid(destination, "expects a cryptex store name"))?;
+        Ok(Box::new(Self {
+            path: resources.cryptex_root.join(store),
+        }))
+    }
+}
+
+#[async_trait]
+impl Sink for CryptexSink {
+    async fn deliver(&self, batch: &OutputBatch<'_>) -> Result<usize> {
+        // Stores are created (and owned) through pyro; never create one implicitly
+        if !self.path.is_dir() {
+            anyhow::bail!("Cryptex store {:?} does not exist", self.path);
+        }
+
+        let catalog = ExtractionCatalog::from_extracted(batch.data);
+        let path = self.path.clone();
+        let changes =
+            tokio::task::spawn_blocking(move || write_catalog(&catalog, &path, uuid::Uuid::nil()))
+                .await??;
+        Ok(changes.created.len() + changes.updated.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use pyro_core::data_extractor::BloodHoundExtractor;
+    use redb::ReadableTableMetadata;
+    use tempfile::TempDir;
+
+    fn resources(dir: &Path) -> SinkResources {
+        SinkResources {
+            node_red: Arc::new(RwLock::new(NodeRedBridge::new())),
+            db: Arc::new(Database::create(dir.join("fire_marshal.redb")).unwrap()),
+            http: reqwest::Client::new(),
+            output_root: dir.join("output"),
+            allowed_hosts: vec!["example.org".to_string()],
+            cryptex_root: dir.join("cryptex"),
+        }
+    }
+
+    fn data() -> ExtractedData {
+        BloodHoundExtractor::extract_from_json(&json!({
+            "meta": { "type": "users" },
+            "data": [
+                {
+                    "ObjectIdentifier": "u1",
+                    "Properties": { "name": "ALICE, \"admin\"" },
+                    "Rels": [{ "RelType": "MemberOf", "TargetObjectIdentifier": "g1" }]
+                },
+                { "ObjectIdentifier": "u2", "Properties": { "name": "BOB" } }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn batch(data: &ExtractedData) -> OutputBatch<'_> {
+        OutputBatch {
+            pipeline_id: "p1",
+            source: "inline",
+            data,
+        }
+    }
+
+    #[test]
+    fn test_resolve_by_scheme() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = SinkRegistry::new(resources(temp_dir.path()));
+
+        for ok in [
+            "file://out/{pipeline_id}.jsonl",
+            "file:out.csv",
+            "node-red:",
+            "node-red://bloodsniffer/out",
+            "https://example.org/hook",
+            "redb:",
+            "redb://graphs/ad.redb",
+            "cryptex://extracted",
+        ] {
+            assert!(registry.resolve(ok).is_ok(), "{}", ok);
+        }
+        assert!(matches!(
+            registry.resolve("s3://bucket/key"),
+            Err(SinkError::UnsupportedScheme(scheme)) if scheme == "s3"
+        ));
+        for invalid in [
+            "file://out.txt",
+            "file:///tmp/out.jsonl",
+            "file://out/../../escape.jsonl",
+            "redb:///data/graph.redb",
+            "redb://../graph.redb",
+            "cryptex://../main",
+            "http://",
+            "http://169.254.169.254/latest",
+        ] {
+            assert!(
+                matches!(
+                    registry.resolve(invalid),
+                    Err(SinkError::InvalidDestination { .. })
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_sinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = SinkRegistry::new(resources(temp_dir.path()));
+        let data = data();
+        let out = temp_dir.path().join("output").join("out");
+
+        for extension in ["jsonl", "csv", "parquet"] {
+            let destination = format!("file://out/{{pipeline_id}}.{}", extension);
+            let report = registry.deliver(&destination, &batch(&data)).await.unwrap();
+            assert_eq!(report.sink, "file");
+            assert_eq!(report.records, 3);
+        }
+
+        let jsonl = std::fs::read_to_string(out.join("p1.jsonl")).unwrap();
+        let first: Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert_eq!(first["record"], "node");
+        assert_eq!(first["type"], "User");
+        assert_eq!(jsonl.lines().count(), 3);
+
+        let csv = std::fs::read_to_string(out.join("p1.csv")).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "record,id,type,label,source,target,properties");
+        assert!(lines[1].starts_with("node,u1,User,\"ALICE, \"\"admin\"\"\",,,"));
+        assert_eq!(lines[3], "edge,,MemberOf,,u1,g1,\"{\"\"RelType\"\":\"\"MemberOf\"\",\"\"TargetObjectIdentifier\"\":\"\"g1\"\"}\"");
+
+        let parquet =
+            SerializedFileReader::new(std::fs::File::open(out.join("p1.parquet")).unwrap())
+                .unwrap();
+        assert_eq!(parquet.metadata().file_metadata().num_rows(), 3);
+
+        // A pipeline id cannot steer the file out of the output directory
+        let escape = OutputBatch {
+            pipeline_id: "../../escape",
+            ..batch(&data)
+        };
+        assert!(registry
+            .deliver("file://{pipeline_id}.jsonl", &escape)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_graph_and_cryptex_sinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let resources = resources(temp_dir.path());
+        let registry = SinkRegistry::new(resources.clone());
+        let data = data();
+
+        registry.deliver("redb:", &batch(&data)).await.unwrap();
+        registry.deliver("redb:", &batch(&data)).await.unwrap();
+        let txn = resources.db.begin_read().unwrap();
+        assert_eq!(txn.open_table(GRAPH_NODES_TABLE).unwrap().len().unwrap(), 2);
+        assert_eq!(txn.open_table(GRAPH_EDGES_TABLE).unwrap().len().unwrap(), 1);
+
+        registry
+            .deliver("redb://graphs/ad.redb", &batch(&data))
+            .await
+            .unwrap();
+        assert!(temp_dir.path().join("output/graphs/ad.redb").exists());
+
+        assert!(registry
+            .deliver("cryptex://extracted", &batch(&data))
+            .await
+            .is_err());
+        std::fs::create_dir_all(resources.cryptex_root.join("extracted")).unwrap();
+        let report = registry
+            .deliver("cryptex://extracted", &batch(&data))
+            .await
+            .unwrap();
+        assert_eq!(report.records, 4);
+    }
+}
@@ -0,0 +1,594 @@
+// Pipeline sources
+// Fetch the BloodHound documents a pipeline record refers to, with the source chosen by the
+// source URI scheme, so records can reference data instead of embedding it as `payload`
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use node_red_bridge::{NodeRedBridge, NodeRedMessage};
+use pyro_core::cryptex_tree::CryptexTree;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::uri::PipelineUri;
+
+/// Folder inside a watched directory that consumed files are moved to
+pub const CONSUMED_DIR: &str = "consumed";
+
+/// Object type given to cryptex functions read through a `cryptex://` source
+pub const CRYPTEX_OBJECT_TYPE: &str = "CryptexFunction";
+
+/// Why a source could not be resolved
+#[derive(Debug, thiserror::Error)]
+pub enum SourceError {
+    #[error("unsupported source scheme '{0}'")]
+    UnsupportedScheme(String),
+    #[error("invalid source '{uri}': {message}")]
+    InvalidSource { uri: String, message: String },
+}
+
+impl SourceError {
+    fn invalid(uri: &PipelineUri, message: impl Into<String>) -> Self {
+        SourceError::InvalidSource {
+            uri: uri.raw.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Where a pipeline's input comes from
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Fetch the input as BloodHound JSON documents
+    async fn fetch(&self) -> Result<Vec<Value>>;
+
+    /// Called once the run's output has been delivered, so consumed input is not read twice
+    async fn acknowledge(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Shared handles sources read through
+#[derive(Clone)]
+pub struct SourceResources {
+    pub node_red: Arc<RwLock<NodeRedBridge>>,
+    pub http: reqwest::Client,
+    /// Directory file and watch sources are resolved under
+    pub input_root: PathBuf,
+    /// Hosts http sources may fetch from
+    pub allowed_hosts: Vec<String>,
+    /// Directory holding pyro's cryptex stores
+    pub cryptex_root: PathBuf,
+}
+
+/// Builds a source for a source URI
+pub type SourceFactory = fn(&PipelineUri, &SourceResources) -> Result<Box<dyn Source>, SourceError>;
+
+/// Sources by URI scheme
+pub struct SourceRegistry {
+    factories: BTreeMap<String, SourceFactory>,
+    resources: SourceResources,
+}
+
+impl SourceRegistry {
+    /// Registry with the built-in sources
+    pub fn new(resources: SourceResources) -> Self {
+        let mut registry = Self {
+            factories: BTreeMap::new(),
+            resources,
+        };
+        registry.register("file", FileSource::build);
+        registry.register("watch", WatchSource::build);
+        registry.register("http", HttpSource::build);
+        registry.register("https", HttpSource::build);
+        registry.register("cryptex", CryptexSource::build);
+        registry.register("node-red", NodeRedSource::build);
+        registry
+    }
+
+    pub fn register(&mut self, scheme: &str, factory: SourceFactory) {
+        self.factories.insert(scheme.to_ascii_lowercase(), factory);
+    }
+
+    /// Source for a pipeline (dictionary: resolve_source)
+    /// Pseudocode: An embedded payload wins and the source string stays a label; otherwise pick
+    /// the source registered for the URI scheme
+    pub fn resolve(&self, source: &str, payload: &Value) -> Result<Box<dyn Source>, SourceError> {
+        if !payload.is_null() {
+            return Ok(Box::new(InlineSource(payload.clone())));
+        }
+
+        let uri = PipelineUri::parse(source);
+        let factory = self
+            .factories
+            .get(&uri.scheme)
+            .ok_or_else(|| SourceError::UnsupportedScheme(uri.scheme.clone()))?;
+        factory(&uri, &self.resources)
+    }
+}
+
+/// The payload embedded in the pipeline record
+struct InlineSource(Value);
+
+#[async_trait]
+impl Source for InlineSource {
+    async fn fetch(&self) -> Result<Vec<Value>> {
+        Ok(vec![self.0.clone()])
+    }
+}
+
+fn read_document(path: &Path) -> Result<Value> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_slice(&bytes).with_context(|| format!("Failed to parse {:?}", path))
+}
+
+/// `file://ad/users.json` or `file://sharphound/*.json` reads one file or every file matching a
+/// glob under the input root, in path order
+struct FileSource {
+    pattern: String,
+}
+
+impl FileSource {
+    fn build(
+        uri: &PipelineUri,
+        resources: &SourceResources,
+    ) -> Result<Box<dyn Source>, SourceError> {
+        if uri.target.is_empty() {
+            return Err(SourceError::invalid(uri, "expects a file path or glob"));
+        }
+        glob::Pattern::new(&uri.target).map_err(|e| SourceError::invalid(uri, e.to_string()))?;
+        let path = uri.path_under(&resources.input_root).ok_or_else(|| {
+            SourceError::invalid(uri, "expects a relative path inside the input directory")
+        })?;
+        // Glob characters in the input root itself must not widen the pattern
+        let root = glob::Pattern::escape(&resources.input_root.to_string_lossy());
+        let relative = path.strip_prefix(&resources.input_root).unwrap_or(&path);
+        Ok(Box::new(Self {
+            pattern: format!("{}/{}", root, relative.to_string_lossy()),
+        }))
+    }
+}
+
+#[async_trait]
+impl Source for FileSource {
+    async fn fetch(&self) -> Result<Vec<Value>> {
+        let pattern = self.pattern.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut paths = glob::glob(&pattern)?
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            if paths.is_empty() {
+                anyhow::bail!("No files match {}", pattern);
+            }
+            paths.sort();
+            paths.iter().map(|path| read_document(path)).collect()
+        })
+        .await?
+    }
+}
+
+/// `watch://incoming` reads every `*.json` file waiting in a directory under the input root and,
+/// once the run is delivered, moves them into its `consumed/` folder
+struct WatchSource {
+    dir: PathBuf,
+    fetched: Mutex<Vec<PathBuf>>,
+}
+
+impl WatchSource {
+    fn build(
+        uri: &PipelineUri,
+        resources: &SourceResources,
+    ) -> Result<Box<dyn Source>, SourceError> {
+        if uri.target.is_empty() {
+            return Err(SourceError::invalid(uri, "expects a directory"));
+        }
+        let dir = uri.path_under(&resources.input_root).ok_or_else(|| {
+            SourceError::invalid(uri, "expects a relative path inside the input directory")
+        })?;
+        Ok(Box::new(Self {
+            dir,
+            fetched: Mutex::new(Vec::new()),
+        }))
+    }
+}
+
+#[async_trait]
+impl Source for WatchSource {
+    async fn fetch(&self) -> Result<Vec<Value>> {
+        let dir = self.dir.clone();
+        let (paths, documents) = tokio::task::spawn_blocking(move || -> Result<_> {
+            let mut paths = Vec::new();
+            for entry in std::fs::read_dir(&dir)
+                .with_context(|| format!("Failed to read watched directory {:?}", dir))?
+            {
+                let path = entry?.path();
+                let is_json = path.extension().and_then(|e| e.to_str()) == Some("json");
+                if path.is_file() && is_json {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+            let documents = paths
+                .iter()
+                .map(|path| read_document(path))
+                .collect::<Result<Vec<_>>>()?;
+            Ok((paths, documents))
+        })
+        .await??;
+
+        *self.fetched.lock().await = paths;
+        Ok(documents)
+    }
+
+    async fn acknowledge(&self) -> Result<()> {
+        let paths = std::mem::take(&mut *self.fetched.lock().await);
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let consumed = self.dir.join(CONSUMED_DIR);
+        tokio::fs::create_dir_all(&consumed)
+            .await
+            .with_context(|| format!("Failed to create {:?}", consumed))?;
+        for path in paths {
+            if let Some(file_name) = path.file_name() {
+                let target = consumed.join(file_name);
+                tokio::fs::rename(&path, &target)
+                    .await
+                    .with_context(|| format!("Failed to move {:?} -> {:?}", path, target))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `https://example.org/export.json` fetches a document from a host listed in
+/// `pipeline.source_hosts` and expects a 2xx response
+struct HttpSource {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl HttpSource {
+    fn build(
+        uri: &PipelineUri,
+        resources: &SourceResources,
+    ) -> Result<Box<dyn Source>, SourceError> {
+        let url =
+            reqwest::Url::parse(&uri.raw).map_err(|e| SourceError::invalid(uri, e.to_string()))?;
+        let Some(host) = url.host_str() else {
+            return Err(SourceError::invalid(uri, "expects a host"));
+        };
+        let allowed = resources
+            .allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host));
+        if !allowed {
+            return Err(SourceError::invalid(
+                uri,
+                format!("host '{}' is not in pipeline.source_hosts", host),
+            ));
+        }
+        Ok(Box::new(Self {
+            http: resources.http.clone(),
+            url: uri.raw.clone(),
+        }))
+    }
+}
+
+#[async_trait]
+impl Source for HttpSource {
+    async fn fetch(&self) -> Result<Vec<Value>> {
+        let document = self
+            .http
+            .get(&self.url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch {}", self.url))?
+            .error_for_status()
+            .with_context(|| format!("Source {} rejected the request", self.url))?
+            .json::<Value>()
+            .await
+            .with_context(|| format!("Source {} did not return JSON", self.url))?;
+        Ok(vec![document])
+    }
+}
+
+/// `cryptex://bloodhound` reads every function of an existing cryptex store as a
+/// `CryptexFunction` object, with a `Contains` relationship to each child
+struct CryptexSource {
+    path: PathBuf,
+}
+
+impl CryptexSource {
+    fn build(
+        uri: &PipelineUri,
+        resources: &SourceResources,
+    ) -> Result<Box<dyn Source>, SourceError> {
+        let store = uri
+            .store_name()
+            .ok_or_else(|| SourceError::invalid(uri, "expects a cryptex store name"))?;
+        Ok(Box::new(Self {
+            path: resources.cryptex_root.join(store),
+        }))
+    }
+}
+
+#[async_trait]
+impl Source for CryptexSource {
+    async fn fetch(&self) -> Result<Vec<Value>> {
+        if !self.path.is_dir() {
+            anyhow::bail!("Cryptex store {:?} does not exist", self.path);
+        }
+
+        let path = self.path.clone();
+        let tree = tokio::task::spawn_blocking(move || CryptexTree::load(&path)).await??;
+        Ok(vec![cryptex_document(&tree)])
+    }
+}
+
+/// A cryptex tree as a BloodHound-style document
+fn cryptex_document(tree: &CryptexTree) -> Value {
+    let data: Vec<Value> = tree
+        .entries()
+        .map(|entry| {
+            let rels: Vec<Value> = entry
+                .children
+                .iter()
+                .map(|child| json!({ "RelType": "Contains", "TargetObjectIdentifier": child }))
+                .collect();
+            json!({
+                "ObjectIdentifier": entry.path_name,
+                "ObjectType": CRYPTEX_OBJECT_TYPE,
+                "Properties": {
+                    "name": entry.name,
+                    "function_code": entry.function_code,
+                    "pseudocode": entry.pseudocode,
+                },
+                "Rels": rels,
+            })
+        })
+        .collect();
+    json!({ "meta": { "type": "cryptex" }, "data": data })
+}
+
+/// `node-red:` takes every message waiting on the bridge's inbound channel;
+/// `node-red://bloodsniffer/upload` only those on that topic. Fetched messages are held until
+/// the run is acknowledged and go back onto the bridge when it never is
+struct NodeRedSource {
+    node_red: Arc<RwLock<NodeRedBridge>>,
+    topic: Option<String>,
+    fetched: Mutex<Vec<NodeRedMessage>>,
+}
+
+impl NodeRedSource {
+    fn build(
+        uri: &PipelineUri,
+        resources: &SourceResources,
+    ) -> Result<Box<dyn Source>, SourceError> {
+        let topic = match uri.target.trim_matches('/') {
+            "" => None,
+            topic => Some(topic.to_string()),
+        };
+        Ok(Box::new(Self {
+            node_red: resources.node_red.clone(),
+            topic,
+            fetched: Mutex::new(Vec::new()),
+        }))
+    }
+}
+
+#[async_trait]
+impl Source for NodeRedSource {
+    async fn fetch(&self) -> Result<Vec<Value>> {
+        let mut bridge = self.node_red.write().await;
+        let mut fetched = self.fetched.lock().await;
+        let mut skipped = Vec::new();
+        while let Some(message) = bridge.try_receive() {
+            match &self.topic {
+                Some(topic) if *topic != message.topic => skipped.push(message),
+                _ => fetched.push(message),
+            }
+        }
+
+        // Leave other topics' messages for their own pipelines
+        let sender = bridge.sender();
+        for message in skipped {
+            sender.send(message)?;
+        }
+        Ok(fetched
+            .iter()
+            .map(|message| message.payload.clone())
+            .collect())
+    }
+
+    async fn acknowledge(&self) -> Result<()> {
+        self.fetched.lock().await.clear();
+        Ok(())
+    }
+}
+
+impl Drop for NodeRedSource {
+    fn drop(&mut self) {
+        let held = std::mem::take(self.fetched.get_mut());
+        if held.is_empty() {
+            return;
+        }
+
+        // The run failed before delivering; queue the messages again for the next one
+        let requeue = |bridge: &NodeRedBridge| {
+            let sender = bridge.sender();
+            for message in held {
+                let _ = sender.send(message);
+            }
+        };
+        match self.node_red.try_read() {
+            Ok(bridge) => requeue(&bridge),
+            Err(_) => {
+                let node_red = self.node_red.clone();
+                if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                    runtime.spawn(async move { requeue(&*node_red.read().await) });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_red_bridge::NodeRedMessage;
+    use tempfile::TempDir;
+
+    fn resources(dir: &Path) -> SourceResources {
+        SourceResources {
+            node_red: Arc::new(RwLock::new(NodeRedBridge::new())),
+            http: reqwest::Client::new(),
+            input_root: dir.join("input"),
+            allowed_hosts: vec!["example.org".to_string()],
+            cryptex_root: dir.join("cryptex"),
+        }
+    }
+
+    fn users(name: &str) -> Value {
+        json!({
+            "meta": { "type": "users" },
+            "data": [{ "ObjectIdentifier": name, "Properties": { "name": name } }]
+        })
+    }
+
+    #[test]
+    fn test_resolve_by_scheme() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = SourceRegistry::new(resources(temp_dir.path()));
+
+        for ok in [
+            "file://ad/*.json",
+            "watch://incoming",
+            "https://example.org/export.json",
+            "cryptex://bloodhound",
+            "node-red:",
+            "node-red://bloodsniffer/upload",
+        ] {
+            assert!(registry.resolve(ok, &Value::Null).is_ok(), "{}", ok);
+        }
+
+        // An embedded payload keeps the source a free-form label
+        assert!(registry.resolve("sharphound upload", &users("u1")).is_ok());
+        assert!(matches!(
+            registry.resolve("s3://bucket/key", &Value::Null),
+            Err(SourceError::UnsupportedScheme(scheme)) if scheme == "s3"
+        ));
+        for invalid in [
+            "file:",
+            "file://ad/[.json",
+            "file:///data/*.json",
+            "file://../*.json",
+            "watch:///data/incoming",
+            "watch://incoming/../..",
+            "https://169.254.169.254/latest/meta-data",
+            "cryptex://../main",
+            "http://",
+        ] {
+            assert!(
+                matches!(
+                    registry.resolve(invalid, &Value::Null),
+                    Err(SourceError::InvalidSource { .. })
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_and_watch_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = SourceRegistry::new(resources(temp_dir.path()));
+        let incoming = temp_dir.path().join("input").join("incoming");
+        std::fs::create_dir_all(&incoming).unwrap();
+        for name in ["b", "a"] {
+            let path = incoming.join(format!("{}.json", name));
+            std::fs::write(path, users(name).to_string()).unwrap();
+        }
+        std::fs::write(incoming.join("notes.txt"), "ignored").unwrap();
+
+        let pattern = "file://incoming/*.json";
+        let documents = registry
+            .resolve(pattern, &Value::Null)
+            .unwrap()
+            .fetch()
+            .await
+            .unwrap();
+        assert_eq!(documents, vec![users("a"), users("b")]);
+
+        let source = registry
+            .resolve("file://none/*.json", &Value::Null)
+            .unwrap();
+        assert!(source.fetch().await.is_err());
+
+        let watch = "watch://incoming";
+        let source = registry.resolve(watch, &Value::Null).unwrap();
+        assert_eq!(source.fetch().await.unwrap().len(), 2);
+        source.acknowledge().await.unwrap();
+        assert!(incoming.join(CONSUMED_DIR).join("a.json").exists());
+        assert!(incoming.join("notes.txt").exists());
+
+        let source = registry.resolve(watch, &Value::Null).unwrap();
+        assert!(source.fetch().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cryptex_and_node_red_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let resources = resources(temp_dir.path());
+        let registry = SourceRegistry::new(resources.clone());
+
+        let source = registry
+            .resolve("cryptex://bloodhound", &Value::Null)
+            .unwrap();
+        assert!(source.fetch().await.is_err());
+
+        let mut tree = CryptexTree::default();
+        let root = tree.insert(None, "extract", "fn extract() {}", "").unwrap();
+        tree.insert(Some(&root), "parse", "fn parse() {}", "")
+            .unwrap();
+        let store = resources.cryptex_root.join("bloodhound");
+        std::fs::create_dir_all(&store).unwrap();
+        tree.save(&store).unwrap();
+
+        let documents = source.fetch().await.unwrap();
+        let data = documents[0]["data"].as_array().unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0]["ObjectType"], CRYPTEX_OBJECT_TYPE);
+
+        {
+            let bridge = resources.node_red.read().await;
+            let sender = bridge.sender();
+            sender
+                .send(NodeRedMessage::new("upload".to_string(), users("u1")))
+                .unwrap();
+            sender
+                .send(NodeRedMessage::new("other".to_string(), users("u2")))
+                .unwrap();
+        }
+        let source = registry.resolve("node-red://upload", &Value::Null).unwrap();
+        assert_eq!(source.fetch().await.unwrap(), vec![users("u1")]);
+
+        // A run that fails before acknowledging leaves its messages for the next one
+        drop(source);
+        let source = registry.resolve("node-red://upload", &Value::Null).unwrap();
+        assert_eq!(source.fetch().await.unwrap(), vec![users("u1")]);
+        source.acknowledge().await.unwrap();
+        drop(source);
+        let source = registry.resolve("node-red://upload", &Value::Null).unwrap();
+        assert!(source.fetch().await.unwrap().is_empty());
+
+        let source = registry.resolve("node-red:", &Value::Null).unwrap();
+        assert_eq!(source.fetch().await.unwrap(), vec![users("u2")]);
+    }
+}
@@ -0,0 +1,178 @@
+// Pipeline store
+// Persist orchestrator pipelines, their status transitions and run history in fire_marshal.redb
+// so they survive a restart
+
+use anyhow::{Context, Result};
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::orchestrator::{Pipeline, PipelineRunStats, PipelineStatus};
+
+/// Pipeline definitions and current status, keyed by pipeline id
+const PIPELINES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("pipelines");
+/// Run statistics, keyed by pipeline id and run number
+const PIPELINE_RUNS_TABLE: TableDefinition<(&str, u64), &[u8]> =
+    TableDefinition::new("pipeline_runs");
+/// Status transitions, keyed by pipeline id and sequence number
+const PIPELINE_TRANSITIONS_TABLE: TableDefinition<(&str, u64), &[u8]> =
+    TableDefinition::new("pipeline_transitions");
+
+/// One run of a pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunEntry {
+    pub run: u64,
+    #[serde(flatten)]
+    pub stats: PipelineRunStats,
+}
+
+/// A change of pipeline status; `from` is empty when the pipeline was created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusTransition {
+    pub from: Option<PipelineStatus>,
+    pub to: PipelineStatus,
+    pub at: i64,
+}
+
+/// Everything recorded for one pipeline, oldest first
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineHistory {
+    pub pipeline_id: String,
+    pub runs: Vec<RunEntry>,
+    pub transitions: Vec<StatusTransition>,
+}
+
+/// Orchestrator state in fire-marshal's database
+#[derive(Clone)]
+pub struct PipelineStore {
+    db: Arc<Database>,
+}
+
+impl PipelineStore {
+    /// Use `db`, creating the pipeline tables if needed
+    pub fn open(db: Arc<Database>) -> Result<Self> {
+        let write_txn = db
+            .begin_write()
+            .context("Failed to begin write transaction")?;
+        {
+            write_txn
+                .open_table(PIPELINES_TABLE)
+                .context("Failed to open pipelines table")?;
+            write_txn
+                .open_table(PIPELINE_RUNS_TABLE)
+                .context("Failed to open pipeline runs table")?;
+            write_txn
+                .open_table(PIPELINE_TRANSITIONS_TABLE)
+                .context("Failed to open pipeline transitions table")?;
+        }
+        write_txn.commit().context("Failed to commit transaction")?;
+
+        Ok(Self { db })
+    }
+
+    /// Every stored pipeline
+    pub fn load(&self) -> Result<Vec<Pipeline>> {
+        let read_txn = self
+            .db
+            .begin_read()
+            .context("Failed to begin read transaction")?;
+        let table = read_txn
+            .open_table(PIPELINES_TABLE)
+            .context("Failed to open pipelines table")?;
+
+        let mut pipelines = Vec::new();
+        for item in table.iter().context("Failed to create iterator")? {
+            let (key, value) = item.context("Failed to read item")?;
+            let pipeline: Pipeline = serde_json::from_slice(value.value())
+                .with_context(|| format!("Failed to parse pipeline {}", key.value()))?;
+            pipelines.push(pipeline);
+        }
+        Ok(pipelines)
+    }
+
+    /// Save a pipeline together with a new or updated run and a status transition, in one
+    /// transaction (dictionary: persist_pipeline)
+    /// Pseudocode: Overwrite the pipeline record, upsert the run under its run number and
+    /// append the transition after the pipeline's last one
+    pub fn save(
+        &self,
+        pipeline: &Pipeline,
+        run: Option<&RunEntry>,
+        transition: Option<&StatusTransition>,
+    ) -> Result<()> {
+        let id = pipeline.id.as_str();
+        let write_txn = self
+            .db
+            .begin_write()
+            .context("Failed to begin write transaction")?;
+        {
+            let mut pipelines = write_txn.open_table(PIPELINES_TABLE)?;
+            pipelines.insert(id, serde_json::to_vec(pipeline)?.as_slice())?;
+
+            if let Some(run) = run {
+                let mut runs = write_txn.open_table(PIPELINE_RUNS_TABLE)?;
+                runs.insert((id, run.run), serde_json::to_vec(run)?.as_slice())?;
+            }
+
+            if let Some(transition) = transition {
+                let mut transitions = write_txn.open_table(PIPELINE_TRANSITIONS_TABLE)?;
+                let next = match transitions.range((id, 0)..=(id, u64::MAX))?.next_back() {
+                    Some(last) => last?.0.value().1 + 1,
+                    None => 0,
+                };
+                transitions.insert((id, next), serde_json::to_vec(transition)?.as_slice())?;
+            }
+        }
+        write_txn
+            .commit()
+            .with_context(|| format!("Failed to persist pipeline {}", id))?;
+        Ok(())
+    }
+
+    /// Remove a pipeline with its runs and transitions
+    pub fn delete(&self, id: &str) -> Result<()> {
+        let write_txn = self
+            .db
+            .begin_write()
+            .context("Failed to begin write transaction")?;
+        {
+            write_txn.open_table(PIPELINES_TABLE)?.remove(id)?;
+            for definition in [PIPELINE_RUNS_TABLE, PIPELINE_TRANSITIONS_TABLE] {
+                let mut table = write_txn.open_table(definition)?;
+                table.retain_in((id, 0)..=(id, u64::MAX), |_, _| false)?;
+            }
+        }
+        write_txn
+            .commit()
+            .with_context(|| format!("Failed to delete pipeline {}", id))?;
+        Ok(())
+    }
+
+    /// Runs and status transitions recorded for a pipeline
+    pub fn history(&self, id: &str) -> Result<PipelineHistory> {
+        let read_txn = self
+            .db
+            .begin_read()
+            .context("Failed to begin read transaction")?;
+
+        let runs_table = read_txn.open_table(PIPELINE_RUNS_TABLE)?;
+        let mut runs = Vec::new();
+        for item in runs_table.range((id, 0)..=(id, u64::MAX))? {
+            let (_, value) = item?;
+            runs.push(serde_json::from_slice(value.value())?);
+        }
+
+        let transitions_table = read_txn.open_table(PIPELINE_TRANSITIONS_TABLE)?;
+        let mut transitions = Vec::new();
+        for item in transitions_table.range((id, 0)..=(id, u64::MAX))? {
+            let (_, value) = item?;
+            transitions.push(serde_json::from_slice(value.value())?);
+        }
+
+        Ok(PipelineHistory {
+            pipeline_id: id.to_string(),
+            runs,
+            transitions,
+        })
+    }
+}
@@ -0,0 +1,483 @@
+// Pipeline transformers
+// Named steps that reshape extracted data between source and destination, resolved from the
+// `name:arguments` strings listed in a pipeline record and run in order
+
+use pyro_core::data_extractor::{Edge, ExtractedData, Node};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::time::Instant;
+
+/// Replacement for redacted property values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Why a transformer chain could not be built or run
+#[derive(Debug, thiserror::Error)]
+pub enum TransformError {
+    #[error("unknown transformer '{0}'")]
+    Unknown(String),
+    #[error("transformer '{name}': {message}")]
+    InvalidArguments { name: String, message: String },
+}
+
+/// A transformer reference such as `rename:samaccountname=account,name=label`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransformerSpec {
+    pub name: String,
+    /// Comma separated arguments after the first `:`
+    pub args: Vec<String>,
+}
+
+impl TransformerSpec {
+    pub fn parse(spec: &str) -> Self {
+        let (name, args) = spec.split_once(':').unwrap_or((spec, ""));
+        TransformerSpec {
+            name: name.trim().to_ascii_lowercase(),
+            args: args
+                .split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    fn invalid(&self, message: impl Into<String>) -> TransformError {
+        TransformError::InvalidArguments {
+            name: self.name.clone(),
+            message: message.into(),
+        }
+    }
+
+    /// Arguments, which must not be empty
+    fn required_args(&self, what: &str) -> Result<Vec<String>, TransformError> {
+        if self.args.is_empty() {
+            return Err(self.invalid(format!("expects {}", what)));
+        }
+        Ok(self.args.clone())
+    }
+
+    /// `key=value` arguments
+    fn pairs(&self) -> Result<Vec<(String, String)>, TransformError> {
+        self.required_args("key=value arguments")?
+            .iter()
+            .map(|arg| {
+                arg.split_once('=')
+                    .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                    .filter(|(k, _)| !k.is_empty())
+                    .ok_or_else(|| self.invalid(format!("'{}' is not key=value", arg)))
+            })
+            .collect()
+    }
+}
+
+/// Pipeline details available to transformers
+#[derive(Debug, Clone)]
+pub struct TransformContext {
+    pub pipeline_id: String,
+    pub source: String,
+    pub started_at: i64,
+}
+
+/// One step of a transformer chain
+pub trait Transformer: Send + Sync {
+    fn apply(&self, data: &mut ExtractedData, context: &TransformContext);
+}
+
+/// Builds a transformer from its spec
+pub type TransformerFactory = fn(&TransformerSpec) -> Result<Box<dyn Transformer>, TransformError>;
+
+/// Counts before and after one transformer ran
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepStats {
+    pub transformer: String,
+    pub nodes_in: usize,
+    pub nodes_out: usize,
+    pub edges_in: usize,
+    pub edges_out: usize,
+    pub duration_ms: u64,
+}
+
+/// Transformers by name
+pub struct TransformerRegistry {
+    factories: BTreeMap<String, TransformerFactory>,
+}
+
+impl TransformerRegistry {
+    /// Registry with the built-in transformers
+    pub fn new() -> Self {
+        let mut registry = Self {
+            factories: BTreeMap::new(),
+        };
+        registry.register("filter", FilterNodeTypes::build);
+        registry.register("drop", DropProperties::build);
+        registry.register("rename", RenameProperties::build);
+        registry.register("redact", RedactProperties::build);
+        registry.register("dedupe", Dedupe::build);
+        registry.register("enrich", Enrich::build);
+        registry
+    }
+
+    pub fn register(&mut self, name: &str, factory: TransformerFactory) {
+        self.factories.insert(name.to_ascii_lowercase(), factory);
+    }
+
+    /// Resolve every spec of a pipeline into a chain, failing on the first bad one
+    pub fn chain(&self, specs: &[String]) -> Result<TransformChain, TransformError> {
+        let steps = specs
+            .iter()
+            .map(|raw| {
+                let spec = TransformerSpec::parse(raw);
+                let factory = self
+                    .factories
+                    .get(&spec.name)
+                    .ok_or_else(|| TransformError::Unknown(spec.name.clone()))?;
+                Ok((raw.clone(), factory(&spec)?))
+            })
+            .collect::<Result<_, TransformError>>()?;
+        Ok(TransformChain { steps })
+    }
+}
+
+impl Default for TransformerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Transformers in the order a pipeline declared them
+pub struct TransformChain {
+    steps: Vec<(String, Box<dyn Transformer>)>,
+}
+
+impl TransformChain {
+    /// Run each step in order (dictionary: run_transformers)
+    /// Pseudocode: Apply every transformer to the data, recording node and edge counts around
+    /// each step, then refresh the metadata totals
+    pub fn run(&self, data: &mut ExtractedData, context: &TransformContext) -> Vec<StepStats> {
+        let mut stats = Vec::with_capacity(self.steps.len());
+        for (spec, step) in &self.steps {
+            let (nodes_in, edges_in) = (data.nodes.len(), data.edges.len());
+            let started = Instant::now();
+            step.apply(data, context);
+            stats.push(StepStats {
+                transformer: spec.clone(),
+                nodes_in,
+                nodes_out: data.nodes.len(),
+                edges_in,
+                edges_out: data.edges.len(),
+                duration_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+
+        data.metadata.total_nodes = data.nodes.len();
+        data.metadata.total_edges = data.edges.len();
+        stats
+    }
+}
+
+/// Properties of every node and edge
+fn property_maps(data: &mut ExtractedData) -> impl Iterator<Item = &mut Map<String, Value>> {
+    data.nodes
+        .iter_mut()
+        .map(|n: &mut Node| &mut n.properties)
+        .chain(data.edges.iter_mut().map(|e: &mut Edge| &mut e.properties))
+        .filter_map(Value::as_object_mut)
+}
+
+/// `filter:User,Computer` keeps nodes of the listed types and the edges between kept nodes
+struct FilterNodeTypes {
+    types: HashSet<String>,
+}
+
+impl FilterNodeTypes {
+    fn build(spec: &TransformerSpec) -> Result<Box<dyn Transformer>, TransformError> {
+        Ok(Box::new(Self {
+            types: spec.required_args("node types")?.into_iter().collect(),
+        }))
+    }
+}
+
+impl Transformer for FilterNodeTypes {
+    fn apply(&self, data: &mut ExtractedData, _context: &TransformContext) {
+        let removed: HashSet<String> = data
+            .nodes
+            .iter()
+            .filter(|n| !self.types.contains(&n.node_type))
+            .map(|n| n.id.clone())
+            .collect();
+        data.nodes.retain(|n| !removed.contains(&n.id));
+        data.edges
+            .retain(|e| !removed.contains(&e.source) && !removed.contains(&e.target));
+    }
+}
+
+/// `drop:description,email` removes properties
+struct DropProperties {
+    properties: Vec<String>,
+}
+
+impl DropProperties {
+    fn build(spec: &TransformerSpec) -> Result<Box<dyn Transformer>, TransformError> {
+        Ok(Box::new(Self {
+            properties: spec.required_args("property names")?,
+        }))
+    }
+}
+
+impl Transformer for DropProperties {
+    fn apply(&self, data: &mut ExtractedData, _context: &TransformContext) {
+        for properties in property_maps(data) {
+            for name in &self.properties {
+                properties.remove(name);
+            }
+        }
+    }
+}
+
+/// `rename:samaccountname=account` renames properties
+struct RenameProperties {
+    renames: Vec<(String, String)>,
+}
+
+impl RenameProperties {
+    fn build(spec: &TransformerSpec) -> Result<Box<dyn Transformer>, TransformError> {
+        Ok(Box::new(Self {
+            renames: spec.pairs()?,
+        }))
+    }
+}
+
+impl Transformer for RenameProperties {
+    fn apply(&self, data: &mut ExtractedData, _context: &TransformContext) {
+        for properties in property_maps(data) {
+            for (from, to) in &self.renames {
+                if let Some(value) = properties.remove(from) {
+                    properties.insert(to.clone(), value);
+                }
+            }
+        }
+    }
+}
+
+/// `redact:email,homedirectory` replaces property values with a marker
+struct RedactProperties {
+    properties: Vec<String>,
+}
+
+impl RedactProperties {
+    fn build(spec: &TransformerSpec) -> Result<Box<dyn Transformer>, TransformError> {
+        Ok(Box::new(Self {
+            properties: spec.required_args("property names")?,
+        }))
+    }
+}
+
+impl Transformer for RedactProperties {
+    fn apply(&self, data: &mut ExtractedData, _context: &TransformContext) {
+        for properties in property_maps(data) {
+            for name in &self.properties {
+                if let Some(value) = properties.get_mut(name) {
+                    if !value.is_null() {
+                        *value = Value::String(REDACTED.to_string());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `dedupe` keeps the first node per id and the first edge per source, target and type;
+/// `dedupe:name` identifies nodes by a property instead
+struct Dedupe {
+    key: Option<String>,
+}
+
+impl Dedupe {
+    fn build(spec: &TransformerSpec) -> Result<Box<dyn Transformer>, TransformError> {
+        if spec.args.len() > 1 {
+            return Err(spec.invalid("expects at most one key property"));
+        }
+        Ok(Box::new(Self {
+            key: spec.args.first().cloned(),
+        }))
+    }
+}
+
+impl Transformer for Dedupe {
+    fn apply(&self, data: &mut ExtractedData, _context: &TransformContext) {
+        let mut seen = HashSet::new();
+        data.nodes.retain(|node| {
+            let key = match &self.key {
+                Some(property) => match node.properties.get(property) {
+                    Some(value) if !value.is_null() => value.to_string(),
+                    _ => return true,
+                },
+                None => node.id.clone(),
+            };
+            seen.insert((node.node_type.clone(), key))
+        });
+
+        let mut seen = HashSet::new();
+        data.edges.retain(|edge| {
+            seen.insert((
+                edge.source.clone(),
+                edge.target.clone(),
+                edge.edge_type.clone(),
+            ))
+        });
+    }
+}
+
+/// `enrich:tag=prod,pipeline=$pipeline_id` adds properties to every node; `$pipeline_id`,
+/// `$source` and `$now` are replaced with the pipeline's values
+struct Enrich {
+    properties: Vec<(String, String)>,
+}
+
+impl Enrich {
+    fn build(spec: &TransformerSpec) -> Result<Box<dyn Transformer>, TransformError> {
+        Ok(Box::new(Self {
+            properties: spec.pairs()?,
+        }))
+    }
+}
+
+impl Transformer for Enrich {
+    fn apply(&self, data: &mut ExtractedData, context: &TransformContext) {
+        let values: Vec<(&String, Value)> = self
+            .properties
+            .iter()
+            .map(|(key, value)| {
+                let value = match value.as_str() {
+                    "$pipeline_id" => Value::from(context.pipeline_id.clone()),
+                    "$source" => Value::from(context.source.clone()),
+                    "$now" => Value::from(context.started_at),
+                    _ => Value::from(value.clone()),
+                };
+                (key, value)
+            })
+            .collect();
+
+        for node in &mut data.nodes {
+            if node.properties.is_null() {
+                node.properties = Value::Object(Map::new());
+            }
+            if let Some(properties) = node.properties.as_object_mut() {
+                for (key, value) in &values {
+                    properties.insert((*key).clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyro_core::data_extractor::BloodHoundExtractor;
+    use serde_json::json;
+
+    fn sample() -> ExtractedData {
+        BloodHoundExtractor::extract_from_json(&json!({
+            "data": [
+                {
+                    "ObjectIdentifier": "u1",
+                    "ObjectType": "User",
+                    "Properties": { "name": "ALICE", "email": "alice@corp.local", "samaccountname": "alice" },
+                    "Rels": [{ "RelType": "MemberOf", "TargetObjectIdentifier": "g1" }]
+                },
+                {
+                    "ObjectIdentifier": "u1",
+                    "ObjectType": "User",
+                    "Properties": { "name": "ALICE", "email": "alice@corp.local", "samaccountname": "alice" },
+                    "Rels": [{ "RelType": "MemberOf", "TargetObjectIdentifier": "g1" }]
+                },
+                {
+                    "ObjectIdentifier": "c1",
+                    "ObjectType": "Computer",
+                    "Properties": { "name": "WS01" },
+                    "Rels": [{ "RelType": "HasSession", "TargetObjectIdentifier": "u1" }]
+                },
+                {
+                    "ObjectIdentifier": "g1",
+                    "ObjectType": "Group",
+                    "Properties": { "name": "ADMINS" }
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn context() -> TransformContext {
+        TransformContext {
+            pipeline_id: "p1".to_string(),
+            source: "inline".to_string(),
+            started_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_chain_runs_in_order_with_stats() {
+        let registry = TransformerRegistry::new();
+        let chain = registry
+            .chain(&[
+                "dedupe".to_string(),
+                "filter:User,Group".to_string(),
+                "rename:samaccountname=account".to_string(),
+                "redact:email".to_string(),
+                "drop:name".to_string(),
+                "enrich:pipeline=$pipeline_id,env=lab".to_string(),
+            ])
+            .unwrap();
+
+        let mut data = sample();
+        let stats = chain.run(&mut data, &context());
+
+        let counts: Vec<(usize, usize, usize, usize)> = stats
+            .iter()
+            .map(|s| (s.nodes_in, s.nodes_out, s.edges_in, s.edges_out))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                (4, 3, 3, 2),
+                (3, 2, 2, 1),
+                (2, 2, 1, 1),
+                (2, 2, 1, 1),
+                (2, 2, 1, 1),
+                (2, 2, 1, 1)
+            ]
+        );
+        assert_eq!(stats[1].transformer, "filter:User,Group");
+        assert_eq!(data.metadata.total_nodes, 2);
+
+        let user = &data.nodes[0].properties;
+        assert_eq!(
+            user,
+            &json!({
+                "account": "alice",
+                "email": REDACTED,
+                "pipeline": "p1",
+                "env": "lab"
+            })
+        );
+    }
+
+    #[test]
+    fn test_chain_rejects_bad_specs() {
+        let registry = TransformerRegistry::new();
+        assert!(matches!(
+            registry.chain(&["decrypt".to_string()]),
+            Err(TransformError::Unknown(name)) if name == "decrypt"
+        ));
+        assert!(matches!(
+            registry.chain(&["rename:account".to_string()]),
+            Err(TransformError::InvalidArguments { .. })
+        ));
+        assert!(matches!(
+            registry.chain(&["filter".to_string()]),
+            Err(TransformError::InvalidArguments { .. })
+        ));
+    }
+}
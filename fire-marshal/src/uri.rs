@@ -0,0 +1,50 @@
+// Pipeline URIs
+// Source and destination URIs split into scheme and target, with the path and cryptex store
+// checks sources and sinks share
+
+use std::path::{Component, Path, PathBuf};
+
+/// A source or destination URI split into scheme and the rest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineUri {
+    pub raw: String,
+    pub scheme: String,
+    /// Everything after `scheme:` with a leading `//` removed
+    pub target: String,
+}
+
+impl PipelineUri {
+    pub fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        let (scheme, target) = raw.split_once(':').unwrap_or((raw, ""));
+        PipelineUri {
+            raw: raw.to_string(),
+            scheme: scheme.to_ascii_lowercase(),
+            target: target.strip_prefix("//").unwrap_or(target).to_string(),
+        }
+    }
+
+    /// The target as a path inside `root`; see [`under_root`]
+    pub fn path_under(&self, root: &Path) -> Option<PathBuf> {
+        under_root(root, &self.target)
+    }
+
+    /// The cryptex store the target names, which may only hold letters, digits, `_` and `-`
+    pub fn store_name(&self) -> Option<&str> {
+        let store = self.target.trim_matches('/');
+        let valid = !store.is_empty()
+            && store
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        valid.then_some(store)
+    }
+}
+
+/// `relative` joined onto `root`, or `None` when it is empty, absolute or climbs out with `..`
+pub fn under_root(root: &Path, relative: &str) -> Option<PathBuf> {
+    let path = Path::new(relative);
+    let contained = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    (contained && !relative.is_empty()).then(|| root.join(path))
+}
@@ -0,0 +1,98 @@
+// Pipeline queue watcher
+// Wake the datapipe workers as soon as a pipeline file lands in the queue directory instead of
+// waiting for the next directory scan
+
+use anyhow::{Context, Result};
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration};
+
+/// Quiet period after the last change before queued files are picked up, so a burst of files
+/// or a file still being written is handled in one scan
+pub const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Change notifications for the pipeline queue directory
+pub struct QueueWatcher {
+    // Dropping the watcher ends the notifications
+    _watcher: RecommendedWatcher,
+    arrivals: mpsc::UnboundedReceiver<()>,
+}
+
+impl QueueWatcher {
+    /// Watch `queue_dir`; fails where the platform or filesystem has no change notifications
+    pub fn new(queue_dir: &Path) -> Result<Self> {
+        let (tx, arrivals) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            if event.is_ok_and(|event| is_arrival(&event)) {
+                let _ = tx.send(());
+            }
+        })
+        .context("Failed to create pipeline queue watcher")?;
+        watcher
+            .watch(queue_dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {:?}", queue_dir))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            arrivals,
+        })
+    }
+
+    /// Wait until pipeline files arrived and no further change followed for `DEBOUNCE`;
+    /// false once the watcher stopped delivering notifications
+    pub async fn arrived(&mut self) -> bool {
+        if self.arrivals.recv().await.is_none() {
+            return false;
+        }
+        loop {
+            match timeout(DEBOUNCE, self.arrivals.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return false,
+                Err(_) => return true,
+            }
+        }
+    }
+}
+
+/// Whether an event may have put a pipeline file in the queue: created, renamed into place or
+/// written by a producer that does not rename. Files claimed by workers leave with a rename
+/// that is not an arrival
+fn is_arrival(event: &Event) -> bool {
+    let relevant = matches!(
+        event.kind,
+        EventKind::Create(_)
+            | EventKind::Modify(ModifyKind::Name(
+                RenameMode::To | RenameMode::Both | RenameMode::Any
+            ))
+            | EventKind::Modify(ModifyKind::Data(_))
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    );
+    relevant
+        && event
+            .paths
+            .iter()
+            .any(|path| path.extension().and_then(|e| e.to_str()) == Some("json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_renamed_pipeline_file_wakes_watcher() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut watcher = QueueWatcher::new(temp_dir.path()).unwrap();
+
+        // Writes under a temporary name alone do not count
+        let temp = temp_dir.path().join(".p1.json.tmp");
+        std::fs::write(&temp, "{}").unwrap();
+        assert!(timeout(DEBOUNCE * 4, watcher.arrived()).await.is_err());
+
+        std::fs::rename(&temp, temp_dir.path().join("p1.json")).unwrap();
+        let arrived = timeout(Duration::from_secs(5), watcher.arrived()).await;
+        assert_eq!(arrived.ok(), Some(true));
+    }
+}
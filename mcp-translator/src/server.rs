@@ -1,4 +1,6 @@
 use anyhow::{Context, Result};
+use pyro_core::cryptex_bundle::{export_cryptex, import_cryptex, ConflictPolicy};
+use pyro_core::cryptex_codegen::generate_crate_tarball;
 use serde_json::{json, Value};
 use std::io::{self, BufRead, BufReader, Write};
 
@@ -72,6 +74,9 @@ impl McpTranslatorServer {
             "get_roadmap" => self.handle_get_roadmap(params).await?,
             "get_implementation_plan" => self.handle_get_implementation_plan(params).await?,
             "update_task_status" => self.handle_update_task_status(params).await?,
+            "export_cryptex" => self.handle_export_cryptex(params).await?,
+            "import_cryptex" => self.handle_import_cryptex(params).await?,
+            "generate_crate" => self.handle_generate_crate(params).await?,
             _ => json!({
                 "error": format!("Unknown method: {}", method)
             }),
@@ -352,4 +357,76 @@ impl McpTranslatorServer {
             }
         }))
     }
+
+    /// Handle export_cryptex request - writes a cryptex (or subtree) to a bundle file
+    pub async fn handle_export_cryptex(&mut self, params: &Value) -> Result<Value> {
+        let cryptex_path = params["cryptex_path"]
+            .as_str()
+            .context("Missing cryptex_path")?;
+        let output_path = params["output_path"]
+            .as_str()
+            .context("Missing output_path")?;
+        let root = params.get("root").and_then(|v| v.as_str());
+
+        let manifest = export_cryptex(cryptex_path, root, output_path)?;
+
+        Ok(json!({
+            "success": true,
+            "bundle_path": output_path,
+            "functions_exported": manifest.functions.len(),
+            "manifest": manifest,
+        }))
+    }
+
+    /// Handle import_cryptex request - merges a bundle file into a cryptex
+    pub async fn handle_import_cryptex(&mut self, params: &Value) -> Result<Value> {
+        let cryptex_path = params["cryptex_path"]
+            .as_str()
+            .context("Missing cryptex_path")?;
+        let bundle_path = params["bundle_path"]
+            .as_str()
+            .context("Missing bundle_path")?;
+        let parent = params.get("parent").and_then(|v| v.as_str());
+        let conflict: ConflictPolicy = match params.get("conflict") {
+            Some(conflict) => serde_json::from_value(conflict.clone())
+                .context("Invalid conflict policy (expected skip, overwrite or rename)")?,
+            None => ConflictPolicy::default(),
+        };
+
+        let report = import_cryptex(cryptex_path, bundle_path, parent, conflict)?;
+
+        Ok(json!({
+            "success": true,
+            "cryptex_path": cryptex_path,
+            "functions_created": report.created.len(),
+            "functions_overwritten": report.overwritten.len(),
+            "functions_skipped": report.skipped.len(),
+            "report": report,
+        }))
+    }
+
+    /// Handle generate_crate request - writes a Rust crate generated from a cryptex as a tarball
+    pub async fn handle_generate_crate(&mut self, params: &Value) -> Result<Value> {
+        let cryptex_path = params["cryptex_path"]
+            .as_str()
+            .context("Missing cryptex_path")?;
+        let output_path = params["output_path"]
+            .as_str()
+            .context("Missing output_path")?;
+        let crate_name = params
+            .get("crate_name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("cryptex");
+        let root = params.get("root").and_then(|v| v.as_str());
+
+        let generated = generate_crate_tarball(cryptex_path, crate_name, root, output_path)?;
+
+        Ok(json!({
+            "success": true,
+            "crate_name": generated.name,
+            "tarball_path": output_path,
+            "files": generated.files.keys().collect::<Vec<_>>(),
+            "stubbed_functions": generated.stubbed,
+        }))
+    }
 }
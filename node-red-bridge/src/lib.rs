@@ -112,6 +112,11 @@ impl NodeRedBridge {
         }
     }
 
+    /// Receive a queued message without waiting
+    pub fn try_receive(&mut self) -> Option<NodeRedMessage> {
+        self.rx.as_mut()?.try_recv().ok()
+    }
+
     /// Get message sender
     pub fn sender(&self) -> mpsc::UnboundedSender<NodeRedMessage> {
         self.tx.clone()
@@ -193,6 +198,19 @@ mod tests {
         assert_eq!(parsed.topic, msg.topic);
     }
 
+    #[test]
+    fn test_try_receive() {
+        let mut bridge = NodeRedBridge::new();
+        assert!(bridge.try_receive().is_none());
+
+        bridge
+            .sender()
+            .send(NodeRedMessage::new("inbound".to_string(), json!({"data": []})))
+            .unwrap();
+        assert_eq!(bridge.try_receive().unwrap().topic, "inbound");
+        assert!(bridge.try_receive().is_none());
+    }
+
     #[test]
     fn test_pipeline_builder() {
         let pipeline = PipelineBuilder::new("cryptex".to_string())
@@ -0,0 +1,207 @@
+// Cryptex bundle handlers
+// Export a store (or subtree) as a portable archive, merge archives into a store and
+// generate a Rust crate from a store
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::middleware::AuthContext;
+use super::state::AppState;
+use super::stores::{resolve_cryptex_store, StoreAccess, StoreQuery};
+use crate::database::CryptexStore;
+use pyro_core::cryptex_bundle::{
+    export_bundle, import_bundle, read_bundle, BundleManifest, ConflictPolicy, ImportReport,
+};
+use pyro_core::cryptex_codegen::{crate_name, generate_crate};
+use pyro_core::cryptex_history::{CryptexHistory, RevisionAction};
+use pyro_core::cryptex_tree::{CryptexTree, FunctionEntry, StoreLock};
+
+/// Largest bundle accepted by the import route
+pub const MAX_BUNDLE_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportBundleQuery {
+    /// Export only this function and its descendants
+    pub root: Option<String>,
+}
+
+/// Export a cryptex store, or one subtree of it, as a `.cryptex.tar.gz` bundle
+pub async fn export_cryptex_bundle(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Query(query): Query<ExportBundleQuery>,
+) -> Result<Response, StatusCode> {
+    let store_name = store.name_or(CryptexStore::DEFAULT);
+    let cryptex_path = resolve_cryptex_store(&state, &auth, store_name, StoreAccess::Use)?;
+    let tree = CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(root) = &query.root {
+        tree.get(root).ok_or(StatusCode::NOT_FOUND)?;
+    }
+
+    let mut archive = Vec::new();
+    export_bundle(&tree, query.root.as_deref(), &mut archive)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.cryptex.tar.gz\"", store_name),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportBundleQuery {
+    /// Function to import the bundle roots under; the store root when absent
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportBundleResponse {
+    pub manifest: BundleManifest,
+    pub report: ImportReport,
+    /// Existing functions whose path name changed when the cryptex was rewritten (old -> new)
+    pub renamed: BTreeMap<String, String>,
+    pub message: String,
+}
+
+/// Merge an uploaded bundle into a cryptex store
+pub async fn import_cryptex_bundle(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Query(query): Query<ImportBundleQuery>,
+    body: Bytes,
+) -> Result<Json<ImportBundleResponse>, StatusCode> {
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
+    let bundle = read_bundle(body.as_ref()).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let _guard = state.cryptex_lock.lock().await;
+    let _store =
+        StoreLock::acquire(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut tree =
+        CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut history =
+        CryptexHistory::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(parent) = &query.parent {
+        tree.get(parent).ok_or(StatusCode::NOT_FOUND)?;
+    }
+
+    let before = tree.clone();
+    let mut report = import_bundle(&mut tree, &bundle, query.parent.as_deref(), query.conflict)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for path in &report.overwritten {
+        if let Some(entry) = before.get(path) {
+            history.ensure_baseline(entry);
+        }
+    }
+
+    let renamed = tree
+        .save(&cryptex_path)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Record imported content as revisions under the final path names
+    history.rename_paths(&renamed);
+    let changes = report
+        .created
+        .iter()
+        .map(|path| (path, RevisionAction::Create))
+        .chain(
+            report
+                .overwritten
+                .iter()
+                .map(|path| (path, RevisionAction::Update)),
+        );
+    for (path, action) in changes {
+        if let Some(entry) = tree.get(path) {
+            let entry = FunctionEntry {
+                path_name: renamed.get(path).unwrap_or(path).clone(),
+                ..entry.clone()
+            };
+            history.record(&entry, action, auth.user_id);
+        }
+    }
+    history
+        .save()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    report.apply_renames(&renamed);
+
+    Ok(Json(ImportBundleResponse {
+        message: format!(
+            "Imported {} function(s): {} created, {} overwritten, {} skipped",
+            bundle.functions.len(),
+            report.created.len(),
+            report.overwritten.len(),
+            report.skipped.len()
+        ),
+        manifest: bundle.manifest,
+        report,
+        renamed,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateCrateQuery {
+    /// Generate only this function and its descendants
+    pub root: Option<String>,
+    /// Crate name; defaults to the store name
+    pub name: Option<String>,
+}
+
+/// Generate a Rust crate from a cryptex store and return it as a `.tar.gz`
+pub async fn generate_cryptex_crate(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Query(query): Query<GenerateCrateQuery>,
+) -> Result<Response, StatusCode> {
+    let store_name = store.name_or(CryptexStore::DEFAULT);
+    let cryptex_path = resolve_cryptex_store(&state, &auth, store_name, StoreAccess::Use)?;
+    let tree = CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(root) = &query.root {
+        tree.get(root).ok_or(StatusCode::NOT_FOUND)?;
+    }
+
+    let name = query.name.as_deref().unwrap_or(store_name);
+    let generated = generate_crate(&tree, name, query.root.as_deref())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut archive = Vec::new();
+    generated
+        .write_tarball(&mut archive)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.tar.gz\"", crate_name(name)),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
@@ -1,17 +1,41 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::{IntoResponse, Json},
+    response::{IntoResponse, Json, Response},
+    Extension,
 };
 use cryptex::Cryptex;
 use node_red_bridge::NodeRedMessage;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value};
+use std::collections::BTreeMap;
 
+pub use super::bundles::{export_cryptex_bundle, generate_cryptex_crate, import_cryptex_bundle};
+pub use super::history::{get_cryptex_revisions, revert_cryptex};
+use super::middleware::AuthContext;
+use super::pipelines;
+pub use super::pipelines::{get_pipeline_status, pipeline_status_callback};
 use super::state::AppState;
+pub use super::stores::{
+    create_cryptex_store, delete_cryptex_store, get_cryptex_store, list_cryptex_stores,
+    update_cryptex_store,
+};
+use super::stores::{resolve_cryptex_store, StoreAccess, StoreQuery};
 pub use crate::auth::handlers::{api_login_with_secret, api_logout, api_validate_session};
-use crate::data_extractor::BloodHoundExtractor;
-use pyro_core::pipeline::{PipelineRecord, PipelineRegistry};
+use crate::database::CryptexStore;
+use pyro_core::cryptex_history::{CryptexHistory, RevisionAction};
+use pyro_core::cryptex_tree::{
+    CryptexEditError, CryptexTree, DeleteMode, FunctionEntry, FunctionUpdate, StoreLock,
+};
+use pyro_core::data_extractor::BloodHoundExtractor;
+use pyro_core::extraction_catalog::{write_catalog, ExtractionCatalog};
+use pyro_core::handoff::{RunStatus, StatusUpdate, SubmitError};
+use pyro_core::node_schema::ValidationReport;
+use pyro_core::pagination::Pagination;
+use pyro_core::pipeline::{
+    is_valid_pipeline_id, PipelineRecord, PipelineRegistry, PipelineSchedule,
+};
+use pyro_core::pipeline_graph::PipelineGraph;
 
 /// Root endpoint - display Pyro info
 pub async fn root() -> impl IntoResponse {
@@ -21,10 +45,24 @@ pub async fn root() -> impl IntoResponse {
         "tagline": "No gods, no masters, only autonomous systems",
         "endpoints": [
             "GET /health",
-            "POST /api/cryptex",
-            "GET /api/cryptex/:path",
-            "POST /api/extract",
+            "GET /api/cryptex-stores",
+            "POST /api/cryptex-stores",
+            "GET /api/cryptex-stores/{name}",
+            "PUT /api/cryptex-stores/{name}",
+            "DELETE /api/cryptex-stores/{name}",
+            "GET /api/cryptex?store=&q=&offset=&limit=",
+            "POST /api/cryptex?store=",
+            "GET /api/cryptex/{path}?store=&view=node|subtree|ancestors&depth=",
+            "PUT /api/cryptex/{path}?store=",
+            "DELETE /api/cryptex/{path}?store=&mode=reject|cascade",
+            "GET /api/cryptex-revisions/{path}?store=&revision=&from=&to=",
+            "POST /api/cryptex-revisions/{path}?store=",
+            "GET /api/cryptex-bundle?store=&root=",
+            "POST /api/cryptex-bundle?store=&parent=&conflict=skip|overwrite|rename",
+            "GET /api/cryptex-crate?store=&root=&name=",
+            "POST /api/extract?store=",
             "POST /api/pipeline",
+            "GET /api/pipeline/{id}",
         ]
     }))
 }
@@ -49,16 +87,28 @@ pub struct CreateCryptexRequest {
 pub struct CreateCryptexResponse {
     pub path_name: String,
     pub name: String,
+    /// Revision recorded for the new function
+    pub revision: u64,
     pub message: String,
 }
 
 /// Create new function in Cryptex structure
 pub async fn create_cryptex(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
     Json(req): Json<CreateCryptexRequest>,
 ) -> Result<Json<CreateCryptexResponse>, StatusCode> {
     // Create or load Cryptex
-    let cryptex_path = state.cryptex_root.join("main");
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
+    let _guard = state.cryptex_lock.lock().await;
+    let _store =
+        StoreLock::acquire(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     let mut cryptex = Cryptex::new(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Add function to cryptex
@@ -71,9 +121,28 @@ pub async fn create_cryptex(
         )
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(CreateCryptexResponse {
-        path_name: node.path_name.clone(),
+    // Record the first revision of the function
+    let entry = FunctionEntry {
         name: node.name.clone(),
+        path_name: node.path_name.clone(),
+        parent: node.parent.clone(),
+        children: node.children.clone(),
+        function_code: node.function_code.clone(),
+        pseudocode: node.pseudocode.clone(),
+    };
+    let mut history =
+        CryptexHistory::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let revision = history
+        .record(&entry, RevisionAction::Create, auth.user_id)
+        .map_or(0, |r| r.revision);
+    history
+        .save()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(CreateCryptexResponse {
+        path_name: entry.path_name,
+        name: entry.name,
+        revision,
         message: "Function added to cryptex structure successfully".to_string(),
     }))
 }
@@ -88,26 +157,310 @@ pub struct GetCryptexResponse {
     pub pseudocode: String,
 }
 
+impl From<&FunctionEntry> for GetCryptexResponse {
+    fn from(entry: &FunctionEntry) -> Self {
+        Self {
+            name: entry.name.clone(),
+            path_name: entry.path_name.clone(),
+            parent: entry.parent.clone(),
+            children: entry.children.clone(),
+            function_code: entry.function_code.clone(),
+            pseudocode: entry.pseudocode.clone(),
+        }
+    }
+}
+
+/// How `GET /api/cryptex/{path}` presents a function
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CryptexView {
+    /// The function itself
+    #[default]
+    Node,
+    /// The function and its descendants, optionally limited by `depth`
+    Subtree,
+    /// The chain of parents from the root down to the function
+    Ancestors,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetCryptexQuery {
+    #[serde(default)]
+    pub view: CryptexView,
+    pub depth: Option<usize>,
+}
+
 /// Get function from Cryptex by path name
 pub async fn get_cryptex(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
     Path(path_name): Path<String>,
-) -> Result<Json<GetCryptexResponse>, StatusCode> {
-    let cryptex_path = state.cryptex_root.join("main");
-    let cryptex = Cryptex::new(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Query(query): Query<GetCryptexQuery>,
+) -> Result<Response, StatusCode> {
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
 
-    let node = cryptex
-        .get_function(&path_name)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
-
-    Ok(Json(GetCryptexResponse {
-        name: node.name,
-        path_name: node.path_name,
-        parent: node.parent,
-        children: node.children,
-        function_code: node.function_code,
-        pseudocode: node.pseudocode,
+    if let CryptexView::Node = query.view {
+        let cryptex = Cryptex::new(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let node = cryptex
+            .get_function(&path_name)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        return Ok(Json(GetCryptexResponse {
+            name: node.name,
+            path_name: node.path_name,
+            parent: node.parent,
+            children: node.children,
+            function_code: node.function_code,
+            pseudocode: node.pseudocode,
+        })
+        .into_response());
+    }
+
+    let tree = CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match query.view {
+        CryptexView::Subtree => {
+            let subtree = tree
+                .subtree(&path_name, query.depth)
+                .ok_or(StatusCode::NOT_FOUND)?;
+            Ok(Json(subtree).into_response())
+        }
+        _ => {
+            let ancestors: Vec<GetCryptexResponse> = tree
+                .ancestors(&path_name)
+                .ok_or(StatusCode::NOT_FOUND)?
+                .into_iter()
+                .map(GetCryptexResponse::from)
+                .collect();
+            Ok(Json(ancestors).into_response())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListCryptexQuery {
+    /// Full-text search over name, function code and pseudocode
+    pub q: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CryptexSummary {
+    pub name: String,
+    pub path_name: String,
+    pub parent: Option<String>,
+    pub children: Vec<String>,
+}
+
+/// List or search functions in the Cryptex structure, one page at a time
+pub async fn list_cryptex(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Query(query): Query<ListCryptexQuery>,
+) -> Result<Response, StatusCode> {
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
+    let tree = CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let pagination = Pagination {
+        offset: query.offset,
+        limit: query.limit,
+    };
+
+    match query.q.as_deref() {
+        Some(q) => Ok(Json(pagination.page(tree.search(q))).into_response()),
+        None => {
+            let summaries: Vec<CryptexSummary> = tree
+                .entries()
+                .map(|entry| CryptexSummary {
+                    name: entry.name.clone(),
+                    path_name: entry.path_name.clone(),
+                    parent: entry.parent.clone(),
+                    children: entry.children.clone(),
+                })
+                .collect();
+            Ok(Json(pagination.page(summaries)).into_response())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCryptexRequest {
+    pub name: Option<String>,
+    pub function_code: Option<String>,
+    pub pseudocode: Option<String>,
+    /// New parent path; `null` moves the function to the root, absent leaves it in place
+    #[serde(default, deserialize_with = "present")]
+    pub parent: Option<Option<String>>,
+}
+
+/// Distinguish an explicit `null` from a missing field
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize)]
+pub struct EditCryptexResponse {
+    /// Path of the edited function after the rewrite (absent after delete)
+    pub path_name: Option<String>,
+    /// Functions removed by a delete
+    pub removed: Vec<String>,
+    /// Functions whose path name changed when the cryptex was rewritten (old -> new)
+    pub renamed: BTreeMap<String, String>,
+    /// Revision recorded for the edited function, if its content changed
+    pub revision: Option<u64>,
+    pub message: String,
+}
+
+pub(super) fn edit_error_status(err: &CryptexEditError) -> StatusCode {
+    match err {
+        CryptexEditError::NotFound(_) => StatusCode::NOT_FOUND,
+        CryptexEditError::Cycle { .. }
+        | CryptexEditError::HasChildren { .. }
+        | CryptexEditError::DuplicateName { .. } => StatusCode::CONFLICT,
+        CryptexEditError::EmptyName => StatusCode::BAD_REQUEST,
+    }
+}
+
+/// Update and/or move a function in the Cryptex structure
+pub async fn update_cryptex(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Path(path_name): Path<String>,
+    Json(req): Json<UpdateCryptexRequest>,
+) -> Result<Json<EditCryptexResponse>, StatusCode> {
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
+    let _guard = state.cryptex_lock.lock().await;
+    let _store =
+        StoreLock::acquire(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut tree =
+        CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut history =
+        CryptexHistory::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Keep the content from before history was recorded
+    if let Some(entry) = tree.get(&path_name) {
+        history.ensure_baseline(entry);
+    }
+
+    tree.update(
+        &path_name,
+        FunctionUpdate {
+            name: req.name,
+            function_code: req.function_code,
+            pseudocode: req.pseudocode,
+        },
+    )
+    .map_err(|e| edit_error_status(&e))?;
+
+    if let Some(parent) = &req.parent {
+        tree.move_to(&path_name, parent.as_deref())
+            .map_err(|e| edit_error_status(&e))?;
+    }
+
+    let renamed = tree
+        .save(&cryptex_path)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let new_path = renamed.get(&path_name).unwrap_or(&path_name).clone();
+
+    history.rename_paths(&renamed);
+    let revision = tree.get(&path_name).and_then(|entry| {
+        let entry = FunctionEntry {
+            path_name: new_path.clone(),
+            ..entry.clone()
+        };
+        history
+            .record(&entry, RevisionAction::Update, auth.user_id)
+            .map(|r| r.revision)
+    });
+    history
+        .save()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EditCryptexResponse {
+        path_name: Some(new_path),
+        removed: Vec::new(),
+        renamed,
+        revision,
+        message: "Function updated successfully".to_string(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteCryptexQuery {
+    #[serde(default)]
+    pub mode: DeleteMode,
+}
+
+/// Delete a function (and optionally its descendants) from the Cryptex structure
+pub async fn delete_cryptex(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Path(path_name): Path<String>,
+    Query(query): Query<DeleteCryptexQuery>,
+) -> Result<Json<EditCryptexResponse>, StatusCode> {
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
+    let _guard = state.cryptex_lock.lock().await;
+    let _store =
+        StoreLock::acquire(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut tree =
+        CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut history =
+        CryptexHistory::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let before = tree.clone();
+    let removed = tree
+        .remove(&path_name, query.mode)
+        .map_err(|e| edit_error_status(&e))?;
+    let renamed = tree
+        .save(&cryptex_path)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Deleted functions keep their history, ending with the deleted content
+    for entry in removed.iter().filter_map(|path| before.get(path)) {
+        history.record(entry, RevisionAction::Delete, auth.user_id);
+    }
+    history.rename_paths(&renamed);
+    history
+        .save()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EditCryptexResponse {
+        path_name: None,
+        message: format!("Deleted {} function(s)", removed.len()),
+        removed,
+        renamed,
+        revision: None,
     }))
 }
 
@@ -121,70 +474,75 @@ pub struct ExtractResponse {
     pub nodes_count: usize,
     pub edges_count: usize,
     pub functions_created: Vec<String>,
+    pub functions_updated: Vec<String>,
+    /// Records per object kind in this extraction
+    pub object_kinds: BTreeMap<String, usize>,
+    /// Records per relationship kind in this extraction
+    pub relationship_kinds: BTreeMap<String, usize>,
+    /// Schema validation of the extracted nodes
+    pub validation: ValidationReport,
 }
 
-/// Extract data from BloodHound format and create functions in Cryptex
+/// Extract data from BloodHound format and catalog it in Cryptex
 pub async fn extract_data(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
     Json(req): Json<ExtractRequest>,
 ) -> Result<Json<ExtractResponse>, StatusCode> {
     // Extract data
-    let extracted =
+    let mut extracted =
         BloodHoundExtractor::extract_from_json(&req.data).map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    // Create Cryptex for extraction
-    let cryptex_path = state.cryptex_root.join("extracted");
-    let mut cryptex = Cryptex::new(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::EXTRACTED),
+        StoreAccess::Use,
+    )?;
 
-    let mut functions_created = Vec::new();
+    let _guard = state.cryptex_lock.lock().await;
+    let tree = CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Create function for parsing nodes
-    let nodes_code = format!(
-        "fn parse_nodes(data: &Value) -> Result<Vec<Node>> {{\n    // Parse nodes from BloodHound data\n    todo!()\n}}"
-    );
-    let nodes_pseudo = "Parse nodes from BloodHound JSON data structure".to_string();
-    let nodes_node = cryptex
-        .add_function(None, "parse_nodes".to_string(), nodes_code, nodes_pseudo)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    functions_created.push(nodes_node.path_name.clone());
+    // Validate against configured schemas, or schemas inferred from everything ingested so far
+    let stored = ExtractionCatalog::stored(&tree);
+    let mut ingested = ExtractionCatalog::from_extracted(&extracted);
+    for (kind, summary) in ingested.objects.iter_mut() {
+        if let Some(previous) = stored.objects.get(kind) {
+            summary.merge_previous(previous);
+        }
+    }
+    let mut schemas = state.schemas.as_ref().clone();
+    schemas.infer_missing(ingested.objects.values());
+    let validation = schemas.validate(&mut extracted.nodes);
 
-    // Create function for parsing edges
-    let edges_code = format!(
-        "fn parse_edges(data: &Value) -> Result<Vec<Edge>> {{\n    // Parse edges/relationships from BloodHound data\n    todo!()\n}}"
-    );
-    let edges_pseudo = "Parse edges/relationships from BloodHound JSON data structure".to_string();
-    let edges_node = cryptex
-        .add_function(
-            Some(nodes_node.path_name.clone()),
-            "parse_edges".to_string(),
-            edges_code,
-            edges_pseudo,
-        )
+    // One entry per object kind and relationship kind, merged with earlier extractions
+    let catalog = ExtractionCatalog::from_extracted(&extracted);
+    let changes = write_catalog(&catalog, &cryptex_path, auth.user_id)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    functions_created.push(edges_node.path_name.clone());
 
-    // Create function for processing extracted data
-    let process_code = format!(
-        "fn process_extracted_data(nodes: Vec<Node>, edges: Vec<Edge>) -> Result<ProcessedData> {{\n    // Process extracted nodes and edges\n    todo!()\n}}"
-    );
-    let process_pseudo = "Process extracted nodes and edges into final data structure".to_string();
-    let process_node = cryptex
-        .add_function(
-            Some(edges_node.path_name.clone()),
-            "process_extracted_data".to_string(),
-            process_code,
-            process_pseudo,
-        )
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    functions_created.push(process_node.path_name.clone());
+    let object_kinds: BTreeMap<String, usize> = catalog
+        .objects
+        .iter()
+        .map(|(kind, summary)| (kind.clone(), summary.count))
+        .collect();
+    let relationship_kinds: BTreeMap<String, usize> = catalog
+        .relationships
+        .iter()
+        .map(|(kind, summary)| (kind.clone(), summary.count))
+        .collect();
 
     // Send to Node-RED
     let message = NodeRedMessage::new(
         "bloodsniffer/extraction".to_string(),
         json!({
-            "functions_created": functions_created.len(),
+            "functions_created": changes.created.len(),
+            "functions_updated": changes.updated.len(),
             "nodes": extracted.nodes.len(),
             "edges": extracted.edges.len(),
+            "object_kinds": object_kinds,
+            "relationship_kinds": relationship_kinds,
+            "validation_warnings": validation.warnings.len() + validation.warnings_truncated,
         }),
     );
 
@@ -194,24 +552,61 @@ pub async fn extract_data(
     Ok(Json(ExtractResponse {
         nodes_count: extracted.nodes.len(),
         edges_count: extracted.edges.len(),
-        functions_created,
+        functions_created: changes.created,
+        functions_updated: changes.updated,
+        object_kinds,
+        relationship_kinds,
+        validation,
     }))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreatePipelineRequest {
     pub pipeline_id: Option<String>,
+    #[serde(default)]
     pub source: String,
+    #[serde(default)]
     pub transformers: Vec<String>,
+    #[serde(default)]
     pub destination: String,
+    /// Omit to have fire-marshal read the data from `source`
+    #[serde(default)]
     pub payload: Value,
+    /// Run on a cron or fixed-interval schedule instead of once
+    #[serde(default)]
+    pub schedule: Option<PipelineSchedule>,
+    /// Steps of a graph pipeline, instead of `source`, `transformers` and `destination`
+    #[serde(default)]
+    pub graph: Option<PipelineGraph>,
+}
+
+impl CreatePipelineRequest {
+    /// Whether the request describes exactly one runnable pipeline; scheduled pipelines read
+    /// fresh data from their sources on every run
+    fn is_valid(&self) -> bool {
+        let Some(graph) = &self.graph else {
+            return !self.transformers.is_empty()
+                && (self.schedule.is_none() || self.payload.is_null());
+        };
+        // Graphs are checked for unknown inputs and cycles before they are queued
+        self.source.is_empty()
+            && self.transformers.is_empty()
+            && self.destination.is_empty()
+            && self.payload.is_null()
+            && (self.schedule.is_none() || !graph.has_payload())
+            && graph.order().is_ok()
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct CreatePipelineResponse {
     pub pipeline_id: String,
     pub message: String,
+    /// Queue file of the pipeline, on fire-marshal's host when handed off
     pub file_path: String,
+    /// `fire-marshal` when submitted over the internal API, `queue` when written to the
+    /// shared work directory
+    pub delivery: &'static str,
 }
 
 /// Create data pipeline
@@ -219,25 +614,64 @@ pub async fn create_pipeline(
     State(state): State<AppState>,
     Json(req): Json<CreatePipelineRequest>,
 ) -> Result<Json<CreatePipelineResponse>, StatusCode> {
-    if req.transformers.is_empty() {
+    if !req.is_valid() {
         return Err(StatusCode::BAD_REQUEST);
     }
 
     let pipeline_id = req
         .pipeline_id
         .unwrap_or_else(PipelineRegistry::generate_id);
-    let registry = PipelineRegistry::new(&state.config.pipeline.work_dir);
-    let record = PipelineRecord::new(
-        pipeline_id.clone(),
-        req.source.clone(),
-        req.transformers.clone(),
-        req.destination.clone(),
-        req.payload.clone(),
-    );
+    if !is_valid_pipeline_id(&pipeline_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let handoff = &state.config.pipeline.handoff;
+    let record = match req.graph.clone() {
+        Some(graph) => PipelineRecord::from_graph(pipeline_id.clone(), graph),
+        None => PipelineRecord::new(
+            pipeline_id.clone(),
+            req.source.clone(),
+            req.transformers.clone(),
+            req.destination.clone(),
+            req.payload.clone(),
+        ),
+    }
+    .with_schedule(req.schedule.clone())
+    .with_status_reports(handoff.callback_url.is_some());
 
-    let file_path = registry
-        .persist(&record)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Recorded first so fire-marshal's own reports supersede it
+    pipelines::record_local_status(&state, &StatusUpdate::new(&pipeline_id, RunStatus::Queued));
+
+    // Hand the record to fire-marshal when configured, falling back to the shared queue only
+    // when fire-marshal cannot be reached; a record it rejects is rejected here too
+    let submitted = match &state.fire_marshal {
+        Some(client) => match client.submit(&record).await {
+            Ok(receipt) => Some(receipt.file),
+            Err(e @ SubmitError::Unreachable(..)) => {
+                eprintln!("⚠️  {}; queueing pipeline {} locally", e, pipeline_id);
+                None
+            }
+            Err(SubmitError::Rejected(_, status)) if status.is_client_error() => {
+                return Err(
+                    StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_REQUEST)
+                );
+            }
+            Err(e) => {
+                eprintln!("⚠️  {}", e);
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+        },
+        None => None,
+    };
+    let (file_path, delivery) = match submitted {
+        Some(file) => (file, "fire-marshal"),
+        None => {
+            let registry = PipelineRegistry::new(&state.config.pipeline.work_dir);
+            let file_path = registry
+                .persist(&record)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            (file_path.display().to_string(), "queue")
+        }
+    };
 
     {
         let node_red = state.node_red.read().await;
@@ -250,7 +684,10 @@ pub async fn create_pipeline(
                     "source": record.source,
                     "destination": record.destination,
                     "transformers": record.transformers,
-                    "file": file_path.display().to_string(),
+                    "schedule": record.schedule,
+                    "graph": record.graph,
+                    "file": file_path,
+                    "delivery": delivery,
                     "queued_at": record.created_at,
                 }),
             ))
@@ -260,6 +697,7 @@ pub async fn create_pipeline(
     Ok(Json(CreatePipelineResponse {
         pipeline_id,
         message: "Pipeline queued successfully".to_string(),
-        file_path: file_path.display().to_string(),
+        file_path,
+        delivery,
     }))
 }
@@ -0,0 +1,158 @@
+// Cryptex revision handlers
+// List, fetch, diff and revert the recorded revisions of a function
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use serde::Deserialize;
+
+use super::handlers::{edit_error_status, EditCryptexResponse};
+use super::middleware::AuthContext;
+use super::state::AppState;
+use super::stores::{resolve_cryptex_store, StoreAccess, StoreQuery};
+use crate::database::CryptexStore;
+use pyro_core::cryptex_history::{CryptexHistory, RevisionSummary};
+use pyro_core::cryptex_tree::{CryptexTree, FunctionEntry, FunctionUpdate, StoreLock};
+use pyro_core::pagination::Pagination;
+
+#[derive(Debug, Deserialize)]
+pub struct RevisionQuery {
+    /// Fetch this revision
+    pub revision: Option<u64>,
+    /// Diff from this revision
+    pub from: Option<u64>,
+    /// Diff to this revision; defaults to the latest
+    pub to: Option<u64>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// List, fetch or diff revisions of a function
+/// (`?revision=N` fetches one, `?from=N&to=M` diffs two, otherwise newest first)
+pub async fn get_cryptex_revisions(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Path(path_name): Path<String>,
+    Query(query): Query<RevisionQuery>,
+) -> Result<Response, StatusCode> {
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
+    let history =
+        CryptexHistory::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(revision) = query.revision {
+        let revision = history
+            .get(&path_name, revision)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        return Ok(Json(revision).into_response());
+    }
+
+    if let Some(from) = query.from {
+        let to = match query.to {
+            Some(to) => to,
+            None => {
+                history
+                    .latest(&path_name)
+                    .ok_or(StatusCode::NOT_FOUND)?
+                    .revision
+            }
+        };
+        let diff = history
+            .diff(&path_name, from, to)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        return Ok(Json(diff).into_response());
+    }
+
+    let summaries: Vec<RevisionSummary> = history
+        .revisions(&path_name)
+        .iter()
+        .rev()
+        .map(RevisionSummary::from)
+        .collect();
+    let pagination = Pagination {
+        offset: query.offset,
+        limit: query.limit,
+    };
+    Ok(Json(pagination.page(summaries)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevertCryptexRequest {
+    pub revision: u64,
+}
+
+/// Restore the name, code and pseudocode of a function from an earlier revision
+pub async fn revert_cryptex(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(store): Query<StoreQuery>,
+    Path(path_name): Path<String>,
+    Json(req): Json<RevertCryptexRequest>,
+) -> Result<Json<EditCryptexResponse>, StatusCode> {
+    let cryptex_path = resolve_cryptex_store(
+        &state,
+        &auth,
+        store.name_or(CryptexStore::DEFAULT),
+        StoreAccess::Use,
+    )?;
+    let _guard = state.cryptex_lock.lock().await;
+    let _store =
+        StoreLock::acquire(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut tree =
+        CryptexTree::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut history =
+        CryptexHistory::load(&cryptex_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let target = history
+        .get(&path_name, req.revision)
+        .ok_or(StatusCode::NOT_FOUND)?
+        .clone();
+    if let Some(entry) = tree.get(&path_name) {
+        history.ensure_baseline(entry);
+    }
+
+    tree.update(
+        &path_name,
+        FunctionUpdate {
+            name: Some(target.name),
+            function_code: Some(target.function_code),
+            pseudocode: Some(target.pseudocode),
+        },
+    )
+    .map_err(|e| edit_error_status(&e))?;
+
+    let renamed = tree
+        .save(&cryptex_path)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let new_path = renamed.get(&path_name).unwrap_or(&path_name).clone();
+
+    history.rename_paths(&renamed);
+    let revision = tree.get(&path_name).map(|entry| {
+        let entry = FunctionEntry {
+            path_name: new_path.clone(),
+            ..entry.clone()
+        };
+        history
+            .record_revert(&entry, auth.user_id, req.revision)
+            .revision
+    });
+    history
+        .save()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EditCryptexResponse {
+        path_name: Some(new_path),
+        removed: Vec::new(),
+        renamed,
+        revision,
+        message: format!("Function reverted to revision {}", req.revision),
+    }))
+}
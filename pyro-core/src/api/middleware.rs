@@ -3,9 +3,10 @@
 
 use crate::api::state::AppState;
 use crate::auth::validate_jwt_token;
-use crate::database::{Database, RedbDatabase};
+use crate::database::Database;
+use crate::tls::ClientCertificate;
 use axum::{
-    extract::Request,
+    extract::{Request, State},
     http::{header, HeaderMap, StatusCode},
     middleware::Next,
     response::Response,
@@ -16,6 +17,8 @@ use axum::{
 pub struct AuthContext {
     pub user_id: uuid::Uuid,
     pub authenticated: bool,
+    /// Subject of the client certificate that authenticated the request, if any
+    pub client_subject: Option<String>,
 }
 
 impl AuthContext {
@@ -23,6 +26,15 @@ impl AuthContext {
         Self {
             user_id,
             authenticated: true,
+            client_subject: None,
+        }
+    }
+
+    pub fn client_certificate(user_id: uuid::Uuid, subject: String) -> Self {
+        Self {
+            user_id,
+            authenticated: true,
+            client_subject: Some(subject),
         }
     }
 
@@ -30,6 +42,7 @@ impl AuthContext {
         Self {
             user_id: uuid::Uuid::nil(),
             authenticated: false,
+            client_subject: None,
         }
     }
 
@@ -64,15 +77,36 @@ fn extract_token_from_cookies(headers: &HeaderMap) -> Option<String> {
         })
 }
 
+/// Map a verified client certificate to a user (dictionary: client_certificate_auth)
+/// Pseudocode: Look up the user whose principal name matches the certificate common name
+fn client_certificate_auth(state: &AppState, client_cert: &ClientCertificate) -> AuthContext {
+    let Some(common_name) = &client_cert.common_name else {
+        return AuthContext::unauthenticated();
+    };
+
+    match state
+        .open_database()
+        .and_then(|db| db.lookup_user(common_name))
+    {
+        Ok(Some(user)) => AuthContext::client_certificate(user.id, client_cert.subject.clone()),
+        _ => AuthContext::unauthenticated(),
+    }
+}
+
 /// Authentication middleware (dictionary: auth_middleware)
-/// Pseudocode: Validate JWT token from Authorization header or cookie, attach auth context to request
-pub async fn auth_middleware(mut request: Request, next: Next) -> Result<Response, StatusCode> {
-    let state = request
+/// Pseudocode: Validate JWT token from Authorization header or cookie, falling back to the
+/// TLS client certificate, and attach auth context to request
+pub async fn auth_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let headers = request.headers().clone();
+    let client_cert = request
         .extensions()
-        .get::<AppState>()
+        .get::<Option<ClientCertificate>>()
         .cloned()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-    let headers = request.headers().clone();
+        .flatten();
 
     // Try to extract token from header first, then cookie
     let token =
@@ -83,7 +117,8 @@ pub async fn auth_middleware(mut request: Request, next: Next) -> Result<Respons
         match validate_jwt_token(&token_str, state.config.auth.jwt_secret.as_bytes()) {
             Ok(claims) => {
                 // Verify session exists in database
-                let db = RedbDatabase::open(&state.config.database.path)
+                let db = state
+                    .open_database()
                     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
                 // Extract session ID from JWT claims (jti field)
@@ -111,6 +146,8 @@ pub async fn auth_middleware(mut request: Request, next: Next) -> Result<Respons
             }
             Err(_) => AuthContext::unauthenticated(),
         }
+    } else if let Some(client_cert) = &client_cert {
+        client_certificate_auth(&state, client_cert)
     } else {
         AuthContext::unauthenticated()
     };
@@ -1,3 +1,7 @@
+pub mod bundles;
 pub mod handlers;
+pub mod history;
 pub mod middleware;
+pub mod pipelines;
 pub mod state;
+pub mod stores;
@@ -0,0 +1,88 @@
+// Pipeline status handlers
+// Run status reported back by fire-marshal, recorded per pipeline and forwarded to Node-RED
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use node_red_bridge::NodeRedMessage;
+use serde_json::json;
+
+use super::state::AppState;
+use crate::database::{Database, PipelineProgress};
+use pyro_core::handoff::{self, StatusUpdate};
+
+/// Accept a run status update from fire-marshal (dictionary: pipeline_status_callback)
+/// Pseudocode: Check the shared hand-off token, record the update against its pipeline and
+/// publish it on the pipelines topic. Not served unless a hand-off token is configured
+pub async fn pipeline_status_callback(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(update): Json<StatusUpdate>,
+) -> Result<StatusCode, StatusCode> {
+    let token = state
+        .config
+        .pipeline
+        .handoff
+        .token
+        .as_deref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !handoff::authorized(presented, token) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let db = state
+        .open_database()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = db
+        .record_pipeline_status(&update)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    {
+        let node_red = state.node_red.read().await;
+        let _ = node_red
+            .send(NodeRedMessage::new(
+                "bloodsniffer/pipelines".to_string(),
+                json!({
+                    "event": "pipeline_status",
+                    "pipeline_id": update.pipeline_id,
+                    "status": progress.status,
+                    "update": update,
+                }),
+            ))
+            .await;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get the run status of a pipeline
+pub async fn get_pipeline_status(
+    State(state): State<AppState>,
+    Path(pipeline_id): Path<String>,
+) -> Result<Json<PipelineProgress>, StatusCode> {
+    let db = state
+        .open_database()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    db.get_pipeline_progress(&pipeline_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Record a status pyro-core knows itself, such as a pipeline it just queued
+pub fn record_local_status(state: &AppState, update: &StatusUpdate) {
+    let recorded = state
+        .open_database()
+        .and_then(|db| db.record_pipeline_status(update));
+    if let Err(e) = recorded {
+        eprintln!(
+            "⚠️  Failed to record status of pipeline {}: {:#}",
+            update.pipeline_id, e
+        );
+    }
+}
@@ -1,17 +1,33 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use node_red_bridge::NodeRedBridge;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
 
-use crate::config::Config;
+use crate::config::{Config, RuntimeConfig};
+use crate::database::{FieldCipher, RedbDatabase};
+use pyro_core::handoff::FireMarshalClient;
+use pyro_core::node_schema::SchemaRegistry;
 
 /// Application state shared across all handlers
 #[derive(Clone)]
 pub struct AppState {
     pub config: Config,
     pub cryptex_root: PathBuf,
+    /// Serializes cryptex writes in this process; edits rebuild the cryptex on disk and also
+    /// take the store's `StoreLock` to exclude fire-marshal
+    pub cryptex_lock: Arc<Mutex<()>>,
     pub node_red: Arc<RwLock<NodeRedBridge>>,
+    /// Reloadable configuration sections, swapped together with `node_red`
+    pub runtime: Arc<RwLock<RuntimeConfig>>,
+    /// Field cipher for encrypting user PII and secrets at rest
+    pub field_cipher: Option<Arc<FieldCipher>>,
+    /// Node schemas from `extraction.schema_file`
+    pub schemas: Arc<SchemaRegistry>,
+    /// Client for fire-marshal's internal API; pipelines go through the shared work
+    /// directory when unset
+    pub fire_marshal: Option<Arc<FireMarshalClient>>,
 }
 
 impl AppState {
@@ -22,22 +38,48 @@ impl AppState {
         std::fs::create_dir_all(&cryptex_root)?;
 
         // Initialize Node-RED bridge
-        let mut bridge = NodeRedBridge::new();
+        let runtime = RuntimeConfig::from_config(&config);
+        let node_red = Arc::new(RwLock::new(runtime.node_red_bridge()));
+        let runtime = Arc::new(RwLock::new(runtime));
 
-        if let Some(mqtt_broker) = &config.node_red.mqtt_broker {
-            bridge = bridge.with_mqtt(mqtt_broker.clone());
-        }
+        // Load master keyring for field-level encryption
+        let field_cipher = FieldCipher::from_config(&config.database.encryption)
+            .context("Failed to load master key")?
+            .map(Arc::new);
 
-        if let Some(http_endpoint) = &config.node_red.http_endpoint {
-            bridge = bridge.with_http(http_endpoint.clone());
-        }
+        let schemas = match &config.extraction.schema_file {
+            Some(path) => SchemaRegistry::load(path)?,
+            None => SchemaRegistry::default(),
+        };
 
-        let node_red = Arc::new(RwLock::new(bridge));
+        let handoff = &config.pipeline.handoff;
+        let fire_marshal = match (&handoff.fire_marshal_url, &handoff.token) {
+            (Some(url), Some(token)) => Some(Arc::new(FireMarshalClient::new(
+                url,
+                token,
+                Duration::from_secs(handoff.timeout_secs),
+            )?)),
+            _ => None,
+        };
 
         Ok(Self {
             config,
             cryptex_root,
+            cryptex_lock: Arc::new(Mutex::new(())),
             node_red,
+            runtime,
+            field_cipher,
+            schemas: Arc::new(schemas),
+            fire_marshal,
+        })
+    }
+
+    /// Open the application database with field encryption applied
+    pub fn open_database(&self) -> Result<RedbDatabase> {
+        let db = RedbDatabase::open(&self.config.database.path)?;
+        Ok(match &self.field_cipher {
+            Some(cipher) => db.with_cipher(cipher.clone()),
+            None => db,
         })
     }
 }
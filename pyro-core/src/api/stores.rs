@@ -0,0 +1,251 @@
+// Cryptex store handlers
+// Named cryptex stores under the cryptex root, owned by the user that created them
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    Extension,
+};
+use cryptex::Cryptex;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use super::middleware::AuthContext;
+use super::state::AppState;
+use crate::database::{CryptexStore, Database, RedbDatabase, Role};
+use pyro_core::cryptex_history::CryptexHistory;
+use pyro_core::cryptex_tree::StoreLock;
+
+/// What a caller wants to do with a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreAccess {
+    /// Read and edit the functions in the store
+    Use,
+    /// Change or delete the store itself
+    Manage,
+}
+
+/// `?store=` parameter accepted by every cryptex route
+#[derive(Debug, Default, Deserialize)]
+pub struct StoreQuery {
+    pub store: Option<String>,
+}
+
+impl StoreQuery {
+    /// Requested store, or `default` when none was named
+    pub fn name_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.store.as_deref().unwrap_or(default)
+    }
+}
+
+/// Check whether the caller may access a store
+fn can_access(
+    db: &RedbDatabase,
+    auth: &AuthContext,
+    store: &CryptexStore,
+    access: StoreAccess,
+) -> anyhow::Result<bool> {
+    if store.owner_id == auth.user_id && !store.owner_id.is_nil() {
+        return Ok(true);
+    }
+    if access == StoreAccess::Use && store.shared {
+        return Ok(true);
+    }
+
+    // Administrators may use and manage every store
+    let is_admin = db
+        .get_user(&auth.user_id)?
+        .map(|user| Role::find_by_name(&user.roles, Role::ADMINISTRATOR).is_some())
+        .unwrap_or(false);
+    Ok(is_admin)
+}
+
+/// Look up a store and check access (dictionary: authorize_cryptex_store)
+/// Pseudocode: Validate the store name, load its record and reject callers that neither
+/// own it, administer the server, nor (for use) find it shared
+fn authorize_cryptex_store(
+    state: &AppState,
+    auth: &AuthContext,
+    name: &str,
+    access: StoreAccess,
+) -> Result<CryptexStore, StatusCode> {
+    if !CryptexStore::is_valid_name(name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let db = state
+        .open_database()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let store = db
+        .get_cryptex_store(name)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !can_access(&db, auth, &store, access).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(store)
+}
+
+/// Resolve the directory of a store the caller may access (dictionary: resolve_cryptex_store)
+/// Pseudocode: Authorize the caller against the named store and return its path under the
+/// cryptex root
+pub fn resolve_cryptex_store(
+    state: &AppState,
+    auth: &AuthContext,
+    name: &str,
+    access: StoreAccess,
+) -> Result<PathBuf, StatusCode> {
+    let store = authorize_cryptex_store(state, auth, name, access)?;
+    Ok(state.cryptex_root.join(&store.name))
+}
+
+/// List cryptex stores visible to the caller
+pub async fn list_cryptex_stores(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+) -> Result<Json<Vec<CryptexStore>>, StatusCode> {
+    let db = state
+        .open_database()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stores = db
+        .list_cryptex_stores()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut visible = Vec::new();
+    for store in stores {
+        if can_access(&db, &auth, &store, StoreAccess::Use)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            visible.push(store);
+        }
+    }
+
+    Ok(Json(visible))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateStoreRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub shared: bool,
+}
+
+/// Create a cryptex store owned by the caller
+pub async fn create_cryptex_store(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Json(req): Json<CreateStoreRequest>,
+) -> Result<Json<CryptexStore>, StatusCode> {
+    if !CryptexStore::is_valid_name(&req.name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let _guard = state.cryptex_lock.lock().await;
+    let db = state
+        .open_database()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if db
+        .get_cryptex_store(&req.name)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some()
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let store = CryptexStore {
+        name: req.name,
+        description: req.description,
+        owner_id: auth.user_id,
+        shared: req.shared,
+        created_at: chrono::Utc::now(),
+    };
+
+    // Initialize the cryptex before recording the store
+    Cryptex::new(state.cryptex_root.join(&store.name))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    db.save_cryptex_store(&store)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(store))
+}
+
+/// Get a cryptex store the caller may use
+pub async fn get_cryptex_store(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(name): Path<String>,
+) -> Result<Json<CryptexStore>, StatusCode> {
+    authorize_cryptex_store(&state, &auth, &name, StoreAccess::Use).map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateStoreRequest {
+    pub description: Option<String>,
+    pub shared: Option<bool>,
+}
+
+/// Update the description or sharing of a cryptex store the caller manages
+pub async fn update_cryptex_store(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(name): Path<String>,
+    Json(req): Json<UpdateStoreRequest>,
+) -> Result<Json<CryptexStore>, StatusCode> {
+    let _guard = state.cryptex_lock.lock().await;
+    let mut store = authorize_cryptex_store(&state, &auth, &name, StoreAccess::Manage)?;
+
+    if let Some(description) = req.description {
+        store.description = Some(description);
+    }
+    if let Some(shared) = req.shared {
+        store.shared = shared;
+    }
+
+    state
+        .open_database()
+        .and_then(|db| db.save_cryptex_store(&store))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(store))
+}
+
+/// Delete a cryptex store the caller manages, along with its functions
+pub async fn delete_cryptex_store(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let _guard = state.cryptex_lock.lock().await;
+    let store = authorize_cryptex_store(&state, &auth, &name, StoreAccess::Manage)?;
+
+    // The built-in stores back the default routes
+    if store.owner_id.is_nil() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    state
+        .open_database()
+        .and_then(|db| db.delete_cryptex_store(&store.name))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // The lock file stays behind so a writer still waiting on it keeps excluding later ones
+    let store_path = state.cryptex_root.join(&store.name);
+    let _store = StoreLock::acquire(&store_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if store_path.exists() {
+        std::fs::remove_dir_all(&store_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    let history_path = CryptexHistory::history_path(&store_path);
+    if history_path.exists() {
+        std::fs::remove_file(&history_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok(Json(serde_json::json!({
+        "name": store.name,
+        "message": "Cryptex store deleted successfully"
+    })))
+}
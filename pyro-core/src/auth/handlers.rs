@@ -5,16 +5,18 @@ use axum::{
     extract::State,
     http::{HeaderMap, StatusCode},
     response::Json,
+    Extension,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::api::middleware::AuthContext;
 use crate::api::state::AppState;
 use crate::auth::{
     crypto::PasswordHasher,
     session::{generate_jwt_token, Session},
 };
-use crate::database::{Database, RedbDatabase};
+use crate::database::{hash_session_token, Database};
 
 /// Login request
 #[derive(Debug, Deserialize)]
@@ -47,7 +49,8 @@ pub async fn api_login_with_secret(
     Json(req): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>, StatusCode> {
     // Open database
-    let db = RedbDatabase::open(&state.config.database.path)
+    let db = state
+        .open_database()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Lookup user
@@ -84,11 +87,11 @@ pub async fn api_login_with_secret(
     )
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Store session in database
+    // Store session in database (only the token hash is persisted)
     let user_session = crate::database::models::UserSession {
         id: session.id,
         user_id: session.user_id,
-        token: token.clone(),
+        token_hash: hash_session_token(&token),
         created_at: session.created_at,
         expires_at: session.expires_at,
     };
@@ -122,7 +125,8 @@ pub async fn api_logout(
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
     // Open database
-    let db = RedbDatabase::open(&state.config.database.path)
+    let db = state
+        .open_database()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Get session to find ID
@@ -141,20 +145,33 @@ pub async fn api_logout(
 }
 
 /// Validate session (dictionary: api_validate_session)
-/// Pseudocode: Check if session token is valid
+/// Pseudocode: Check if session token is valid, or report the client certificate identity
 pub async fn api_validate_session(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     headers: HeaderMap,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     // Get token from Authorization header
     let token = headers
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.strip_prefix("Bearer "))
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .and_then(|s| s.strip_prefix("Bearer "));
+
+    let Some(token) = token else {
+        // Authenticated by TLS client certificate
+        return match auth.client_subject {
+            Some(subject) if auth.is_authenticated() => Ok(Json(json!({
+                "valid": true,
+                "user_id": auth.user_id.to_string(),
+                "client_subject": subject
+            }))),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        };
+    };
 
     // Open database
-    let db = RedbDatabase::open(&state.config.database.path)
+    let db = state
+        .open_database()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Get session
@@ -8,7 +8,7 @@ use crate::api::state::AppState;
 use crate::auth::crypto::PasswordHasher;
 use crate::config::Config;
 use crate::database::{
-    models::{AuthSecret, Role, User},
+    models::{AuthSecret, CryptexStore, Role, User},
     Database, RedbDatabase,
 };
 use chrono::Utc;
@@ -49,11 +49,14 @@ pub fn bloodsniffer_create_daemon_context() -> tokio::task::JoinHandle<()> {
 /// Pseudocode: Run database migrations to update schema
 pub async fn bloodsniffer_migrate_db(state: &AppState) -> Result<()> {
     // Open database connection
-    let db = RedbDatabase::open(&state.config.database.path).context("Failed to open database")?;
+    let db = state.open_database().context("Failed to open database")?;
 
     // Run migrations
     db.migrate().context("Failed to run migrations")?;
 
+    // Register the built-in cryptex stores
+    bloodsniffer_register_builtin_stores(&db)?;
+
     // Check if installation exists
     let has_installation = db
         .has_installation()
@@ -69,10 +72,28 @@ pub async fn bloodsniffer_migrate_db(state: &AppState) -> Result<()> {
     Ok(())
 }
 
+/// Register built-in cryptex stores (dictionary: bloodsniffer_register_builtin_stores)
+/// Pseudocode: Record the shared main and extracted stores if they have no store record yet
+fn bloodsniffer_register_builtin_stores(db: &RedbDatabase) -> Result<()> {
+    let builtin = [
+        (CryptexStore::DEFAULT, "Default cryptex store"),
+        (CryptexStore::EXTRACTED, "BloodHound extraction output"),
+    ];
+
+    for (name, description) in builtin {
+        if db.get_cryptex_store(name)?.is_none() {
+            db.save_cryptex_store(&CryptexStore::builtin(name, description))
+                .with_context(|| format!("Failed to register cryptex store '{}'", name))?;
+        }
+    }
+
+    Ok(())
+}
+
 /// Create default admin (dictionary: bloodsniffer_create_default_admin)
 /// Pseudocode: Create default administrator user if none exists
 pub async fn bloodsniffer_create_default_admin(state: &AppState) -> Result<()> {
-    let db = RedbDatabase::open(&state.config.database.path).context("Failed to open database")?;
+    let db = state.open_database().context("Failed to open database")?;
 
     bloodsniffer_create_default_admin_internal(&db, &state.config).await
 }
@@ -171,7 +192,7 @@ pub fn bloodsniffer_ensure_directories(config: &Config) -> Result<()> {
 /// Default config file path (dictionary: bloodsniffer_default_config_path)
 /// Pseudocode: Get default path for configuration file
 pub fn bloodsniffer_default_config_path() -> String {
-    "bloodsniffer.toml".to_string()
+    crate::config::DEFAULT_CONFIG_PATH.to_string()
 }
 
 /// Connect to graph database (dictionary: bloodsniffer_connect_graph)
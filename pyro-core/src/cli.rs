@@ -0,0 +1,467 @@
+// Management CLI for the pyro binary
+// Subcommands for operating a BloodSniffer installation without the HTTP API
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use rand::{distributions::Alphanumeric, Rng};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::api::state::AppState;
+use crate::auth::crypto::PasswordHasher;
+use crate::bootstrap::{bloodsniffer_ensure_directories, bloodsniffer_migrate_db};
+use crate::config::{env_var_name, Config, ConfigErrors, ConfigFlags, ConfigIssue, ConfigLoader};
+use crate::database::{
+    models::{AuthSecret, Role, User},
+    Database, FieldCipher,
+};
+
+/// BloodSniffer - Autonomous Data Liberation System
+#[derive(Debug, Parser)]
+#[command(name = "pyro", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub flags: ConfigFlags,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run migrations and start the API server (default)
+    Serve,
+    /// Run database migrations and exit
+    Migrate,
+    /// Create a new user
+    CreateUser {
+        /// Principal (login) name
+        principal_name: String,
+        /// Password; a random one is generated and printed when omitted
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(long)]
+        email: Option<String>,
+        #[arg(long)]
+        first_name: Option<String>,
+        #[arg(long)]
+        last_name: Option<String>,
+        /// Grant the Administrator role
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Reset a user's password and revoke their sessions
+    ResetPassword {
+        /// Principal (login) name
+        principal_name: String,
+        /// New password; a random one is generated and printed when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// List active user sessions
+    ListSessions,
+    /// Revoke a user session by ID
+    RevokeSession {
+        /// Session ID as shown by `list-sessions`
+        session_id: uuid::Uuid,
+    },
+    /// Inspect or create the configuration file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load the configuration and report problems
+    Check,
+    /// Write a default configuration file
+    Init {
+        /// Overwrite an existing configuration file
+        #[arg(long)]
+        force: bool,
+        /// Also generate a master key file for field encryption at this path
+        #[arg(long)]
+        master_key: Option<PathBuf>,
+    },
+}
+
+/// Run a management command (dictionary: cli_run_command)
+/// Pseudocode: Dispatch a non-serve subcommand against the configured installation
+pub async fn cli_run_command(command: Command, loader: &ConfigLoader) -> Result<()> {
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Config(ConfigCommand::Check) => cli_config_check(loader),
+        Command::Config(ConfigCommand::Init { force, master_key }) => {
+            cli_config_init(loader.path(), force, master_key.as_deref())
+        }
+        command => {
+            let config = loader.load()?;
+            bloodsniffer_ensure_directories(&config)?;
+            let state = AppState::new(config).await?;
+
+            match command {
+                Command::Migrate => {
+                    bloodsniffer_migrate_db(&state).await?;
+                    println!("🩸 Database migrated");
+                    Ok(())
+                }
+                Command::CreateUser {
+                    principal_name,
+                    password,
+                    email,
+                    first_name,
+                    last_name,
+                    admin,
+                } => cli_create_user(
+                    &state,
+                    principal_name,
+                    password,
+                    email,
+                    first_name,
+                    last_name,
+                    admin,
+                ),
+                Command::ResetPassword {
+                    principal_name,
+                    password,
+                } => cli_reset_password(&state, &principal_name, password),
+                Command::ListSessions => cli_list_sessions(&state),
+                Command::RevokeSession { session_id } => cli_revoke_session(&state, &session_id),
+                Command::Serve | Command::Config(_) => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Create user (dictionary: cli_create_user)
+/// Pseudocode: Hash the password, assign roles and store a new user
+fn cli_create_user(
+    state: &AppState,
+    principal_name: String,
+    password: Option<String>,
+    email_address: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    admin: bool,
+) -> Result<()> {
+    let db = state.open_database().context("Failed to open database")?;
+
+    if db.lookup_user(&principal_name)?.is_some() {
+        anyhow::bail!("User '{}' already exists", principal_name);
+    }
+
+    let roles = if admin {
+        let all_roles = db.get_all_roles().context("Failed to get roles")?;
+        let admin_role = Role::find_by_name(&all_roles, Role::ADMINISTRATOR)
+            .ok_or_else(|| anyhow::anyhow!("Administrator role not found"))?;
+        vec![admin_role.clone()]
+    } else {
+        Vec::new()
+    };
+
+    let (password, generated) = password_or_generated(password);
+    let auth_secret = new_auth_secret(&password)?;
+
+    let user = User {
+        id: uuid::Uuid::new_v4(),
+        principal_name,
+        email_address,
+        first_name,
+        last_name,
+        all_environments: admin,
+        roles,
+        auth_secret: Some(auth_secret.clone()),
+    };
+
+    db.initialize_secret_auth(&user, &auth_secret)
+        .context("Failed to create user")?;
+
+    println!("🩸 Created user '{}' ({})", user.principal_name, user.id);
+    if generated {
+        println!("   Generated password: {}", password);
+    }
+
+    Ok(())
+}
+
+/// Reset password (dictionary: cli_reset_password)
+/// Pseudocode: Replace the user's secret and end every session they hold
+fn cli_reset_password(
+    state: &AppState,
+    principal_name: &str,
+    password: Option<String>,
+) -> Result<()> {
+    let db = state.open_database().context("Failed to open database")?;
+
+    let mut user = db
+        .lookup_user(principal_name)?
+        .ok_or_else(|| anyhow::anyhow!("User '{}' not found", principal_name))?;
+
+    let (password, generated) = password_or_generated(password);
+    let auth_secret = new_auth_secret(&password)?;
+    user.auth_secret = Some(auth_secret.clone());
+
+    db.initialize_secret_auth(&user, &auth_secret)
+        .context("Failed to update user secret")?;
+
+    let mut revoked = 0;
+    for session in db.list_sessions()? {
+        if session.user_id == user.id {
+            db.end_user_session(&session.id)?;
+            revoked += 1;
+        }
+    }
+
+    println!(
+        "🩸 Password reset for '{}' ({} session(s) revoked)",
+        principal_name, revoked
+    );
+    if generated {
+        println!("   Generated password: {}", password);
+    }
+
+    Ok(())
+}
+
+/// List sessions (dictionary: cli_list_sessions)
+/// Pseudocode: Print every stored session with its owner and expiry
+fn cli_list_sessions(state: &AppState) -> Result<()> {
+    let db = state.open_database().context("Failed to open database")?;
+
+    let mut sessions = db.list_sessions()?;
+    sessions.sort_by_key(|s| s.created_at);
+
+    if sessions.is_empty() {
+        println!("No sessions");
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    println!(
+        "{:<36}  {:<36}  {:<20}  {:<20}  STATUS",
+        "SESSION", "USER", "CREATED", "EXPIRES"
+    );
+    for session in sessions {
+        println!(
+            "{:<36}  {:<36}  {:<20}  {:<20}  {}",
+            session.id,
+            session.user_id,
+            session.created_at.format("%Y-%m-%d %H:%M:%S"),
+            session.expires_at.format("%Y-%m-%d %H:%M:%S"),
+            if session.expires_at < now {
+                "expired"
+            } else {
+                "active"
+            }
+        );
+    }
+
+    Ok(())
+}
+
+/// Revoke session (dictionary: cli_revoke_session)
+/// Pseudocode: Delete a session so its bearer token stops working
+fn cli_revoke_session(state: &AppState, session_id: &uuid::Uuid) -> Result<()> {
+    let db = state.open_database().context("Failed to open database")?;
+
+    if db.get_user_session(session_id)?.is_none() {
+        anyhow::bail!("Session {} not found", session_id);
+    }
+
+    db.end_user_session(session_id)?;
+    println!("🩸 Revoked session {}", session_id);
+
+    Ok(())
+}
+
+/// Check configuration (dictionary: cli_config_check)
+/// Pseudocode: Merge every configuration layer, validate it and report all problems
+fn cli_config_check(loader: &ConfigLoader) -> Result<()> {
+    if !loader.path().exists() {
+        println!(
+            "⚠️  {} not found - defaults and environment only",
+            loader.path().display()
+        );
+    }
+
+    let config = loader.load().inspect_err(|err| {
+        if let Some(errors) = err.downcast_ref::<ConfigErrors>() {
+            print_config_errors(errors);
+        }
+    })?;
+    config.validate().inspect_err(print_config_errors)?;
+    FieldCipher::from_config(&config.database.encryption).context("Failed to load master key")?;
+
+    println!(
+        "🩸 Configuration OK{}",
+        if config.dev_mode { " (dev mode)" } else { "" }
+    );
+    println!("   server:   {}:{}", config.server.host, config.server.port);
+    println!("   database: {}", config.database.path.display());
+    println!("   pipeline: {}", config.pipeline.work_dir.display());
+
+    Ok(())
+}
+
+/// Print every configuration error with the environment variable that overrides it
+fn print_config_errors(errors: &ConfigErrors) {
+    println!("❌ {} configuration error(s):", errors.0.len());
+    for issue in &errors.0 {
+        print_issue(issue);
+    }
+}
+
+/// Print a single configuration issue
+fn print_issue(issue: &ConfigIssue) {
+    println!(
+        "   {}: {} (env: {})",
+        issue.field,
+        issue.message,
+        env_var_name(&issue.field)
+    );
+}
+
+/// Initialize configuration (dictionary: cli_config_init)
+/// Pseudocode: Write default configuration and optionally a fresh master key file
+fn cli_config_init(config_path: &Path, force: bool, master_key: Option<&Path>) -> Result<()> {
+    if config_path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists (use --force to overwrite)",
+            config_path.display()
+        );
+    }
+
+    let mut config = Config::default();
+
+    if let Some(key_path) = master_key {
+        if key_path.exists() && !force {
+            anyhow::bail!(
+                "{} already exists (use --force to overwrite)",
+                key_path.display()
+            );
+        }
+        if key_path.exists() {
+            std::fs::remove_file(key_path)
+                .with_context(|| format!("Failed to remove {:?}", key_path))?;
+        }
+
+        // Created owner-only so the key is never readable by others, not even briefly
+        let key_id = format!("k{}", Utc::now().format("%Y%m%d"));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(key_path)
+            .and_then(|mut file| writeln!(file, "{}", FieldCipher::generate_key_entry(&key_id)))
+            .with_context(|| format!("Failed to write master key file {:?}", key_path))?;
+        config.database.encryption.key_file = Some(key_path.to_path_buf());
+        println!("🩸 Wrote master key {} to {}", key_id, key_path.display());
+    }
+
+    config.save_to(config_path)?;
+    println!(
+        "🩸 Wrote default configuration to {}",
+        config_path.display()
+    );
+
+    Ok(())
+}
+
+/// Use the given password or generate a random one
+fn password_or_generated(password: Option<String>) -> (String, bool) {
+    match password {
+        Some(password) => (password, false),
+        None => {
+            let generated = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(24)
+                .map(char::from)
+                .collect();
+            (generated, true)
+        }
+    }
+}
+
+/// Create an Argon2 auth secret for a password
+fn new_auth_secret(password: &str) -> Result<AuthSecret> {
+    let digest = PasswordHasher::new()
+        .hash_password(password)
+        .context("Failed to hash password")?;
+
+    Ok(AuthSecret {
+        digest,
+        digest_method: "argon2".to_string(),
+        expires_at: Some(Utc::now() + chrono::Duration::days(90)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subcommands() {
+        let cli = Cli::parse_from(["pyro"]);
+        assert!(cli.command.is_none());
+        assert!(cli.flags.config.is_none());
+
+        let cli = Cli::parse_from(["pyro", "--config", "/etc/pyro.toml", "create-user", "bob"]);
+        assert_eq!(cli.flags.config, Some(PathBuf::from("/etc/pyro.toml")));
+        assert!(matches!(
+            cli.command,
+            Some(Command::CreateUser { ref principal_name, admin: false, .. }) if principal_name == "bob"
+        ));
+
+        let cli = Cli::parse_from(["pyro", "config", "init", "--force"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Config(ConfigCommand::Init { force: true, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_flags_override_config() {
+        let cli = Cli::parse_from([
+            "pyro",
+            "serve",
+            "--no-env",
+            "--dev",
+            "--set",
+            "server.port=4100",
+        ]);
+        let config = cli.flags.loader().load().unwrap();
+        assert!(config.dev_mode);
+        assert_eq!(config.server.port, 4100);
+
+        assert!(Cli::try_parse_from(["pyro", "--set", "server.port"]).is_err());
+    }
+
+    #[test]
+    fn test_config_init_refuses_overwrite() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("bloodsniffer.toml");
+        let key_path = temp_dir.path().join("master.key");
+
+        cli_config_init(&config_path, false, Some(&key_path)).unwrap();
+        assert!(cli_config_init(&config_path, false, None).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        cli_config_init(&config_path, true, Some(&key_path)).unwrap();
+
+        let config = Config::load_from(&config_path).unwrap();
+        assert_eq!(config.database.encryption.key_file, Some(key_path.clone()));
+        assert!(FieldCipher::from_config(&config.database.encryption)
+            .unwrap()
+            .is_some());
+    }
+}
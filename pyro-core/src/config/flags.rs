@@ -0,0 +1,54 @@
+// Configuration flags
+// Global command line flags shared by the pyro and fire-marshal binaries
+
+use clap::Args;
+use std::path::PathBuf;
+
+use super::ConfigLoader;
+
+/// Flags layering a configuration file, the environment and single fields over the defaults
+#[derive(Debug, Clone, Default, Args)]
+pub struct ConfigFlags {
+    /// Path to the configuration file (defaults to ./bloodsniffer.toml if present)
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Ignore BLOODSNIFFER_* environment overrides
+    #[arg(long, global = true)]
+    pub no_env: bool,
+
+    /// Development mode: allow insecure defaults
+    #[arg(long, global = true)]
+    pub dev: bool,
+
+    /// Override a configuration field, e.g. `--set server.port=4000` (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true, value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+}
+
+impl ConfigFlags {
+    /// Build the layered configuration loader: defaults, file, environment, then flags
+    pub fn loader(&self) -> ConfigLoader {
+        let mut loader = ConfigLoader::new();
+        if let Some(path) = &self.config {
+            loader = loader.with_file(path);
+        }
+        if self.no_env {
+            loader = loader.without_env();
+        }
+        for (key, value) in &self.overrides {
+            loader = loader.with_override(key, value);
+        }
+        if self.dev {
+            loader = loader.with_override("dev_mode", "true");
+        }
+        loader
+    }
+}
+
+/// Parse a `KEY=VALUE` override flag
+fn parse_override(raw: &str) -> Result<(String, String), String> {
+    raw.split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", raw))
+}
@@ -0,0 +1,373 @@
+// Layered configuration loading
+// defaults -> configuration file -> BLOODSNIFFER_* environment variables -> CLI overrides
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+use super::validation::{ConfigErrors, ConfigIssue};
+use super::{Config, DEFAULT_CONFIG_PATH};
+
+/// Prefix for configuration environment variables
+pub const ENV_PREFIX: &str = "BLOODSNIFFER_";
+
+/// Loader that merges every configuration layer into a single `Config`.
+///
+/// Every field can be overridden from the environment by joining its path with
+/// underscores, e.g. `pipeline.datapipe_interval_secs` is read from
+/// `BLOODSNIFFER_PIPELINE_DATAPIPE_INTERVAL_SECS`.
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    path: PathBuf,
+    require_file: bool,
+    use_env: bool,
+    overrides: Vec<(String, String)>,
+}
+
+impl ConfigLoader {
+    /// Load from the default file (optional), environment and no overrides
+    pub fn new() -> Self {
+        Self {
+            path: PathBuf::from(DEFAULT_CONFIG_PATH),
+            require_file: false,
+            use_env: true,
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Use an explicit configuration file, which must exist
+    pub fn with_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = path.as_ref().to_path_buf();
+        self.require_file = true;
+        self
+    }
+
+    /// Skip the environment layer
+    pub fn without_env(mut self) -> Self {
+        self.use_env = false;
+        self
+    }
+
+    /// Override a field by dotted path (`server.port`), applied after the environment
+    pub fn with_override(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.push((key.into(), value.into()));
+        self
+    }
+
+    /// Configuration file path used by this loader
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Merge all layers and deserialize the result, reporting unknown file keys and every
+    /// field that does not fit its type together
+    pub fn load(&self) -> Result<Config> {
+        let defaults =
+            serde_json::to_value(Config::default()).context("Failed to serialize defaults")?;
+        let mut merged = defaults.clone();
+        let mut issues = Vec::new();
+
+        // File layer
+        match std::fs::read_to_string(&self.path) {
+            Ok(config_str) => {
+                let file: toml::Value = toml::from_str(&config_str).with_context(|| {
+                    format!("Failed to parse configuration file {:?}", self.path)
+                })?;
+                let file = serde_json::to_value(file)?;
+                merge(&mut merged, file, "", &mut issues);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !self.require_file => {}
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read configuration file {:?}", self.path))
+            }
+        }
+
+        // Environment layer
+        if self.use_env {
+            let mut leaves = Vec::new();
+            collect_leaves(&merged, String::new(), &mut leaves);
+
+            for path in leaves {
+                let var = env_var_name(&path);
+                if let Ok(raw) = std::env::var(&var) {
+                    if let Err(message) = set_leaf(&mut merged, &path, &raw) {
+                        issues.push(ConfigIssue::new(path, format!("{} ({})", message, var)));
+                    }
+                }
+            }
+        }
+
+        // CLI override layer
+        for (key, raw) in &self.overrides {
+            if let Err(message) = set_leaf(&mut merged, key, raw) {
+                issues.push(ConfigIssue::new(key.clone(), message));
+            }
+        }
+
+        match serde_json::from_value(merged.clone()) {
+            Ok(config) if issues.is_empty() => return Ok(config),
+            Ok(_) => {}
+            Err(err) => {
+                let fields = field_errors(&defaults, &merged);
+                if fields.is_empty() && issues.is_empty() {
+                    return Err(err).context("Failed to build configuration");
+                }
+                issues.extend(fields);
+            }
+        }
+        Err(ConfigErrors(issues).into())
+    }
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Environment variable name for a dotted field path
+pub fn env_var_name(path: &str) -> String {
+    format!("{}{}", ENV_PREFIX, path.replace('.', "_").to_uppercase())
+}
+
+/// Recursively merge `overlay` into `base`, replacing non-object values. Keys `base` does not
+/// have and values in place of whole sections are reported instead of merged.
+fn merge(base: &mut Value, overlay: Value, prefix: &str, issues: &mut Vec<ConfigIssue>) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, &path, issues),
+                    None => issues.push(ConfigIssue::new(path, "unknown configuration key")),
+                }
+            }
+        }
+        (Value::Object(_), _) => issues.push(ConfigIssue::new(prefix, "expected a table")),
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Fields whose merged value cannot be deserialized, each tried alone on top of the defaults
+fn field_errors(defaults: &Value, merged: &Value) -> Vec<ConfigIssue> {
+    let mut leaves = Vec::new();
+    collect_leaves(defaults, String::new(), &mut leaves);
+
+    leaves
+        .into_iter()
+        .filter_map(|path| {
+            let pointer = format!("/{}", path.replace('.', "/"));
+            let value = merged.pointer(&pointer)?;
+            if defaults.pointer(&pointer) == Some(value) {
+                return None;
+            }
+            let mut candidate = defaults.clone();
+            *candidate.pointer_mut(&pointer)? = value.clone();
+            serde_json::from_value::<Config>(candidate)
+                .err()
+                .map(|err| ConfigIssue::new(path, err.to_string()))
+        })
+        .collect()
+}
+
+/// Collect dotted paths of every non-object value
+pub(super) fn collect_leaves(value: &Value, prefix: String, leaves: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                collect_leaves(child, path, leaves);
+            }
+        }
+        _ => leaves.push(prefix),
+    }
+}
+
+/// Replace the value at a dotted path, parsing `raw` according to the current type
+fn set_leaf(root: &mut Value, path: &str, raw: &str) -> std::result::Result<(), String> {
+    let mut current = root;
+    for segment in path.split('.') {
+        current = current
+            .as_object_mut()
+            .and_then(|map: &mut Map<String, Value>| map.get_mut(segment))
+            .ok_or_else(|| "unknown configuration key".to_string())?;
+    }
+
+    if current.is_object() {
+        return Err("cannot override a whole section".to_string());
+    }
+
+    *current = parse_as(current, raw)?;
+    Ok(())
+}
+
+/// Parse a raw string into a JSON value shaped like `current`
+fn parse_as(current: &Value, raw: &str) -> std::result::Result<Value, String> {
+    let raw = raw.trim();
+    match current {
+        Value::Bool(_) => match raw.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(Value::Bool(true)),
+            "false" | "0" | "no" | "off" => Ok(Value::Bool(false)),
+            _ => Err(format!("expected a boolean, got '{}'", raw)),
+        },
+        Value::Number(n) if n.is_u64() || n.is_i64() => raw
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| format!("expected an integer, got '{}'", raw)),
+        Value::Number(_) => raw
+            .parse::<f64>()
+            .map(Value::from)
+            .map_err(|_| format!("expected a number, got '{}'", raw)),
+        Value::Array(_) if raw.starts_with('[') => {
+            serde_json::from_str(raw).map_err(|e| format!("expected a JSON array: {}", e))
+        }
+        Value::Array(_) => Ok(Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| Value::String(s.to_string()))
+                .collect(),
+        )),
+        // Empty values clear optional settings
+        Value::Null | Value::String(_) if raw.is_empty() => Ok(Value::Null),
+        _ => Ok(Value::String(raw.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_env_var_name() {
+        assert_eq!(
+            env_var_name("pipeline.datapipe_interval_secs"),
+            "BLOODSNIFFER_PIPELINE_DATAPIPE_INTERVAL_SECS"
+        );
+        assert_eq!(
+            env_var_name("database.encryption.key_file"),
+            "BLOODSNIFFER_DATABASE_ENCRYPTION_KEY_FILE"
+        );
+    }
+
+    #[test]
+    fn test_file_layer_is_partial() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bloodsniffer.toml");
+        std::fs::write(&path, "[server]\nport = 4000\n").unwrap();
+
+        let config = ConfigLoader::new()
+            .with_file(&path)
+            .without_env()
+            .load()
+            .unwrap();
+        assert_eq!(config.server.port, 4000);
+        assert_eq!(config.server.host, "127.0.0.1");
+        assert_eq!(config.pipeline.datapipe_interval_secs, 60);
+    }
+
+    #[test]
+    fn test_explicit_file_must_exist() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("missing.toml");
+        assert!(ConfigLoader::new().with_file(&missing).load().is_err());
+    }
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bloodsniffer.toml");
+        std::fs::write(&path, "[server]\nport = 4000\n").unwrap();
+
+        let config = ConfigLoader::new()
+            .with_file(&path)
+            .without_env()
+            .with_override("server.port", "5000")
+            .with_override("node_red.mqtt_broker", "")
+            .with_override("dev_mode", "true")
+            .load()
+            .unwrap();
+        assert_eq!(config.server.port, 5000);
+        assert!(config.node_red.mqtt_broker.is_none());
+        assert!(config.dev_mode);
+    }
+
+    #[test]
+    fn test_unknown_file_keys_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bloodsniffer.toml");
+        std::fs::write(
+            &path,
+            "graph = \"bolt://x\"\n[server]\nport = 4000\nhots = \"0.0.0.0\"\n[extra]\nx = 1\n",
+        )
+        .unwrap();
+
+        let err = ConfigLoader::new()
+            .with_file(&path)
+            .without_env()
+            .load()
+            .unwrap_err();
+        let errors = err.downcast_ref::<ConfigErrors>().unwrap();
+        let mut fields: Vec<&str> = errors.0.iter().map(|i| i.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(fields, vec!["extra", "graph", "server.hots"]);
+    }
+
+    #[test]
+    fn test_field_type_errors_report_all_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bloodsniffer.toml");
+        std::fs::write(
+            &path,
+            "[server]\nport = 70000\n[pipeline]\nworkers = \"many\"\ndatapipe_interval_secs = 30\n",
+        )
+        .unwrap();
+
+        let err = ConfigLoader::new()
+            .with_file(&path)
+            .without_env()
+            .with_override("auth.session_duration_hours", "1.5")
+            .load()
+            .unwrap_err();
+        let errors = err.downcast_ref::<ConfigErrors>().unwrap();
+        let mut fields: Vec<&str> = errors.0.iter().map(|i| i.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            vec![
+                "auth.session_duration_hours",
+                "pipeline.workers",
+                "server.port"
+            ]
+        );
+        assert!(errors.to_string().contains("70000"));
+    }
+
+    #[test]
+    fn test_invalid_overrides_report_all_paths() {
+        let err = ConfigLoader::new()
+            .without_env()
+            .with_override("server.port", "not-a-port")
+            .with_override("server.nope", "1")
+            .with_override("auth.session_duration_hours", "soon")
+            .load()
+            .unwrap_err();
+
+        let errors = err.downcast_ref::<ConfigErrors>().unwrap();
+        let fields: Vec<&str> = errors.0.iter().map(|i| i.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["server.port", "server.nope", "auth.session_duration_hours"]
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::logging::LogLevel;
+
+mod flags;
+mod layers;
+mod reload;
+mod validation;
+
+pub use flags::ConfigFlags;
+pub use layers::{env_var_name, ConfigLoader};
+pub use reload::{spawn_config_watcher, spawn_runtime_updates, RuntimeConfig};
+pub use validation::{ConfigErrors, ConfigIssue};
+
+/// Default configuration file path
+pub const DEFAULT_CONFIG_PATH: &str = "bloodsniffer.toml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Development mode: accept insecure defaults during validation
+    #[serde(default)]
+    pub dev_mode: bool,
+    /// Server configuration
+    pub server: ServerConfig,
+    /// Database configuration
+    pub database: DatabaseConfig,
+    /// Node-RED configuration
+    pub node_red: NodeRedConfig,
+    /// Cryptex configuration
+    pub cryptex: CryptexConfig,
+    /// Graph configuration
+    pub graph: GraphConfig,
+    /// Pipeline configuration
+    pub pipeline: PipelineConfig,
+    /// Authentication configuration
+    pub auth: AuthConfig,
+    /// Default admin configuration
+    pub default_admin: DefaultAdminConfig,
+    /// Logging configuration
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Extraction configuration
+    #[serde(default)]
+    pub extraction: ExtractionConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// HTTPS and client certificate authentication
+    #[serde(default)]
+    pub tls: TlsConfig,
+}
+
+/// Self-signed development certificates are written here when no paths are configured
+const DEV_TLS_DIR: &str = "./data/tls";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Serve HTTPS instead of plain HTTP
+    pub enabled: bool,
+    /// PEM certificate chain (generated in dev mode when missing)
+    pub cert_path: Option<PathBuf>,
+    /// PEM private key (generated in dev mode when missing)
+    pub key_path: Option<PathBuf>,
+    /// PEM CA bundle used to verify client certificates; enables mutual TLS
+    pub client_ca_path: Option<PathBuf>,
+    /// Reject connections without a valid client certificate
+    pub require_client_cert: bool,
+}
+
+impl TlsConfig {
+    /// Certificate path, falling back to the development location
+    pub fn cert_path(&self) -> PathBuf {
+        self.cert_path
+            .clone()
+            .unwrap_or_else(|| Path::new(DEV_TLS_DIR).join("cert.pem"))
+    }
+
+    /// Private key path, falling back to the development location
+    pub fn key_path(&self) -> PathBuf {
+        self.key_path
+            .clone()
+            .unwrap_or_else(|| Path::new(DEV_TLS_DIR).join("key.pem"))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    pub path: PathBuf,
+    /// Field-level encryption for user PII and secrets
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// Master key file (`key_id:base64_key` per line, active key first).
+    /// Falls back to the `BLOODSNIFFER_MASTER_KEY` environment variable when unset.
+    pub key_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeRedConfig {
+    pub mqtt_broker: Option<String>,
+    pub http_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CryptexConfig {
+    pub default_theme: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphConfig {
+    pub uri: String,
+    pub username: String,
+    pub password: String,
+    pub driver: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineConfig {
+    pub work_dir: PathBuf,
+    pub datapipe_interval_secs: u64,
+    /// Queued pipelines fire-marshal runs at the same time
+    #[serde(default = "default_pipeline_workers")]
+    pub workers: usize,
+    /// Retries of failed queued pipelines
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Hand-off of pipelines from pyro-core to fire-marshal over HTTP
+    #[serde(default)]
+    pub handoff: HandoffConfig,
+    /// TOML and YAML pipeline definitions fire-marshal registers at startup
+    #[serde(default)]
+    pub definitions_dir: Option<PathBuf>,
+    /// Hosts `http(s)://` pipeline sources may fetch from; none are allowed when empty
+    #[serde(default)]
+    pub source_hosts: Vec<String>,
+    /// Hosts `http(s)://` pipeline destinations may post to; none are allowed when empty
+    #[serde(default)]
+    pub sink_hosts: Vec<String>,
+}
+
+fn default_pipeline_workers() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Attempts per queued pipeline, including the first, before it is dead-lettered
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled after every further failure
+    pub backoff_secs: u64,
+    /// Upper bound for the delay between retries
+    pub max_backoff_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff_secs: 30,
+            max_backoff_secs: 3600,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay after the given number of failed attempts
+    pub fn backoff(&self, failures: u32) -> u64 {
+        let exponent = failures.saturating_sub(1).min(63);
+        self.backoff_secs
+            .saturating_mul(1u64 << exponent)
+            .min(self.max_backoff_secs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HandoffConfig {
+    /// fire-marshal base URL pyro-core submits pipelines to; pipelines are written to the shared
+    /// `work_dir` queue when unset or unreachable
+    pub fire_marshal_url: Option<String>,
+    /// pyro-core base URL fire-marshal reports run status to
+    pub callback_url: Option<String>,
+    /// Shared secret both services send as a bearer token; the internal endpoints are
+    /// disabled without it
+    pub token: Option<String>,
+    /// Timeout of hand-off and status requests
+    pub timeout_secs: u64,
+    /// Address fire-marshal listens on; reach it from other hosts by binding beyond loopback,
+    /// which requires `token` on every API request
+    pub listen: String,
+}
+
+impl HandoffConfig {
+    /// Whether fire-marshal only accepts connections from its own host
+    pub fn listens_on_loopback(&self) -> bool {
+        self.listen
+            .parse::<std::net::SocketAddr>()
+            .is_ok_and(|addr| addr.ip().is_loopback())
+    }
+}
+
+impl Default for HandoffConfig {
+    fn default() -> Self {
+        Self {
+            fire_marshal_url: None,
+            callback_url: None,
+            token: None,
+            timeout_secs: 10,
+            listen: "127.0.0.1:3001".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    pub session_duration_hours: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefaultAdminConfig {
+    pub principal_name: String,
+    pub password: String,
+    pub email_address: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub expire_now: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Verbosity: error, warn, info, debug or trace
+    pub level: LogLevel,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtractionConfig {
+    /// Node schemas keyed by node type (TOML, or JSON by extension). Node types without a
+    /// configured schema are validated against a schema inferred from ingested data.
+    pub schema_file: Option<PathBuf>,
+}
+
+impl Config {
+    /// Load configuration from defaults, the default file (if present) and
+    /// `BLOODSNIFFER_*` environment variables
+    pub fn load() -> Result<Self> {
+        ConfigLoader::new().load()
+    }
+
+    /// Load configuration layered over the given file, which must exist
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        ConfigLoader::new().with_file(path).load()
+    }
+
+    /// Save configuration to the default file
+    pub fn save(&self) -> Result<()> {
+        self.save_to(DEFAULT_CONFIG_PATH)
+    }
+
+    /// Save configuration to the given file
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let config_str =
+            toml::to_string_pretty(self).context("Failed to serialize configuration")?;
+        std::fs::write(path, config_str)
+            .with_context(|| format!("Failed to write configuration file {:?}", path))?;
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dev_mode: false,
+            server: ServerConfig {
+                host: "127.0.0.1".to_string(),
+                port: 3000,
+                tls: TlsConfig::default(),
+            },
+            database: DatabaseConfig {
+                path: PathBuf::from("./data/bloodsniffer.redb"),
+                encryption: EncryptionConfig::default(),
+            },
+            node_red: NodeRedConfig {
+                mqtt_broker: Some("tcp://localhost:1883".to_string()),
+                http_endpoint: Some("http://localhost:1880/bloodsniffer".to_string()),
+            },
+            cryptex: CryptexConfig {
+                default_theme: "anarchist".to_string(),
+            },
+            graph: GraphConfig {
+                uri: "neo4j://localhost:7687".to_string(),
+                username: "neo4j".to_string(),
+                password: "ChangeMe123!".to_string(),
+                driver: "neo4j".to_string(),
+            },
+            pipeline: PipelineConfig {
+                work_dir: PathBuf::from("./work/pipelines"),
+                datapipe_interval_secs: 60,
+                workers: default_pipeline_workers(),
+                retry: RetryConfig::default(),
+                handoff: HandoffConfig::default(),
+                definitions_dir: None,
+                source_hosts: Vec::new(),
+                sink_hosts: Vec::new(),
+            },
+            auth: AuthConfig {
+                jwt_secret: std::env::var("BLOOD_SNIFFER_JWT_SECRET")
+                    .or_else(|_| std::env::var("PYRO_JWT_SECRET"))
+                    .unwrap_or_else(|_| "change-me-in-prod".to_string()),
+                session_duration_hours: 24,
+            },
+            default_admin: DefaultAdminConfig {
+                principal_name: "admin".to_string(),
+                password: "ChangeMe123!".to_string(),
+                email_address: "admin@bloodsniffer.local".to_string(),
+                first_name: "Admin".to_string(),
+                last_name: "User".to_string(),
+                expire_now: false,
+            },
+            logging: LoggingConfig::default(),
+            extraction: ExtractionConfig::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        assert_eq!(config.server.port, 3000);
+        assert_eq!(config.cryptex.default_theme, "anarchist");
+        assert_eq!(config.auth.session_duration_hours, 24);
+        assert!(!config.auth.jwt_secret.is_empty());
+        assert_eq!(config.graph.driver, "neo4j");
+        assert_eq!(config.pipeline.datapipe_interval_secs, 60);
+        assert!(config
+            .pipeline
+            .work_dir
+            .to_string_lossy()
+            .ends_with("work/pipelines"));
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_up_to_limit() {
+        let retry = RetryConfig {
+            max_attempts: 10,
+            backoff_secs: 30,
+            max_backoff_secs: 600,
+        };
+        let delays: Vec<u64> = (1..=7).map(|failures| retry.backoff(failures)).collect();
+        assert_eq!(delays, vec![30, 60, 120, 240, 480, 600, 600]);
+        assert_eq!(retry.backoff(u32::MAX), 600);
+    }
+}
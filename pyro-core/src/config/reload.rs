@@ -0,0 +1,376 @@
+// Runtime configuration reload
+// Re-reads the configuration on file change or SIGHUP and publishes the sections
+// that can be swapped without restarting the process
+
+use anyhow::Result;
+use node_red_bridge::NodeRedBridge;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{watch, RwLock};
+
+use super::layers::collect_leaves;
+use super::{Config, ConfigErrors, ConfigLoader, LoggingConfig, NodeRedConfig, RetryConfig};
+use crate::logging::{self, LogLevel};
+
+/// How often the configuration file is checked for changes
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Field paths that take effect without a restart
+const RELOADABLE_FIELDS: &[&str] = &[
+    "node_red",
+    "pipeline.datapipe_interval_secs",
+    "pipeline.retry",
+    "logging",
+];
+
+/// Configuration sections that can be swapped while running
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeConfig {
+    pub node_red: NodeRedConfig,
+    pub datapipe_interval_secs: u64,
+    pub retry: RetryConfig,
+    pub logging: LoggingConfig,
+}
+
+impl RuntimeConfig {
+    /// Extract the reloadable sections of a configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            node_red: config.node_red.clone(),
+            datapipe_interval_secs: config.pipeline.datapipe_interval_secs,
+            retry: config.pipeline.retry.clone(),
+            logging: config.logging.clone(),
+        }
+    }
+
+    /// Build a Node-RED bridge for the configured endpoints
+    pub fn node_red_bridge(&self) -> NodeRedBridge {
+        let mut bridge = NodeRedBridge::new();
+
+        if let Some(mqtt_broker) = &self.node_red.mqtt_broker {
+            bridge = bridge.with_mqtt(mqtt_broker.clone());
+        }
+
+        if let Some(http_endpoint) = &self.node_red.http_endpoint {
+            bridge = bridge.with_http(http_endpoint.clone());
+        }
+
+        bridge
+    }
+}
+
+/// Field paths that changed between two configurations
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigChanges {
+    /// Changes applied to the running process
+    pub applied: Vec<String>,
+    /// Changes that only take effect after a restart
+    pub restart_required: Vec<String>,
+}
+
+impl ConfigChanges {
+    /// Compare two configurations field by field
+    pub fn between(old: &Config, new: &Config) -> Self {
+        let old = leaf_values(old);
+        let new = leaf_values(new);
+
+        let mut changes = Self::default();
+        for (path, value) in &new {
+            if old.get(path) == Some(value) {
+                continue;
+            }
+            if is_reloadable(path) {
+                changes.applied.push(path.clone());
+            } else {
+                changes.restart_required.push(path.clone());
+            }
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+}
+
+/// Reload configuration (dictionary: reload_config)
+/// Pseudocode: Load every layer again, reject invalid reloadable values, and apply only
+/// the reloadable sections on top of the running configuration
+pub fn reload_config(loader: &ConfigLoader, current: &Config) -> Result<(Config, ConfigChanges)> {
+    let loaded = loader.load()?;
+
+    // Problems in fields that need a restart are reported when the process restarts
+    if let Err(errors) = loaded.validate() {
+        let blocking: Vec<_> = errors
+            .0
+            .into_iter()
+            .filter(|issue| is_reloadable(&issue.field))
+            .collect();
+        if !blocking.is_empty() {
+            return Err(ConfigErrors(blocking).into());
+        }
+    }
+
+    let changes = ConfigChanges::between(current, &loaded);
+
+    let mut next = current.clone();
+    next.node_red = loaded.node_red;
+    next.pipeline.datapipe_interval_secs = loaded.pipeline.datapipe_interval_secs;
+    next.pipeline.retry = loaded.pipeline.retry;
+    next.logging = loaded.logging;
+
+    Ok((next, changes))
+}
+
+/// Spawn the configuration watcher (dictionary: spawn_config_watcher)
+/// Pseudocode: Poll the file modification time and listen for SIGHUP, reload on either,
+/// report changes, and publish the new runtime sections to subscribers
+pub fn spawn_config_watcher(
+    loader: ConfigLoader,
+    initial: Config,
+) -> watch::Receiver<RuntimeConfig> {
+    logging::set_level(initial.logging.level);
+    let (tx, rx) = watch::channel(RuntimeConfig::from_config(&initial));
+
+    tokio::spawn(async move {
+        let mut current = initial;
+        let mut modified = file_modified(loader.path());
+        let mut ticker = tokio::time::interval(RELOAD_POLL_INTERVAL);
+        let mut hangup = hangup_signal();
+
+        loop {
+            let triggered = tokio::select! {
+                _ = ticker.tick() => {
+                    let latest = file_modified(loader.path());
+                    let changed = latest != modified;
+                    modified = latest;
+                    changed
+                }
+                _ = recv_hangup(&mut hangup) => true,
+            };
+
+            if tx.is_closed() {
+                break;
+            }
+            if !triggered {
+                continue;
+            }
+
+            match reload_config(&loader, &current) {
+                Ok((next, changes)) => {
+                    report_changes(&changes);
+                    current = next;
+                    logging::set_level(current.logging.level);
+                    tx.send_if_modified(|runtime| {
+                        let next = RuntimeConfig::from_config(&current);
+                        let modified = *runtime != next;
+                        *runtime = next;
+                        modified
+                    });
+                }
+                Err(err) => {
+                    eprintln!(
+                        "⚠️  Configuration reload rejected, keeping current settings: {err:#}"
+                    )
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Apply runtime configuration (dictionary: apply_runtime_config)
+/// Pseudocode: Build the bridge for the new endpoints, take both locks so readers never see a
+/// bridge and settings that disagree, carry inbound messages nobody has read yet over to the
+/// new bridge, then swap both
+pub async fn apply_runtime(
+    runtime: &RwLock<RuntimeConfig>,
+    node_red: &RwLock<NodeRedBridge>,
+    next: RuntimeConfig,
+) {
+    let bridge = next.node_red_bridge();
+
+    let mut current = runtime.write().await;
+    let mut node_red = node_red.write().await;
+
+    let sender = bridge.sender();
+    while let Some(message) = node_red.try_receive() {
+        let _ = sender.send(message);
+    }
+
+    *node_red = bridge;
+    *current = next;
+}
+
+/// Apply every runtime configuration update published by the config watcher
+pub fn spawn_runtime_updates(
+    runtime: Arc<RwLock<RuntimeConfig>>,
+    node_red: Arc<RwLock<NodeRedBridge>>,
+    mut updates: watch::Receiver<RuntimeConfig>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while updates.changed().await.is_ok() {
+            let next = updates.borrow_and_update().clone();
+            apply_runtime(&runtime, &node_red, next).await;
+        }
+    })
+}
+
+fn report_changes(changes: &ConfigChanges) {
+    if changes.is_empty() {
+        if logging::enabled(LogLevel::Debug) {
+            println!("🔄 Configuration reloaded: no changes");
+        }
+        return;
+    }
+    if !changes.applied.is_empty() && logging::enabled(LogLevel::Info) {
+        println!("🔄 Configuration reloaded: {}", changes.applied.join(", "));
+    }
+    if !changes.restart_required.is_empty() && logging::enabled(LogLevel::Warn) {
+        println!(
+            "⚠️  Restart required to apply: {}",
+            changes.restart_required.join(", ")
+        );
+    }
+}
+
+fn is_reloadable(path: &str) -> bool {
+    RELOADABLE_FIELDS.iter().any(|field| {
+        path == *field
+            || path
+                .strip_prefix(field)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+fn leaf_values(config: &Config) -> BTreeMap<String, Value> {
+    let value = serde_json::to_value(config).unwrap_or(Value::Null);
+    let mut leaves = Vec::new();
+    collect_leaves(&value, String::new(), &mut leaves);
+
+    leaves
+        .into_iter()
+        .map(|path| {
+            let leaf = path
+                .split('.')
+                .fold(&value, |current, segment| &current[segment])
+                .clone();
+            (path, leaf)
+        })
+        .collect()
+}
+
+fn file_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(unix)]
+type HangupSignal = Option<tokio::signal::unix::Signal>;
+#[cfg(not(unix))]
+type HangupSignal = ();
+
+#[cfg(unix)]
+fn hangup_signal() -> HangupSignal {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok()
+}
+
+#[cfg(not(unix))]
+fn hangup_signal() -> HangupSignal {}
+
+#[cfg(unix)]
+async fn recv_hangup(signal: &mut HangupSignal) {
+    match signal {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn recv_hangup(_signal: &mut HangupSignal) {
+    std::future::pending().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_changes_split_by_reloadability() {
+        let old = Config::default();
+        let mut new = old.clone();
+        new.node_red.http_endpoint = Some("http://nodered:1880/bloodsniffer".to_string());
+        new.logging.level = LogLevel::Debug;
+        new.server.port = 4000;
+
+        let changes = ConfigChanges::between(&old, &new);
+        assert_eq!(
+            changes.applied,
+            vec!["logging.level", "node_red.http_endpoint"]
+        );
+        assert_eq!(changes.restart_required, vec!["server.port"]);
+        assert!(ConfigChanges::between(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_reload_applies_only_reloadable_sections() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bloodsniffer.toml");
+        std::fs::write(
+            &path,
+            "dev_mode = true\n[server]\nport = 4000\n[pipeline]\ndatapipe_interval_secs = 15\n",
+        )
+        .unwrap();
+
+        let loader = ConfigLoader::new().with_file(&path).without_env();
+        let current = Config {
+            dev_mode: true,
+            ..Config::default()
+        };
+
+        let (next, changes) = reload_config(&loader, &current).unwrap();
+        assert_eq!(next.pipeline.datapipe_interval_secs, 15);
+        assert_eq!(next.server.port, 3000);
+        assert_eq!(changes.applied, vec!["pipeline.datapipe_interval_secs"]);
+        assert_eq!(changes.restart_required, vec!["server.port"]);
+    }
+
+    #[tokio::test]
+    async fn test_apply_runtime_keeps_unread_messages() {
+        let config = Config::default();
+        let runtime = RwLock::new(RuntimeConfig::from_config(&config));
+        let node_red = RwLock::new(runtime.read().await.node_red_bridge());
+        let message = node_red_bridge::NodeRedMessage::new("in".to_string(), Value::Null);
+        node_red.read().await.sender().send(message).unwrap();
+
+        let mut next = RuntimeConfig::from_config(&config);
+        next.datapipe_interval_secs = 15;
+        apply_runtime(&runtime, &node_red, next).await;
+
+        assert_eq!(runtime.read().await.datapipe_interval_secs, 15);
+        let carried = node_red.write().await.try_receive().unwrap();
+        assert_eq!(carried.topic, "in");
+        assert!(node_red.write().await.try_receive().is_none());
+    }
+
+    #[test]
+    fn test_reload_rejects_invalid_reloadable_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bloodsniffer.toml");
+        std::fs::write(&path, "[pipeline]\ndatapipe_interval_secs = 0\n").unwrap();
+
+        let loader = ConfigLoader::new().with_file(&path).without_env();
+        let err = reload_config(&loader, &Config::default()).unwrap_err();
+        let errors = err.downcast_ref::<ConfigErrors>().unwrap();
+
+        // Insecure defaults need a restart to change, so only the interval blocks the reload
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].field, "pipeline.datapipe_interval_secs");
+    }
+}
@@ -0,0 +1,342 @@
+// Configuration validation
+// Collects every problem with its field path instead of failing on the first one
+
+use std::fmt;
+use std::net::SocketAddr;
+
+use super::Config;
+
+/// Values shipped in the defaults that must never reach production
+const DEFAULT_PASSWORD: &str = "ChangeMe123!";
+const DEFAULT_JWT_SECRET: &str = "change-me-in-prod";
+
+const MIN_JWT_SECRET_LEN: usize = 32;
+const MIN_ADMIN_PASSWORD_LEN: usize = 12;
+
+/// A single configuration problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// Dotted field path, e.g. `auth.jwt_secret`
+    pub field: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Every problem found while loading or validating a configuration
+#[derive(Debug, thiserror::Error)]
+pub struct ConfigErrors(pub Vec<ConfigIssue>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} configuration error(s):", self.0.len())?;
+        for issue in &self.0 {
+            write!(f, "\n  - {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Validate the configuration, reporting all problems at once.
+    /// Insecure defaults are only accepted when `dev_mode` is enabled.
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut issues = Vec::new();
+        let mut check = |ok: bool, field: &str, message: &str| {
+            if !ok {
+                issues.push(ConfigIssue::new(field, message));
+            }
+        };
+
+        // Server
+        check(self.server.port != 0, "server.port", "must not be 0");
+        check(
+            format!("{}:{}", self.server.host, self.server.port)
+                .parse::<SocketAddr>()
+                .is_ok(),
+            "server.host",
+            "must be an IP address",
+        );
+
+        // TLS: development certificates are generated on startup, production ones must exist
+        let tls = &self.server.tls;
+        if tls.enabled && !self.dev_mode {
+            for (field, path) in [
+                ("server.tls.cert_path", &tls.cert_path),
+                ("server.tls.key_path", &tls.key_path),
+            ] {
+                match path {
+                    Some(path) => check(path.is_file(), field, "file does not exist"),
+                    None => check(false, field, "is required when TLS is enabled"),
+                }
+            }
+        }
+        if let Some(ca_path) = &tls.client_ca_path {
+            check(
+                ca_path.is_file(),
+                "server.tls.client_ca_path",
+                "file does not exist",
+            );
+        }
+        check(
+            !tls.require_client_cert || (tls.enabled && tls.client_ca_path.is_some()),
+            "server.tls.require_client_cert",
+            "requires TLS to be enabled and server.tls.client_ca_path",
+        );
+
+        // Database
+        check(
+            !self.database.path.as_os_str().is_empty(),
+            "database.path",
+            "must not be empty",
+        );
+        if let Some(key_file) = &self.database.encryption.key_file {
+            check(
+                key_file.is_file(),
+                "database.encryption.key_file",
+                "file does not exist",
+            );
+        }
+
+        // Node-RED
+        if let Some(endpoint) = &self.node_red.http_endpoint {
+            check(
+                endpoint.starts_with("http://") || endpoint.starts_with("https://"),
+                "node_red.http_endpoint",
+                "must be an http:// or https:// URL",
+            );
+        }
+        if let Some(broker) = &self.node_red.mqtt_broker {
+            check(
+                ["tcp://", "ssl://", "mqtt://", "mqtts://", "ws://", "wss://"]
+                    .iter()
+                    .any(|scheme| broker.starts_with(scheme)),
+                "node_red.mqtt_broker",
+                "must be a tcp://, ssl://, mqtt(s):// or ws(s):// URL",
+            );
+        }
+
+        // Graph
+        check(!self.graph.uri.is_empty(), "graph.uri", "must not be empty");
+
+        // Pipeline
+        check(
+            self.pipeline.datapipe_interval_secs > 0,
+            "pipeline.datapipe_interval_secs",
+            "must be greater than 0",
+        );
+        check(
+            !self.pipeline.work_dir.as_os_str().is_empty(),
+            "pipeline.work_dir",
+            "must not be empty",
+        );
+        check(
+            self.pipeline.workers > 0,
+            "pipeline.workers",
+            "must be greater than 0",
+        );
+        check(
+            self.pipeline.retry.max_attempts > 0,
+            "pipeline.retry.max_attempts",
+            "must be greater than 0",
+        );
+        check(
+            self.pipeline.retry.backoff_secs > 0,
+            "pipeline.retry.backoff_secs",
+            "must be greater than 0",
+        );
+        check(
+            self.pipeline.retry.max_backoff_secs >= self.pipeline.retry.backoff_secs,
+            "pipeline.retry.max_backoff_secs",
+            "must not be less than pipeline.retry.backoff_secs",
+        );
+
+        let handoff = &self.pipeline.handoff;
+        for (field, url) in [
+            (
+                "pipeline.handoff.fire_marshal_url",
+                &handoff.fire_marshal_url,
+            ),
+            ("pipeline.handoff.callback_url", &handoff.callback_url),
+        ] {
+            if let Some(url) = url {
+                check(
+                    url.starts_with("http://") || url.starts_with("https://"),
+                    field,
+                    "must be an http:// or https:// URL",
+                );
+                check(
+                    handoff.token.is_some(),
+                    "pipeline.handoff.token",
+                    &format!("is required with {}", field),
+                );
+            }
+        }
+        check(
+            handoff.timeout_secs > 0,
+            "pipeline.handoff.timeout_secs",
+            "must be greater than 0",
+        );
+        check(
+            handoff.listen.parse::<SocketAddr>().is_ok(),
+            "pipeline.handoff.listen",
+            "must be an IP address and port",
+        );
+        check(
+            handoff.token.is_some() || handoff.listens_on_loopback(),
+            "pipeline.handoff.token",
+            "is required when pipeline.handoff.listen is not a loopback address",
+        );
+
+        // Extraction
+        if let Some(schema_file) = &self.extraction.schema_file {
+            check(
+                schema_file.is_file(),
+                "extraction.schema_file",
+                "file does not exist",
+            );
+        }
+
+        // Authentication
+        check(
+            (1..=24 * 30).contains(&self.auth.session_duration_hours),
+            "auth.session_duration_hours",
+            "must be between 1 and 720",
+        );
+        check(
+            !self.default_admin.principal_name.is_empty(),
+            "default_admin.principal_name",
+            "must not be empty",
+        );
+
+        // Insecure values are tolerated in dev mode only
+        if !self.dev_mode {
+            check(
+                self.auth.jwt_secret != DEFAULT_JWT_SECRET,
+                "auth.jwt_secret",
+                "is the built-in default",
+            );
+            check(
+                self.auth.jwt_secret.len() >= MIN_JWT_SECRET_LEN,
+                "auth.jwt_secret",
+                "must be at least 32 characters",
+            );
+            if let Some(token) = &self.pipeline.handoff.token {
+                check(
+                    token.len() >= MIN_JWT_SECRET_LEN,
+                    "pipeline.handoff.token",
+                    "must be at least 32 characters",
+                );
+            }
+            check(
+                self.default_admin.password != DEFAULT_PASSWORD,
+                "default_admin.password",
+                "is the built-in default",
+            );
+            check(
+                self.default_admin.password.len() >= MIN_ADMIN_PASSWORD_LEN,
+                "default_admin.password",
+                "must be at least 12 characters",
+            );
+            check(
+                !self.graph.password.is_empty() && self.graph.password != DEFAULT_PASSWORD,
+                "graph.password",
+                "is empty or the built-in default",
+            );
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(issues))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_rejected_outside_dev_mode() {
+        let mut config = Config::default();
+        config.auth.jwt_secret = DEFAULT_JWT_SECRET.to_string();
+
+        let errors = config.validate().unwrap_err();
+        let fields: Vec<&str> = errors.0.iter().map(|i| i.field.as_str()).collect();
+        assert!(fields.contains(&"auth.jwt_secret"));
+        assert!(fields.contains(&"default_admin.password"));
+        assert!(fields.contains(&"graph.password"));
+
+        config.dev_mode = true;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_reports_all_errors() {
+        let mut config = Config {
+            dev_mode: true,
+            ..Config::default()
+        };
+        config.server.port = 0;
+        config.server.host = "not an ip".to_string();
+        config.node_red.http_endpoint = Some("localhost:1880".to_string());
+        config.pipeline.datapipe_interval_secs = 0;
+
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.0.len(), 4);
+        assert!(errors.to_string().contains("node_red.http_endpoint"));
+    }
+
+    #[test]
+    fn test_tls_requires_certificates_outside_dev_mode() {
+        let mut config = Config {
+            dev_mode: true,
+            ..Config::default()
+        };
+        config.server.tls.enabled = true;
+        assert!(config.validate().is_ok());
+
+        config.dev_mode = false;
+        let errors = config.validate().unwrap_err();
+        let fields: Vec<&str> = errors.0.iter().map(|i| i.field.as_str()).collect();
+        assert!(fields.contains(&"server.tls.cert_path"));
+        assert!(fields.contains(&"server.tls.key_path"));
+    }
+
+    #[test]
+    fn test_reachable_listen_requires_token() {
+        let mut config = Config {
+            dev_mode: true,
+            ..Config::default()
+        };
+        config.pipeline.handoff.listen = "0.0.0.0:3001".to_string();
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.0[0].field, "pipeline.handoff.token");
+
+        config.pipeline.handoff.token = Some("x".repeat(32));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_secure_config_passes() {
+        let mut config = Config::default();
+        config.auth.jwt_secret = "x".repeat(48);
+        config.default_admin.password = "a-long-admin-passphrase".to_string();
+        config.graph.password = "graph-secret".to_string();
+        assert!(config.validate().is_ok());
+    }
+}
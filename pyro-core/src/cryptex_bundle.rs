@@ -0,0 +1,613 @@
+// Cryptex bundles
+// Portable gzip-compressed tar archives of a cryptex (or subtree) with a versioned
+// manifest and integrity hashes, and a merging import with conflict policies
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::cryptex_history::content_hash;
+use crate::cryptex_tree::{CryptexTree, FunctionEntry, FunctionUpdate};
+
+/// Identifies a cryptex bundle manifest
+pub const BUNDLE_FORMAT: &str = "pyro-cryptex-bundle";
+
+/// Newest bundle format this build reads and the one it writes
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const FUNCTIONS_FILE: &str = "functions.json";
+
+/// Most a single archive entry may decompress to
+const MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Most a whole archive may decompress to
+const MAX_BUNDLE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Description of a bundle's contents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    /// Subtree the bundle was exported from; `None` for a whole cryptex
+    pub root: Option<String>,
+    /// SHA-256 of every data file in the archive
+    pub files: Vec<BundleFile>,
+    /// Content hash of every function, in archive order
+    pub functions: Vec<BundleFunction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleFunction {
+    pub path_name: String,
+    pub content_hash: String,
+}
+
+/// A verified bundle read back from an archive
+#[derive(Debug, Clone)]
+pub struct CryptexBundle {
+    pub manifest: BundleManifest,
+    /// Functions ordered parents first
+    pub functions: Vec<FunctionEntry>,
+}
+
+/// Why a bundle could not be read
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("archive is missing {0}")]
+    MissingFile(String),
+    #[error("not a cryptex bundle (format '{0}')")]
+    UnknownFormat(String),
+    #[error("bundle version {0} is newer than the supported version {BUNDLE_FORMAT_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("integrity check failed for {0}")]
+    IntegrityMismatch(String),
+    #[error("archive entry {0} is not listed in the manifest")]
+    UnexpectedFile(String),
+    #[error("archive entry {0} exceeds the bundle size limit")]
+    TooLarge(String),
+}
+
+/// What to do when an imported function has the same name as an existing sibling
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the existing function; imported children merge below it
+    #[default]
+    Skip,
+    /// Replace the existing function's code and pseudocode
+    Overwrite,
+    /// Import under a new name (`name_2`, `name_3`, ...)
+    Rename,
+}
+
+/// Outcome of merging a bundle into a tree. Paths are the tree's path names
+/// before it is saved.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub skipped: Vec<String>,
+    /// Functions imported under a new name (bundle path -> new name)
+    pub renamed: BTreeMap<String, String>,
+}
+
+impl ImportReport {
+    /// Translate path names after the tree was saved (old -> new)
+    pub fn apply_renames(&mut self, renamed: &BTreeMap<String, String>) {
+        for paths in [&mut self.created, &mut self.overwritten, &mut self.skipped] {
+            for path in paths.iter_mut() {
+                if let Some(new) = renamed.get(path) {
+                    *path = new.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Append a regular file to a tar archive
+pub(crate) fn append_tar_file<W: Write>(
+    archive: &mut tar::Builder<W>,
+    path: &str,
+    contents: &[u8],
+    mtime: u64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, path, contents)
+        .with_context(|| format!("Failed to write {} to archive", path))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Functions of `tree` below `root` (or all of them), parents first, with the
+/// bundle roots detached from parents outside the bundle
+fn collect_functions(tree: &CryptexTree, root: Option<&str>) -> Option<Vec<FunctionEntry>> {
+    let paths: Vec<String> = match root {
+        Some(root) => {
+            tree.get(root)?;
+            let mut paths = vec![root.to_string()];
+            paths.extend(tree.descendants(root));
+            paths
+        }
+        None => {
+            let mut paths = Vec::new();
+            for entry in tree.entries().filter(|e| {
+                e.parent
+                    .as_deref()
+                    .is_none_or(|parent| tree.get(parent).is_none())
+            }) {
+                paths.push(entry.path_name.clone());
+                paths.extend(tree.descendants(&entry.path_name));
+            }
+            paths
+        }
+    };
+
+    let included: HashSet<&str> = paths.iter().map(String::as_str).collect();
+    let functions = paths
+        .iter()
+        .filter_map(|path| tree.get(path))
+        .map(|entry| FunctionEntry {
+            parent: entry
+                .parent
+                .clone()
+                .filter(|parent| included.contains(parent.as_str())),
+            children: entry
+                .children
+                .iter()
+                .filter(|child| included.contains(child.as_str()))
+                .cloned()
+                .collect(),
+            ..entry.clone()
+        })
+        .collect();
+
+    Some(functions)
+}
+
+/// Export a cryptex or subtree as a bundle (dictionary: export_bundle)
+/// Pseudocode: Collect the functions parents first, hash each function and the data file,
+/// then write the manifest and data into a gzip-compressed tar archive
+pub fn export_bundle<W: Write>(
+    tree: &CryptexTree,
+    root: Option<&str>,
+    writer: W,
+) -> Result<BundleManifest> {
+    let functions = collect_functions(tree, root)
+        .with_context(|| format!("Function '{}' not found", root.unwrap_or_default()))?;
+    let data = serde_json::to_vec_pretty(&functions)?;
+
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_FORMAT_VERSION,
+        created_at: Utc::now(),
+        root: root.map(str::to_string),
+        files: vec![BundleFile {
+            path: FUNCTIONS_FILE.to_string(),
+            size: data.len() as u64,
+            sha256: sha256_hex(&data),
+        }],
+        functions: functions
+            .iter()
+            .map(|f| BundleFunction {
+                path_name: f.path_name.clone(),
+                content_hash: content_hash(&f.name, &f.function_code, &f.pseudocode),
+            })
+            .collect(),
+    };
+    let manifest_data = serde_json::to_vec_pretty(&manifest)?;
+
+    let mut archive = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    let mtime = manifest.created_at.timestamp().max(0) as u64;
+    append_tar_file(&mut archive, MANIFEST_FILE, &manifest_data, mtime)?;
+    append_tar_file(&mut archive, FUNCTIONS_FILE, &data, mtime)?;
+    archive.into_inner()?.finish()?;
+
+    Ok(manifest)
+}
+
+/// Read and verify a bundle (dictionary: read_bundle)
+/// Pseudocode: Unpack the archive, which starts with the manifest, refusing entries the
+/// manifest does not list and entries or archives that decompress past the size limits;
+/// check the manifest format and version, then verify the data file hash and every
+/// function's content hash
+pub fn read_bundle<R: Read>(reader: R) -> Result<CryptexBundle> {
+    let mut manifest: Option<BundleManifest> = None;
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut remaining = MAX_BUNDLE_BYTES;
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    for entry in archive.entries().context("Failed to read bundle archive")? {
+        let mut entry = entry.context("Failed to read bundle entry")?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let expected = match &manifest {
+            None => path == MANIFEST_FILE,
+            Some(manifest) => {
+                !files.contains_key(&path) && manifest.files.iter().any(|f| f.path == path)
+            }
+        };
+        if !expected {
+            return Err(BundleError::UnexpectedFile(path).into());
+        }
+
+        let contents = read_limited(&mut entry, MAX_FILE_BYTES.min(remaining), &path)?;
+        remaining -= contents.len() as u64;
+        match manifest {
+            None => manifest = Some(parse_manifest(&contents)?),
+            Some(_) => {
+                files.insert(path, contents);
+            }
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| BundleError::MissingFile(MANIFEST_FILE.to_string()))?;
+    for file in &manifest.files {
+        let contents = files
+            .get(&file.path)
+            .ok_or_else(|| BundleError::MissingFile(file.path.clone()))?;
+        if contents.len() as u64 != file.size || sha256_hex(contents) != file.sha256 {
+            return Err(BundleError::IntegrityMismatch(file.path.clone()).into());
+        }
+    }
+
+    let data = files
+        .get(FUNCTIONS_FILE)
+        .ok_or_else(|| BundleError::MissingFile(FUNCTIONS_FILE.to_string()))?;
+    let functions: Vec<FunctionEntry> =
+        serde_json::from_slice(data).context("Failed to parse bundle functions")?;
+
+    let expected: HashMap<&str, &str> = manifest
+        .functions
+        .iter()
+        .map(|f| (f.path_name.as_str(), f.content_hash.as_str()))
+        .collect();
+    if expected.len() != functions.len() {
+        return Err(BundleError::IntegrityMismatch(FUNCTIONS_FILE.to_string()).into());
+    }
+    for function in &functions {
+        let hash = content_hash(
+            &function.name,
+            &function.function_code,
+            &function.pseudocode,
+        );
+        if expected.get(function.path_name.as_str()) != Some(&hash.as_str()) {
+            return Err(BundleError::IntegrityMismatch(function.path_name.clone()).into());
+        }
+    }
+
+    Ok(CryptexBundle {
+        manifest,
+        functions,
+    })
+}
+
+/// Read an archive entry, refusing to decompress more than `limit` bytes of it
+fn read_limited<R: Read>(entry: R, limit: u64, path: &str) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    entry
+        .take(limit + 1)
+        .read_to_end(&mut contents)
+        .with_context(|| format!("Failed to read {} from bundle", path))?;
+    if contents.len() as u64 > limit {
+        return Err(BundleError::TooLarge(path.to_string()).into());
+    }
+    Ok(contents)
+}
+
+/// Parse a manifest and check this build can read the bundle it describes
+fn parse_manifest(data: &[u8]) -> Result<BundleManifest> {
+    let manifest: BundleManifest =
+        serde_json::from_slice(data).context("Failed to parse bundle manifest")?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(BundleError::UnknownFormat(manifest.format).into());
+    }
+    if manifest.version > BUNDLE_FORMAT_VERSION {
+        return Err(BundleError::UnsupportedVersion(manifest.version).into());
+    }
+    Ok(manifest)
+}
+
+/// Merge a bundle into a tree (dictionary: import_bundle)
+/// Pseudocode: Walk the bundle parents first, placing its roots under `parent`; when a
+/// sibling with the same name exists apply the conflict policy, then continue with the
+/// children below whichever function now stands for the imported one
+pub fn import_bundle(
+    tree: &mut CryptexTree,
+    bundle: &CryptexBundle,
+    parent: Option<&str>,
+    policy: ConflictPolicy,
+) -> Result<ImportReport> {
+    if let Some(parent) = parent {
+        tree.get(parent)
+            .with_context(|| format!("Function '{}' not found", parent))?;
+    }
+
+    let by_path: HashMap<&str, &FunctionEntry> = bundle
+        .functions
+        .iter()
+        .map(|f| (f.path_name.as_str(), f))
+        .collect();
+    let mut queue: VecDeque<(&FunctionEntry, Option<String>)> = bundle
+        .functions
+        .iter()
+        .filter(|f| f.parent.as_deref().is_none_or(|p| !by_path.contains_key(p)))
+        .map(|f| (f, parent.map(str::to_string)))
+        .collect();
+
+    let mut report = ImportReport::default();
+    let mut visited = HashSet::new();
+    while let Some((function, target_parent)) = queue.pop_front() {
+        if !visited.insert(function.path_name.as_str()) {
+            continue;
+        }
+
+        let existing = tree
+            .child_named(target_parent.as_deref(), &function.name)
+            .map(|e| e.path_name.clone());
+        let placed = match (existing, policy) {
+            (Some(existing), ConflictPolicy::Skip) => {
+                report.skipped.push(existing.clone());
+                existing
+            }
+            (Some(existing), ConflictPolicy::Overwrite) => {
+                tree.update(
+                    &existing,
+                    FunctionUpdate {
+                        name: None,
+                        function_code: Some(function.function_code.clone()),
+                        pseudocode: Some(function.pseudocode.clone()),
+                    },
+                )?;
+                report.overwritten.push(existing.clone());
+                existing
+            }
+            (Some(_), ConflictPolicy::Rename) => {
+                let name = (2..)
+                    .map(|n| format!("{}_{}", function.name, n))
+                    .find(|name| tree.child_named(target_parent.as_deref(), name).is_none())
+                    .expect("unbounded suffixes");
+                let path = tree.insert(
+                    target_parent.as_deref(),
+                    &name,
+                    &function.function_code,
+                    &function.pseudocode,
+                )?;
+                report.renamed.insert(function.path_name.clone(), name);
+                report.created.push(path.clone());
+                path
+            }
+            (None, _) => {
+                let path = tree.insert(
+                    target_parent.as_deref(),
+                    &function.name,
+                    &function.function_code,
+                    &function.pseudocode,
+                )?;
+                report.created.push(path.clone());
+                path
+            }
+        };
+
+        for child in function
+            .children
+            .iter()
+            .filter_map(|c| by_path.get(c.as_str()))
+        {
+            queue.push_back((child, Some(placed.clone())));
+        }
+    }
+
+    Ok(report)
+}
+
+/// Export the cryptex at `cryptex_path` to a bundle file
+pub fn export_cryptex<P: AsRef<Path>, Q: AsRef<Path>>(
+    cryptex_path: P,
+    root: Option<&str>,
+    bundle_path: Q,
+) -> Result<BundleManifest> {
+    let tree = CryptexTree::load(cryptex_path)?;
+    let bundle_path = bundle_path.as_ref();
+    let file = std::fs::File::create(bundle_path)
+        .with_context(|| format!("Failed to create bundle {:?}", bundle_path))?;
+    export_bundle(&tree, root, std::io::BufWriter::new(file))
+}
+
+/// Import a bundle file into the cryptex at `cryptex_path`, returning final path names
+pub fn import_cryptex<P: AsRef<Path>, Q: AsRef<Path>>(
+    cryptex_path: P,
+    bundle_path: Q,
+    parent: Option<&str>,
+    policy: ConflictPolicy,
+) -> Result<ImportReport> {
+    let bundle_path = bundle_path.as_ref();
+    let file = std::fs::File::open(bundle_path)
+        .with_context(|| format!("Failed to open bundle {:?}", bundle_path))?;
+    let bundle = read_bundle(std::io::BufReader::new(file))?;
+
+    let mut tree = CryptexTree::load(&cryptex_path)?;
+    let mut report = import_bundle(&mut tree, &bundle, parent, policy)?;
+    let renamed = tree.save(&cryptex_path)?;
+    report.apply_renames(&renamed);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path_name: &str, parent: Option<&str>, children: &[&str]) -> FunctionEntry {
+        let name = path_name.rsplit('/').next().unwrap();
+        FunctionEntry {
+            name: name.to_string(),
+            path_name: path_name.to_string(),
+            parent: parent.map(str::to_string),
+            children: children.iter().map(|c| c.to_string()).collect(),
+            function_code: format!("fn {}() {{}}", name),
+            pseudocode: format!("Pseudocode for {}", name),
+        }
+    }
+
+    fn source_tree() -> CryptexTree {
+        CryptexTree::from_entries([
+            entry("extract", None, &["extract/parse"]),
+            entry("extract/parse", Some("extract"), &["extract/parse/nodes"]),
+            entry("extract/parse/nodes", Some("extract/parse"), &[]),
+            entry("ingest", None, &[]),
+        ])
+    }
+
+    fn bundle_bytes(tree: &CryptexTree, root: Option<&str>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        export_bundle(tree, root, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_export_roundtrip_subtree() {
+        let bytes = bundle_bytes(&source_tree(), Some("extract/parse"));
+        let bundle = read_bundle(bytes.as_slice()).unwrap();
+
+        assert_eq!(bundle.manifest.version, BUNDLE_FORMAT_VERSION);
+        assert_eq!(bundle.manifest.root.as_deref(), Some("extract/parse"));
+        let paths: Vec<&str> = bundle
+            .functions
+            .iter()
+            .map(|f| f.path_name.as_str())
+            .collect();
+        assert_eq!(paths, vec!["extract/parse", "extract/parse/nodes"]);
+        // The subtree root is detached from its parent outside the bundle
+        assert!(bundle.functions[0].parent.is_none());
+
+        let whole = read_bundle(bundle_bytes(&source_tree(), None).as_slice()).unwrap();
+        assert_eq!(whole.functions.len(), 4);
+    }
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut archive = tar::Builder::new(GzEncoder::new(&mut bytes, Compression::fast()));
+            for (path, contents) in entries {
+                append_tar_file(&mut archive, path, contents, 0).unwrap();
+            }
+            archive.into_inner().unwrap().finish().unwrap();
+        }
+        bytes
+    }
+
+    fn bundle_error(bytes: &[u8]) -> BundleError {
+        read_bundle(bytes)
+            .unwrap_err()
+            .downcast::<BundleError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tampered_bundle_rejected() {
+        let tree = source_tree();
+        let mut bundle = read_bundle(bundle_bytes(&tree, None).as_slice()).unwrap();
+        bundle.manifest.functions[0].content_hash = "0".repeat(64);
+
+        // Rebuild the archive with the altered manifest
+        let manifest = serde_json::to_vec(&bundle.manifest).unwrap();
+        let functions = serde_json::to_vec_pretty(&bundle.functions).unwrap();
+        let bytes = archive(&[(MANIFEST_FILE, &manifest), (FUNCTIONS_FILE, &functions)]);
+
+        assert!(matches!(
+            bundle_error(&bytes),
+            BundleError::IntegrityMismatch(_)
+        ));
+    }
+
+    #[test]
+    fn test_unlisted_and_oversized_entries_rejected() {
+        let bundle = read_bundle(bundle_bytes(&source_tree(), None).as_slice()).unwrap();
+        let manifest = serde_json::to_vec(&bundle.manifest).unwrap();
+        let functions = serde_json::to_vec_pretty(&bundle.functions).unwrap();
+
+        let extra = archive(&[
+            (MANIFEST_FILE, &manifest),
+            (FUNCTIONS_FILE, &functions),
+            ("payload.bin", b"unlisted"),
+        ]);
+        assert!(
+            matches!(bundle_error(&extra), BundleError::UnexpectedFile(p) if p == "payload.bin")
+        );
+
+        // Data ahead of the manifest cannot be checked against it
+        let reordered = archive(&[(FUNCTIONS_FILE, &functions), (MANIFEST_FILE, &manifest)]);
+        assert!(matches!(
+            bundle_error(&reordered),
+            BundleError::UnexpectedFile(_)
+        ));
+
+        // Highly compressible data is cut off once it decompresses past the limit
+        let huge = vec![b' '; MAX_FILE_BYTES as usize + 1];
+        let oversized = archive(&[(MANIFEST_FILE, &manifest), (FUNCTIONS_FILE, &huge)]);
+        assert!(matches!(bundle_error(&oversized), BundleError::TooLarge(_)));
+    }
+
+    #[test]
+    fn test_import_conflict_policies() {
+        let bundle = read_bundle(bundle_bytes(&source_tree(), None).as_slice()).unwrap();
+        let mut target = CryptexTree::from_entries([FunctionEntry {
+            function_code: "fn extract() { local() }".to_string(),
+            ..entry("extract", None, &[])
+        }]);
+
+        let report =
+            import_bundle(&mut target.clone(), &bundle, None, ConflictPolicy::Skip).unwrap();
+        assert_eq!(report.skipped, vec!["extract"]);
+        assert_eq!(report.created.len(), 3);
+
+        let report = import_bundle(&mut target, &bundle, None, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(report.overwritten, vec!["extract"]);
+        assert_eq!(
+            target.get("extract").unwrap().function_code,
+            "fn extract() {}"
+        );
+        assert!(target.get("extract/parse/nodes").is_some());
+
+        let report = import_bundle(&mut target, &bundle, None, ConflictPolicy::Rename).unwrap();
+        assert_eq!(report.renamed.get("extract").unwrap(), "extract_2");
+        assert_eq!(report.renamed.get("ingest").unwrap(), "ingest_2");
+        assert!(target.child_named(None, "extract_2").is_some());
+        assert_eq!(target.len(), 8);
+    }
+
+    #[test]
+    fn test_import_cryptex_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bundle_path = temp_dir.path().join("export.cryptex.tar.gz");
+        let source = temp_dir.path().join("source");
+        source_tree().save(&source).unwrap();
+
+        let manifest = export_cryptex(&source, None, &bundle_path).unwrap();
+        assert_eq!(manifest.functions.len(), 4);
+
+        let target = temp_dir.path().join("target");
+        let report = import_cryptex(&target, &bundle_path, None, ConflictPolicy::Skip).unwrap();
+        assert_eq!(report.created.len(), 4);
+        assert_eq!(CryptexTree::load(&target).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,364 @@
+// Cryptex code generation
+// Turn a cryptex tree back into a Rust crate: one module per function with children,
+// functions in tree order and pseudocode as doc comments
+
+use anyhow::{Context, Result};
+use flate2::{write::GzEncoder, Compression};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+
+use crate::cryptex_bundle::append_tar_file;
+use crate::cryptex_tree::{CryptexTree, FunctionEntry};
+
+/// Rust keywords that cannot be used as plain identifiers
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Files of a generated crate, keyed by path relative to the crate root
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedCrate {
+    pub name: String,
+    pub files: BTreeMap<String, String>,
+    /// Functions whose code was not valid Rust and was replaced by a stub
+    pub stubbed: Vec<String>,
+}
+
+impl GeneratedCrate {
+    /// Write the crate as a gzip-compressed tarball rooted at `<name>/`
+    pub fn write_tarball<W: Write>(&self, writer: W) -> Result<()> {
+        let mut archive = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+        let mtime = chrono::Utc::now().timestamp().max(0) as u64;
+        for (path, contents) in &self.files {
+            let path = format!("{}/{}", self.name, path);
+            append_tar_file(&mut archive, &path, contents.as_bytes(), mtime)?;
+        }
+        archive.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+/// Crate name derived from free text, e.g. a store name
+pub fn crate_name(name: &str) -> String {
+    identifier(name)
+}
+
+/// Rust identifier derived from a function name
+fn identifier(name: &str) -> String {
+    let mut ident: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if ident.is_empty() || ident == "_" {
+        ident = "function".to_string();
+    }
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+    ident
+}
+
+/// Check that `code` consists only of Rust items (no inner attributes, which would be
+/// invalid once the code is embedded in a module)
+fn is_rust_items(code: &str) -> bool {
+    syn::parse_file(code).is_ok_and(|file| !file.items.is_empty() && file.attrs.is_empty())
+}
+
+fn push_doc(out: &mut String, prefix: &str, text: &str) {
+    for line in text.trim_end().lines() {
+        if line.is_empty() {
+            out.push_str(prefix.trim_end());
+        } else {
+            let _ = write!(out, "{} {}", prefix, line);
+        }
+        out.push('\n');
+    }
+}
+
+/// Generate a Rust crate from a cryptex tree (dictionary: generate_crate)
+/// Pseudocode: Start at the roots (or `root`), emit every function in its parent's module
+/// with its pseudocode as doc comments, and give each function with children a module of
+/// its own holding those children
+pub fn generate_crate(
+    tree: &CryptexTree,
+    name: &str,
+    root: Option<&str>,
+) -> Result<GeneratedCrate> {
+    let roots: Vec<&FunctionEntry> = match root {
+        Some(root) => vec![tree
+            .get(root)
+            .with_context(|| format!("Function '{}' not found", root))?],
+        None => tree
+            .entries()
+            .filter(|e| e.parent.as_deref().is_none_or(|p| tree.get(p).is_none()))
+            .collect(),
+    };
+
+    let mut generated = GeneratedCrate {
+        name: crate_name(name),
+        ..GeneratedCrate::default()
+    };
+
+    let mut lib = String::new();
+    let _ = writeln!(lib, "//! Generated from cryptex `{}`", name);
+    lib.push_str(
+        "//!\n//! Each function with children in the cryptex becomes a module holding them.\n\n",
+    );
+    lib.push_str("#![allow(dead_code, unused_variables, clippy::all)]\n");
+
+    let mut visited = HashSet::new();
+    let body = emit_module(tree, &roots, "src", &mut generated, &mut visited);
+    if !body.is_empty() {
+        lib.push('\n');
+        lib.push_str(&body);
+    }
+    generated.files.insert("src/lib.rs".to_string(), lib);
+
+    generated.files.insert(
+        "Cargo.toml".to_string(),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+            generated.name
+        ),
+    );
+
+    Ok(generated)
+}
+
+/// Emit `entries` as the items of one module whose child module files live in `dir`
+fn emit_module<'a>(
+    tree: &'a CryptexTree,
+    entries: &[&'a FunctionEntry],
+    dir: &str,
+    generated: &mut GeneratedCrate,
+    visited: &mut HashSet<&'a str>,
+) -> String {
+    let mut modules = String::new();
+    let mut items = String::new();
+    let mut used = HashSet::new();
+
+    for entry in entries {
+        // Guard against corrupt data where a node is its own descendant
+        if !visited.insert(entry.path_name.as_str()) {
+            continue;
+        }
+
+        let base = identifier(&entry.name);
+        let mut ident = base.clone();
+        let mut suffix = 2;
+        while !used.insert(ident.clone()) {
+            ident = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+
+        if !items.is_empty() {
+            items.push('\n');
+        }
+        push_doc(&mut items, "///", &entry.pseudocode);
+        if !entry.pseudocode.trim().is_empty() {
+            items.push_str("///\n");
+        }
+        let _ = writeln!(items, "/// Cryptex path: `{}`", entry.path_name);
+
+        if is_rust_items(&entry.function_code) {
+            items.push_str(entry.function_code.trim_end());
+            items.push('\n');
+        } else {
+            if !entry.function_code.trim().is_empty() {
+                items.push_str("///\n/// Original source:\n///\n/// ```text\n");
+                push_doc(&mut items, "///", &entry.function_code);
+                items.push_str("/// ```\n");
+            }
+            let _ = writeln!(items, "pub fn {}() {{\n    todo!()\n}}", ident);
+            generated.stubbed.push(entry.path_name.clone());
+        }
+
+        let children: Vec<&FunctionEntry> = entry
+            .children
+            .iter()
+            .filter_map(|child| tree.get(child))
+            .collect();
+        if children.is_empty() {
+            continue;
+        }
+
+        let child_dir = format!("{}/{}", dir, ident);
+        let mut module = String::new();
+        let _ = writeln!(module, "//! Functions below `{}`", entry.path_name);
+        let body = emit_module(tree, &children, &child_dir, generated, visited);
+        if !body.is_empty() {
+            module.push('\n');
+            module.push_str(&body);
+        }
+        generated
+            .files
+            .insert(format!("{}/{}.rs", dir, ident), module);
+        let _ = writeln!(modules, "pub mod {};", ident);
+    }
+
+    match (modules.is_empty(), items.is_empty()) {
+        (true, _) => items,
+        (false, true) => modules,
+        (false, false) => format!("{}\n{}", modules, items),
+    }
+}
+
+/// Generate a crate from the cryptex at `cryptex_path` and write it as a tarball
+pub fn generate_crate_tarball<P: AsRef<Path>, Q: AsRef<Path>>(
+    cryptex_path: P,
+    name: &str,
+    root: Option<&str>,
+    tarball_path: Q,
+) -> Result<GeneratedCrate> {
+    let tree = CryptexTree::load(cryptex_path)?;
+    let generated = generate_crate(&tree, name, root)?;
+
+    let tarball_path = tarball_path.as_ref();
+    let file = std::fs::File::create(tarball_path)
+        .with_context(|| format!("Failed to create {:?}", tarball_path))?;
+    generated.write_tarball(std::io::BufWriter::new(file))?;
+
+    Ok(generated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        path_name: &str,
+        parent: Option<&str>,
+        children: &[&str],
+        code: &str,
+    ) -> FunctionEntry {
+        FunctionEntry {
+            name: path_name.rsplit('/').next().unwrap().to_string(),
+            path_name: path_name.to_string(),
+            parent: parent.map(str::to_string),
+            children: children.iter().map(|c| c.to_string()).collect(),
+            function_code: code.to_string(),
+            pseudocode: format!("Pseudocode for {}\n\nSecond paragraph", path_name),
+        }
+    }
+
+    fn sample_tree() -> CryptexTree {
+        CryptexTree::from_entries([
+            entry(
+                "extract",
+                None,
+                &["extract/parse", "extract/type"],
+                "pub fn extract() {}",
+            ),
+            entry(
+                "extract/parse",
+                Some("extract"),
+                &["extract/parse/nodes"],
+                "fn parse(data: &str) -> usize {\n    data.len()\n}",
+            ),
+            // Go source that is not valid Rust
+            entry(
+                "extract/parse/nodes",
+                Some("extract/parse"),
+                &[],
+                "func nodes(x int) error {\n\treturn nil\n}",
+            ),
+            entry("extract/type", Some("extract"), &[], ""),
+            entry("ingest", None, &[], "fn ingest() { extract::extract(); }"),
+        ])
+    }
+
+    /// Parse `path` and every out-of-line module it declares
+    fn parse_module(generated: &GeneratedCrate, path: &str, dir: &str, parsed: &mut Vec<String>) {
+        let source = generated
+            .files
+            .get(path)
+            .unwrap_or_else(|| panic!("missing module file {}", path));
+        let file =
+            syn::parse_file(source).unwrap_or_else(|e| panic!("{} does not parse: {}", path, e));
+        parsed.push(path.to_string());
+
+        for item in file.items {
+            if let syn::Item::Mod(module) = item {
+                if module.content.is_none() {
+                    let name = module.ident.to_string();
+                    let child_dir = format!("{}/{}", dir, name);
+                    parse_module(
+                        generated,
+                        &format!("{}/{}.rs", dir, name),
+                        &child_dir,
+                        parsed,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generated_crate_parses() {
+        let generated = generate_crate(&sample_tree(), "main store", None).unwrap();
+        assert_eq!(generated.name, "main_store");
+        assert!(generated.files["Cargo.toml"].contains("name = \"main_store\""));
+
+        let mut parsed = Vec::new();
+        parse_module(&generated, "src/lib.rs", "src", &mut parsed);
+        let mut rust_files: Vec<&String> = generated
+            .files
+            .keys()
+            .filter(|p| p.ends_with(".rs"))
+            .collect();
+        rust_files.sort();
+        parsed.sort();
+        assert_eq!(parsed.iter().collect::<Vec<_>>(), rust_files);
+        assert_eq!(
+            parsed,
+            vec!["src/extract.rs", "src/extract/parse.rs", "src/lib.rs"]
+        );
+
+        // Functions appear in tree order, invalid code becomes a documented stub
+        let extract = &generated.files["src/extract.rs"];
+        assert!(extract.find("fn parse").unwrap() < extract.find("fn type_").unwrap());
+        assert!(generated.files["src/extract/parse.rs"].contains("/// ```text"));
+        assert_eq!(
+            generated.stubbed,
+            vec!["extract/parse/nodes", "extract/type"]
+        );
+        assert!(generated.files["src/lib.rs"]
+            .contains("/// Pseudocode for ingest\n///\n/// Second paragraph"));
+    }
+
+    #[test]
+    fn test_crate_tarball() {
+        let generated = generate_crate(&sample_tree(), "main", Some("extract/parse")).unwrap();
+        let mut bytes = Vec::new();
+        generated.write_tarball(&mut bytes).unwrap();
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(bytes.as_slice()));
+        let mut paths: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec!["main/Cargo.toml", "main/src/lib.rs", "main/src/parse.rs"]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+// Cryptex revision history
+// Per-function revisions kept next to the cryptex, with content hashes and line diffs
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{Algorithm, ChangeTag, TextDiff};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::cryptex_tree::FunctionEntry;
+
+/// Longest a single diff may search for a minimal edit script
+const DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What produced a revision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionAction {
+    /// Content that existed before history was recorded for the function
+    Baseline,
+    Create,
+    Update,
+    Revert,
+    Delete,
+}
+
+/// One recorded state of a function
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revision {
+    /// Sequence number within the function's history, starting at 1
+    pub revision: u64,
+    pub action: RevisionAction,
+    /// User that made the change; nil for baselines
+    pub author: Uuid,
+    pub created_at: DateTime<Utc>,
+    /// SHA-256 of name, function code and pseudocode
+    pub content_hash: String,
+    /// Path name when the revision was recorded
+    pub path_name: String,
+    pub parent: Option<String>,
+    pub name: String,
+    pub function_code: String,
+    pub pseudocode: String,
+    /// Revision whose content a revert restored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverted_from: Option<u64>,
+}
+
+/// Revision metadata without the function body
+#[derive(Debug, Clone, Serialize)]
+pub struct RevisionSummary {
+    pub revision: u64,
+    pub action: RevisionAction,
+    pub author: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub content_hash: String,
+    pub path_name: String,
+}
+
+impl From<&Revision> for RevisionSummary {
+    fn from(revision: &Revision) -> Self {
+        Self {
+            revision: revision.revision,
+            action: revision.action,
+            author: revision.author,
+            created_at: revision.created_at,
+            content_hash: revision.content_hash.clone(),
+            path_name: revision.path_name.clone(),
+        }
+    }
+}
+
+/// Kind of a line in a diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// One line of a line-based diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// Differences between two revisions of a function
+#[derive(Debug, Clone, Serialize)]
+pub struct RevisionDiff {
+    pub path_name: String,
+    pub from: u64,
+    pub to: u64,
+    pub from_hash: String,
+    pub to_hash: String,
+    /// Old and new name when the function was renamed
+    pub name: Option<(String, String)>,
+    pub function_code: Vec<DiffLine>,
+    pub pseudocode: Vec<DiffLine>,
+}
+
+/// Hash the content of a function (dictionary: cryptex_content_hash)
+/// Pseudocode: SHA-256 over name, function code and pseudocode separated by NUL bytes
+pub fn content_hash(name: &str, function_code: &str, pseudocode: &str) -> String {
+    let mut hasher = Sha256::new();
+    for field in [name, function_code, pseudocode] {
+        hasher.update(field.as_bytes());
+        hasher.update([0u8]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Line diff of two texts (dictionary: diff_lines)
+/// Pseudocode: Linear-space Myers diff over lines, emitting equal, deleted and inserted
+/// lines in order; past the deadline the remaining lines are diffed coarsely rather than
+/// minimally
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let diff = TextDiff::configure()
+        .algorithm(Algorithm::Myers)
+        .timeout(DIFF_TIMEOUT)
+        .diff_slices(&old, &new);
+
+    diff.iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Insert => DiffOp::Insert,
+                ChangeTag::Delete => DiffOp::Delete,
+            },
+            text: change.value().to_string(),
+        })
+        .collect()
+}
+
+/// Revision history of every function in one cryptex, keyed by current path name
+#[derive(Debug, Clone)]
+pub struct CryptexHistory {
+    path: PathBuf,
+    revisions: BTreeMap<String, Vec<Revision>>,
+}
+
+impl CryptexHistory {
+    /// History file stored next to the cryptex at `cryptex_path`
+    pub fn history_path<P: AsRef<Path>>(cryptex_path: P) -> PathBuf {
+        let cryptex_path = cryptex_path.as_ref();
+        let mut name = cryptex_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".history.json");
+        cryptex_path.with_file_name(name)
+    }
+
+    /// Load the history of the cryptex at `cryptex_path`, empty if none was recorded
+    pub fn load<P: AsRef<Path>>(cryptex_path: P) -> Result<Self> {
+        let path = Self::history_path(cryptex_path);
+        let revisions = if path.exists() {
+            let data = std::fs::read(&path)
+                .with_context(|| format!("Failed to read history {:?}", path))?;
+            serde_json::from_slice(&data)
+                .with_context(|| format!("Failed to parse history {:?}", path))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self { path, revisions })
+    }
+
+    /// Write the history, replacing the previous file atomically
+    pub fn save(&self) -> Result<()> {
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, serde_json::to_vec_pretty(&self.revisions)?)
+            .with_context(|| format!("Failed to write history {:?}", temp))?;
+        std::fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to replace history {:?}", self.path))?;
+        Ok(())
+    }
+
+    /// Revisions of a function, oldest first
+    pub fn revisions(&self, path_name: &str) -> &[Revision] {
+        self.revisions
+            .get(path_name)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    pub fn get(&self, path_name: &str, revision: u64) -> Option<&Revision> {
+        self.revisions(path_name)
+            .iter()
+            .find(|r| r.revision == revision)
+    }
+
+    pub fn latest(&self, path_name: &str) -> Option<&Revision> {
+        self.revisions(path_name).last()
+    }
+
+    /// Record the current state of a function that has no history yet
+    pub fn ensure_baseline(&mut self, entry: &FunctionEntry) {
+        if self.revisions(&entry.path_name).is_empty() {
+            self.push(entry, RevisionAction::Baseline, Uuid::nil(), None);
+        }
+    }
+
+    /// Record a new state of a function. Updates that leave the content unchanged
+    /// (e.g. a pure move) are not recorded and return `None`.
+    pub fn record(
+        &mut self,
+        entry: &FunctionEntry,
+        action: RevisionAction,
+        author: Uuid,
+    ) -> Option<&Revision> {
+        let hash = content_hash(&entry.name, &entry.function_code, &entry.pseudocode);
+        let unchanged = self.latest(&entry.path_name).is_some_and(|latest| {
+            latest.content_hash == hash && latest.action != RevisionAction::Delete
+        });
+        if action == RevisionAction::Update && unchanged {
+            return None;
+        }
+
+        Some(self.push(entry, action, author, None))
+    }
+
+    /// Record that `entry` was restored from `revision`
+    pub fn record_revert(
+        &mut self,
+        entry: &FunctionEntry,
+        author: Uuid,
+        revision: u64,
+    ) -> &Revision {
+        self.push(entry, RevisionAction::Revert, author, Some(revision))
+    }
+
+    fn push(
+        &mut self,
+        entry: &FunctionEntry,
+        action: RevisionAction,
+        author: Uuid,
+        reverted_from: Option<u64>,
+    ) -> &Revision {
+        let revisions = self.revisions.entry(entry.path_name.clone()).or_default();
+        revisions.push(Revision {
+            revision: revisions.last().map_or(1, |r| r.revision + 1),
+            action,
+            author,
+            created_at: Utc::now(),
+            content_hash: content_hash(&entry.name, &entry.function_code, &entry.pseudocode),
+            path_name: entry.path_name.clone(),
+            parent: entry.parent.clone(),
+            name: entry.name.clone(),
+            function_code: entry.function_code.clone(),
+            pseudocode: entry.pseudocode.clone(),
+            reverted_from,
+        });
+        revisions.last().expect("revision was just pushed")
+    }
+
+    /// Follow path renames from a cryptex rewrite (old -> new)
+    pub fn rename_paths(&mut self, renamed: &BTreeMap<String, String>) {
+        // Take every renamed history out first so chains like a -> b, b -> c stay intact
+        let moved: Vec<(String, Vec<Revision>)> = renamed
+            .iter()
+            .filter_map(|(old, new)| self.revisions.remove(old).map(|r| (new.clone(), r)))
+            .collect();
+        for (new, revisions) in moved {
+            self.revisions.insert(new, revisions);
+        }
+    }
+
+    /// Diff two revisions of a function
+    pub fn diff(&self, path_name: &str, from: u64, to: u64) -> Option<RevisionDiff> {
+        let old = self.get(path_name, from)?;
+        let new = self.get(path_name, to)?;
+
+        Some(RevisionDiff {
+            path_name: path_name.to_string(),
+            from,
+            to,
+            from_hash: old.content_hash.clone(),
+            to_hash: new.content_hash.clone(),
+            name: (old.name != new.name).then(|| (old.name.clone(), new.name.clone())),
+            function_code: diff_lines(&old.function_code, &new.function_code),
+            pseudocode: diff_lines(&old.pseudocode, &new.pseudocode),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(path_name: &str, code: &str) -> FunctionEntry {
+        FunctionEntry {
+            name: path_name.rsplit('/').next().unwrap().to_string(),
+            path_name: path_name.to_string(),
+            parent: None,
+            children: Vec::new(),
+            function_code: code.to_string(),
+            pseudocode: "Parse users".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let diff = diff_lines("a\nb\nc", "a\nc\nd");
+        let ops: Vec<(DiffOp, &str)> = diff.iter().map(|l| (l.op, l.text.as_str())).collect();
+        assert_eq!(
+            ops,
+            vec![
+                (DiffOp::Equal, "a"),
+                (DiffOp::Delete, "b"),
+                (DiffOp::Equal, "c"),
+                (DiffOp::Insert, "d"),
+            ]
+        );
+
+        // Large unrelated texts diff without a quadratic table
+        let old: String = (0..20_000).map(|i| format!("old {i}\n")).collect();
+        let new: String = (0..20_000).map(|i| format!("new {i}\n")).collect();
+        let diff = diff_lines(&old, &new);
+        assert_eq!(diff.len(), 40_000);
+        assert!(diff.iter().all(|line| line.op != DiffOp::Equal));
+    }
+
+    #[test]
+    fn test_history_records_and_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let cryptex_path = temp_dir.path().join("main");
+        let author = Uuid::new_v4();
+
+        let mut history = CryptexHistory::load(&cryptex_path).unwrap();
+        history.ensure_baseline(&entry("parse", "fn parse() {}"));
+        history.ensure_baseline(&entry("parse", "ignored"));
+        let updated = entry("parse", "fn parse() {\n    todo!()\n}");
+        assert_eq!(
+            history
+                .record(&updated, RevisionAction::Update, author)
+                .unwrap()
+                .revision,
+            2
+        );
+        assert!(history
+            .record(&updated, RevisionAction::Update, author)
+            .is_none());
+
+        let renamed = BTreeMap::from([("parse".to_string(), "extract/parse".to_string())]);
+        history.rename_paths(&renamed);
+        history.save().unwrap();
+
+        let history = CryptexHistory::load(&cryptex_path).unwrap();
+        assert!(CryptexHistory::history_path(&cryptex_path).exists());
+        assert!(history.revisions("parse").is_empty());
+        let revisions = history.revisions("extract/parse");
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].action, RevisionAction::Baseline);
+        assert_eq!(revisions[1].author, author);
+
+        let diff = history.diff("extract/parse", 1, 2).unwrap();
+        assert!(diff.name.is_none());
+        assert_ne!(diff.from_hash, diff.to_hash);
+        assert!(diff
+            .function_code
+            .iter()
+            .any(|l| l.op == DiffOp::Insert && l.text.contains("todo!()")));
+    }
+}
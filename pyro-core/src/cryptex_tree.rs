@@ -0,0 +1,870 @@
+// Cryptex tree queries
+// Read-only snapshot of a cryptex for listing, traversal and full-text search
+
+use anyhow::{Context, Result};
+use cryptex::Cryptex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+/// A function stored in a cryptex
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionEntry {
+    pub name: String,
+    pub path_name: String,
+    pub parent: Option<String>,
+    pub children: Vec<String>,
+    pub function_code: String,
+    pub pseudocode: String,
+}
+
+/// Nested view of a function and its descendants
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    pub path_name: String,
+    pub pseudocode: String,
+    pub children: Vec<TreeNode>,
+    /// True when the depth limit cut off further descendants
+    pub truncated: bool,
+}
+
+/// Search match with the fields that contained every query term
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub name: String,
+    pub path_name: String,
+    pub matched_fields: Vec<&'static str>,
+    /// Line of the first match, trimmed
+    pub snippet: String,
+}
+
+/// Why an edit to the tree was refused
+#[derive(Debug, thiserror::Error)]
+pub enum CryptexEditError {
+    #[error("function '{0}' not found")]
+    NotFound(String),
+    #[error("moving '{path}' under '{parent}' would create a cycle")]
+    Cycle { path: String, parent: String },
+    #[error("function '{path}' still has {count} children")]
+    HasChildren { path: String, count: usize },
+    #[error("function name must not be empty")]
+    EmptyName,
+    /// The cryptex keys functions by parent and name, so siblings cannot share a name
+    #[error("a function named '{name}' already exists under {parent}")]
+    DuplicateName { parent: String, name: String },
+}
+
+impl CryptexEditError {
+    fn duplicate(parent: Option<&str>, name: &str) -> Self {
+        Self::DuplicateName {
+            parent: parent.map_or_else(|| "the root".to_string(), |p| format!("'{}'", p)),
+            name: name.to_string(),
+        }
+    }
+}
+
+/// How deleting a function with children behaves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteMode {
+    /// Refuse to delete a function that still has children
+    #[default]
+    Reject,
+    /// Delete the function together with all of its descendants
+    Cascade,
+}
+
+/// Fields to change on a function; `None` keeps the current value
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FunctionUpdate {
+    pub name: Option<String>,
+    pub function_code: Option<String>,
+    pub pseudocode: Option<String>,
+}
+
+/// Exclusive lock on a cryptex store, held across every process writing it and released on
+/// drop. The lock file sits next to the store because saving replaces the store directory.
+#[derive(Debug)]
+pub struct StoreLock(std::fs::File);
+
+impl StoreLock {
+    /// Wait until no other writer holds the cryptex at `path`
+    pub fn acquire<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let lock_path = sibling_path(path.as_ref(), "lock");
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open cryptex lock {:?}", lock_path))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock cryptex {:?}", path.as_ref()))?;
+        Ok(Self(file))
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// In-memory snapshot of a cryptex keyed by path name
+#[derive(Debug, Clone, Default)]
+pub struct CryptexTree {
+    entries: BTreeMap<String, FunctionEntry>,
+}
+
+impl CryptexTree {
+    /// Load every function of the cryptex stored at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        recover_interrupted_save(path)?;
+        let cryptex =
+            Cryptex::new(path).with_context(|| format!("Failed to open cryptex {:?}", path))?;
+
+        let entries = cryptex
+            .list_functions()
+            .into_iter()
+            .map(|node| FunctionEntry {
+                name: node.name.clone(),
+                path_name: node.path_name.clone(),
+                parent: node.parent.clone(),
+                children: node.children.clone(),
+                function_code: node.function_code.clone(),
+                pseudocode: node.pseudocode.clone(),
+            });
+
+        Ok(Self::from_entries(entries))
+    }
+
+    /// Build a snapshot from entries
+    pub fn from_entries<I: IntoIterator<Item = FunctionEntry>>(entries: I) -> Self {
+        Self {
+            entries: entries
+                .into_iter()
+                .map(|entry| (entry.path_name.clone(), entry))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, path_name: &str) -> Option<&FunctionEntry> {
+        self.entries.get(path_name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All functions ordered by path name
+    pub fn entries(&self) -> impl Iterator<Item = &FunctionEntry> {
+        self.entries.values()
+    }
+
+    /// Nested subtree below `path_name`, limited to `depth` levels of children
+    pub fn subtree(&self, path_name: &str, depth: Option<usize>) -> Option<TreeNode> {
+        let entry = self.get(path_name)?;
+        let mut visited = HashSet::new();
+        Some(self.build_node(entry, depth, &mut visited))
+    }
+
+    fn build_node<'a>(
+        &'a self,
+        entry: &'a FunctionEntry,
+        depth: Option<usize>,
+        visited: &mut HashSet<&'a str>,
+    ) -> TreeNode {
+        visited.insert(&entry.path_name);
+
+        let children: Vec<&FunctionEntry> = entry
+            .children
+            .iter()
+            .filter_map(|child| self.get(child))
+            .collect();
+
+        let (children, truncated) = match depth {
+            Some(0) => (Vec::new(), !children.is_empty()),
+            _ => {
+                let next_depth = depth.map(|d| d - 1);
+                let mut nodes = Vec::with_capacity(children.len());
+                for child in children {
+                    // Guard against corrupt data where a node is its own descendant
+                    if !visited.contains(child.path_name.as_str()) {
+                        nodes.push(self.build_node(child, next_depth, visited));
+                    }
+                }
+                (nodes, false)
+            }
+        };
+
+        TreeNode {
+            name: entry.name.clone(),
+            path_name: entry.path_name.clone(),
+            pseudocode: entry.pseudocode.clone(),
+            children,
+            truncated,
+        }
+    }
+
+    /// Ancestor chain of `path_name`, root first, excluding the node itself
+    pub fn ancestors(&self, path_name: &str) -> Option<Vec<&FunctionEntry>> {
+        let mut current = self.get(path_name)?;
+        let mut chain = Vec::new();
+        let mut visited = HashSet::from([current.path_name.as_str()]);
+
+        while let Some(parent) = current.parent.as_deref().and_then(|p| self.get(p)) {
+            if !visited.insert(&parent.path_name) {
+                break;
+            }
+            chain.push(parent);
+            current = parent;
+        }
+
+        chain.reverse();
+        Some(chain)
+    }
+
+    /// Path names of every descendant of `path_name`, breadth first
+    pub fn descendants(&self, path_name: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        let mut visited = HashSet::from([path_name.to_string()]);
+        let mut queue = VecDeque::from([path_name.to_string()]);
+
+        while let Some(current) = queue.pop_front() {
+            for child in self
+                .get(&current)
+                .map(|e| e.children.as_slice())
+                .unwrap_or(&[])
+            {
+                if self.entries.contains_key(child) && visited.insert(child.clone()) {
+                    found.push(child.clone());
+                    queue.push_back(child.clone());
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Child of `parent` (or root when `None`) with the given name
+    pub fn child_named(&self, parent: Option<&str>, name: &str) -> Option<&FunctionEntry> {
+        match parent {
+            Some(parent) => self
+                .get(parent)?
+                .children
+                .iter()
+                .filter_map(|child| self.get(child))
+                .find(|entry| entry.name == name),
+            None => self
+                .entries
+                .values()
+                .find(|entry| entry.parent.is_none() && entry.name == name),
+        }
+    }
+
+    /// Add a function under `parent` (or as a root), returning its path name.
+    /// Siblings must have distinct names.
+    pub fn insert(
+        &mut self,
+        parent: Option<&str>,
+        name: &str,
+        function_code: &str,
+        pseudocode: &str,
+    ) -> Result<String, CryptexEditError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(CryptexEditError::EmptyName);
+        }
+        if let Some(parent) = parent {
+            if !self.entries.contains_key(parent) {
+                return Err(CryptexEditError::NotFound(parent.to_string()));
+            }
+        }
+
+        let path_name = match parent {
+            Some(parent) => format!("{}/{}", parent, name),
+            None => name.to_string(),
+        };
+        if self.child_named(parent, name).is_some() || self.entries.contains_key(&path_name) {
+            return Err(CryptexEditError::duplicate(parent, name));
+        }
+
+        if let Some(parent) = parent.and_then(|p| self.entries.get_mut(p)) {
+            parent.children.push(path_name.clone());
+        }
+        self.entries.insert(
+            path_name.clone(),
+            FunctionEntry {
+                name: name.to_string(),
+                path_name: path_name.clone(),
+                parent: parent.map(str::to_string),
+                children: Vec::new(),
+                function_code: function_code.to_string(),
+                pseudocode: pseudocode.to_string(),
+            },
+        );
+
+        Ok(path_name)
+    }
+
+    /// Change the name, code or pseudocode of a function
+    pub fn update(
+        &mut self,
+        path_name: &str,
+        update: FunctionUpdate,
+    ) -> Result<&FunctionEntry, CryptexEditError> {
+        if update.name.as_deref().is_some_and(|n| n.trim().is_empty()) {
+            return Err(CryptexEditError::EmptyName);
+        }
+
+        let parent = self
+            .get(path_name)
+            .ok_or_else(|| CryptexEditError::NotFound(path_name.to_string()))?
+            .parent
+            .clone();
+        if let Some(name) = update.name.as_deref().map(str::trim) {
+            let taken = self
+                .child_named(parent.as_deref(), name)
+                .is_some_and(|sibling| sibling.path_name != path_name);
+            if taken {
+                return Err(CryptexEditError::duplicate(parent.as_deref(), name));
+            }
+        }
+
+        let entry = self
+            .entries
+            .get_mut(path_name)
+            .ok_or_else(|| CryptexEditError::NotFound(path_name.to_string()))?;
+
+        if let Some(name) = update.name {
+            entry.name = name.trim().to_string();
+        }
+        if let Some(function_code) = update.function_code {
+            entry.function_code = function_code;
+        }
+        if let Some(pseudocode) = update.pseudocode {
+            entry.pseudocode = pseudocode;
+        }
+
+        Ok(entry)
+    }
+
+    /// Re-parent a function (`None` makes it a root), keeping both parents' children in sync
+    pub fn move_to(
+        &mut self,
+        path_name: &str,
+        new_parent: Option<&str>,
+    ) -> Result<(), CryptexEditError> {
+        let entry = self
+            .get(path_name)
+            .ok_or_else(|| CryptexEditError::NotFound(path_name.to_string()))?;
+        let (old_parent, name) = (entry.parent.clone(), entry.name.clone());
+
+        if let Some(parent) = new_parent {
+            if !self.entries.contains_key(parent) {
+                return Err(CryptexEditError::NotFound(parent.to_string()));
+            }
+            if parent == path_name || self.descendants(path_name).iter().any(|d| d == parent) {
+                return Err(CryptexEditError::Cycle {
+                    path: path_name.to_string(),
+                    parent: parent.to_string(),
+                });
+            }
+        }
+
+        if old_parent.as_deref() == new_parent {
+            return Ok(());
+        }
+        if self.child_named(new_parent, &name).is_some() {
+            return Err(CryptexEditError::duplicate(new_parent, &name));
+        }
+
+        if let Some(old) = old_parent.as_deref().and_then(|p| self.entries.get_mut(p)) {
+            old.children.retain(|child| child != path_name);
+        }
+        if let Some(new) = new_parent.and_then(|p| self.entries.get_mut(p)) {
+            new.children.push(path_name.to_string());
+        }
+        if let Some(entry) = self.entries.get_mut(path_name) {
+            entry.parent = new_parent.map(str::to_string);
+        }
+
+        Ok(())
+    }
+
+    /// Delete a function, returning the path names removed
+    pub fn remove(
+        &mut self,
+        path_name: &str,
+        mode: DeleteMode,
+    ) -> Result<Vec<String>, CryptexEditError> {
+        let entry = self
+            .get(path_name)
+            .ok_or_else(|| CryptexEditError::NotFound(path_name.to_string()))?;
+        let parent = entry.parent.clone();
+
+        let descendants = self.descendants(path_name);
+        if mode == DeleteMode::Reject && !descendants.is_empty() {
+            return Err(CryptexEditError::HasChildren {
+                path: path_name.to_string(),
+                count: entry.children.len(),
+            });
+        }
+
+        if let Some(parent) = parent.as_deref().and_then(|p| self.entries.get_mut(p)) {
+            parent.children.retain(|child| child != path_name);
+        }
+
+        let mut removed = vec![path_name.to_string()];
+        removed.extend(descendants);
+        for path in &removed {
+            self.entries.remove(path);
+        }
+
+        Ok(removed)
+    }
+
+    /// Rewrite the cryptex at `path` with this tree (dictionary: cryptex_tree_save)
+    /// Pseudocode: Refuse a tree with same-named siblings, which the cryptex would merge, put
+    /// back a cryptex an earlier save left aside, re-add every function parent first into a
+    /// staging cryptex, swap it in place of the old one, and return the old -> new path names
+    /// that changed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<BTreeMap<String, String>> {
+        let path = path.as_ref();
+        // Roots are functions without a parent, or whose parent no longer exists
+        let parent_of = |entry: &FunctionEntry| {
+            entry
+                .parent
+                .clone()
+                .filter(|parent| self.entries.contains_key(parent))
+        };
+        let mut siblings = HashSet::new();
+        for entry in self.entries.values() {
+            let parent = parent_of(entry);
+            if !siblings.insert((parent.clone(), entry.name.as_str())) {
+                return Err(CryptexEditError::duplicate(parent.as_deref(), &entry.name).into());
+            }
+        }
+
+        let staging = sibling_path(path, "staging");
+        let backup = sibling_path(path, "previous");
+        recover_interrupted_save(path)?;
+        remove_path(&staging)?;
+        remove_path(&backup)?;
+
+        let mut renamed = BTreeMap::new();
+        {
+            let mut cryptex = Cryptex::new(&staging)
+                .with_context(|| format!("Failed to create staging cryptex {:?}", staging))?;
+
+            let mut queue: VecDeque<(&FunctionEntry, Option<String>)> = self
+                .entries
+                .values()
+                .filter(|entry| parent_of(entry).is_none())
+                .map(|entry| (entry, None))
+                .collect();
+
+            while let Some((entry, parent)) = queue.pop_front() {
+                let node = cryptex.add_function(
+                    parent,
+                    entry.name.clone(),
+                    entry.function_code.clone(),
+                    entry.pseudocode.clone(),
+                )?;
+                let new_path = node.path_name.clone();
+
+                for child in entry.children.iter().filter_map(|c| self.get(c)) {
+                    queue.push_back((child, Some(new_path.clone())));
+                }
+                if new_path != entry.path_name {
+                    renamed.insert(entry.path_name.clone(), new_path);
+                }
+            }
+        }
+
+        if path.exists() {
+            std::fs::rename(path, &backup)
+                .with_context(|| format!("Failed to move {:?} aside", path))?;
+        }
+        if let Err(err) = std::fs::rename(&staging, path) {
+            let _ = std::fs::rename(&backup, path);
+            return Err(err).with_context(|| format!("Failed to replace cryptex {:?}", path));
+        }
+        remove_path(&backup)?;
+
+        Ok(renamed)
+    }
+
+    /// Case-insensitive full-text search over name, function code and pseudocode.
+    /// Every whitespace-separated term must match; name matches rank first.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut hits: Vec<SearchHit> = self
+            .entries
+            .values()
+            .filter_map(|entry| {
+                let fields = [
+                    ("name", entry.name.as_str()),
+                    ("pseudocode", entry.pseudocode.as_str()),
+                    ("function_code", entry.function_code.as_str()),
+                ];
+                let lowered: Vec<String> =
+                    fields.iter().map(|(_, text)| text.to_lowercase()).collect();
+
+                let all_terms_match = terms
+                    .iter()
+                    .all(|term| lowered.iter().any(|text| text.contains(term)));
+                if !all_terms_match {
+                    return None;
+                }
+
+                let matched: Vec<usize> = (0..fields.len())
+                    .filter(|&i| terms.iter().any(|term| lowered[i].contains(term)))
+                    .collect();
+                let first = matched[0];
+                let snippet = fields[first]
+                    .1
+                    .lines()
+                    .find(|line| {
+                        let line = line.to_lowercase();
+                        terms.iter().any(|term| line.contains(term))
+                    })
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+
+                Some(SearchHit {
+                    name: entry.name.clone(),
+                    path_name: entry.path_name.clone(),
+                    matched_fields: matched.iter().map(|&i| fields[i].0).collect(),
+                    snippet,
+                })
+            })
+            .collect();
+
+        hits.sort_by_key(|hit| hit.matched_fields.first() != Some(&"name"));
+        hits
+    }
+}
+
+/// Move the cryptex back from `.previous` when a save stopped after moving it aside and
+/// before swapping the new one in
+fn recover_interrupted_save(path: &Path) -> Result<()> {
+    let backup = sibling_path(path, "previous");
+    if !path.exists() && backup.exists() {
+        std::fs::rename(&backup, path)
+            .with_context(|| format!("Failed to restore cryptex {:?} from {:?}", path, backup))?;
+    }
+    Ok(())
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path).with_context(|| format!("Failed to remove {:?}", path))?;
+    } else if path.exists() {
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        path_name: &str,
+        parent: Option<&str>,
+        children: &[&str],
+        code: &str,
+    ) -> FunctionEntry {
+        FunctionEntry {
+            name: path_name.rsplit('/').next().unwrap().to_string(),
+            path_name: path_name.to_string(),
+            parent: parent.map(str::to_string),
+            children: children.iter().map(|c| c.to_string()).collect(),
+            function_code: code.to_string(),
+            pseudocode: format!("Pseudocode for {}", path_name.rsplit('/').next().unwrap()),
+        }
+    }
+
+    fn sample_tree() -> CryptexTree {
+        CryptexTree::from_entries([
+            entry("extract", None, &["extract/parse"], "fn extract() {}"),
+            entry(
+                "extract/parse",
+                Some("extract"),
+                &["extract/parse/nodes"],
+                "fn parse() { validate_input() }",
+            ),
+            entry(
+                "extract/parse/nodes",
+                Some("extract/parse"),
+                &[],
+                "fn nodes() {\n    let users = load_users();\n}",
+            ),
+            entry("ingest", None, &[], "fn ingest() { parse_stream() }"),
+        ])
+    }
+
+    #[test]
+    fn test_subtree_depth() {
+        let tree = sample_tree();
+
+        let shallow = tree.subtree("extract", Some(1)).unwrap();
+        assert_eq!(shallow.children.len(), 1);
+        assert!(shallow.children[0].children.is_empty());
+        assert!(shallow.children[0].truncated);
+
+        let full = tree.subtree("extract", None).unwrap();
+        assert_eq!(
+            full.children[0].children[0].path_name,
+            "extract/parse/nodes"
+        );
+        assert!(tree.subtree("missing", None).is_none());
+    }
+
+    #[test]
+    fn test_ancestors_root_first() {
+        let tree = sample_tree();
+        let chain: Vec<&str> = tree
+            .ancestors("extract/parse/nodes")
+            .unwrap()
+            .iter()
+            .map(|e| e.path_name.as_str())
+            .collect();
+        assert_eq!(chain, vec!["extract", "extract/parse"]);
+        assert!(tree.ancestors("extract").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_move_updates_both_parents() {
+        let mut tree = sample_tree();
+        tree.move_to("extract/parse/nodes", Some("ingest")).unwrap();
+
+        assert!(tree.get("extract/parse").unwrap().children.is_empty());
+        assert_eq!(
+            tree.get("ingest").unwrap().children,
+            vec!["extract/parse/nodes"]
+        );
+        assert_eq!(
+            tree.get("extract/parse/nodes").unwrap().parent.as_deref(),
+            Some("ingest")
+        );
+
+        tree.move_to("extract/parse/nodes", None).unwrap();
+        assert!(tree.get("ingest").unwrap().children.is_empty());
+        assert!(tree.get("extract/parse/nodes").unwrap().parent.is_none());
+    }
+
+    #[test]
+    fn test_move_rejects_cycles() {
+        let mut tree = sample_tree();
+        assert!(matches!(
+            tree.move_to("extract", Some("extract/parse/nodes")),
+            Err(CryptexEditError::Cycle { .. })
+        ));
+        assert!(matches!(
+            tree.move_to("extract", Some("extract")),
+            Err(CryptexEditError::Cycle { .. })
+        ));
+        assert!(matches!(
+            tree.move_to("extract", Some("missing")),
+            Err(CryptexEditError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_delete_modes() {
+        let mut tree = sample_tree();
+        assert!(matches!(
+            tree.remove("extract/parse", DeleteMode::Reject),
+            Err(CryptexEditError::HasChildren { count: 1, .. })
+        ));
+
+        let removed = tree.remove("extract/parse", DeleteMode::Cascade).unwrap();
+        assert_eq!(removed, vec!["extract/parse", "extract/parse/nodes"]);
+        assert!(tree.get("extract").unwrap().children.is_empty());
+        assert_eq!(tree.len(), 2);
+
+        assert_eq!(
+            tree.remove("ingest", DeleteMode::Reject).unwrap(),
+            vec!["ingest"]
+        );
+    }
+
+    #[test]
+    fn test_insert_and_child_named() {
+        let mut tree = sample_tree();
+        let path = tree
+            .insert(
+                Some("extract"),
+                "resolve",
+                "fn resolve() {}",
+                "Resolve names",
+            )
+            .unwrap();
+        assert_eq!(path, "extract/resolve");
+        assert_eq!(
+            tree.child_named(Some("extract"), "resolve")
+                .unwrap()
+                .path_name,
+            path
+        );
+        assert!(tree.get("extract").unwrap().children.contains(&path));
+
+        // Siblings cannot share a name
+        assert!(matches!(
+            tree.insert(Some("extract"), "resolve", "", ""),
+            Err(CryptexEditError::DuplicateName { .. })
+        ));
+        assert_eq!(
+            tree.child_named(None, "ingest").unwrap().path_name,
+            "ingest"
+        );
+        assert!(matches!(
+            tree.insert(Some("missing"), "x", "", ""),
+            Err(CryptexEditError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_save_roundtrip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("main");
+
+        let mut tree = sample_tree();
+        tree.update(
+            "ingest",
+            FunctionUpdate {
+                pseudocode: Some("Ingest a stream".to_string()),
+                ..FunctionUpdate::default()
+            },
+        )
+        .unwrap();
+        tree.move_to("ingest", Some("extract")).unwrap();
+        let renamed = tree.save(&path).unwrap();
+
+        let ingest_path = renamed
+            .get("ingest")
+            .cloned()
+            .unwrap_or_else(|| "ingest".to_string());
+        let reloaded = CryptexTree::load(&path).unwrap();
+        assert_eq!(reloaded.len(), 4);
+
+        let ingest = reloaded.get(&ingest_path).unwrap();
+        assert_eq!(ingest.pseudocode, "Ingest a stream");
+        let parent = ingest.parent.as_deref().unwrap();
+        assert_eq!(reloaded.get(parent).unwrap().name, "extract");
+        assert!(!temp_dir.path().join("main.staging").exists());
+        assert!(!temp_dir.path().join("main.previous").exists());
+    }
+
+    #[test]
+    fn test_interrupted_save_is_recovered() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("main");
+        sample_tree().save(&path).unwrap();
+
+        // A save that stopped between moving the cryptex aside and swapping in the new one
+        std::fs::rename(&path, temp_dir.path().join("main.previous")).unwrap();
+        assert_eq!(CryptexTree::load(&path).unwrap().len(), 4);
+        assert!(!temp_dir.path().join("main.previous").exists());
+
+        std::fs::rename(&path, temp_dir.path().join("main.previous")).unwrap();
+        let mut tree = CryptexTree::default();
+        tree.insert(None, "extra", "", "").unwrap();
+        tree.save(&path).unwrap();
+        assert_eq!(CryptexTree::load(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_same_named_siblings_are_never_saved() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("main");
+        let mut tree = sample_tree();
+        tree.insert(Some("ingest"), "parse", "fn parse() {}", "")
+            .unwrap();
+        tree.insert(None, "nodes", "", "").unwrap();
+
+        // Moves and renames that would put two "parse" or "nodes" side by side are refused
+        assert!(matches!(
+            tree.move_to("ingest/parse", Some("extract")),
+            Err(CryptexEditError::DuplicateName { .. })
+        ));
+        let rename = FunctionUpdate {
+            name: Some("nodes".to_string()),
+            ..FunctionUpdate::default()
+        };
+        assert!(matches!(
+            tree.update("ingest", rename),
+            Err(CryptexEditError::DuplicateName { .. })
+        ));
+
+        // The same name under different parents survives a save and reload
+        assert!(tree.save(&path).unwrap().is_empty());
+        let reloaded = CryptexTree::load(&path).unwrap();
+        assert_eq!(reloaded.len(), 6);
+        assert!(reloaded.get("extract/parse").is_some());
+        assert!(reloaded.get("ingest/parse").is_some());
+
+        // A tree that already holds same-named siblings is refused before anything is written
+        let mut merged = CryptexTree::from_entries([
+            entry("parse", None, &[], "fn first() {}"),
+            entry("parse#2", None, &[], "fn second() {}"),
+        ]);
+        merged.entries.get_mut("parse#2").unwrap().name = "parse".to_string();
+        assert!(merged.save(&path).is_err());
+        assert_eq!(CryptexTree::load(&path).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_store_lock_excludes_other_writers() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("main");
+        let held = StoreLock::acquire(&path).unwrap();
+
+        // Another open of the lock file, as another process would make, cannot take it
+        let other = std::fs::File::open(temp_dir.path().join("main.lock")).unwrap();
+        assert!(other.try_lock().is_err());
+        drop(held);
+        assert!(other.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_search_all_terms_and_ranking() {
+        let tree = sample_tree();
+
+        let hits = tree.search("PARSE");
+        let paths: Vec<&str> = hits.iter().map(|h| h.path_name.as_str()).collect();
+        // Name match ranks ahead of the code-only match in `ingest`
+        assert_eq!(paths, vec!["extract/parse", "ingest"]);
+        assert_eq!(hits[1].matched_fields, vec!["function_code"]);
+
+        let hits = tree.search("nodes load_users");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].matched_fields,
+            vec!["name", "pseudocode", "function_code"]
+        );
+
+        let hits = tree.search("load_users");
+        assert_eq!(hits[0].snippet, "let users = load_users();");
+
+        assert!(tree.search("   ").is_empty());
+    }
+}
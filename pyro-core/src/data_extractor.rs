@@ -54,19 +54,31 @@ impl BloodHoundExtractor {
         let mut nodes = Vec::new();
         let mut edges = Vec::new();
 
+        // SharpHound files name their collection in `meta.type` ("users", "computers", ...)
+        let collection = json
+            .get("meta")
+            .and_then(|m| m.get("type"))
+            .and_then(|t| t.as_str());
+
         // Extract nodes (computers, users, groups, etc.)
         if let Some(data_array) = json.get("data").and_then(|d| d.as_array()) {
             for item in data_array {
+                let object_id = item
+                    .get("ObjectIdentifier")
+                    .and_then(|o| o.as_str())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string());
+
                 if let Some(node_data) = item.get("Properties") {
-                    let node_type = item
-                        .get("ObjectIdentifier")
-                        .and_then(|o| o.as_str())
-                        .unwrap_or("unknown");
+                    let label = node_data
+                        .get("name")
+                        .and_then(|n| n.as_str())
+                        .unwrap_or(&object_id);
 
                     let node = Node {
-                        id: Uuid::new_v4().to_string(),
-                        label: node_type.to_string(),
-                        node_type: node_type.to_string(),
+                        id: object_id.clone(),
+                        label: label.to_string(),
+                        node_type: Self::object_kind(item, collection),
                         properties: node_data.clone(),
                     };
                     nodes.push(node);
@@ -76,8 +88,12 @@ impl BloodHoundExtractor {
                 if let Some(rels) = item.get("Rels").and_then(|r| r.as_array()) {
                     for rel in rels {
                         let edge = Edge {
-                            source: Uuid::new_v4().to_string(),
-                            target: Uuid::new_v4().to_string(),
+                            source: object_id.clone(),
+                            target: rel
+                                .get("TargetObjectIdentifier")
+                                .and_then(|t| t.as_str())
+                                .map(str::to_string)
+                                .unwrap_or_else(|| Uuid::new_v4().to_string()),
                             edge_type: rel
                                 .get("RelType")
                                 .and_then(|t| t.as_str())
@@ -104,6 +120,33 @@ impl BloodHoundExtractor {
             metadata,
         })
     }
+
+    /// Kind of a BloodHound object, e.g. `User` or `Computer`
+    /// Pseudocode: Prefer the object's own `ObjectType`/`Kind`, otherwise derive it from the
+    /// file's collection type ("users" -> "User"), otherwise "unknown"
+    fn object_kind(item: &Value, collection: Option<&str>) -> String {
+        if let Some(kind) = ["ObjectType", "Kind"]
+            .iter()
+            .find_map(|key| item.get(*key).and_then(|k| k.as_str()))
+            .filter(|k| !k.is_empty())
+        {
+            return kind.to_string();
+        }
+
+        match collection.map(str::to_ascii_lowercase).as_deref() {
+            Some("gpos") => "GPO".to_string(),
+            Some("ous") => "OU".to_string(),
+            Some(collection) if !collection.is_empty() => {
+                let singular = collection.strip_suffix('s').unwrap_or(collection);
+                let mut chars = singular.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            }
+            _ => "unknown".to_string(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -134,4 +177,35 @@ mod tests {
         assert!(result.nodes.len() > 0);
         assert_eq!(result.metadata.source, "bloodhound");
     }
+
+    #[test]
+    fn test_object_kinds_and_identifiers() {
+        let json = json!({
+            "meta": { "type": "computers", "count": 2 },
+            "data": [
+                {
+                    "ObjectIdentifier": "S-1-5-21-1000",
+                    "Properties": { "name": "WS01.CORP.LOCAL" },
+                    "Rels": [
+                        { "RelType": "AdminTo", "TargetObjectIdentifier": "S-1-5-21-2000" }
+                    ]
+                },
+                {
+                    "ObjectIdentifier": "S-1-5-21-3000",
+                    "ObjectType": "Group",
+                    "Properties": {}
+                }
+            ]
+        });
+
+        let result = BloodHoundExtractor::extract_from_json(&json).unwrap();
+        assert_eq!(result.nodes[0].node_type, "Computer");
+        assert_eq!(result.nodes[0].id, "S-1-5-21-1000");
+        assert_eq!(result.nodes[0].label, "WS01.CORP.LOCAL");
+        assert_eq!(result.nodes[1].node_type, "Group");
+        assert_eq!(result.nodes[1].label, "S-1-5-21-3000");
+        assert_eq!(result.edges[0].source, "S-1-5-21-1000");
+        assert_eq!(result.edges[0].target, "S-1-5-21-2000");
+        assert_eq!(result.edges[0].edge_type, "AdminTo");
+    }
 }
@@ -0,0 +1,235 @@
+// Encryption at rest for sensitive database fields
+// AES-256-GCM field encryption keyed from a master key file or environment variable
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::config::EncryptionConfig;
+
+/// Environment variable holding master key material when no key file is configured
+pub const MASTER_KEY_ENV: &str = "BLOODSNIFFER_MASTER_KEY";
+
+/// Prefix marking an encrypted field value (`enc:v1:<key_id>:<base64(nonce || ciphertext)>`)
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Hash a session token for storage (dictionary: hash_session_token)
+/// Pseudocode: Store only the SHA-256 digest of bearer tokens so a leaked database cannot replay sessions
+pub fn hash_session_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Check whether a stored field value is encrypted
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Field cipher backed by a master keyring.
+///
+/// The first key is active and used for all new writes; the remaining keys are
+/// retired and only used to decrypt values written before a rotation.
+pub struct FieldCipher {
+    keys: Vec<(String, Aes256Gcm)>,
+}
+
+impl FieldCipher {
+    /// Create a cipher from `(key_id, key)` pairs, active key first
+    pub fn new(keys: Vec<(String, [u8; KEY_LEN])>) -> Result<Self> {
+        if keys.is_empty() {
+            anyhow::bail!("Master keyring is empty");
+        }
+
+        let mut ciphers = Vec::with_capacity(keys.len());
+        for (key_id, key) in keys {
+            if key_id.is_empty() || key_id.contains(':') {
+                anyhow::bail!("Invalid master key id '{}'", key_id);
+            }
+            if ciphers.iter().any(|(id, _)| id == &key_id) {
+                anyhow::bail!("Duplicate master key id '{}'", key_id);
+            }
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+            ciphers.push((key_id, cipher));
+        }
+
+        Ok(Self { keys: ciphers })
+    }
+
+    /// Parse key material: one `key_id:base64_key` entry per line (or comma separated)
+    pub fn from_key_material(material: &str) -> Result<Self> {
+        let mut keys = Vec::new();
+
+        for entry in material
+            .split(['\n', ','])
+            .map(str::trim)
+            .filter(|e| !e.is_empty() && !e.starts_with('#'))
+        {
+            let (key_id, encoded) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Master key entry must be 'key_id:base64_key'"))?;
+            let bytes = STANDARD
+                .decode(encoded.trim())
+                .with_context(|| format!("Master key '{}' is not valid base64", key_id))?;
+            let key: [u8; KEY_LEN] = bytes.try_into().map_err(|_| {
+                anyhow::anyhow!("Master key '{}' must be {} bytes", key_id, KEY_LEN)
+            })?;
+            keys.push((key_id.trim().to_string(), key));
+        }
+
+        Self::new(keys)
+    }
+
+    /// Load the keyring from the configured key file or environment variable.
+    /// Returns `None` when no master key is configured.
+    pub fn from_config(config: &EncryptionConfig) -> Result<Option<Self>> {
+        if let Some(key_file) = &config.key_file {
+            let material = std::fs::read_to_string(key_file)
+                .with_context(|| format!("Failed to read master key file {:?}", key_file))?;
+            return Self::from_key_material(&material).map(Some);
+        }
+
+        match std::env::var(MASTER_KEY_ENV) {
+            Ok(material) if !material.trim().is_empty() => {
+                Self::from_key_material(&material).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Generate a fresh `key_id:base64_key` entry for a key file
+    pub fn generate_key_entry(key_id: &str) -> String {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        format!("{}:{}", key_id, STANDARD.encode(key))
+    }
+
+    /// Identifier of the key used for new writes
+    pub fn active_key_id(&self) -> &str {
+        &self.keys[0].0
+    }
+
+    /// Encrypt a field value with the active key
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let (key_id, cipher) = &self.keys[0];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt field"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(format!(
+            "{}{}:{}",
+            ENCRYPTED_PREFIX,
+            key_id,
+            STANDARD.encode(sealed)
+        ))
+    }
+
+    /// Decrypt a field value. Plaintext values written before encryption was
+    /// enabled are returned unchanged.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(rest) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let (key_id, encoded) = rest
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Malformed encrypted field"))?;
+        let (_, cipher) = self
+            .keys
+            .iter()
+            .find(|(id, _)| id == key_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown master key id '{}'", key_id))?;
+
+        let sealed = STANDARD
+            .decode(encoded)
+            .context("Encrypted field is not valid base64")?;
+        if sealed.len() < NONCE_LEN {
+            anyhow::bail!("Encrypted field is truncated");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt field with key '{}'", key_id))?;
+
+        String::from_utf8(plaintext).context("Decrypted field is not valid UTF-8")
+    }
+
+    /// Check whether a stored value must be re-encrypted with the active key
+    pub fn needs_rotation(&self, value: &str) -> bool {
+        match value.strip_prefix(ENCRYPTED_PREFIX) {
+            Some(rest) => !rest.starts_with(&format!("{}:", self.active_key_id())),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher_with(entries: &[&str]) -> FieldCipher {
+        FieldCipher::from_key_material(&entries.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let entry = FieldCipher::generate_key_entry("k1");
+        let cipher = cipher_with(&[&entry]);
+
+        let sealed = cipher.encrypt("admin@bloodsniffer.local").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("admin@bloodsniffer.local"));
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), "admin@bloodsniffer.local");
+
+        // Plaintext values pass through untouched
+        assert_eq!(cipher.decrypt("legacy").unwrap(), "legacy");
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old_entry = FieldCipher::generate_key_entry("k1");
+        let new_entry = FieldCipher::generate_key_entry("k2");
+
+        let old_cipher = cipher_with(&[&old_entry]);
+        let sealed = old_cipher.encrypt("secret").unwrap();
+
+        // New key active, old key retained for decryption
+        let rotated = cipher_with(&[&new_entry, &old_entry]);
+        assert_eq!(rotated.active_key_id(), "k2");
+        assert!(rotated.needs_rotation(&sealed));
+        assert_eq!(rotated.decrypt(&sealed).unwrap(), "secret");
+
+        let resealed = rotated.encrypt("secret").unwrap();
+        assert!(!rotated.needs_rotation(&resealed));
+
+        // Without the old key the value can no longer be read
+        let new_only = cipher_with(&[&new_entry]);
+        assert!(new_only.decrypt(&sealed).is_err());
+    }
+
+    #[test]
+    fn test_invalid_key_material() {
+        assert!(FieldCipher::from_key_material("").is_err());
+        assert!(FieldCipher::from_key_material("k1:not-base64!").is_err());
+        assert!(FieldCipher::from_key_material("k1:c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn test_hash_session_token() {
+        let hash = hash_session_token("test_token_123");
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, "test_token_123");
+        assert_eq!(hash, hash_session_token("test_token_123"));
+    }
+}
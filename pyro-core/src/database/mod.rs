@@ -1,14 +1,19 @@
 // Database module for BloodSniffer
 // Translated from cmd/api/src/database
 
+pub mod encryption;
 pub mod migrations;
 pub mod models;
 pub mod redb_store;
 
-pub use models::{AuthSecret, Installation, Role, User, UserSession};
+pub use encryption::{hash_session_token, FieldCipher};
+pub use models::{
+    AuthSecret, CryptexStore, Installation, PipelineProgress, Role, User, UserSession,
+};
 pub use redb_store::RedbDatabase;
 
 use anyhow::Result;
+use pyro_core::handoff::StatusUpdate;
 
 /// Database trait for BloodSniffer
 pub trait Database: Send + Sync {
@@ -27,16 +32,22 @@ pub trait Database: Send + Sync {
     /// Lookup user by principal name
     fn lookup_user(&self, principal_name: &str) -> Result<Option<User>>;
 
+    /// Get user by ID
+    fn get_user(&self, user_id: &uuid::Uuid) -> Result<Option<User>>;
+
     /// Delete user
     fn delete_user(&self, user: &User) -> Result<()>;
 
     /// Initialize secret auth for user
     fn initialize_secret_auth(&self, user: &User, secret: &AuthSecret) -> Result<User>;
 
-    /// Create user session
+    /// Create user session (keyed by its token hash)
     fn create_session(&self, session: &UserSession) -> Result<()>;
 
-    /// Get session by token
+    /// List all sessions
+    fn list_sessions(&self) -> Result<Vec<UserSession>>;
+
+    /// Get session by raw bearer token
     fn get_session(&self, token: &str) -> Result<Option<UserSession>>;
 
     /// Get user session by session ID
@@ -47,4 +58,22 @@ pub trait Database: Send + Sync {
 
     /// Delete session
     fn delete_session(&self, session_id: &uuid::Uuid) -> Result<()>;
+
+    /// Create or update a cryptex store record
+    fn save_cryptex_store(&self, store: &CryptexStore) -> Result<()>;
+
+    /// Get cryptex store by name
+    fn get_cryptex_store(&self, name: &str) -> Result<Option<CryptexStore>>;
+
+    /// List all cryptex stores
+    fn list_cryptex_stores(&self) -> Result<Vec<CryptexStore>>;
+
+    /// Delete cryptex store record, returning whether it existed
+    fn delete_cryptex_store(&self, name: &str) -> Result<bool>;
+
+    /// Record a pipeline run status update, returning the pipeline's progress
+    fn record_pipeline_status(&self, update: &StatusUpdate) -> Result<PipelineProgress>;
+
+    /// Get a pipeline's run status by pipeline ID
+    fn get_pipeline_progress(&self, pipeline_id: &str) -> Result<Option<PipelineProgress>>;
 }
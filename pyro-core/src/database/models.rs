@@ -1,6 +1,7 @@
 // Database models for BloodSniffer
 
 use chrono::{DateTime, Utc};
+use pyro_core::handoff::{RunStatus, StatusUpdate};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -55,7 +56,88 @@ pub struct Installation {
 pub struct UserSession {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub token: String,
+    /// SHA-256 hash of the bearer token; the raw token is never stored
+    pub token_hash: String,
     pub created_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
 }
+
+/// Named cryptex store under the cryptex root
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CryptexStore {
+    pub name: String,
+    pub description: Option<String>,
+    /// User that created the store; nil for the built-in stores
+    pub owner_id: Uuid,
+    /// Shared stores are usable by every authenticated user
+    pub shared: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CryptexStore {
+    /// Store used when a cryptex route does not name one
+    pub const DEFAULT: &'static str = "main";
+
+    /// Store written by `/api/extract` when it does not name one
+    pub const EXTRACTED: &'static str = "extracted";
+
+    /// Longest accepted store name
+    pub const MAX_NAME_LEN: usize = 64;
+
+    /// Store names become directory names, so only `[A-Za-z0-9_-]` is allowed
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= Self::MAX_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// Built-in shared store owned by no user
+    pub fn builtin(name: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            owner_id: Uuid::nil(),
+            shared: true,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Run status of a pipeline as reported by fire-marshal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineProgress {
+    pub pipeline_id: String,
+    pub status: RunStatus,
+    pub updated_at: i64,
+    /// Latest updates, oldest first
+    pub updates: Vec<StatusUpdate>,
+}
+
+impl PipelineProgress {
+    /// Updates kept per pipeline
+    pub const MAX_UPDATES: usize = 50;
+
+    pub fn new(update: StatusUpdate) -> Self {
+        Self {
+            pipeline_id: update.pipeline_id.clone(),
+            status: update.status,
+            updated_at: update.at,
+            updates: vec![update],
+        }
+    }
+
+    /// Add an update; one older than the current status is only logged
+    pub fn apply(&mut self, update: StatusUpdate) {
+        if update.at >= self.updated_at {
+            self.status = update.status;
+            self.updated_at = update.at;
+        }
+        self.updates.push(update);
+        if self.updates.len() > Self::MAX_UPDATES {
+            let excess = self.updates.len() - Self::MAX_UPDATES;
+            self.updates.drain(..excess);
+        }
+    }
+}
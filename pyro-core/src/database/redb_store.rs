@@ -1,21 +1,29 @@
 // ReDB database implementation
 
 use anyhow::{Context, Result};
+use pyro_core::handoff::StatusUpdate;
 use redb::{Database, ReadableTable, TableDefinition};
 use std::path::Path;
 use std::sync::Arc;
 
-use super::models::{AuthSecret, Installation, Role, User, UserSession};
+use super::encryption::{hash_session_token, is_encrypted, FieldCipher};
+use super::models::{
+    AuthSecret, CryptexStore, Installation, PipelineProgress, Role, User, UserSession,
+};
 use super::Database as DatabaseTrait;
 
 const USERS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("users");
 const ROLES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("roles");
 const INSTALLATION_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("installation");
 const SESSIONS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("sessions");
+const CRYPTEX_STORES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("cryptex_stores");
+const PIPELINE_PROGRESS_TABLE: TableDefinition<&str, &[u8]> =
+    TableDefinition::new("pipeline_progress");
 
 /// ReDB database implementation
 pub struct RedbDatabase {
     db: Arc<Database>,
+    cipher: Option<Arc<FieldCipher>>,
 }
 
 impl RedbDatabase {
@@ -40,13 +48,134 @@ impl RedbDatabase {
             write_txn
                 .open_table(SESSIONS_TABLE)
                 .context("Failed to open sessions table")?;
+            write_txn
+                .open_table(CRYPTEX_STORES_TABLE)
+                .context("Failed to open cryptex stores table")?;
+            write_txn
+                .open_table(PIPELINE_PROGRESS_TABLE)
+                .context("Failed to open pipeline progress table")?;
         }
         write_txn.commit().context("Failed to commit transaction")?;
 
-        Ok(Self { db: Arc::new(db) })
+        Ok(Self {
+            db: Arc::new(db),
+            cipher: None,
+        })
+    }
+
+    /// Encrypt user PII and secrets with the given field cipher
+    pub fn with_cipher(mut self, cipher: Arc<FieldCipher>) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Re-encrypt every user record with the active master key.
+    /// Plaintext records are encrypted and records sealed with a retired key are
+    /// resealed, so the retired key can be dropped from the keyring afterwards.
+    /// Returns the number of users rewritten.
+    pub fn rotate_encryption_key(&self) -> Result<usize> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(0);
+        };
+
+        let mut stale = Vec::new();
+        {
+            let read_txn = self
+                .db
+                .begin_read()
+                .context("Failed to begin read transaction")?;
+            let table = read_txn
+                .open_table(USERS_TABLE)
+                .context("Failed to open table")?;
+
+            for item in table.iter().context("Failed to create iterator")? {
+                let (_, value) = item.context("Failed to read item")?;
+                let stored: User = serde_json::from_slice(value.value())?;
+                if sealed_fields(&stored).any(|field| cipher.needs_rotation(field)) {
+                    stale.push(stored);
+                }
+            }
+        }
+
+        let write_txn = self
+            .db
+            .begin_write()
+            .context("Failed to begin write transaction")?;
+        {
+            let mut table = write_txn
+                .open_table(USERS_TABLE)
+                .context("Failed to open table")?;
+            for stored in &stale {
+                let resealed = self.seal_user(&self.unseal_user(stored.clone())?)?;
+                let data = serde_json::to_vec(&resealed)?;
+                table.insert(stored.principal_name.as_str(), data.as_slice())?;
+            }
+        }
+        write_txn.commit()?;
+
+        Ok(stale.len())
+    }
+
+    /// Encrypt sensitive user fields before they are written
+    fn seal_user(&self, user: &User) -> Result<User> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(user.clone());
+        };
+
+        let seal = |value: &Option<String>| -> Result<Option<String>> {
+            value.as_deref().map(|v| cipher.encrypt(v)).transpose()
+        };
+
+        let mut sealed = user.clone();
+        sealed.email_address = seal(&user.email_address)?;
+        sealed.first_name = seal(&user.first_name)?;
+        sealed.last_name = seal(&user.last_name)?;
+        if let Some(secret) = sealed.auth_secret.as_mut() {
+            secret.digest = cipher.encrypt(&secret.digest)?;
+        }
+
+        Ok(sealed)
+    }
+
+    /// Decrypt sensitive user fields after they are read
+    fn unseal_user(&self, mut user: User) -> Result<User> {
+        let Some(cipher) = &self.cipher else {
+            if sealed_fields(&user).any(is_encrypted) {
+                anyhow::bail!(
+                    "User '{}' is encrypted but no master key is configured",
+                    user.principal_name
+                );
+            }
+            return Ok(user);
+        };
+
+        let unseal = |value: Option<String>| -> Result<Option<String>> {
+            value.map(|v| cipher.decrypt(&v)).transpose()
+        };
+
+        user.email_address = unseal(user.email_address)?;
+        user.first_name = unseal(user.first_name)?;
+        user.last_name = unseal(user.last_name)?;
+        if let Some(secret) = user.auth_secret.as_mut() {
+            secret.digest = cipher.decrypt(&secret.digest)?;
+        }
+
+        Ok(user)
     }
 }
 
+/// Stored user fields that are subject to encryption
+fn sealed_fields(user: &User) -> impl Iterator<Item = &str> {
+    [
+        user.email_address.as_deref(),
+        user.first_name.as_deref(),
+        user.last_name.as_deref(),
+        user.auth_secret.as_ref().map(|s| s.digest.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+}
+
 impl DatabaseTrait for RedbDatabase {
     fn migrate(&self) -> Result<()> {
         // Ensure tables exist
@@ -58,9 +187,28 @@ impl DatabaseTrait for RedbDatabase {
             write_txn.open_table(USERS_TABLE)?;
             write_txn.open_table(ROLES_TABLE)?;
             write_txn.open_table(INSTALLATION_TABLE)?;
-            write_txn.open_table(SESSIONS_TABLE)?;
+            write_txn.open_table(CRYPTEX_STORES_TABLE)?;
+            write_txn.open_table(PIPELINE_PROGRESS_TABLE)?;
+            let mut sessions = write_txn.open_table(SESSIONS_TABLE)?;
+
+            // Drop legacy sessions that were keyed by the raw bearer token
+            let mut legacy = Vec::new();
+            for item in sessions.iter()? {
+                let (key, value) = item?;
+                if serde_json::from_slice::<UserSession>(value.value()).is_err() {
+                    legacy.push(key.value().to_string());
+                }
+            }
+            for key in legacy {
+                sessions.remove(key.as_str())?;
+            }
         }
         write_txn.commit()?;
+
+        // Encrypt plaintext users and reseal users written with a retired key
+        self.rotate_encryption_key()
+            .context("Failed to rotate field encryption")?;
+
         Ok(())
     }
 
@@ -144,12 +292,33 @@ impl DatabaseTrait for RedbDatabase {
 
         if let Some(data) = table.get(principal_name).context("Failed to get user")? {
             let user: User = serde_json::from_slice(data.value())?;
-            Ok(Some(user))
+            Ok(Some(self.unseal_user(user)?))
         } else {
             Ok(None)
         }
     }
 
+    fn get_user(&self, user_id: &uuid::Uuid) -> Result<Option<User>> {
+        // Users are keyed by principal name, so scan for the ID
+        let read_txn = self
+            .db
+            .begin_read()
+            .context("Failed to begin read transaction")?;
+        let table = read_txn
+            .open_table(USERS_TABLE)
+            .context("Failed to open table")?;
+
+        for item in table.iter().context("Failed to create iterator")? {
+            let (_, value) = item.context("Failed to read item")?;
+            let user: User = serde_json::from_slice(value.value())?;
+            if user.id == *user_id {
+                return Ok(Some(self.unseal_user(user)?));
+            }
+        }
+
+        Ok(None)
+    }
+
     fn delete_user(&self, user: &User) -> Result<()> {
         let write_txn = self
             .db
@@ -166,7 +335,7 @@ impl DatabaseTrait for RedbDatabase {
     }
 
     fn initialize_secret_auth(&self, user: &User, _secret: &AuthSecret) -> Result<User> {
-        let data = serde_json::to_vec(user)?;
+        let data = serde_json::to_vec(&self.seal_user(user)?)?;
 
         let write_txn = self
             .db
@@ -194,12 +363,33 @@ impl DatabaseTrait for RedbDatabase {
             let mut table = write_txn
                 .open_table(SESSIONS_TABLE)
                 .context("Failed to open table")?;
-            table.insert(session.token.as_str(), data.as_slice())?;
+            table.insert(session.token_hash.as_str(), data.as_slice())?;
         }
         write_txn.commit()?;
         Ok(())
     }
 
+    fn list_sessions(&self) -> Result<Vec<UserSession>> {
+        let read_txn = self
+            .db
+            .begin_read()
+            .context("Failed to begin read transaction")?;
+        let table = read_txn
+            .open_table(SESSIONS_TABLE)
+            .context("Failed to open table")?;
+
+        let mut sessions = Vec::new();
+        let iter = table.iter().context("Failed to create iterator")?;
+
+        for item in iter {
+            let (_, value) = item.context("Failed to read item")?;
+            let session: UserSession = serde_json::from_slice(value.value())?;
+            sessions.push(session);
+        }
+
+        Ok(sessions)
+    }
+
     fn get_session(&self, token: &str) -> Result<Option<UserSession>> {
         let read_txn = self
             .db
@@ -209,7 +399,11 @@ impl DatabaseTrait for RedbDatabase {
             .open_table(SESSIONS_TABLE)
             .context("Failed to open table")?;
 
-        if let Some(data) = table.get(token).context("Failed to get session")? {
+        let token_hash = hash_session_token(token);
+        if let Some(data) = table
+            .get(token_hash.as_str())
+            .context("Failed to get session")?
+        {
             let session: UserSession = serde_json::from_slice(data.value())?;
             Ok(Some(session))
         } else {
@@ -284,6 +478,123 @@ impl DatabaseTrait for RedbDatabase {
 
         Ok(())
     }
+
+    fn save_cryptex_store(&self, store: &CryptexStore) -> Result<()> {
+        let data = serde_json::to_vec(store)?;
+
+        let write_txn = self
+            .db
+            .begin_write()
+            .context("Failed to begin write transaction")?;
+        {
+            let mut table = write_txn
+                .open_table(CRYPTEX_STORES_TABLE)
+                .context("Failed to open table")?;
+            table.insert(store.name.as_str(), data.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn get_cryptex_store(&self, name: &str) -> Result<Option<CryptexStore>> {
+        let read_txn = self
+            .db
+            .begin_read()
+            .context("Failed to begin read transaction")?;
+        let table = read_txn
+            .open_table(CRYPTEX_STORES_TABLE)
+            .context("Failed to open table")?;
+
+        if let Some(data) = table.get(name).context("Failed to get cryptex store")? {
+            Ok(Some(serde_json::from_slice(data.value())?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn list_cryptex_stores(&self) -> Result<Vec<CryptexStore>> {
+        let read_txn = self
+            .db
+            .begin_read()
+            .context("Failed to begin read transaction")?;
+        let table = read_txn
+            .open_table(CRYPTEX_STORES_TABLE)
+            .context("Failed to open table")?;
+
+        let mut stores = Vec::new();
+        for item in table.iter().context("Failed to create iterator")? {
+            let (_, value) = item.context("Failed to read item")?;
+            stores.push(serde_json::from_slice(value.value())?);
+        }
+
+        Ok(stores)
+    }
+
+    fn delete_cryptex_store(&self, name: &str) -> Result<bool> {
+        let write_txn = self
+            .db
+            .begin_write()
+            .context("Failed to begin write transaction")?;
+        let existed = {
+            let mut table = write_txn
+                .open_table(CRYPTEX_STORES_TABLE)
+                .context("Failed to open table")?;
+            let removed = table.remove(name)?;
+            removed.is_some()
+        };
+        write_txn.commit()?;
+        Ok(existed)
+    }
+
+    fn record_pipeline_status(&self, update: &StatusUpdate) -> Result<PipelineProgress> {
+        let write_txn = self
+            .db
+            .begin_write()
+            .context("Failed to begin write transaction")?;
+        let progress = {
+            let mut table = write_txn
+                .open_table(PIPELINE_PROGRESS_TABLE)
+                .context("Failed to open table")?;
+            let existing: Option<PipelineProgress> = table
+                .get(update.pipeline_id.as_str())
+                .context("Failed to get pipeline progress")?
+                .map(|data| serde_json::from_slice(data.value()))
+                .transpose()?;
+            let progress = match existing {
+                Some(mut progress) => {
+                    progress.apply(update.clone());
+                    progress
+                }
+                None => PipelineProgress::new(update.clone()),
+            };
+            table.insert(
+                update.pipeline_id.as_str(),
+                serde_json::to_vec(&progress)?.as_slice(),
+            )?;
+            progress
+        };
+        write_txn.commit()?;
+        Ok(progress)
+    }
+
+    fn get_pipeline_progress(&self, pipeline_id: &str) -> Result<Option<PipelineProgress>> {
+        let read_txn = self
+            .db
+            .begin_read()
+            .context("Failed to begin read transaction")?;
+        let table = read_txn
+            .open_table(PIPELINE_PROGRESS_TABLE)
+            .context("Failed to open table")?;
+
+        if let Some(data) = table
+            .get(pipeline_id)
+            .context("Failed to get pipeline progress")?
+        {
+            Ok(Some(serde_json::from_slice(data.value())?))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -293,6 +604,23 @@ mod tests {
     use std::fs;
     use tempfile::TempDir;
 
+    fn test_user() -> User {
+        User {
+            id: uuid::Uuid::new_v4(),
+            principal_name: "test_user".to_string(),
+            email_address: Some("test@example.com".to_string()),
+            first_name: Some("Test".to_string()),
+            last_name: Some("User".to_string()),
+            all_environments: true,
+            roles: vec![],
+            auth_secret: Some(AuthSecret {
+                digest: "test_hash".to_string(),
+                digest_method: "argon2".to_string(),
+                expires_at: None,
+            }),
+        }
+    }
+
     fn create_test_db() -> (RedbDatabase, TempDir) {
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
@@ -377,7 +705,7 @@ mod tests {
         let session = UserSession {
             id: uuid::Uuid::new_v4(),
             user_id: uuid::Uuid::new_v4(),
-            token: "test_token_123".to_string(),
+            token_hash: hash_session_token("test_token_123"),
             created_at: chrono::Utc::now(),
             expires_at: chrono::Utc::now() + chrono::Duration::hours(24),
         };
@@ -388,7 +716,15 @@ mod tests {
         // Get session
         let found_session = db.get_session("test_token_123").unwrap();
         assert!(found_session.is_some());
-        assert_eq!(found_session.unwrap().token, "test_token_123");
+        assert_eq!(
+            found_session.unwrap().token_hash,
+            hash_session_token("test_token_123")
+        );
+
+        // List sessions
+        let sessions = db.list_sessions().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, session.id);
 
         // Delete session
         db.delete_session(&session.id).unwrap();
@@ -397,4 +733,146 @@ mod tests {
         let found_session = db.get_session("test_token_123").unwrap();
         assert!(found_session.is_none());
     }
+
+    #[test]
+    fn test_session_token_not_stored() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        {
+            let db = RedbDatabase::open(&db_path).unwrap();
+            let session = UserSession {
+                id: uuid::Uuid::new_v4(),
+                user_id: uuid::Uuid::new_v4(),
+                token_hash: hash_session_token("raw_bearer_token"),
+                created_at: chrono::Utc::now(),
+                expires_at: chrono::Utc::now() + chrono::Duration::hours(24),
+            };
+            db.create_session(&session).unwrap();
+        }
+
+        let raw = fs::read(&db_path).unwrap();
+        let needle = b"raw_bearer_token";
+        assert!(!raw.windows(needle.len()).any(|w| w == needle));
+    }
+
+    #[test]
+    fn test_user_fields_encrypted_at_rest() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let key_entry = FieldCipher::generate_key_entry("k1");
+        let cipher = Arc::new(FieldCipher::from_key_material(&key_entry).unwrap());
+
+        {
+            let db = RedbDatabase::open(&db_path)
+                .unwrap()
+                .with_cipher(cipher.clone());
+            let user = test_user();
+            db.initialize_secret_auth(&user, user.auth_secret.as_ref().unwrap())
+                .unwrap();
+
+            let found = db.lookup_user("test_user").unwrap().unwrap();
+            assert_eq!(found.email_address.as_deref(), Some("test@example.com"));
+            assert_eq!(found.auth_secret.unwrap().digest, "test_hash");
+        }
+
+        let raw = fs::read(&db_path).unwrap();
+        let needle = b"test@example.com";
+        assert!(!raw.windows(needle.len()).any(|w| w == needle));
+
+        // Reading without the master key fails instead of leaking ciphertext
+        let db = RedbDatabase::open(&db_path).unwrap();
+        assert!(db.lookup_user("test_user").is_err());
+    }
+
+    #[test]
+    fn test_rotate_encryption_key() {
+        let (db, _temp) = create_test_db();
+        let user = test_user();
+        db.initialize_secret_auth(&user, user.auth_secret.as_ref().unwrap())
+            .unwrap();
+
+        // Enabling encryption seals existing plaintext users
+        let old_entry = FieldCipher::generate_key_entry("k1");
+        let db = db.with_cipher(Arc::new(
+            FieldCipher::from_key_material(&old_entry).unwrap(),
+        ));
+        assert_eq!(db.rotate_encryption_key().unwrap(), 1);
+        assert_eq!(db.rotate_encryption_key().unwrap(), 0);
+
+        // Rotating to a new active key reseals with it
+        let new_entry = FieldCipher::generate_key_entry("k2");
+        let db = db.with_cipher(Arc::new(
+            FieldCipher::from_key_material(&format!("{}\n{}", new_entry, old_entry)).unwrap(),
+        ));
+        assert_eq!(db.rotate_encryption_key().unwrap(), 1);
+
+        let db = db.with_cipher(Arc::new(
+            FieldCipher::from_key_material(&new_entry).unwrap(),
+        ));
+        let found = db.lookup_user("test_user").unwrap().unwrap();
+        assert_eq!(found.last_name.as_deref(), Some("User"));
+    }
+
+    #[test]
+    fn test_cryptex_store_crud() {
+        let (db, _temp) = create_test_db();
+        let user = test_user();
+        db.initialize_secret_auth(&user, user.auth_secret.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(
+            db.get_user(&user.id).unwrap().unwrap().principal_name,
+            "test_user"
+        );
+        assert!(db.get_user(&uuid::Uuid::new_v4()).unwrap().is_none());
+
+        let store = CryptexStore {
+            name: "team-red".to_string(),
+            description: None,
+            owner_id: user.id,
+            shared: false,
+            created_at: chrono::Utc::now(),
+        };
+        db.save_cryptex_store(&store).unwrap();
+        db.save_cryptex_store(&CryptexStore::builtin(CryptexStore::DEFAULT, "Default"))
+            .unwrap();
+
+        assert_eq!(db.get_cryptex_store("team-red").unwrap(), Some(store));
+        assert_eq!(db.list_cryptex_stores().unwrap().len(), 2);
+
+        assert!(db.delete_cryptex_store("team-red").unwrap());
+        assert!(!db.delete_cryptex_store("team-red").unwrap());
+        assert!(db.get_cryptex_store("team-red").unwrap().is_none());
+
+        assert!(CryptexStore::is_valid_name("team_red-2"));
+        assert!(!CryptexStore::is_valid_name("../main"));
+        assert!(!CryptexStore::is_valid_name(""));
+    }
+
+    #[test]
+    fn test_pipeline_progress() {
+        use pyro_core::handoff::{RunStatus, StatusUpdate};
+
+        let (db, _temp) = create_test_db();
+        assert!(db.get_pipeline_progress("p1").unwrap().is_none());
+
+        let mut queued = StatusUpdate::new("p1", RunStatus::Queued);
+        queued.at = 100;
+        let mut running = StatusUpdate::new("p1", RunStatus::Running);
+        running.at = 110;
+        db.record_pipeline_status(&queued).unwrap();
+        db.record_pipeline_status(&running).unwrap();
+
+        // A late update is logged without rolling the status back
+        let progress = db.record_pipeline_status(&queued).unwrap();
+        assert_eq!(progress.status, RunStatus::Running);
+        assert_eq!(progress.updated_at, 110);
+        assert_eq!(progress.updates.len(), 3);
+
+        for _ in 0..PipelineProgress::MAX_UPDATES {
+            db.record_pipeline_status(&running).unwrap();
+        }
+        let progress = db.get_pipeline_progress("p1").unwrap().unwrap();
+        assert_eq!(progress.updates.len(), PipelineProgress::MAX_UPDATES);
+    }
 }
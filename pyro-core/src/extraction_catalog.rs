@@ -0,0 +1,459 @@
+// Extraction catalog
+// Describe extracted BloodHound data per object kind and relationship kind, and keep those
+// descriptions in a cryptex so repeated extractions accumulate into one entry per kind
+
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::cryptex_history::{CryptexHistory, RevisionAction};
+use crate::cryptex_tree::{
+    CryptexEditError, CryptexTree, FunctionEntry, FunctionUpdate, StoreLock,
+};
+use crate::data_extractor::ExtractedData;
+
+/// Root function holding one entry per object kind
+pub const OBJECTS_ROOT: &str = "objects";
+/// Root function holding one entry per relationship kind
+pub const RELATIONSHIPS_ROOT: &str = "relationships";
+/// Sample property sets kept per kind
+pub const MAX_SAMPLES: usize = 3;
+
+/// JSON type of a property value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonType {
+    Null,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => JsonType::Null,
+            Value::Bool(_) => JsonType::Boolean,
+            Value::Number(n) if n.is_i64() || n.is_u64() => JsonType::Integer,
+            Value::Number(_) => JsonType::Number,
+            Value::String(_) => JsonType::String,
+            Value::Array(_) => JsonType::Array,
+            Value::Object(_) => JsonType::Object,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JsonType::Null => "null",
+            JsonType::Boolean => "boolean",
+            JsonType::Integer => "integer",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+        }
+    }
+}
+
+/// How often a property was seen and with which types
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertyStats {
+    /// Records of the kind that carried the property
+    pub present: usize,
+    pub types: BTreeSet<JsonType>,
+}
+
+/// Everything ingested so far for one object or relationship kind
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KindSummary {
+    pub kind: String,
+    /// Records seen across all extractions
+    pub count: usize,
+    /// Records seen by the most recent extraction
+    pub last_count: usize,
+    pub extractions: u64,
+    pub last_extracted_at: i64,
+    pub properties: BTreeMap<String, PropertyStats>,
+    /// Property sets from the most recent extraction
+    pub samples: Vec<Value>,
+}
+
+impl KindSummary {
+    fn new(kind: &str, extracted_at: i64) -> Self {
+        KindSummary {
+            kind: kind.to_string(),
+            count: 0,
+            last_count: 0,
+            extractions: 1,
+            last_extracted_at: extracted_at,
+            properties: BTreeMap::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Count one record and the properties it carries
+    fn observe(&mut self, properties: &Value) {
+        self.count += 1;
+        self.last_count += 1;
+        if let Some(object) = properties.as_object() {
+            for (name, value) in object {
+                let stats = self.properties.entry(name.clone()).or_default();
+                stats.present += 1;
+                stats.types.insert(JsonType::of(value));
+            }
+        }
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(properties.clone());
+        }
+    }
+
+    /// Whether every record of the kind carried the property
+    pub fn is_required(&self, property: &str) -> bool {
+        self.properties
+            .get(property)
+            .is_some_and(|stats| stats.present == self.count)
+    }
+
+    /// Fold an earlier summary of the same kind into this (newer) one
+    pub fn merge_previous(&mut self, previous: &KindSummary) {
+        self.count += previous.count;
+        self.extractions += previous.extractions;
+        for (name, stats) in &previous.properties {
+            let merged = self.properties.entry(name.clone()).or_default();
+            merged.present += stats.present;
+            merged.types.extend(stats.types.iter().copied());
+        }
+    }
+
+    /// Read a summary back from a catalog entry's code
+    pub fn from_code(code: &str) -> Option<Self> {
+        serde_json::from_str(code).ok()
+    }
+
+    fn to_code(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Human readable description of the kind
+    fn pseudocode(&self, category: &str) -> String {
+        let extracted_at = Utc
+            .timestamp_opt(self.last_extracted_at, 0)
+            .single()
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default();
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} kind {}: {} record(s) over {} extraction(s), {} in the latest ({})",
+            category, self.kind, self.count, self.extractions, self.last_count, extracted_at
+        );
+        if !self.properties.is_empty() {
+            out.push_str("\nSchema:\n");
+            for (name, stats) in &self.properties {
+                let types: Vec<&str> = stats.types.iter().map(JsonType::as_str).collect();
+                let _ = writeln!(
+                    out,
+                    "- {}: {} ({}, {}/{})",
+                    name,
+                    types.join(" | "),
+                    if self.is_required(name) {
+                        "required"
+                    } else {
+                        "optional"
+                    },
+                    stats.present,
+                    self.count
+                );
+            }
+        }
+        if !self.samples.is_empty() {
+            out.push_str("\nSamples:\n");
+            for sample in &self.samples {
+                let _ = writeln!(out, "- {}", sample);
+            }
+        }
+        out
+    }
+}
+
+/// Paths of the catalog entries written by [`ExtractionCatalog::apply`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CatalogChanges {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl CatalogChanges {
+    /// Replace provisional path names with the ones assigned when the tree was saved
+    pub fn apply_renames(&mut self, renamed: &BTreeMap<String, String>) {
+        for paths in [&mut self.created, &mut self.updated] {
+            for path in paths.iter_mut() {
+                if let Some(new) = renamed.get(path) {
+                    *path = new.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Per-kind summaries of one extraction
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionCatalog {
+    pub objects: BTreeMap<String, KindSummary>,
+    pub relationships: BTreeMap<String, KindSummary>,
+}
+
+impl ExtractionCatalog {
+    /// Summarize extracted data (dictionary: catalog_extraction)
+    /// Pseudocode: Group nodes by kind and edges by relationship type, count each group and
+    /// record which properties appear with which JSON types
+    pub fn from_extracted(data: &ExtractedData) -> Self {
+        let extracted_at = data.metadata.extracted_at;
+        let mut catalog = ExtractionCatalog::default();
+
+        for node in &data.nodes {
+            catalog
+                .objects
+                .entry(node.node_type.clone())
+                .or_insert_with(|| KindSummary::new(&node.node_type, extracted_at))
+                .observe(&node.properties);
+        }
+        for edge in &data.edges {
+            catalog
+                .relationships
+                .entry(edge.edge_type.clone())
+                .or_insert_with(|| KindSummary::new(&edge.edge_type, extracted_at))
+                .observe(&edge.properties);
+        }
+
+        catalog
+    }
+
+    /// Summaries already stored in a cryptex tree by earlier extractions
+    pub fn stored(tree: &CryptexTree) -> Self {
+        let kinds = |root_name: &str| -> BTreeMap<String, KindSummary> {
+            tree.child_named(None, root_name)
+                .map(|root| {
+                    root.children
+                        .iter()
+                        .filter_map(|child| tree.get(child))
+                        .filter_map(|entry| KindSummary::from_code(&entry.function_code))
+                        .map(|summary| (summary.kind.clone(), summary))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        ExtractionCatalog {
+            objects: kinds(OBJECTS_ROOT),
+            relationships: kinds(RELATIONSHIPS_ROOT),
+        }
+    }
+
+    /// Write the catalog into a cryptex tree (dictionary: apply_catalog)
+    /// Pseudocode: Ensure the objects and relationships roots exist, then create one entry per
+    /// kind below them or merge the new summary into the kind's existing entry
+    pub fn apply(&self, tree: &mut CryptexTree) -> Result<CatalogChanges, CryptexEditError> {
+        let mut changes = CatalogChanges::default();
+        let groups = [
+            (
+                OBJECTS_ROOT,
+                "Object",
+                "Object kinds ingested by extraction, one entry per kind",
+                &self.objects,
+            ),
+            (
+                RELATIONSHIPS_ROOT,
+                "Relationship",
+                "Relationship kinds ingested by extraction, one entry per kind",
+                &self.relationships,
+            ),
+        ];
+
+        for (root_name, category, description, kinds) in groups {
+            if kinds.is_empty() {
+                continue;
+            }
+            let root = match tree.child_named(None, root_name) {
+                Some(root) => root.path_name.clone(),
+                None => {
+                    let path = tree.insert(None, root_name, "", description)?;
+                    changes.created.push(path.clone());
+                    path
+                }
+            };
+
+            for (kind, summary) in kinds {
+                let existing = tree
+                    .child_named(Some(&root), kind)
+                    .map(|entry| (entry.path_name.clone(), entry.function_code.clone()));
+                match existing {
+                    Some((path, code)) => {
+                        let mut merged = summary.clone();
+                        if let Some(previous) = KindSummary::from_code(&code) {
+                            merged.merge_previous(&previous);
+                        }
+                        tree.update(
+                            &path,
+                            FunctionUpdate {
+                                name: None,
+                                function_code: Some(merged.to_code()),
+                                pseudocode: Some(merged.pseudocode(category)),
+                            },
+                        )?;
+                        changes.updated.push(path);
+                    }
+                    None => {
+                        let path = tree.insert(
+                            Some(&root),
+                            kind,
+                            &summary.to_code(),
+                            &summary.pseudocode(category),
+                        )?;
+                        changes.created.push(path);
+                    }
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+/// Apply a catalog to the cryptex at `cryptex_path` and record the changed entries as
+/// revisions by `author`, holding the store lock so pyro and fire-marshal never write the
+/// same cryptex at once
+pub fn write_catalog<P: AsRef<Path>>(
+    catalog: &ExtractionCatalog,
+    cryptex_path: P,
+    author: Uuid,
+) -> Result<CatalogChanges> {
+    let cryptex_path = cryptex_path.as_ref();
+    let _lock = StoreLock::acquire(cryptex_path)?;
+    let mut tree = CryptexTree::load(cryptex_path)?;
+    let mut history = CryptexHistory::load(cryptex_path)?;
+
+    let before = tree.clone();
+    let mut changes = catalog.apply(&mut tree)?;
+    for path in &changes.updated {
+        if let Some(entry) = before.get(path) {
+            history.ensure_baseline(entry);
+        }
+    }
+
+    let renamed = tree.save(cryptex_path)?;
+
+    history.rename_paths(&renamed);
+    let recorded = changes
+        .created
+        .iter()
+        .map(|path| (path, RevisionAction::Create))
+        .chain(
+            changes
+                .updated
+                .iter()
+                .map(|path| (path, RevisionAction::Update)),
+        );
+    for (path, action) in recorded {
+        if let Some(entry) = tree.get(path) {
+            let entry = FunctionEntry {
+                path_name: renamed.get(path).unwrap_or(path).clone(),
+                ..entry.clone()
+            };
+            history.record(&entry, action, author);
+        }
+    }
+    history.save()?;
+    changes.apply_renames(&renamed);
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_extractor::BloodHoundExtractor;
+    use serde_json::json;
+
+    fn users() -> ExtractedData {
+        BloodHoundExtractor::extract_from_json(&json!({
+            "meta": { "type": "users" },
+            "data": [
+                {
+                    "ObjectIdentifier": "S-1-5-21-1001",
+                    "Properties": { "name": "ALICE@CORP.LOCAL", "enabled": true, "lastlogon": 1700000000 },
+                    "Rels": [{ "RelType": "MemberOf", "TargetObjectIdentifier": "S-1-5-21-512" }]
+                },
+                {
+                    "ObjectIdentifier": "S-1-5-21-1002",
+                    "Properties": { "name": "BOB@CORP.LOCAL", "enabled": "false" },
+                    "Rels": [{ "RelType": "MemberOf", "TargetObjectIdentifier": "S-1-5-21-512" }]
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_catalog_schema() {
+        let catalog = ExtractionCatalog::from_extracted(&users());
+        let user = &catalog.objects["User"];
+        assert_eq!(user.count, 2);
+        assert_eq!(user.samples.len(), 2);
+        assert!(user.is_required("name"));
+        assert!(!user.is_required("lastlogon"));
+        assert_eq!(
+            user.properties["enabled"].types,
+            BTreeSet::from([JsonType::Boolean, JsonType::String])
+        );
+        assert_eq!(catalog.relationships["MemberOf"].count, 2);
+    }
+
+    #[test]
+    fn test_repeated_extractions_merge() {
+        let mut tree = CryptexTree::default();
+        let catalog = ExtractionCatalog::from_extracted(&users());
+
+        let first = catalog.apply(&mut tree).unwrap();
+        assert_eq!(
+            first.created,
+            vec![
+                "objects",
+                "objects/User",
+                "relationships",
+                "relationships/MemberOf"
+            ]
+        );
+        assert!(tree
+            .get("objects/User")
+            .unwrap()
+            .pseudocode
+            .contains("- name: string (required, 2/2)"));
+
+        assert_eq!(ExtractionCatalog::stored(&tree).objects["User"].count, 2);
+
+        let second = catalog.apply(&mut tree).unwrap();
+        assert!(second.created.is_empty());
+        assert_eq!(
+            second.updated,
+            vec!["objects/User", "relationships/MemberOf"]
+        );
+        assert_eq!(tree.len(), 4);
+
+        let user =
+            KindSummary::from_code(&tree.get("objects/User").unwrap().function_code).unwrap();
+        assert_eq!(user.count, 4);
+        assert_eq!(user.last_count, 2);
+        assert_eq!(user.extractions, 2);
+        assert_eq!(user.properties["lastlogon"].present, 2);
+    }
+}
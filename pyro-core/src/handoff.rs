@@ -0,0 +1,227 @@
+// Pipeline hand-off
+// Submit pipeline records to fire-marshal over HTTP and report their run status back to
+// pyro-core, both authenticated with a shared bearer token
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::pipeline::PipelineRecord;
+
+/// fire-marshal endpoint accepting pipeline records
+pub const SUBMIT_PATH: &str = "/internal/pipelines";
+
+/// pyro-core endpoint accepting run status updates
+pub const STATUS_PATH: &str = "/internal/pipelines/status";
+
+/// Where a pipeline is in its run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// Accepted into fire-marshal's queue
+    Queued,
+    /// Registered to run on its schedule
+    Scheduled,
+    /// Claimed by a worker
+    Running,
+    /// Transformed and delivered
+    Succeeded,
+    /// Failed and waiting to be tried again
+    Retrying,
+    /// Given up on; listed under fire-marshal's dead letters
+    DeadLettered,
+}
+
+/// A change in a pipeline's run status, reported by fire-marshal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusUpdate {
+    pub pipeline_id: String,
+    pub status: RunStatus,
+    pub at: i64,
+    /// Attempt of a retried or dead-lettered run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempt: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Run statistics of a succeeded run
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+}
+
+impl StatusUpdate {
+    pub fn new(pipeline_id: impl Into<String>, status: RunStatus) -> Self {
+        Self {
+            pipeline_id: pipeline_id.into(),
+            status,
+            at: chrono::Utc::now().timestamp(),
+            attempt: None,
+            message: None,
+            details: Value::Null,
+        }
+    }
+
+    pub fn with_attempt(mut self, attempt: u32) -> Self {
+        self.attempt = Some(attempt);
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// fire-marshal's answer to a submitted record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitReceipt {
+    pub pipeline_id: String,
+    /// Queue file the record was written to
+    pub file: String,
+}
+
+/// Why fire-marshal did not take a submitted record
+#[derive(Debug, thiserror::Error)]
+pub enum SubmitError {
+    /// fire-marshal could not be reached in time; the record can go to the shared queue instead
+    #[error("Failed to reach fire-marshal at {0}: {1}")]
+    Unreachable(String, reqwest::Error),
+    /// fire-marshal answered with an error status
+    #[error("fire-marshal rejected pipeline {0} with {1}")]
+    Rejected(String, reqwest::StatusCode),
+    #[error("fire-marshal request failed: {0}")]
+    Failed(reqwest::Error),
+}
+
+/// Client for fire-marshal's internal pipeline API
+pub struct FireMarshalClient {
+    http: reqwest::Client,
+    submit_url: String,
+    token: String,
+}
+
+impl FireMarshalClient {
+    /// Client for fire-marshal at `base_url`
+    pub fn new(base_url: &str, token: &str, timeout: Duration) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to build fire-marshal client")?;
+
+        Ok(Self {
+            http,
+            submit_url: endpoint(base_url, SUBMIT_PATH),
+            token: token.to_string(),
+        })
+    }
+
+    /// Hand a pipeline record to fire-marshal
+    pub async fn submit(&self, record: &PipelineRecord) -> Result<SubmitReceipt, SubmitError> {
+        let response = self
+            .http
+            .post(&self.submit_url)
+            .bearer_auth(&self.token)
+            .json(record)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_connect() || e.is_timeout() {
+                    SubmitError::Unreachable(self.submit_url.clone(), e)
+                } else {
+                    SubmitError::Failed(e)
+                }
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(SubmitError::Rejected(record.id.clone(), status));
+        }
+        response.json().await.map_err(SubmitError::Failed)
+    }
+}
+
+/// Report a run status update to a pipeline's callback URL
+pub async fn report_status(
+    http: &reqwest::Client,
+    callback_url: &str,
+    token: &str,
+    update: &StatusUpdate,
+) -> Result<()> {
+    let response = http
+        .post(callback_url)
+        .bearer_auth(token)
+        .json(update)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", callback_url))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("{} answered {}", callback_url, status));
+    }
+    Ok(())
+}
+
+/// Join a base URL and an endpoint path
+pub fn endpoint(base_url: &str, path: &str) -> String {
+    format!("{}{}", base_url.trim_end_matches('/'), path)
+}
+
+/// Whether an `Authorization` header carries the shared token; compared in constant time
+pub fn authorized(header: Option<&str>, token: &str) -> bool {
+    let Some(presented) = header.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let (presented, token) = (presented.as_bytes(), token.as_bytes());
+    presented.len() == token.len()
+        && presented
+            .iter()
+            .zip(token)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_authorized() {
+        let token = "0123456789abcdef0123456789abcdef";
+        assert!(authorized(Some(&format!("Bearer {}", token)), token));
+        assert!(!authorized(Some("Bearer 0123456789abcdef"), token));
+        assert!(!authorized(Some(token), token));
+        assert!(!authorized(None, token));
+    }
+
+    #[test]
+    fn test_submit_url() {
+        let client = FireMarshalClient::new(
+            "http://fire-marshal:3001/",
+            "secret",
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(
+            client.submit_url,
+            "http://fire-marshal:3001/internal/pipelines"
+        );
+    }
+
+    #[test]
+    fn test_status_update_format() {
+        let update = StatusUpdate::new("p1", RunStatus::DeadLettered)
+            .with_attempt(5)
+            .with_message("connection refused");
+        let value = serde_json::to_value(&update).unwrap();
+        assert_eq!(value["status"], json!("dead_lettered"));
+        assert_eq!(value["attempt"], json!(5));
+        assert!(value.get("details").is_none());
+    }
+}
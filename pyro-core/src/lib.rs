@@ -1,4 +1,16 @@
 pub mod branding;
 pub mod config;
+pub mod cryptex_bundle;
+pub mod cryptex_codegen;
+pub mod cryptex_history;
+pub mod cryptex_tree;
 pub mod data_extractor;
+pub mod extraction_catalog;
+pub mod handoff;
+pub mod logging;
+pub mod node_schema;
+pub mod pagination;
 pub mod pipeline;
+pub mod pipeline_definition;
+pub mod pipeline_graph;
+pub mod tls;
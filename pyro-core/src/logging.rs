@@ -0,0 +1,60 @@
+// Runtime log level
+// A process-wide level that configuration reloads can swap without a restart
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Log verbosity, from least to most verbose
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogLevel::Error,
+            1 => LogLevel::Warn,
+            2 => LogLevel::Info,
+            3 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Set the process-wide log level
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Current process-wide log level
+pub fn level() -> LogLevel {
+    LogLevel::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Check whether messages at `level` should be printed
+pub fn enabled(level: LogLevel) -> bool {
+    level <= self::level()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_ordering() {
+        assert!(LogLevel::Error < LogLevel::Debug);
+        assert_eq!(LogLevel::from_u8(LogLevel::Debug as u8), LogLevel::Debug);
+
+        let level: LogLevel = serde_json::from_str("\"warn\"").unwrap();
+        assert_eq!(level, LogLevel::Warn);
+    }
+}
@@ -1,9 +1,12 @@
 use anyhow::{Context, Result};
 use axum::{
-    middleware::from_fn,
+    extract::DefaultBodyLimit,
+    middleware::{from_fn, from_fn_with_state},
     routing::{get, post},
     Router,
 };
+use clap::Parser;
+use pyro_core::{config, tls};
 use std::net::SocketAddr;
 use tokio::signal;
 
@@ -11,21 +14,34 @@ mod api;
 mod auth;
 mod bootstrap;
 mod branding;
-mod config;
-mod data_extractor;
+mod cli;
 mod database;
 
 use api::{handlers, middleware, state::AppState};
 use bootstrap::bloodsniffer_ensure_directories;
-use config::Config;
+use cli::{Cli, Command};
+use config::ConfigLoader;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let loader = cli.flags.loader();
+
+    match cli.command {
+        None | Some(Command::Serve) => serve(&loader).await,
+        Some(command) => cli::cli_run_command(command, &loader).await,
+    }
+}
+
+/// Start the API server
+async fn serve(loader: &ConfigLoader) -> Result<()> {
     // Initialize branding
     branding::print_banner();
 
-    // Load configuration
-    let config = Config::load()?;
+    // Load and validate configuration
+    let config = loader.load()?;
+    config.validate()?;
 
     // Ensure directories exist
     bloodsniffer_ensure_directories(&config)?;
@@ -36,21 +52,65 @@ async fn main() -> Result<()> {
     // Run database migrations
     bootstrap::bloodsniffer_migrate_db(&state).await?;
 
+    // Reload Node-RED, pipeline interval and logging settings on file change or SIGHUP
+    let _config_watcher = config::spawn_runtime_updates(
+        state.runtime.clone(),
+        state.node_red.clone(),
+        config::spawn_config_watcher(loader.clone(), config.clone()),
+    );
+
     // Build router
     let public_routes = Router::new()
         .route("/", get(handlers::root))
         .route("/health", get(handlers::health))
-        .route("/api/login", post(handlers::api_login_with_secret));
+        .route("/api/login", post(handlers::api_login_with_secret))
+        .route(
+            pyro_core::handoff::STATUS_PATH,
+            post(handlers::pipeline_status_callback),
+        );
 
     let protected_routes = Router::new()
         .route("/api/logout", post(handlers::api_logout))
         .route("/api/validate", get(handlers::api_validate_session))
-        .route("/api/cryptex", post(handlers::create_cryptex))
-        .route("/api/cryptex/:id", get(handlers::get_cryptex))
+        .route(
+            "/api/cryptex-stores",
+            get(handlers::list_cryptex_stores).post(handlers::create_cryptex_store),
+        )
+        .route(
+            "/api/cryptex-stores/{name}",
+            get(handlers::get_cryptex_store)
+                .put(handlers::update_cryptex_store)
+                .delete(handlers::delete_cryptex_store),
+        )
+        .route(
+            "/api/cryptex",
+            get(handlers::list_cryptex).post(handlers::create_cryptex),
+        )
+        .route(
+            "/api/cryptex/{*path}",
+            get(handlers::get_cryptex)
+                .put(handlers::update_cryptex)
+                .delete(handlers::delete_cryptex),
+        )
+        .route(
+            "/api/cryptex-revisions/{*path}",
+            get(handlers::get_cryptex_revisions).post(handlers::revert_cryptex),
+        )
+        .route(
+            "/api/cryptex-bundle",
+            get(handlers::export_cryptex_bundle)
+                .post(handlers::import_cryptex_bundle)
+                .layer(DefaultBodyLimit::max(api::bundles::MAX_BUNDLE_SIZE)),
+        )
+        .route("/api/cryptex-crate", get(handlers::generate_cryptex_crate))
         .route("/api/extract", post(handlers::extract_data))
         .route("/api/pipeline", post(handlers::create_pipeline))
+        .route("/api/pipeline/{id}", get(handlers::get_pipeline_status))
         .layer(from_fn(middleware::require_auth_middleware))
-        .layer(from_fn(middleware::auth_middleware));
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::auth_middleware,
+        ));
 
     let app = public_routes.merge(protected_routes).with_state(state);
 
@@ -59,12 +119,14 @@ async fn main() -> Result<()> {
     let addr: SocketAddr = addr_str
         .parse()
         .with_context(|| format!("Invalid server address '{}'", addr_str))?;
-    println!("🩸 BloodSniffer is active at {}", addr);
+    let scheme = if config.server.tls.enabled {
+        "https"
+    } else {
+        "http"
+    };
+    println!("🩸 BloodSniffer is active at {}://{}", scheme, addr);
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    tls::serve(app, addr, &config, shutdown_signal()).await?;
 
     Ok(())
 }
@@ -0,0 +1,556 @@
+// Node schemas
+// Expected properties per node type, loaded from a schema file or inferred from ingested data,
+// used to validate extracted nodes and coerce well-known BloodHound fields
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::data_extractor::Node;
+use crate::extraction_catalog::{JsonType, KindSummary};
+
+/// Warnings kept per validation report; the rest are only counted
+pub const MAX_WARNINGS: usize = 1000;
+
+/// Seconds between 1601-01-01 (Windows FILETIME epoch) and 1970-01-01
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Integers above this are FILETIMEs (100ns ticks) rather than Unix seconds
+const FILETIME_THRESHOLD: i64 = 100_000_000_000_000;
+
+/// BloodHound properties holding points in time
+const TIMESTAMP_FIELDS: &[&str] = &[
+    "lastlogon",
+    "lastlogontimestamp",
+    "pwdlastset",
+    "whencreated",
+    "whenchanged",
+];
+
+/// BloodHound properties holding flags
+const BOOLEAN_FIELDS: &[&str] = &[
+    "enabled",
+    "admincount",
+    "hasspn",
+    "sensitive",
+    "dontreqpreauth",
+    "passwordnotreqd",
+    "pwdneverexpires",
+    "unconstraineddelegation",
+    "trustedtoauth",
+    "highvalue",
+];
+
+/// Expected type of a property
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PropertyType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// Unix seconds; FILETIMEs and RFC 3339 strings are converted
+    Timestamp,
+    Array,
+    Object,
+}
+
+impl PropertyType {
+    /// Type known for a well-known BloodHound property
+    pub fn known(property: &str) -> Option<Self> {
+        let property = property.to_ascii_lowercase();
+        if TIMESTAMP_FIELDS.contains(&property.as_str()) {
+            Some(PropertyType::Timestamp)
+        } else if BOOLEAN_FIELDS.contains(&property.as_str()) {
+            Some(PropertyType::Boolean)
+        } else {
+            None
+        }
+    }
+
+    /// Single type that covers every JSON type seen, ignoring nulls
+    fn covering(types: impl IntoIterator<Item = JsonType>) -> Option<Self> {
+        let mut covering = None;
+        for json_type in types {
+            let next = match json_type {
+                JsonType::Null => continue,
+                JsonType::Boolean => PropertyType::Boolean,
+                JsonType::Integer => PropertyType::Integer,
+                JsonType::Number => PropertyType::Number,
+                JsonType::String => PropertyType::String,
+                JsonType::Array => PropertyType::Array,
+                JsonType::Object => PropertyType::Object,
+            };
+            covering = match (covering, next) {
+                (None, next) => Some(next),
+                (Some(current), next) if current == next => Some(current),
+                (Some(PropertyType::Integer), PropertyType::Number)
+                | (Some(PropertyType::Number), PropertyType::Integer) => Some(PropertyType::Number),
+                _ => return None,
+            };
+        }
+        covering
+    }
+
+    /// Convert `value` to this type, or `None` when it cannot be represented
+    fn coerce(&self, value: &Value) -> Option<Value> {
+        match (self, value) {
+            (PropertyType::String, Value::Number(_) | Value::Bool(_)) => {
+                Some(Value::String(value.to_string()))
+            }
+            (PropertyType::Integer, Value::Number(n)) => n
+                .as_f64()
+                .filter(|f| f.fract() == 0.0)
+                .map(|f| Value::from(f as i64)),
+            (PropertyType::Integer, Value::String(s)) => {
+                s.trim().parse::<i64>().ok().map(Value::from)
+            }
+            (PropertyType::Number, Value::Number(_)) => Some(value.clone()),
+            (PropertyType::Number, Value::String(s)) => {
+                s.trim().parse::<f64>().ok().map(Value::from)
+            }
+            (PropertyType::Boolean, Value::Bool(_)) => Some(value.clone()),
+            (PropertyType::Boolean, Value::Number(n)) => match n.as_i64() {
+                Some(0) => Some(Value::Bool(false)),
+                Some(1) => Some(Value::Bool(true)),
+                _ => None,
+            },
+            (PropertyType::Boolean, Value::String(s)) => {
+                match s.trim().to_ascii_lowercase().as_str() {
+                    "true" | "1" | "yes" => Some(Value::Bool(true)),
+                    "false" | "0" | "no" => Some(Value::Bool(false)),
+                    _ => None,
+                }
+            }
+            (PropertyType::Timestamp, Value::Number(n)) => n
+                .as_i64()
+                .or_else(|| n.as_f64().map(|f| f as i64))
+                .map(|t| Value::from(unix_seconds(t))),
+            (PropertyType::Timestamp, Value::String(s)) => {
+                let s = s.trim();
+                s.parse::<i64>()
+                    .map(unix_seconds)
+                    .ok()
+                    .or_else(|| DateTime::parse_from_rfc3339(s).ok().map(|t| t.timestamp()))
+                    .map(Value::from)
+            }
+            (PropertyType::Array, Value::Array(_)) | (PropertyType::Object, Value::Object(_)) => {
+                Some(value.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `value` already has this type
+    fn accepts(&self, value: &Value) -> bool {
+        match self {
+            PropertyType::String => value.is_string(),
+            PropertyType::Integer => value.is_i64() || value.is_u64(),
+            PropertyType::Number => value.is_number(),
+            PropertyType::Boolean => value.is_boolean(),
+            PropertyType::Timestamp => value.as_i64().is_some_and(|t| t < FILETIME_THRESHOLD),
+            PropertyType::Array => value.is_array(),
+            PropertyType::Object => value.is_object(),
+        }
+    }
+}
+
+/// Unix seconds from either Unix seconds or a Windows FILETIME.
+/// BloodHound's "never" markers (0 and -1) are kept as they are.
+fn unix_seconds(value: i64) -> i64 {
+    if value >= FILETIME_THRESHOLD {
+        value / 10_000_000 - FILETIME_EPOCH_OFFSET
+    } else {
+        value
+    }
+}
+
+/// Expectations for one property
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertySchema {
+    /// Expected type; any type is accepted when absent
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub property_type: Option<PropertyType>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Where a schema came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaSource {
+    #[default]
+    Config,
+    Inferred,
+}
+
+/// Expected properties of one node type
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSchema {
+    /// Warn about properties the schema does not list
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(default)]
+    pub properties: BTreeMap<String, PropertySchema>,
+    #[serde(skip_deserializing)]
+    pub source: SchemaSource,
+}
+
+impl NodeSchema {
+    /// Infer a schema from what was ingested for a kind (dictionary: infer_schema)
+    /// Pseudocode: Every property seen becomes part of the schema, required when every record
+    /// carried it, typed by the known BloodHound type or the single type covering its values
+    pub fn infer(summary: &KindSummary) -> Self {
+        let properties = summary
+            .properties
+            .iter()
+            .map(|(name, stats)| {
+                let property_type = PropertyType::known(name)
+                    .or_else(|| PropertyType::covering(stats.types.iter().copied()));
+                let schema = PropertySchema {
+                    property_type,
+                    required: summary.is_required(name),
+                };
+                (name.clone(), schema)
+            })
+            .collect();
+
+        NodeSchema {
+            strict: false,
+            properties,
+            source: SchemaSource::Inferred,
+        }
+    }
+}
+
+/// What was wrong with a node's property
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum ValidationIssue {
+    /// The node has no properties object
+    NotAnObject,
+    MissingRequired,
+    TypeMismatch {
+        expected: PropertyType,
+        found: JsonType,
+    },
+    /// Property not listed by a strict schema
+    Unexpected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationWarning {
+    pub node_id: String,
+    pub node_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    #[serde(flatten)]
+    pub issue: ValidationIssue,
+}
+
+/// Outcome of validating a batch of nodes
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    /// Nodes checked against a schema
+    pub validated: usize,
+    /// Property values converted to their schema type
+    pub coerced: usize,
+    pub warnings: Vec<ValidationWarning>,
+    /// Warnings beyond `MAX_WARNINGS` that were dropped from the list
+    pub warnings_truncated: usize,
+    /// Node types validated against an inferred rather than configured schema
+    pub inferred_schemas: Vec<String>,
+}
+
+impl ValidationReport {
+    fn warn(&mut self, node: &Node, property: Option<&str>, issue: ValidationIssue) {
+        if self.warnings.len() < MAX_WARNINGS {
+            self.warnings.push(ValidationWarning {
+                node_id: node.id.clone(),
+                node_type: node.node_type.clone(),
+                property: property.map(str::to_string),
+                issue,
+            });
+        } else {
+            self.warnings_truncated += 1;
+        }
+    }
+}
+
+/// Schemas keyed by node type
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<String, NodeSchema>,
+}
+
+impl SchemaRegistry {
+    /// Load schemas from a TOML (or, by extension, JSON) file keyed by node type
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schema file {:?}", path))?;
+
+        let registry: SchemaRegistry = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse schema file {:?}", path))?
+        } else {
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse schema file {:?}", path))?
+        };
+        Ok(registry)
+    }
+
+    pub fn get(&self, node_type: &str) -> Option<&NodeSchema> {
+        self.schemas.get(node_type)
+    }
+
+    pub fn insert(&mut self, node_type: impl Into<String>, schema: NodeSchema) {
+        self.schemas.insert(node_type.into(), schema);
+    }
+
+    pub fn node_types(&self) -> impl Iterator<Item = &str> {
+        self.schemas.keys().map(String::as_str)
+    }
+
+    /// Infer schemas for kinds that have no configured schema
+    pub fn infer_missing<'a, I>(&mut self, summaries: I)
+    where
+        I: IntoIterator<Item = &'a KindSummary>,
+    {
+        for summary in summaries {
+            if !self.schemas.contains_key(&summary.kind) {
+                self.insert(summary.kind.clone(), NodeSchema::infer(summary));
+            }
+        }
+    }
+
+    /// Validate nodes against their schemas, coercing values in place (dictionary: validate_nodes)
+    /// Pseudocode: For each node with a schema, convert every typed property to its schema
+    /// type, then warn about missing required properties, values that could not be converted
+    /// and, for strict schemas, properties the schema does not list
+    pub fn validate(&self, nodes: &mut [Node]) -> ValidationReport {
+        let mut report = ValidationReport {
+            inferred_schemas: self
+                .schemas
+                .iter()
+                .filter(|(_, schema)| schema.source == SchemaSource::Inferred)
+                .map(|(node_type, _)| node_type.clone())
+                .collect(),
+            ..ValidationReport::default()
+        };
+
+        for node in nodes.iter_mut() {
+            let Some(schema) = self.schemas.get(&node.node_type) else {
+                continue;
+            };
+            report.validated += 1;
+
+            let mut properties = match std::mem::take(&mut node.properties) {
+                Value::Object(properties) => properties,
+                Value::Null => Map::new(),
+                other => {
+                    node.properties = other;
+                    report.warn(node, None, ValidationIssue::NotAnObject);
+                    continue;
+                }
+            };
+
+            let mut issues = Vec::new();
+            for (name, expected) in &schema.properties {
+                match properties.get_mut(name) {
+                    None => {
+                        if expected.required {
+                            issues.push((name.clone(), ValidationIssue::MissingRequired));
+                        }
+                    }
+                    Some(Value::Null) => {}
+                    Some(value) => {
+                        let Some(property_type) = expected.property_type else {
+                            continue;
+                        };
+                        if property_type.accepts(value) {
+                            continue;
+                        }
+                        match property_type.coerce(value) {
+                            Some(coerced) => {
+                                *value = coerced;
+                                report.coerced += 1;
+                            }
+                            None => issues.push((
+                                name.clone(),
+                                ValidationIssue::TypeMismatch {
+                                    expected: property_type,
+                                    found: JsonType::of(value),
+                                },
+                            )),
+                        }
+                    }
+                }
+            }
+            if schema.strict {
+                for name in properties.keys() {
+                    if !schema.properties.contains_key(name) {
+                        issues.push((name.clone(), ValidationIssue::Unexpected));
+                    }
+                }
+            }
+
+            node.properties = Value::Object(properties);
+            for (name, issue) in issues {
+                report.warn(node, Some(&name), issue);
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_extractor::{BloodHoundExtractor, DataMetadata, ExtractedData};
+    use crate::extraction_catalog::ExtractionCatalog;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn users() -> Vec<Node> {
+        BloodHoundExtractor::extract_from_json(&json!({
+            "meta": { "type": "users" },
+            "data": [
+                {
+                    "ObjectIdentifier": "S-1-5-21-1001",
+                    "Properties": {
+                        "name": "ALICE@CORP.LOCAL",
+                        "enabled": "True",
+                        "lastlogon": 133_000_000_000_000_000_i64,
+                        "pwdlastset": "2023-06-01T12:00:00Z"
+                    }
+                },
+                {
+                    "ObjectIdentifier": "S-1-5-21-1002",
+                    "Properties": {
+                        "name": "BOB@CORP.LOCAL",
+                        "enabled": "maybe",
+                        "lastlogon": -1,
+                        "title": "Engineer"
+                    }
+                }
+            ]
+        }))
+        .unwrap()
+        .nodes
+    }
+
+    #[test]
+    fn test_configured_schema_coerces_and_warns() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("schemas.toml");
+        std::fs::write(
+            &path,
+            r#"
+[User]
+strict = true
+
+[User.properties.name]
+type = "string"
+required = true
+
+[User.properties.objectid]
+type = "string"
+required = true
+
+[User.properties.enabled]
+type = "boolean"
+
+[User.properties.lastlogon]
+type = "timestamp"
+
+[User.properties.pwdlastset]
+type = "timestamp"
+"#,
+        )
+        .unwrap();
+        let registry = SchemaRegistry::load(&path).unwrap();
+
+        let mut nodes = users();
+        let report = registry.validate(&mut nodes);
+        assert_eq!(report.validated, 2);
+        assert_eq!(report.coerced, 3);
+        assert!(report.inferred_schemas.is_empty());
+
+        assert_eq!(nodes[0].properties["enabled"], json!(true));
+        assert_eq!(nodes[0].properties["lastlogon"], json!(1_655_526_400));
+        assert_eq!(nodes[0].properties["pwdlastset"], json!(1_685_620_800));
+        assert_eq!(nodes[1].properties["lastlogon"], json!(-1));
+
+        let issues: Vec<(&str, Option<&str>, &ValidationIssue)> = report
+            .warnings
+            .iter()
+            .map(|w| (w.node_id.as_str(), w.property.as_deref(), &w.issue))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    "S-1-5-21-1001",
+                    Some("objectid"),
+                    &ValidationIssue::MissingRequired
+                ),
+                (
+                    "S-1-5-21-1002",
+                    Some("enabled"),
+                    &ValidationIssue::TypeMismatch {
+                        expected: PropertyType::Boolean,
+                        found: JsonType::String
+                    }
+                ),
+                (
+                    "S-1-5-21-1002",
+                    Some("objectid"),
+                    &ValidationIssue::MissingRequired
+                ),
+                ("S-1-5-21-1002", Some("title"), &ValidationIssue::Unexpected),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inferred_schema_uses_known_fields() {
+        let mut nodes = users();
+        let catalog = ExtractionCatalog::from_extracted(&ExtractedData {
+            nodes: nodes.clone(),
+            edges: Vec::new(),
+            metadata: DataMetadata {
+                extracted_at: 0,
+                source: "test".to_string(),
+                total_nodes: nodes.len(),
+                total_edges: 0,
+            },
+        });
+
+        let mut registry = SchemaRegistry::default();
+        registry.infer_missing(catalog.objects.values());
+        let schema = registry.get("User").unwrap();
+        assert_eq!(schema.source, SchemaSource::Inferred);
+        assert_eq!(
+            schema.properties["enabled"].property_type,
+            Some(PropertyType::Boolean)
+        );
+        assert_eq!(
+            schema.properties["lastlogon"].property_type,
+            Some(PropertyType::Timestamp)
+        );
+        assert!(schema.properties["name"].required);
+        assert!(!schema.properties["title"].required);
+
+        let report = registry.validate(&mut nodes);
+        assert_eq!(report.inferred_schemas, vec!["User"]);
+        assert_eq!(nodes[0].properties["enabled"], json!(true));
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].property.as_deref(), Some("enabled"));
+    }
+}
@@ -0,0 +1,81 @@
+// Pagination for list endpoints
+// Offset/limit query parameters and a page envelope with the total count
+
+use serde::{Deserialize, Serialize};
+
+/// Default page size when no limit is given
+pub const DEFAULT_PAGE_LIMIT: usize = 50;
+
+/// Largest page size a client may request
+pub const MAX_PAGE_LIMIT: usize = 500;
+
+/// Offset/limit query parameters
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Pagination {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+impl Pagination {
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    /// Requested limit, clamped to `1..=MAX_PAGE_LIMIT`
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT)
+    }
+
+    /// Slice one page out of a full result set
+    pub fn page<T>(&self, items: Vec<T>) -> Page<T> {
+        let total = items.len();
+        let offset = self.offset();
+        let limit = self.limit();
+        let items = items.into_iter().skip(offset).take(limit).collect();
+
+        Page {
+            items,
+            total,
+            offset,
+            limit,
+        }
+    }
+}
+
+/// One page of results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of results across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_bounds() {
+        let pagination = Pagination {
+            offset: Some(8),
+            limit: Some(5),
+        };
+        let page = pagination.page((0..10).collect());
+        assert_eq!(page.items, vec![8, 9]);
+        assert_eq!(page.total, 10);
+
+        let page = Pagination::default().page(vec![1, 2, 3]);
+        assert_eq!(page.limit, DEFAULT_PAGE_LIMIT);
+        assert_eq!(page.items.len(), 3);
+
+        let unbounded = Pagination {
+            offset: None,
+            limit: Some(100_000),
+        };
+        assert_eq!(unbounded.limit(), MAX_PAGE_LIMIT);
+    }
+}
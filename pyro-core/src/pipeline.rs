@@ -1,19 +1,88 @@
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 use std::fs;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::pipeline_graph::PipelineGraph;
+
 /// Definition for a queued data pipeline
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PipelineRecord {
     pub id: String,
+    /// Source URI (`file:`, `watch:`, `http(s):`, `cryptex:`, `node-red:`), or a free-form
+    /// label when the data is embedded as `payload`; graph pipelines list their sources
+    #[serde(default)]
     pub source: String,
+    #[serde(default)]
     pub transformers: Vec<String>,
+    /// Destination URI; graph pipelines list their sinks
+    #[serde(default)]
     pub destination: String,
+    /// Embedded input; `null` when the source URI is read instead
+    #[serde(default)]
     pub payload: Value,
     pub created_at: i64,
+    /// Run on a schedule instead of once; scheduled pipelines read from their source URI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<PipelineSchedule>,
+    /// Whether fire-marshal reports the run status of this pipeline to its configured
+    /// `callback_url`; queue files from before carry the URL itself as `callback_url`
+    #[serde(
+        default,
+        alias = "callback_url",
+        deserialize_with = "report_status_flag",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub report_status: bool,
+    /// Steps of a graph pipeline, run instead of `source`, `transformers` and `destination`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graph: Option<PipelineGraph>,
+}
+
+/// When a recurring pipeline runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineSchedule {
+    /// Cron expression, with or without a leading seconds field (`0 2 * * *` is 02:00 UTC daily)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// Fixed interval between runs, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every_secs: Option<u64>,
+    /// What to do with runs missed while fire-marshal was down
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+    /// What to do when a run is due while the previous one is still going
+    #[serde(default)]
+    pub concurrency: ConcurrencyPolicy,
+}
+
+/// Handling of scheduled runs that were missed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchUpPolicy {
+    /// Drop missed runs and wait for the next one
+    Skip,
+    /// Run once for all missed runs
+    #[default]
+    Once,
+    /// Run every missed run, one after another
+    All,
+}
+
+/// Handling of a run that is due while the previous one is still going
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConcurrencyPolicy {
+    /// Drop the new run
+    #[default]
+    Skip,
+    /// Start the new run once the previous one finishes
+    Queue,
+    /// Cancel the previous run and start the new one
+    Replace,
 }
 
 impl PipelineRecord {
@@ -31,8 +100,56 @@ impl PipelineRecord {
             destination: destination.into(),
             payload,
             created_at: chrono::Utc::now().timestamp(),
+            schedule: None,
+            report_status: false,
+            graph: None,
         }
     }
+
+    /// A graph pipeline; `source` and `destination` summarize its sources and sinks
+    pub fn from_graph(id: impl Into<String>, graph: PipelineGraph) -> Self {
+        let mut record = Self::new(
+            id,
+            graph.sources().collect::<Vec<_>>().join(", "),
+            Vec::new(),
+            graph.destinations().collect::<Vec<_>>().join(", "),
+            Value::Null,
+        );
+        record.graph = Some(graph);
+        record
+    }
+
+    /// Run the pipeline on a schedule
+    pub fn with_schedule(mut self, schedule: Option<PipelineSchedule>) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Have fire-marshal report run status to pyro-core
+    pub fn with_status_reports(mut self, report_status: bool) -> Self {
+        self.report_status = report_status;
+        self
+    }
+}
+
+/// `report_status` as a flag, or as the callback URL older records carried
+fn report_status_flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Set(bool),
+        Url(Option<String>),
+    }
+
+    Ok(match Flag::deserialize(deserializer)? {
+        Flag::Set(set) => set,
+        Flag::Url(url) => url.is_some(),
+    })
+}
+
+/// Pipeline IDs become queue file names, so they cannot leave the queue directory
+pub fn is_valid_pipeline_id(id: &str) -> bool {
+    !id.is_empty() && !id.starts_with('.') && !id.contains(['/', '\\'])
 }
 
 /// Registry for storing queued pipelines on disk
@@ -48,18 +165,24 @@ impl PipelineRegistry {
     }
 
     /// Persist a pipeline record to disk
+    ///
+    /// The record is written under a temporary name and renamed into place, so fire-marshal
+    /// never picks up a partially written file.
     pub fn persist(&self, record: &PipelineRecord) -> Result<PathBuf> {
         fs::create_dir_all(&self.work_dir)
             .with_context(|| format!("Failed to create {:?}", self.work_dir))?;
 
-        let file_path = self
-            .work_dir
-            .join(format!("{}.json", record.id.replace(':', "_")));
+        let file_name = format!("{}.json", record.id.replace(':', "_"));
+        let file_path = self.work_dir.join(&file_name);
+        let temp_path = self.work_dir.join(format!(".{}.tmp", file_name));
         let serialized =
             serde_json::to_vec_pretty(record).context("Failed to serialize pipeline record")?;
 
-        fs::write(&file_path, serialized)
-            .with_context(|| format!("Failed to write pipeline file {:?}", file_path))?;
+        fs::write(&temp_path, serialized)
+            .with_context(|| format!("Failed to write pipeline file {:?}", temp_path))?;
+        fs::rename(&temp_path, &file_path).with_context(|| {
+            format!("Failed to move {:?} -> {:?}", temp_path, file_path)
+        })?;
 
         Ok(file_path)
     }
@@ -90,7 +213,61 @@ mod tests {
 
         let path = registry.persist(&record).unwrap();
         assert!(path.exists());
+        // Only the renamed record is left behind
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
     }
-}
 
+    #[test]
+    fn record_without_payload_references_source() {
+        let record: PipelineRecord = serde_json::from_value(json!({
+            "id": "test",
+            "source": "file:///data/sharphound/*.json",
+            "transformers": ["dedupe"],
+            "destination": "redb:",
+            "created_at": 0
+        }))
+        .unwrap();
+        assert!(record.payload.is_null());
+        assert!(record.schedule.is_none());
+
+        let record: PipelineRecord = serde_json::from_value(json!({
+            "id": "nightly",
+            "source": "watch:///data/incoming",
+            "transformers": ["dedupe"],
+            "destination": "redb:",
+            "created_at": 0,
+            "schedule": { "cron": "0 2 * * *", "concurrency": "queue" }
+        }))
+        .unwrap();
+        let schedule = record.schedule.unwrap();
+        assert_eq!(schedule.catch_up, CatchUpPolicy::Once);
+        assert_eq!(schedule.concurrency, ConcurrencyPolicy::Queue);
+        assert!(!record.report_status);
+    }
 
+    #[test]
+    fn test_report_status_reads_old_callback_url() {
+        let record = |flag: (&str, Value)| -> PipelineRecord {
+            let mut value = json!({ "id": "p1", "source": "inline", "created_at": 0 });
+            value[flag.0] = flag.1;
+            serde_json::from_value(value).unwrap()
+        };
+        assert!(record(("report_status", json!(true))).report_status);
+        assert!(record(("callback_url", json!("http://pyro:3000/status"))).report_status);
+        assert!(!record(("callback_url", Value::Null)).report_status);
+
+        let record = PipelineRecord::new("p1", "inline", vec![], "redb:", Value::Null);
+        let value = serde_json::to_value(record.with_status_reports(false)).unwrap();
+        assert!(value.get("report_status").is_none());
+    }
+
+    #[test]
+    fn test_pipeline_ids_stay_in_queue() {
+        assert!(is_valid_pipeline_id("nightly"));
+        assert!(is_valid_pipeline_id("urn:pipeline:1"));
+        assert!(!is_valid_pipeline_id(""));
+        assert!(!is_valid_pipeline_id("../escape"));
+        assert!(!is_valid_pipeline_id(".hidden"));
+        assert!(!is_valid_pipeline_id("a\\b"));
+    }
+}
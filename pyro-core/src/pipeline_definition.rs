@@ -0,0 +1,490 @@
+// Pipeline definitions
+// Pipelines written as TOML or YAML files - sources, transformers with parameters, destinations
+// and a schedule - checked against the definition schema and compiled into pipeline records
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+use crate::pipeline::{is_valid_pipeline_id, PipelineRecord, PipelineSchedule};
+use crate::pipeline_graph::{GraphNode, GraphStep, PipelineGraph};
+
+/// Syntax a definition is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionFormat {
+    Toml,
+    Yaml,
+}
+
+impl DefinitionFormat {
+    /// Format of a definition file by extension: `.toml`, `.yaml` or `.yml`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "toml" => Some(DefinitionFormat::Toml),
+            "yaml" | "yml" => Some(DefinitionFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Format named by a media type such as `application/toml` or `application/yaml`
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let media_type = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        let subtype = media_type.rsplit(['/', '+']).next()?;
+        match subtype {
+            "toml" => Some(DefinitionFormat::Toml),
+            "yaml" | "x-yaml" | "yml" => Some(DefinitionFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// A pipeline definition file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineDefinition {
+    /// Pipeline ID; definitions loaded again replace the pipeline with this ID
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Source URIs; several sources are merged before the transformers run
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Transformers, run in order
+    #[serde(default)]
+    pub transformers: Vec<TransformerDefinition>,
+    /// Destination URIs; every destination receives the transformed output
+    #[serde(default)]
+    pub destinations: Vec<String>,
+    /// Run on a cron or fixed-interval schedule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<PipelineSchedule>,
+}
+
+/// A transformer and its parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformerDefinition {
+    pub name: String,
+    /// A value, a list of values or a table of `key = value` pairs; see `spec`
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+impl TransformerDefinition {
+    /// The transformer as a `name:arguments` spec (dictionary: transformer_definition_spec)
+    /// Pseudocode: No params give the bare name; a value is passed as is, a list joined with
+    /// commas and a table as comma separated `key=value` pairs. List and table entries must be
+    /// plain values without commas, since commas separate the arguments
+    pub fn spec(&self) -> Result<String, String> {
+        let name = self.name.trim();
+        if name.is_empty() || name.contains([':', ',']) {
+            return Err("transformer name must not be empty or contain ':' or ','".to_string());
+        }
+
+        let args = match &self.params {
+            Value::Null => return Ok(name.to_string()),
+            Value::Array(values) => values
+                .iter()
+                .map(argument)
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            Value::Object(pairs) => pairs
+                .iter()
+                .map(|(key, value)| match argument(value) {
+                    Ok(_) if key.is_empty() || key.contains([',', '=']) => Err(format!(
+                        "parameter '{key}' must not be empty or contain ',' or '='"
+                    )),
+                    Ok(value) => Ok(format!("{key}={value}")),
+                    Err(message) => Err(format!("parameter '{key}': {message}")),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            value => scalar(value).ok_or("params must be a value, a list or a table")?,
+        };
+        Ok(format!("{name}:{args}"))
+    }
+}
+
+/// A plain parameter value as text
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// One argument of a list or table
+fn argument(value: &Value) -> Result<String, String> {
+    let text = scalar(value).ok_or("list and table entries must be plain values")?;
+    if text.contains(',') {
+        return Err(format!("'{text}' must not contain ','"));
+    }
+    Ok(text)
+}
+
+/// A problem with a definition, with its position when the parser knows it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefinitionIssue {
+    /// Field path, e.g. `transformers[1].params`; empty for syntax errors
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub field: String,
+    /// 1-based line of the problem
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// 1-based column of the problem
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl DefinitionIssue {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    fn at(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: Some(line),
+            column: Some(column),
+            ..Self::new("", message)
+        }
+    }
+}
+
+impl fmt::Display for DefinitionIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "line {line}, column {column}: ")?;
+        }
+        if !self.field.is_empty() {
+            write!(f, "{}: ", self.field)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Every problem found in a definition
+#[derive(Debug, Clone, thiserror::Error)]
+pub struct DefinitionErrors(pub Vec<DefinitionIssue>);
+
+impl fmt::Display for DefinitionErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} pipeline definition error(s):", self.0.len())?;
+        for issue in &self.0 {
+            write!(f, "\n  - {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// 1-based line and column of a byte offset
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+impl PipelineDefinition {
+    /// Parse a definition, reporting syntax errors, unknown fields and values of the wrong
+    /// type with their line and column
+    pub fn parse(text: &str, format: DefinitionFormat) -> Result<Self, DefinitionErrors> {
+        let issue = match format {
+            DefinitionFormat::Toml => match toml::from_str(text) {
+                Ok(definition) => return Ok(definition),
+                Err(err) => match err.span() {
+                    Some(span) => {
+                        let (line, column) = position(text, span.start);
+                        DefinitionIssue::at(line, column, err.message())
+                    }
+                    None => DefinitionIssue::new("", err.message()),
+                },
+            },
+            DefinitionFormat::Yaml => match serde_yaml::from_str(text) {
+                Ok(definition) => return Ok(definition),
+                Err(err) => {
+                    let message = err.to_string();
+                    match err.location() {
+                        Some(at) => {
+                            // The message repeats the position at its end
+                            let suffix = format!(" at line {} column {}", at.line(), at.column());
+                            let message = message.strip_suffix(&suffix).unwrap_or(&message);
+                            DefinitionIssue::at(at.line(), at.column(), message)
+                        }
+                        None => DefinitionIssue::new("", message),
+                    }
+                }
+            },
+        };
+        Err(DefinitionErrors(vec![issue]))
+    }
+
+    /// Parse a definition file, picking the format by extension
+    pub fn load(path: &Path) -> Result<Self> {
+        let format = DefinitionFormat::from_path(path)
+            .with_context(|| format!("{:?} is not a .toml, .yaml or .yml file", path))?;
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pipeline definition {:?}", path))?;
+        Ok(Self::parse(&text, format)?)
+    }
+
+    /// Check the definition and build the pipeline record it describes
+    /// (dictionary: compile_pipeline_definition)
+    /// Pseudocode: Collect every problem with its field path - the ID, missing sources or
+    /// destinations, transformer parameters and the schedule. One source and one destination
+    /// make a linear record; otherwise sources are joined, run through one transform step and
+    /// fanned out to a sink per destination
+    pub fn compile(&self) -> Result<PipelineRecord, DefinitionErrors> {
+        let mut issues = Vec::new();
+        if !is_valid_pipeline_id(&self.id) {
+            issues.push(DefinitionIssue::new(
+                "id",
+                "must not be empty, start with '.' or contain '/' or '\\'",
+            ));
+        }
+        for (field, uris) in [
+            ("sources", &self.sources),
+            ("destinations", &self.destinations),
+        ] {
+            if uris.is_empty() {
+                issues.push(DefinitionIssue::new(field, "needs at least one URI"));
+            }
+            for (i, uri) in uris.iter().enumerate() {
+                if uri.trim().is_empty() {
+                    issues.push(DefinitionIssue::new(
+                        format!("{field}[{i}]"),
+                        "must not be empty",
+                    ));
+                }
+            }
+        }
+        let mut specs = Vec::with_capacity(self.transformers.len());
+        for (i, transformer) in self.transformers.iter().enumerate() {
+            match transformer.spec() {
+                Ok(spec) => specs.push(spec),
+                Err(message) => {
+                    issues.push(DefinitionIssue::new(format!("transformers[{i}]"), message))
+                }
+            }
+        }
+        if let Some(schedule) = &self.schedule {
+            match (&schedule.cron, schedule.every_secs) {
+                (Some(_), None) | (None, Some(1..)) => {}
+                (None, Some(0)) => issues.push(DefinitionIssue::new(
+                    "schedule.every_secs",
+                    "must be at least 1",
+                )),
+                _ => issues.push(DefinitionIssue::new(
+                    "schedule",
+                    "needs exactly one of cron or every_secs",
+                )),
+            }
+        }
+        if !issues.is_empty() {
+            return Err(DefinitionErrors(issues));
+        }
+
+        let record = match (self.sources.as_slice(), self.destinations.as_slice()) {
+            ([source], [destination]) => {
+                PipelineRecord::new(&self.id, source, specs, destination, Value::Null)
+            }
+            _ => PipelineRecord::from_graph(&self.id, self.graph(specs)),
+        };
+        Ok(record.with_schedule(self.schedule.clone()))
+    }
+
+    /// Graph of a definition with several sources or destinations
+    fn graph(&self, specs: Vec<String>) -> PipelineGraph {
+        let node =
+            |id: String, step: GraphStep, inputs: Vec<String>| GraphNode { id, step, inputs };
+        let mut nodes: Vec<GraphNode> = self
+            .sources
+            .iter()
+            .enumerate()
+            .map(|(i, uri)| {
+                let step = GraphStep::Source {
+                    uri: uri.clone(),
+                    payload: Value::Null,
+                };
+                node(format!("source-{}", i + 1), step, Vec::new())
+            })
+            .collect();
+
+        let mut last: Vec<String> = nodes.iter().map(|n| n.id.clone()).collect();
+        if last.len() > 1 {
+            nodes.push(node("join".to_string(), GraphStep::Join, last));
+            last = vec!["join".to_string()];
+        }
+        if !specs.is_empty() {
+            let step = GraphStep::Transform {
+                transformers: specs,
+            };
+            nodes.push(node("transform".to_string(), step, last));
+            last = vec!["transform".to_string()];
+        }
+        for (i, destination) in self.destinations.iter().enumerate() {
+            let step = GraphStep::Sink {
+                destination: destination.clone(),
+            };
+            nodes.push(node(format!("sink-{}", i + 1), step, last.clone()));
+        }
+        PipelineGraph { nodes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_and_yaml_definitions_compile_alike() {
+        let toml = r#"
+id = "nightly-refresh"
+sources = ["watch:///data/sharphound"]
+destinations = ["redb:"]
+
+[[transformers]]
+name = "filter"
+params = ["User", "Computer"]
+
+[[transformers]]
+name = "enrich"
+params = { env = "lab", run = "$pipeline_id" }
+
+[[transformers]]
+name = "dedupe"
+
+[schedule]
+cron = "0 2 * * *"
+concurrency = "queue"
+"#;
+        let yaml = r#"
+id: nightly-refresh
+sources: [watch:///data/sharphound]
+transformers:
+  - name: filter
+    params: [User, Computer]
+  - name: enrich
+    params: { env: lab, run: $pipeline_id }
+  - name: dedupe
+destinations: ["redb:"]
+schedule:
+  cron: 0 2 * * *
+  concurrency: queue
+"#;
+        let from_toml = PipelineDefinition::parse(toml, DefinitionFormat::Toml).unwrap();
+        let from_yaml = PipelineDefinition::parse(yaml, DefinitionFormat::Yaml).unwrap();
+        assert_eq!(from_toml, from_yaml);
+
+        let record = from_toml.compile().unwrap();
+        assert_eq!(record.source, "watch:///data/sharphound");
+        assert_eq!(
+            record.transformers,
+            vec![
+                "filter:User,Computer",
+                "enrich:env=lab,run=$pipeline_id",
+                "dedupe"
+            ]
+        );
+        assert_eq!(record.destination, "redb:");
+        assert!(record.graph.is_none());
+        assert_eq!(record.schedule.unwrap().cron.as_deref(), Some("0 2 * * *"));
+    }
+
+    #[test]
+    fn test_several_sources_and_destinations_make_a_graph() {
+        let definition = PipelineDefinition {
+            id: "merge".to_string(),
+            description: None,
+            sources: vec![
+                "file:///data/ad/*.json".into(),
+                "https://example.org/a.json".into(),
+            ],
+            transformers: vec![],
+            destinations: vec!["redb:".into(), "node-red:".into()],
+            schedule: None,
+        };
+        let record = definition.compile().unwrap();
+        let graph = record.graph.unwrap();
+        let ids: Vec<&str> = graph.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["source-1", "source-2", "join", "sink-1", "sink-2"]
+        );
+        assert!(graph.order().is_ok());
+        assert_eq!(record.destination, "redb:, node-red:");
+    }
+
+    #[test]
+    fn test_errors_carry_positions_and_fields() {
+        let toml = "id = \"x\"\nsources = [\"redb:\"]\n\n[[transformers]]\nnmae = \"dedupe\"\n";
+        let errors = PipelineDefinition::parse(toml, DefinitionFormat::Toml).unwrap_err();
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!((errors.0[0].line, errors.0[0].column), (Some(5), Some(1)));
+        assert!(errors.0[0].message.contains("unknown field `nmae`"));
+
+        let yaml = "id: x\nsources:\n  - \"redb:\"\nschedule:\n  every_secs: soon\n";
+        let errors = PipelineDefinition::parse(yaml, DefinitionFormat::Yaml).unwrap_err();
+        assert_eq!(errors.0[0].line, Some(5));
+        assert!(!errors.0[0].message.contains("at line"));
+
+        let definition = PipelineDefinition::parse(
+            r#"
+id = "../escape"
+sources = ["redb:"]
+transformers = [{ name = "drop", params = [["nested"]] }]
+schedule = { every_secs = 0 }
+"#,
+            DefinitionFormat::Toml,
+        )
+        .unwrap();
+        let fields: Vec<String> = definition
+            .compile()
+            .unwrap_err()
+            .0
+            .into_iter()
+            .map(|issue| issue.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "id",
+                "destinations",
+                "transformers[0]",
+                "schedule.every_secs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_detection() {
+        assert_eq!(
+            DefinitionFormat::from_path(Path::new("defs/nightly.YML")),
+            Some(DefinitionFormat::Yaml)
+        );
+        assert_eq!(DefinitionFormat::from_path(Path::new("nightly.json")), None);
+        assert_eq!(
+            DefinitionFormat::from_content_type("application/toml; charset=utf-8"),
+            Some(DefinitionFormat::Toml)
+        );
+        assert_eq!(
+            DefinitionFormat::from_content_type("application/vnd.pipeline+yaml"),
+            Some(DefinitionFormat::Yaml)
+        );
+        assert_eq!(
+            DefinitionFormat::from_content_type("application/json"),
+            None
+        );
+    }
+}
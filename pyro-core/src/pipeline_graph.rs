@@ -0,0 +1,344 @@
+// Pipeline graphs
+// Pipelines defined as directed acyclic graphs of sources, transformer steps, branches, joins
+// and sinks, checked for unknown inputs and cycles before they are queued
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::data_extractor::Node;
+
+/// A pipeline as a graph of steps; data flows from sources along `inputs` to sinks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineGraph {
+    pub nodes: Vec<GraphNode>,
+}
+
+/// One step of a pipeline graph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphNode {
+    /// Unique within the graph
+    pub id: String,
+    #[serde(flatten)]
+    pub step: GraphStep,
+    /// Nodes whose output feeds this one; the outputs of several inputs are merged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+}
+
+/// What a graph node does, tagged by `type`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GraphStep {
+    /// Reads a source URI, or the embedded payload
+    Source {
+        uri: String,
+        #[serde(default, skip_serializing_if = "Value::is_null")]
+        payload: Value,
+    },
+    /// Runs a transformer chain, e.g. `["dedupe", "redact:email"]`
+    Transform { transformers: Vec<String> },
+    /// Passes on the nodes matching `when`; when too few match, nothing downstream of it runs
+    Branch { when: BranchCondition },
+    /// Merges its inputs, keeping the first node per id and edge per source, target and type
+    Join,
+    /// Delivers its input to a destination
+    Sink { destination: String },
+}
+
+impl GraphStep {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GraphStep::Source { .. } => "source",
+            GraphStep::Transform { .. } => "transform",
+            GraphStep::Branch { .. } => "branch",
+            GraphStep::Join => "join",
+            GraphStep::Sink { .. } => "sink",
+        }
+    }
+}
+
+/// Which nodes take a branch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BranchCondition {
+    /// Nodes of one of these types; any type when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub node_types: Vec<String>,
+    /// Nodes with this property set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    /// Nodes whose `property` has this value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<Value>,
+    /// Fewest matching nodes for the branch to be taken
+    #[serde(default = "default_min_matches")]
+    pub min_matches: usize,
+}
+
+fn default_min_matches() -> usize {
+    1
+}
+
+impl BranchCondition {
+    /// Whether a node takes the branch
+    pub fn matches(&self, node: &Node) -> bool {
+        if !self.node_types.is_empty() && !self.node_types.contains(&node.node_type) {
+            return false;
+        }
+        let Some(property) = &self.property else {
+            return true;
+        };
+        match (node.properties.get(property), &self.equals) {
+            (Some(value), Some(expected)) => value == expected,
+            (Some(value), None) => !value.is_null(),
+            (None, _) => false,
+        }
+    }
+}
+
+/// Why a pipeline graph cannot run
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GraphError {
+    #[error("pipeline graph has no nodes")]
+    Empty,
+    #[error("node ids must not be empty")]
+    EmptyId,
+    #[error("duplicate node '{0}'")]
+    DuplicateNode(String),
+    #[error("node '{node}' reads unknown input '{input}'")]
+    UnknownInput { node: String, input: String },
+    #[error("source '{0}' cannot have inputs")]
+    SourceWithInputs(String),
+    #[error("node '{0}' needs at least one input")]
+    MissingInputs(String),
+    #[error("sink '{0}' cannot feed other nodes")]
+    SinkWithOutputs(String),
+    #[error("node '{0}' does not lead to a sink")]
+    DeadEnd(String),
+    #[error("cycle {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+impl PipelineGraph {
+    /// Check the graph and order its nodes so every node comes after its inputs
+    /// (dictionary: order_pipeline_graph)
+    /// Pseudocode: Index node ids, check every input exists and every node's place - sources
+    /// without inputs, sinks without outputs, everything else between them - then peel off
+    /// nodes whose inputs are all ordered, in declaration order. Nodes left over sit on a
+    /// cycle, which is traced and reported
+    pub fn order(&self) -> Result<Vec<usize>, GraphError> {
+        if self.nodes.is_empty() {
+            return Err(GraphError::Empty);
+        }
+
+        let mut index = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.id.is_empty() {
+                return Err(GraphError::EmptyId);
+            }
+            if index.insert(node.id.as_str(), i).is_some() {
+                return Err(GraphError::DuplicateNode(node.id.clone()));
+            }
+        }
+
+        let mut outputs = vec![Vec::new(); self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            let is_source = matches!(node.step, GraphStep::Source { .. });
+            if is_source && !node.inputs.is_empty() {
+                return Err(GraphError::SourceWithInputs(node.id.clone()));
+            }
+            if !is_source && node.inputs.is_empty() {
+                return Err(GraphError::MissingInputs(node.id.clone()));
+            }
+            for input in &node.inputs {
+                let &from = index
+                    .get(input.as_str())
+                    .ok_or_else(|| GraphError::UnknownInput {
+                        node: node.id.clone(),
+                        input: input.clone(),
+                    })?;
+                outputs[from].push(i);
+            }
+        }
+        for (node, outputs) in self.nodes.iter().zip(&outputs) {
+            match (&node.step, outputs.is_empty()) {
+                (GraphStep::Sink { .. }, false) => {
+                    return Err(GraphError::SinkWithOutputs(node.id.clone()))
+                }
+                (GraphStep::Sink { .. }, true) | (_, false) => {}
+                (_, true) => return Err(GraphError::DeadEnd(node.id.clone())),
+            }
+        }
+
+        let mut pending: Vec<usize> = self.nodes.iter().map(|n| n.inputs.len()).collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut ready: Vec<usize> = (0..self.nodes.len()).filter(|&i| pending[i] == 0).collect();
+        loop {
+            // Lowest index first keeps the order stable across runs
+            ready.sort_unstable_by(|a, b| b.cmp(a));
+            let Some(next) = ready.pop() else { break };
+            order.push(next);
+            for &output in &outputs[next] {
+                pending[output] -= 1;
+                if pending[output] == 0 {
+                    ready.push(output);
+                }
+            }
+        }
+
+        if order.len() < self.nodes.len() {
+            return Err(GraphError::Cycle(self.trace_cycle(&index, &pending)));
+        }
+        Ok(order)
+    }
+
+    /// Walk inputs from a node left unordered until a node repeats
+    fn trace_cycle(&self, index: &HashMap<&str, usize>, pending: &[usize]) -> Vec<String> {
+        let Some(start) = (0..self.nodes.len()).find(|&i| pending[i] > 0) else {
+            return Vec::new();
+        };
+        let mut path = vec![start];
+        loop {
+            let current = path[path.len() - 1];
+            let Some(next) = self.nodes[current]
+                .inputs
+                .iter()
+                .map(|input| index[input.as_str()])
+                .find(|&i| pending[i] > 0)
+            else {
+                return Vec::new();
+            };
+            if let Some(at) = path.iter().position(|&i| i == next) {
+                // Inputs point upstream; report the cycle in the direction data flows
+                let mut cycle = vec![self.nodes[next].id.clone()];
+                cycle.extend(path[at..].iter().rev().map(|&i| self.nodes[i].id.clone()));
+                return cycle;
+            }
+            path.push(next);
+        }
+    }
+
+    /// Source URIs, in declaration order
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().filter_map(|node| match &node.step {
+            GraphStep::Source { uri, .. } => Some(uri.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Sink destinations, in declaration order
+    pub fn destinations(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().filter_map(|node| match &node.step {
+            GraphStep::Sink { destination } => Some(destination.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether any source embeds its input
+    pub fn has_payload(&self) -> bool {
+        self.nodes.iter().any(
+            |node| matches!(&node.step, GraphStep::Source { payload, .. } if !payload.is_null()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn graph(nodes: Value) -> PipelineGraph {
+        serde_json::from_value(json!({ "nodes": nodes })).unwrap()
+    }
+
+    #[test]
+    fn test_order_fans_out_and_joins() {
+        let graph = graph(json!([
+            { "id": "out", "type": "sink", "destination": "redb:", "inputs": ["merged"] },
+            { "id": "ad", "type": "source", "uri": "file:///data/ad/*.json" },
+            { "id": "azure", "type": "source", "uri": "file:///data/azure/*.json" },
+            { "id": "merged", "type": "join", "inputs": ["ad", "azure"] },
+            {
+                "id": "admins", "type": "branch", "inputs": ["merged"],
+                "when": { "node_types": ["User"], "property": "admincount", "equals": true }
+            },
+            { "id": "alert", "type": "sink", "destination": "node-red:", "inputs": ["admins"] }
+        ]));
+
+        let order: Vec<&str> = graph
+            .order()
+            .unwrap()
+            .into_iter()
+            .map(|i| graph.nodes[i].id.as_str())
+            .collect();
+        assert_eq!(
+            order,
+            vec!["ad", "azure", "merged", "out", "admins", "alert"]
+        );
+        assert_eq!(graph.sources().count(), 2);
+        assert_eq!(
+            graph.destinations().collect::<Vec<_>>(),
+            vec!["redb:", "node-red:"]
+        );
+    }
+
+    #[test]
+    fn test_cycles_and_bad_edges_rejected() {
+        let cyclic = graph(json!([
+            { "id": "src", "type": "source", "uri": "inline" },
+            { "id": "a", "type": "join", "inputs": ["src", "c"] },
+            { "id": "b", "type": "transform", "transformers": ["dedupe"], "inputs": ["a"] },
+            { "id": "c", "type": "transform", "transformers": [], "inputs": ["b"] },
+            { "id": "out", "type": "sink", "destination": "redb:", "inputs": ["b"] }
+        ]));
+        assert_eq!(
+            cyclic.order(),
+            Err(GraphError::Cycle(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "a".to_string()
+            ]))
+        );
+
+        let unknown = graph(json!([
+            { "id": "out", "type": "sink", "destination": "redb:", "inputs": ["missing"] }
+        ]));
+        assert!(matches!(
+            unknown.order(),
+            Err(GraphError::UnknownInput { .. })
+        ));
+
+        let dead_end = graph(json!([
+            { "id": "src", "type": "source", "uri": "inline" },
+            { "id": "t", "type": "transform", "transformers": [], "inputs": ["src"] },
+            { "id": "out", "type": "sink", "destination": "redb:", "inputs": ["src"] }
+        ]));
+        assert_eq!(dead_end.order(), Err(GraphError::DeadEnd("t".to_string())));
+    }
+
+    #[test]
+    fn test_branch_condition() {
+        let node = |node_type: &str, properties: Value| Node {
+            id: "n".to_string(),
+            label: "n".to_string(),
+            node_type: node_type.to_string(),
+            properties,
+        };
+        let when: BranchCondition = serde_json::from_value(json!({
+            "node_types": ["User"],
+            "property": "enabled"
+        }))
+        .unwrap();
+        assert_eq!(when.min_matches, 1);
+        assert!(when.matches(&node("User", json!({ "enabled": false }))));
+        assert!(!when.matches(&node("User", json!({ "enabled": null }))));
+        assert!(!when.matches(&node("Computer", json!({ "enabled": true }))));
+
+        let when = BranchCondition {
+            equals: Some(json!(true)),
+            ..when
+        };
+        assert!(!when.matches(&node("User", json!({ "enabled": false }))));
+    }
+}
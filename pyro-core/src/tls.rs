@@ -0,0 +1,353 @@
+// TLS termination for the axum servers
+// HTTPS with optional mutual TLS, self-signed development certificates and
+// certificate hot reload
+
+use anyhow::{Context, Result};
+use axum::{middleware::AddExtension, Extension, Router};
+use axum_server::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+    Handle,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::future::Future;
+use std::io::{self, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+
+use crate::config::{Config, TlsConfig};
+
+/// How often certificate files are checked for changes
+const CERT_RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long in-flight requests may finish after a shutdown signal
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Verified client certificate presented during the TLS handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// Full distinguished name, e.g. `CN=alice, O=BloodSniffer`
+    pub subject: String,
+    /// Subject common name, used to look up the matching user
+    pub common_name: Option<String>,
+}
+
+impl ClientCertificate {
+    /// Parse the subject of a DER encoded certificate
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            common_name,
+        })
+    }
+}
+
+/// Serve an application over HTTP or HTTPS (dictionary: tls_serve)
+/// Pseudocode: Bind plain TCP when TLS is disabled, otherwise load (or generate) the
+/// certificate, watch it for changes and attach client certificates to requests
+pub async fn serve<F>(app: Router, addr: SocketAddr, config: &Config, shutdown: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let tls = &config.server.tls;
+
+    if !tls.enabled {
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await?;
+        return Ok(());
+    }
+
+    if config.dev_mode && ensure_self_signed(tls, &config.server.host)? {
+        println!(
+            "🔐 Generated self-signed development certificate at {}",
+            tls.cert_path().display()
+        );
+    }
+
+    let rustls_config = RustlsConfig::from_config(Arc::new(load_server_config(tls)?));
+    let _reloader = spawn_certificate_reloader(tls.clone(), rustls_config.clone());
+
+    let handle = Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown.await;
+        shutdown_handle.graceful_shutdown(Some(SHUTDOWN_GRACE_PERIOD));
+    });
+
+    axum_server::bind(addr)
+        .acceptor(ClientCertAcceptor::new(RustlsAcceptor::new(rustls_config)))
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+/// Build a rustls server configuration from the configured PEM files
+pub fn load_server_config(tls: &TlsConfig) -> Result<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = load_certs(&tls.cert_path())?;
+    let key = load_private_key(&tls.key_path())?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("Failed to select TLS protocol versions")?;
+
+    let builder = match &tls.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("Invalid client CA certificate in {:?}", ca_path))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if tls.require_client_cert {
+                verifier.build()
+            } else {
+                verifier.allow_unauthenticated().build()
+            }
+            .context("Failed to build client certificate verifier")?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .context("Certificate and private key do not match")?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(server_config)
+}
+
+/// Generate a self-signed certificate for development (dictionary: ensure_self_signed)
+/// Pseudocode: If the certificate or key file is missing, create both for the server host and localhost
+pub fn ensure_self_signed(tls: &TlsConfig, host: &str) -> Result<bool> {
+    let cert_path = tls.cert_path();
+    let key_path = tls.key_path();
+    if cert_path.exists() && key_path.exists() {
+        return Ok(false);
+    }
+
+    let mut names = vec!["localhost".to_string()];
+    if host != "localhost" {
+        names.push(host.to_string());
+    }
+    let certified = rcgen::generate_simple_self_signed(names)
+        .context("Failed to generate self-signed certificate")?;
+
+    for path in [&cert_path, &key_path] {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+    }
+    std::fs::write(&cert_path, certified.cert.pem())
+        .with_context(|| format!("Failed to write certificate {:?}", cert_path))?;
+
+    // Created owner-only so the key is never readable by others, not even briefly
+    if key_path.exists() {
+        std::fs::remove_file(&key_path)
+            .with_context(|| format!("Failed to remove {:?}", key_path))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&key_path)
+        .and_then(|mut file| file.write_all(certified.key_pair.serialize_pem().as_bytes()))
+        .with_context(|| format!("Failed to write private key {:?}", key_path))?;
+
+    Ok(true)
+}
+
+/// Spawn the certificate reloader (dictionary: spawn_certificate_reloader)
+/// Pseudocode: Poll certificate, key and client CA modification times and swap the
+/// server configuration when any of them changes; keep serving the old one on errors
+pub fn spawn_certificate_reloader(
+    tls: TlsConfig,
+    rustls_config: RustlsConfig,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut modified = certificate_mtimes(&tls);
+        let mut ticker = tokio::time::interval(CERT_RELOAD_POLL_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let latest = certificate_mtimes(&tls);
+            if latest == modified {
+                continue;
+            }
+            modified = latest;
+
+            match load_server_config(&tls) {
+                Ok(server_config) => {
+                    rustls_config.reload_from_config(Arc::new(server_config));
+                    println!("🔐 Reloaded TLS certificate {}", tls.cert_path().display());
+                }
+                Err(err) => eprintln!("⚠️  TLS certificate reload failed: {err:#}"),
+            }
+        }
+    })
+}
+
+fn certificate_mtimes(tls: &TlsConfig) -> Vec<Option<SystemTime>> {
+    let mut paths = vec![tls.cert_path(), tls.key_path()];
+    paths.extend(tls.client_ca_path.clone());
+    paths
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to parse certificates in {:?}", path))?;
+
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {:?}", path);
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key in {:?}", path))?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {:?}", path))
+}
+
+/// Acceptor that records the verified client certificate as a request extension
+#[derive(Debug, Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(inner: RustlsAcceptor) -> Self {
+        Self { inner }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, Option<ClientCertificate>>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let client_cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| ClientCertificate::from_der(cert));
+            let service = Extension(client_cert).layer(service);
+
+            Ok((stream, service))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn dev_tls(temp_dir: &TempDir) -> TlsConfig {
+        TlsConfig {
+            enabled: true,
+            cert_path: Some(temp_dir.path().join("tls/cert.pem")),
+            key_path: Some(temp_dir.path().join("tls/key.pem")),
+            ..TlsConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_self_signed_certificate_loads() {
+        let temp_dir = TempDir::new().unwrap();
+        let tls = dev_tls(&temp_dir);
+
+        assert!(ensure_self_signed(&tls, "127.0.0.1").unwrap());
+        assert!(!ensure_self_signed(&tls, "127.0.0.1").unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(tls.key_path())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let server_config = load_server_config(&tls).unwrap();
+        assert_eq!(server_config.alpn_protocols[0], b"h2");
+    }
+
+    #[test]
+    fn test_mutual_tls_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut tls = dev_tls(&temp_dir);
+        ensure_self_signed(&tls, "localhost").unwrap();
+
+        // The server certificate doubles as the client CA for this test
+        tls.client_ca_path = tls.cert_path.clone();
+        tls.require_client_cert = true;
+        assert!(load_server_config(&tls).is_ok());
+
+        tls.client_ca_path = Some(temp_dir.path().join("missing-ca.pem"));
+        assert!(load_server_config(&tls).is_err());
+    }
+
+    #[test]
+    fn test_client_certificate_subject() {
+        let mut params = rcgen::CertificateParams::new(vec!["alice".to_string()]).unwrap();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "alice");
+        params
+            .distinguished_name
+            .push(rcgen::DnType::OrganizationName, "BloodSniffer");
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+
+        let client = ClientCertificate::from_der(cert.der()).unwrap();
+        assert_eq!(client.common_name.as_deref(), Some("alice"));
+        assert!(client.subject.contains("O=BloodSniffer"));
+    }
+}